@@ -0,0 +1,366 @@
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+use {Commute, CommuteRef};
+use distributions::{beta_cdf, beta_pdf, beta_quantile, student_t_quantile};
+
+/// A commutative Bayesian model of a rate, such as a conversion rate, from
+/// successes out of trials.
+///
+/// The rate has a beta prior, which is conjugate to the binomial
+/// likelihood: after `s` successes in `n` trials, a `Beta(alpha, beta)`
+/// prior becomes a `Beta(alpha + s, beta + n - s)` posterior. Unlike the
+/// normal approximation of `Experiment`, the posterior is exact for any
+/// number of trials, so it is useful early in an experiment or for rare
+/// events, and two models can be compared directly with
+/// `probability_better_than`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct BetaBinomial {
+    alpha: f64,
+    beta: f64,
+    successes: u64,
+    trials: u64,
+}
+
+impl BetaBinomial {
+    /// Create a model with a uniform prior, `Beta(1, 1)`.
+    pub fn new() -> BetaBinomial {
+        BetaBinomial::with_prior(1.0, 1.0)
+    }
+
+    /// Create a model with a `Beta(alpha, beta)` prior, which counts as
+    /// `alpha` prior successes and `beta` prior failures.
+    ///
+    /// This panics if `alpha` or `beta` is not positive.
+    pub fn with_prior(alpha: f64, beta: f64) -> BetaBinomial {
+        assert!(alpha > 0.0 && beta > 0.0,
+                "prior parameters must be positive");
+        BetaBinomial { alpha, beta, successes: 0, trials: 0 }
+    }
+
+    /// Add a trial, which is `true` for a success.
+    pub fn add(&mut self, success: bool) {
+        self.add_trials(success as u64, 1);
+    }
+
+    /// Add `successes` successes out of `trials` trials.
+    ///
+    /// This panics if there are more successes than trials.
+    pub fn add_trials(&mut self, successes: u64, trials: u64) {
+        assert!(successes <= trials, "successes must not exceed trials");
+        self.successes += successes;
+        self.trials += trials;
+    }
+
+    /// Returns the number of successes.
+    pub fn successes(&self) -> u64 {
+        self.successes
+    }
+
+    /// Returns the number of trials.
+    pub fn trials(&self) -> u64 {
+        self.trials
+    }
+
+    /// Returns the parameters `(alpha, beta)` of the beta posterior.
+    pub fn posterior(&self) -> (f64, f64) {
+        (self.alpha + self.successes as f64,
+         self.beta + (self.trials - self.successes) as f64)
+    }
+
+    /// Returns the mean of the posterior, the expected rate.
+    pub fn posterior_mean(&self) -> f64 {
+        let (a, b) = self.posterior();
+        a / (a + b)
+    }
+
+    /// Returns the equal-tailed credible interval of the rate.
+    ///
+    /// `level` is the posterior probability of the interval, e.g., `0.95`.
+    /// This panics if `level` is not in `(0, 1)`.
+    pub fn credible_interval(&self, level: f64) -> (f64, f64) {
+        assert!(level > 0.0 && level < 1.0, "level must be in (0, 1)");
+        let (a, b) = self.posterior();
+        let tail = (1.0 - level) / 2.0;
+        (beta_quantile(tail, a, b), beta_quantile(1.0 - tail, a, b))
+    }
+
+    /// Returns the posterior probability that the rate of this model is
+    /// greater than the rate of `other`.
+    ///
+    /// This integrates the density of this posterior times the CDF of the
+    /// other posterior with Simpson's rule, over all but `1e-12` of the
+    /// mass at each end, for an absolute error of about `1e-9`.
+    pub fn probability_better_than(&self, other: &BetaBinomial) -> f64 {
+        const INTERVALS: usize = 1000;
+        let ((a1, b1), (a2, b2)) = (self.posterior(), other.posterior());
+        let lo = beta_quantile(1e-12, a1, b1);
+        let hi = beta_quantile(1.0 - 1e-12, a1, b1);
+        let h = (hi - lo) / INTERVALS as f64;
+        let sum: f64 = (0..INTERVALS + 1).map(|i| {
+            let x = lo + i as f64 * h;
+            let w = if i == 0 || i == INTERVALS {
+                1.0
+            } else if i % 2 == 1 {
+                4.0
+            } else {
+                2.0
+            };
+            w * beta_pdf(x, a1, b1) * beta_cdf(x, a2, b2)
+        }).sum();
+        (sum * h / 3.0).max(0.0).min(1.0)
+    }
+}
+
+impl Commute for BetaBinomial {
+    fn merge(&mut self, v: BetaBinomial) {
+        self.merge_ref(&v);
+    }
+}
+
+impl CommuteRef for BetaBinomial {
+    fn merge_ref(&mut self, v: &BetaBinomial) {
+        assert_eq!((self.alpha, self.beta), (v.alpha, v.beta),
+                   "models must have the same prior");
+        self.successes += v.successes;
+        self.trials += v.trials;
+    }
+}
+
+impl Default for BetaBinomial {
+    fn default() -> BetaBinomial {
+        BetaBinomial::new()
+    }
+}
+
+impl FromIterator<bool> for BetaBinomial {
+    fn from_iter<I: IntoIterator<Item=bool>>(it: I) -> BetaBinomial {
+        let mut v = BetaBinomial::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl Extend<bool> for BetaBinomial {
+    fn extend<I: IntoIterator<Item=bool>>(&mut self, it: I) {
+        for success in it {
+            self.add(success);
+        }
+    }
+}
+
+/// A commutative Bayesian model of normally distributed data with unknown
+/// mean and variance.
+///
+/// The mean and variance have a normal-inverse-gamma prior, which is
+/// conjugate to the normal likelihood, so the posterior after every sample
+/// is again normal-inverse-gamma. The prior counts as `kappa` samples with
+/// mean `mu` for the mean, and as `2 * alpha` samples with variance
+/// `beta / alpha` for the variance.
+///
+/// The posterior predictive distribution of the next sample is a Student's
+/// t distribution, whose intervals widen for small samples and approach the
+/// usual `mean ± z * sd` for large ones. This makes them suitable as
+/// alerting thresholds that adapt as data arrives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct NormalInverseGamma {
+    mu: f64,
+    kappa: f64,
+    alpha: f64,
+    beta: f64,
+    len: u64,
+    mean: f64,
+    /// The sum of squared deviations from `mean`.
+    sq_dev: f64,
+}
+
+impl NormalInverseGamma {
+    /// Create a model with a normal-inverse-gamma prior with parameters
+    /// `mu`, `kappa`, `alpha` and `beta`.
+    ///
+    /// This panics if `kappa`, `alpha` or `beta` is not positive.
+    pub fn new(mu: f64, kappa: f64, alpha: f64, beta: f64)
+              -> NormalInverseGamma {
+        assert!(kappa > 0.0 && alpha > 0.0 && beta > 0.0,
+                "prior parameters must be positive");
+        NormalInverseGamma {
+            mu,
+            kappa,
+            alpha,
+            beta,
+            len: 0,
+            mean: 0.0,
+            sq_dev: 0.0,
+        }
+    }
+
+    /// Add a sample.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        let x = sample.to_f64().unwrap();
+        self.len += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.len as f64;
+        self.sq_dev += delta * (x - self.mean);
+    }
+
+    /// Returns the parameters `(mu, kappa, alpha, beta)` of the posterior.
+    pub fn posterior(&self) -> (f64, f64, f64, f64) {
+        let n = self.len as f64;
+        let kappa = self.kappa + n;
+        let d = self.mean - self.mu;
+        let mu = (self.kappa * self.mu + n * self.mean) / kappa;
+        let beta = self.beta + self.sq_dev / 2.0
+                   + self.kappa * n * d * d / (2.0 * kappa);
+        (mu, kappa, self.alpha + n / 2.0, beta)
+    }
+
+    /// Returns the posterior mean of the mean.
+    pub fn mean(&self) -> f64 {
+        self.posterior().0
+    }
+
+    /// Returns the posterior mean of the variance.
+    ///
+    /// `None` is returned while `alpha` is at most `1` and the mean is
+    /// infinite.
+    pub fn variance(&self) -> Option<f64> {
+        let (_, _, alpha, beta) = self.posterior();
+        if alpha > 1.0 { Some(beta / (alpha - 1.0)) } else { None }
+    }
+
+    /// Returns the equal-tailed interval that contains the next sample
+    /// with probability `level`, e.g., `0.99`, under the posterior
+    /// predictive distribution.
+    ///
+    /// This panics if `level` is not in `(0, 1)`.
+    pub fn predictive_interval(&self, level: f64) -> (f64, f64) {
+        assert!(level > 0.0 && level < 1.0, "level must be in (0, 1)");
+        let (mu, kappa, alpha, beta) = self.posterior();
+        let scale = (beta * (kappa + 1.0) / (alpha * kappa)).sqrt();
+        let t = student_t_quantile(1.0 - (1.0 - level) / 2.0, 2.0 * alpha);
+        (mu - t * scale, mu + t * scale)
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl Commute for NormalInverseGamma {
+    fn merge(&mut self, v: NormalInverseGamma) {
+        self.merge_ref(&v);
+    }
+}
+
+impl CommuteRef for NormalInverseGamma {
+    fn merge_ref(&mut self, v: &NormalInverseGamma) {
+        assert_eq!((self.mu, self.kappa, self.alpha, self.beta),
+                   (v.mu, v.kappa, v.alpha, v.beta),
+                   "models must have the same prior");
+        if v.len == 0 {
+            return;
+        }
+        let (n1, n2) = (self.len as f64, v.len as f64);
+        let n = n1 + n2;
+        let delta = v.mean - self.mean;
+        self.mean += delta * n2 / n;
+        self.sq_dev += v.sq_dev + delta * delta * n1 * n2 / n;
+        self.len += v.len;
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for NormalInverseGamma {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BetaBinomial, NormalInverseGamma};
+    use Commute;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn posterior() {
+        let mut model = BetaBinomial::with_prior(2.0, 2.0);
+        model.add_trials(3, 10);
+        assert_eq!(model.posterior(), (5.0, 9.0));
+        assert!(close(model.posterior_mean(), 5.0 / 14.0));
+        let (lo, hi) = BetaBinomial::new().credible_interval(0.9);
+        assert!(close(lo, 0.05) && close(hi, 0.95));
+        let (lo, hi) = model.credible_interval(0.95);
+        assert!(lo < 5.0 / 14.0 && 5.0 / 14.0 < hi);
+    }
+
+    #[test]
+    fn superiority() {
+        // Beta(2, 1) against Beta(1, 1): the integral of 2x * x.
+        let a: BetaBinomial = vec![true].into_iter().collect();
+        let b = BetaBinomial::new();
+        assert!(close(a.probability_better_than(&b), 2.0 / 3.0));
+        assert!(close(b.probability_better_than(&a), 1.0 / 3.0));
+        assert!(close(a.probability_better_than(&a), 0.5));
+
+        let mut control = BetaBinomial::new();
+        control.add_trials(100, 1000);
+        let mut treatment = BetaBinomial::new();
+        treatment.add_trials(130, 1000);
+        let p = treatment.probability_better_than(&control);
+        assert!(p > 0.97 && p < 0.99);
+        assert!(close(p + control.probability_better_than(&treatment), 1.0));
+    }
+
+    #[test]
+    fn merge() {
+        let mut m1: BetaBinomial = vec![true, false].into_iter().collect();
+        let m2: BetaBinomial = vec![true, true, false].into_iter().collect();
+        m1.merge(m2);
+        assert_eq!((m1.successes(), m1.trials()), (3, 5));
+    }
+
+    #[test]
+    fn normal_inverse_gamma() {
+        let mut model = NormalInverseGamma::new(0.0, 1.0, 1.0, 1.0);
+        assert_eq!(model.variance(), None);
+        // A t distribution with 2 degrees of freedom and a scale of
+        // `sqrt(2)`.
+        let (lo, hi) = model.predictive_interval(0.95);
+        assert!(close(hi, 4.302652729911275 * 2f64.sqrt()) && close(lo, -hi));
+
+        model.extend(vec![1, 2, 3]);
+        assert_eq!(model.posterior(), (1.5, 4.0, 2.5, 3.5));
+        assert!(close(model.variance().unwrap(), 3.5 / 1.5));
+        let (lo, hi) = model.predictive_interval(0.95);
+        assert!(close((lo + hi) / 2.0, 1.5));
+
+        // More data narrows the interval towards `mean ± 1.96 * sd`.
+        model.extend((0..10000).map(|i| i % 2 * 2));
+        let (lo, hi) = model.predictive_interval(0.95);
+        assert!((hi - lo - 2.0 * 1.96).abs() < 0.01);
+    }
+
+    #[test]
+    fn normal_inverse_gamma_merge() {
+        let mut m1 = NormalInverseGamma::new(5.0, 0.5, 2.0, 3.0);
+        let mut m2 = m1;
+        let mut all = m1;
+        m1.extend(vec![1.0, 4.0, 2.5]);
+        m2.extend(vec![7.0, -1.0]);
+        all.extend(vec![1.0, 4.0, 2.5, 7.0, -1.0]);
+        m1.merge(m2);
+        let (p1, p2) = (m1.posterior(), all.posterior());
+        assert!(close(p1.0, p2.0) && close(p1.3, p2.3));
+        assert_eq!(m1.len(), 5);
+    }
+}
@@ -0,0 +1,136 @@
+use std::prelude::v1::*;
+
+use num_traits::ToPrimitive;
+
+use distributions::chi_squared_sf;
+use frequency::Frequencies;
+
+/// How closely first digits conform to Benford's law, by Nigrini's mean
+/// absolute deviation criteria.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conformity {
+    /// A mean absolute deviation of at most `0.006`.
+    Close,
+    /// A mean absolute deviation of at most `0.012`.
+    Acceptable,
+    /// A mean absolute deviation of at most `0.015`.
+    Marginal,
+    /// A mean absolute deviation of more than `0.015`.
+    Nonconformity,
+}
+
+/// The result of `benford_test`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenfordTest {
+    /// The chi-squared statistic of the first digit counts.
+    pub chi_squared: f64,
+    /// The p-value of the chi-squared test, with 8 degrees of freedom.
+    pub p_value: f64,
+    /// The mean absolute deviation of the observed proportions of each
+    /// digit from the proportions expected by Benford's law.
+    pub mad: f64,
+    /// The conformity implied by `mad`.
+    pub conformity: Conformity,
+}
+
+/// Returns the frequencies of the first significant digits of a stream of
+/// numbers.
+///
+/// The sign is ignored, so `-25` and `0.25` both have a first digit of `2`.
+/// Zeros and non-finite numbers have no first digit and are skipped.
+pub fn leading_digits<T, I>(it: I) -> Frequencies<u8>
+        where T: ToPrimitive, I: IntoIterator<Item=T> {
+    it.into_iter()
+      .filter_map(|x| leading_digit(x.to_f64().unwrap()))
+      .collect()
+}
+
+fn leading_digit(x: f64) -> Option<u8> {
+    let x = x.abs();
+    if x == 0.0 || !x.is_finite() {
+        return None;
+    }
+    let mut m = x / 10f64.powi(x.log10().floor() as i32);
+    // `log10` can round to the wrong side of a power of ten.
+    if m >= 10.0 {
+        m /= 10.0;
+    } else if m < 1.0 {
+        m *= 10.0;
+    }
+    Some((m as u8).max(1).min(9))
+}
+
+/// Tests whether first digit frequencies conform to Benford's law.
+///
+/// Benford's law predicts that the first digit `d` of many naturally
+/// occurring numbers appears with probability `log10(1 + 1/d)`, and
+/// deviations from it are a common screen for fabricated data. Since the
+/// chi-squared test rejects even slight deviations in large samples, the
+/// mean absolute deviation is given as well, which does not depend on the
+/// sample size.
+///
+/// Counts of values other than `1` to `9` are ignored. `None` is returned
+/// if there are no such counts.
+pub fn benford_test(digits: &Frequencies<u8>) -> Option<BenfordTest> {
+    let total: u64 = (1..10).map(|d| digits.count(&d)).sum();
+    if total == 0 {
+        return None;
+    }
+    let n = total as f64;
+    let (mut chi_squared, mut mad) = (0.0, 0.0);
+    for d in 1..10u8 {
+        let expected = (1.0 + 1.0 / d as f64).log10();
+        let observed = digits.count(&d) as f64 / n;
+        chi_squared += n * (observed - expected).powi(2) / expected;
+        mad += (observed - expected).abs() / 9.0;
+    }
+    let conformity = if mad <= 0.006 {
+        Conformity::Close
+    } else if mad <= 0.012 {
+        Conformity::Acceptable
+    } else if mad <= 0.015 {
+        Conformity::Marginal
+    } else {
+        Conformity::Nonconformity
+    };
+    Some(BenfordTest {
+        chi_squared,
+        p_value: chi_squared_sf(chi_squared, 8.0),
+        mad,
+        conformity,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Conformity, benford_test, leading_digits};
+    use frequency::Frequencies;
+
+    #[test]
+    fn digits() {
+        let digits = leading_digits(vec![1000.0, -25.0, 0.25, 0.0, 9.99e-5,
+                                         ::std::f64::NAN]);
+        assert_eq!(digits.count(&1), 1);
+        assert_eq!(digits.count(&2), 2);
+        assert_eq!(digits.count(&9), 1);
+        assert_eq!(digits.len(), 3);
+    }
+
+    #[test]
+    fn conforming() {
+        // Powers of two famously follow Benford's law.
+        let powers = (0..1000).map(|i| 2f64.powi(i));
+        let test = benford_test(&leading_digits(powers)).unwrap();
+        assert_eq!(test.conformity, Conformity::Close);
+        assert!(test.p_value > 0.5);
+    }
+
+    #[test]
+    fn nonconforming() {
+        let uniform = leading_digits((1..10).cycle().take(900));
+        let test = benford_test(&uniform).unwrap();
+        assert_eq!(test.conformity, Conformity::Nonconformity);
+        assert!(test.p_value < 1e-10);
+        assert_eq!(benford_test(&Frequencies::new()), None);
+    }
+}
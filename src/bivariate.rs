@@ -0,0 +1,207 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+use {Commute, CommuteRef};
+
+/// Online state for computing the covariance and correlation of pairs of
+/// samples.
+///
+/// Samples are given as `(x, y)` pairs. Like `OnlineStats`, this uses
+/// Welford's method, so the statistics are computed in one pass and merged
+/// exactly. The covariance and variances are population statistics, with
+/// `n` in the denominator.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct OnlineBivariate {
+    size: u64,
+    mean_x: f64,
+    mean_y: f64,
+    m2_x: f64,
+    m2_y: f64,
+    comoment: f64,
+}
+
+impl OnlineBivariate {
+    /// Create initial state.
+    pub fn new() -> OnlineBivariate {
+        Default::default()
+    }
+
+    /// Add a new pair of samples.
+    pub fn add<T: ToPrimitive>(&mut self, x: T, y: T) {
+        let (x, y) = (x.to_f64().unwrap(), y.to_f64().unwrap());
+        self.size += 1;
+        let n = self.size as f64;
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.mean_x += dx / n;
+        self.mean_y += dy / n;
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_y += dy * (y - self.mean_y);
+        self.comoment += dx * (y - self.mean_y);
+    }
+
+    /// Returns the mean of the `x` samples.
+    pub fn mean_x(&self) -> f64 {
+        self.mean_x
+    }
+
+    /// Returns the mean of the `y` samples.
+    pub fn mean_y(&self) -> f64 {
+        self.mean_y
+    }
+
+    /// Returns the variance of the `x` samples.
+    pub fn variance_x(&self) -> f64 {
+        self.m2_x / self.size as f64
+    }
+
+    /// Returns the variance of the `y` samples.
+    pub fn variance_y(&self) -> f64 {
+        self.m2_y / self.size as f64
+    }
+
+    /// Returns the covariance of `x` and `y`.
+    pub fn covariance(&self) -> f64 {
+        self.comoment / self.size as f64
+    }
+
+    /// Returns Pearson's correlation coefficient of `x` and `y`, in
+    /// `[-1, 1]`.
+    ///
+    /// If either variable has zero variance, `NaN` is returned.
+    pub fn pearson_correlation(&self) -> f64 {
+        let r = self.comoment / (self.m2_x * self.m2_y).sqrt();
+        // Rounding can push perfectly correlated data slightly past 1.
+        if r.is_nan() { r } else { r.max(-1.0).min(1.0) }
+    }
+
+    /// Returns the slope of the least squares line through the pairs, `y =
+    /// intercept + slope * x`.
+    ///
+    /// If all `x` samples are equal, `NaN` is returned.
+    pub fn slope(&self) -> f64 {
+        self.comoment / self.m2_x
+    }
+
+    /// Returns the intercept of the least squares line.
+    pub fn intercept(&self) -> f64 {
+        self.mean_y - self.slope() * self.mean_x
+    }
+
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl Commute for OnlineBivariate {
+    fn merge(&mut self, v: OnlineBivariate) {
+        if v.size == 0 {
+            return;
+        }
+        if self.size == 0 {
+            *self = v;
+            return;
+        }
+        let (s1, s2) = (self.size as f64, v.size as f64);
+        let n = s1 + s2;
+        let dx = v.mean_x - self.mean_x;
+        let dy = v.mean_y - self.mean_y;
+        self.m2_x += v.m2_x + dx * dx * s1 * s2 / n;
+        self.m2_y += v.m2_y + dy * dy * s1 * s2 / n;
+        self.comoment += v.comoment + dx * dy * s1 * s2 / n;
+        self.mean_x += dx * s2 / n;
+        self.mean_y += dy * s2 / n;
+        self.size += v.size;
+    }
+}
+
+impl CommuteRef for OnlineBivariate {
+    fn merge_ref(&mut self, v: &OnlineBivariate) {
+        self.merge(*v);
+    }
+}
+
+impl Default for OnlineBivariate {
+    fn default() -> OnlineBivariate {
+        OnlineBivariate {
+            size: 0,
+            mean_x: 0.0,
+            mean_y: 0.0,
+            m2_x: 0.0,
+            m2_y: 0.0,
+            comoment: 0.0,
+        }
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<(T, T)> for OnlineBivariate {
+    fn from_iter<I: IntoIterator<Item=(T, T)>>(it: I) -> OnlineBivariate {
+        let mut v = OnlineBivariate::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<(T, T)> for OnlineBivariate {
+    fn extend<I: IntoIterator<Item=(T, T)>>(&mut self, it: I) {
+        for (x, y) in it {
+            self.add(x, y)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OnlineBivariate;
+    use Commute;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn line() {
+        let stats: OnlineBivariate =
+            (0..10).map(|x| (x, 3 * x + 2)).collect();
+        assert!(close(stats.slope(), 3.0));
+        assert!(close(stats.intercept(), 2.0));
+        assert!(close(stats.pearson_correlation(), 1.0));
+        assert!(close(stats.covariance(), 3.0 * stats.variance_x()));
+
+        let flipped: OnlineBivariate =
+            (0..10).map(|x| (x, -x)).collect();
+        assert!(close(flipped.pearson_correlation(), -1.0));
+        let flat: OnlineBivariate = (0..3).map(|x| (x, 1)).collect();
+        assert!(flat.pearson_correlation().is_nan());
+    }
+
+    #[test]
+    fn covariance() {
+        let stats: OnlineBivariate =
+            vec![(1, 2), (2, 1), (3, 4), (4, 3)].into_iter().collect();
+        assert!(close(stats.covariance(), 0.75));
+        assert!(close(stats.pearson_correlation(), 0.6));
+        assert!(close(stats.mean_x(), 2.5));
+    }
+
+    #[test]
+    fn merge() {
+        let data = [(1.0, 5.0), (2.0, 3.0), (4.0, 6.0), (7.0, 1.0),
+                    (8.0, 2.0)];
+        let all: OnlineBivariate = data.iter().cloned().collect();
+        let mut s1: OnlineBivariate = data[..2].iter().cloned().collect();
+        let s2: OnlineBivariate = data[2..].iter().cloned().collect();
+        s1.merge(s2);
+        s1.merge(OnlineBivariate::new());
+        assert_eq!(s1.len(), 5);
+        assert!(close(s1.covariance(), all.covariance()));
+        assert!(close(s1.slope(), all.slope()));
+        assert!(close(s1.mean_y(), all.mean_y()));
+    }
+}
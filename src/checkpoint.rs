@@ -0,0 +1,381 @@
+use std::prelude::v1::*;
+
+use std::error;
+use std::fmt;
+
+use hashing::hash_with_seed;
+use records::Record;
+
+const MAGIC: &[u8; 4] = b"RSCK";
+const VERSION: u8 = 4;
+const CHECKSUM_LEN: usize = 8;
+
+/// The oldest version that can still be restored.
+const MIN_VERSION: u8 = 1;
+
+/// Returns the length of the header of a checkpoint. Before version 4, the
+/// length of the payload is a `u32`, so the header is shorter.
+fn header_len(version: u8) -> usize {
+    if version < 4 { 10 } else { 14 }
+}
+
+/// Accumulators whose state can be saved to and restored from bytes.
+///
+/// The format is deterministic (equal states produce equal bytes) and
+/// versioned, and every checkpoint carries a checksum that is verified on
+/// restore. This makes it suitable as the plaintext for SGX sealing, so an
+/// enclave can persist its statistics across restarts. Note that the
+/// checksum only detects corruption; authenticity comes from the sealing
+/// itself.
+///
+/// Checkpoints written by older versions of the format can still be
+/// restored. State that those versions did not record is restored as
+/// unknown, e.g. the skewness of `OnlineStats` from before version 3 is
+/// `NaN`.
+///
+/// Accumulators over generic values support any `Record` type.
+pub trait Checkpoint: Sized {
+    /// Serialize the state of this accumulator.
+    fn to_checkpoint(&self) -> Vec<u8>;
+
+    /// Restore an accumulator from a checkpoint.
+    fn from_checkpoint(bytes: &[u8]) -> Result<Self, CheckpointError>;
+
+    /// Write a checkpoint into a caller provided buffer.
+    ///
+    /// On success, the number of bytes written is returned. If `buf` is too
+    /// small, nothing is written and the required size is reported in the
+    /// error.
+    fn write_checkpoint(
+        &self,
+        buf: &mut [u8],
+    ) -> Result<usize, CheckpointError> {
+        let bytes = self.to_checkpoint();
+        if buf.len() < bytes.len() {
+            return Err(CheckpointError::BufferTooSmall(bytes.len()));
+        }
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Ok(bytes.len())
+    }
+}
+
+/// An error that occurred while writing or restoring a checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointError {
+    /// The buffer given to `write_checkpoint` is too small. The number of
+    /// bytes required is included.
+    BufferTooSmall(usize),
+    /// The bytes do not start with the checkpoint magic number.
+    BadMagic,
+    /// The checkpoint was written by an unsupported version of the format.
+    UnsupportedVersion(u8),
+    /// The checkpoint holds a different kind of accumulator.
+    WrongKind,
+    /// The checksum does not match the contents of the checkpoint.
+    ChecksumMismatch,
+    /// The checkpoint is truncated or its contents are invalid.
+    Malformed,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheckpointError::BufferTooSmall(n) => {
+                write!(f, "checkpoint buffer too small, need {} bytes", n)
+            }
+            CheckpointError::BadMagic => write!(f, "not a checkpoint"),
+            CheckpointError::UnsupportedVersion(v) => {
+                write!(f, "unsupported checkpoint version {}", v)
+            }
+            CheckpointError::WrongKind => {
+                write!(f, "checkpoint holds a different kind of accumulator")
+            }
+            CheckpointError::ChecksumMismatch => {
+                write!(f, "checkpoint checksum mismatch")
+            }
+            CheckpointError::Malformed => write!(f, "malformed checkpoint"),
+        }
+    }
+}
+
+impl error::Error for CheckpointError {
+    fn description(&self) -> &str {
+        "checkpoint error"
+    }
+}
+
+/// The kinds of accumulator that can be checkpointed.
+#[derive(Clone, Copy)]
+pub enum Kind {
+    OnlineStats = 1,
+    MinMax = 2,
+    Unsorted = 3,
+    Frequencies = 4,
+}
+
+/// Frame a payload with the checkpoint header and checksum.
+pub fn seal(kind: Kind, payload: Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(header_len(VERSION) + payload.len()
+                                     + CHECKSUM_LEN);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(kind as u8);
+    (payload.len() as u64).write_le(&mut out);
+    out.extend_from_slice(&payload);
+    let checksum = hash_with_seed(&out[..], 0);
+    checksum.write_le(&mut out);
+    out
+}
+
+/// Verify the framing of a checkpoint and return a reader for its payload.
+pub fn unseal<'a>(
+    kind: Kind,
+    bytes: &'a [u8],
+) -> Result<Reader<'a>, CheckpointError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC[..] {
+        return Err(CheckpointError::BadMagic);
+    }
+    if bytes.len() == MAGIC.len() {
+        return Err(CheckpointError::Malformed);
+    }
+    let version = bytes[4];
+    if version < MIN_VERSION || version > VERSION {
+        return Err(CheckpointError::UnsupportedVersion(version));
+    }
+    let header = header_len(version);
+    if bytes.len() < header + CHECKSUM_LEN {
+        return Err(CheckpointError::Malformed);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if hash_with_seed(body, 0) != <u64 as Record>::from_le(checksum) {
+        return Err(CheckpointError::ChecksumMismatch);
+    }
+    if body[5] != kind as u8 {
+        return Err(CheckpointError::WrongKind);
+    }
+    let len = if version < 4 {
+        <u32 as Record>::from_le(&body[6..header]) as u64
+    } else {
+        <u64 as Record>::from_le(&body[6..header])
+    };
+    if (body.len() - header) as u64 != len {
+        return Err(CheckpointError::Malformed);
+    }
+    Ok(Reader { version, bytes: &body[header..] })
+}
+
+/// A cursor over the payload of a checkpoint.
+pub struct Reader<'a> {
+    version: u8,
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// The version of the format the checkpoint was written with.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Read the next fixed-width value.
+    pub fn read<T: Record>(&mut self) -> Result<T, CheckpointError> {
+        if self.bytes.len() < T::SIZE {
+            return Err(CheckpointError::Malformed);
+        }
+        let (v, rest) = self.bytes.split_at(T::SIZE);
+        self.bytes = rest;
+        Ok(T::from_le(v))
+    }
+
+    /// Read an optional fixed-width value.
+    pub fn read_option<T: Record>(
+        &mut self,
+    ) -> Result<Option<T>, CheckpointError> {
+        match self.read::<u8>()? {
+            0 => Ok(None),
+            1 => self.read().map(Some),
+            _ => Err(CheckpointError::Malformed),
+        }
+    }
+
+    /// Check that the entire payload has been read.
+    pub fn finish(self) -> Result<(), CheckpointError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(CheckpointError::Malformed)
+        }
+    }
+}
+
+/// Write an optional fixed-width value.
+pub fn write_option<T: Record>(v: Option<&T>, out: &mut Vec<u8>) {
+    match v {
+        None => out.push(0),
+        Some(v) => {
+            out.push(1);
+            v.write_le(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Checkpoint, CheckpointError};
+    use {Commute, Frequencies, MinMax, OnlineStats, Unsorted};
+
+    #[test]
+    fn online_stats() {
+        let stats = OnlineStats::from_slice(&[1.0, 2.0, 4.0]);
+        let restored =
+            OnlineStats::from_checkpoint(&stats.to_checkpoint()).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.mean(), stats.mean());
+        assert_eq!(restored.variance(), stats.variance());
+        assert_eq!(restored.sigma_counts(), None);
+
+        let mut tracked = OnlineStats::with_sigma_counts();
+        tracked.extend(vec![1, 2, 4]);
+        let restored =
+            OnlineStats::from_checkpoint(&tracked.to_checkpoint()).unwrap();
+        assert_eq!(restored.sigma_counts(), tracked.sigma_counts());
+    }
+
+    #[test]
+    fn minmax() {
+        let mx: MinMax<i32> = vec![3, -1, 7].into_iter().collect();
+        let restored = MinMax::<i32>::from_checkpoint(&mx.to_checkpoint())
+            .unwrap();
+        assert_eq!(restored.min(), Some(&-1));
+        assert_eq!(restored.max(), Some(&7));
+        assert_eq!(restored.len(), 3);
+
+        let empty = MinMax::<i32>::new();
+        let restored = MinMax::<i32>::from_checkpoint(&empty.to_checkpoint())
+            .unwrap();
+        assert_eq!(restored.min(), None);
+    }
+
+    #[test]
+    fn unsorted() {
+        let data: Unsorted<u16> = vec![5, 1, 3].into_iter().collect();
+        let mut restored =
+            Unsorted::<u16>::from_checkpoint(&data.to_checkpoint()).unwrap();
+        assert_eq!(restored.median(), Some(3.0));
+
+        let mut sorted: Unsorted<u16> = vec![3, 1, 5].into_iter().collect();
+        sorted.median();
+        assert_eq!(sorted.to_checkpoint(), data.to_checkpoint());
+    }
+
+    #[test]
+    fn unsorted_nan_deterministic() {
+        let nan = ::std::f64::NAN;
+        let mut xs = vec![nan, 2.0, -0.0, -nan, 1.0, 0.0];
+        xs.extend((0..20).map(|i| i as f64 / 3.0));
+        let data: Unsorted<f64> = xs.iter().cloned().collect();
+        let reversed: Unsorted<f64> = xs.iter().rev().cloned().collect();
+        assert_eq!(data.to_checkpoint(), reversed.to_checkpoint());
+
+        let mut restored =
+            Unsorted::<f64>::from_checkpoint(&data.to_checkpoint()).unwrap();
+        assert_eq!(restored.len(), 26);
+        assert_eq!(restored.to_checkpoint(), data.to_checkpoint());
+        assert_eq!(restored.quantile_function(0.0), Some(0.0));
+    }
+
+    #[test]
+    fn frequencies_deterministic_and_mergeable() {
+        let f1: Frequencies<u64> = (0..100).map(|x| x % 7).collect();
+        let f2: Frequencies<u64> = (0..100).rev().map(|x| x % 7).collect();
+        assert_eq!(f1.to_checkpoint(), f2.to_checkpoint());
+
+        let mut restored =
+            Frequencies::<u64>::from_checkpoint(&f1.to_checkpoint()).unwrap();
+        restored.merge(f2);
+        assert_eq!(restored.count(&0), 30);
+    }
+
+    #[test]
+    fn integrity() {
+        let mut bytes = OnlineStats::from_slice(&[1, 2]).to_checkpoint();
+        assert_eq!(MinMax::<f64>::from_checkpoint(&bytes).unwrap_err(),
+                   CheckpointError::WrongKind);
+        let i = bytes.len() / 2;
+        bytes[i] ^= 1;
+        assert_eq!(OnlineStats::from_checkpoint(&bytes).unwrap_err(),
+                   CheckpointError::ChecksumMismatch);
+        assert_eq!(OnlineStats::from_checkpoint(b"nope").unwrap_err(),
+                   CheckpointError::BadMagic);
+        bytes[4] = 99;
+        assert_eq!(OnlineStats::from_checkpoint(&bytes).unwrap_err(),
+                   CheckpointError::UnsupportedVersion(99));
+    }
+
+    #[test]
+    fn version_1() {
+        // `OnlineStats` of `[1, 2, 4]`, from before the sigma counts and
+        // the higher moments were recorded.
+        let bytes = [
+            82, 83, 67, 75, 1, 1, 24, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0, 171, 170, 170, 170, 170, 170, 2, 64,
+            56, 142, 227, 56, 142, 227, 248, 63,
+            51, 117, 2, 209, 253, 52, 216, 151,
+        ];
+        let stats = OnlineStats::from_slice(&[1.0, 2.0, 4.0]);
+        let restored = OnlineStats::from_checkpoint(&bytes).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.mean(), stats.mean());
+        assert_eq!(restored.variance(), stats.variance());
+        assert_eq!(restored.sigma_counts(), None);
+    }
+
+    #[test]
+    fn version_2() {
+        // `OnlineStats` of `[1, 2, 4]` with sigma counts, from before the
+        // higher moments were recorded.
+        let bytes = [
+            82, 83, 67, 75, 2, 1, 49, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0, 171, 170, 170, 170, 170, 170, 2, 64,
+            56, 142, 227, 56, 142, 227, 248, 63,
+            1, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0,
+            105, 25, 243, 133, 119, 107, 103, 130,
+        ];
+        let restored = OnlineStats::from_checkpoint(&bytes).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.sigma_counts(), Some([2, 3, 3]));
+        assert!(restored.skewness().is_nan());
+
+        let stats = OnlineStats::from_slice(&[1.0, 2.0, 4.0]);
+        let restored =
+            OnlineStats::from_checkpoint(&stats.to_checkpoint()).unwrap();
+        assert_eq!(restored.skewness(), stats.skewness());
+        assert_eq!(restored.kurtosis(), stats.kurtosis());
+    }
+
+    #[test]
+    fn version_3() {
+        // A `MinMax<i32>` of `[3, -1, 7]`, with the 32-bit payload length
+        // of versions before 4.
+        let bytes = [
+            82, 83, 67, 75, 3, 2, 18, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0, 1, 255, 255, 255, 255, 1, 7, 0, 0, 0,
+            81, 236, 139, 124, 242, 85, 189, 142,
+        ];
+        let restored = MinMax::<i32>::from_checkpoint(&bytes).unwrap();
+        assert_eq!((restored.min(), restored.max()), (Some(&-1), Some(&7)));
+        assert_eq!(restored.len(), 3);
+    }
+
+    #[test]
+    fn caller_buffer() {
+        let stats = OnlineStats::from_slice(&[1, 2]);
+        let mut small = [0; 4];
+        let needed = stats.to_checkpoint().len();
+        assert_eq!(stats.write_checkpoint(&mut small),
+                   Err(CheckpointError::BufferTooSmall(needed)));
+        let mut buf = [0; 128];
+        let n = stats.write_checkpoint(&mut buf).unwrap();
+        assert_eq!(OnlineStats::from_checkpoint(&buf[..n]).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::f64::consts::PI;
+use std::fmt;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+use {Commute, CommuteRef};
+
+/// Online state for computing statistics of angles.
+///
+/// Angles wrap around, so the usual mean and variance are meaningless for
+/// them: the mean of 350 and 10 degrees should be 0, not 180. Instead, each
+/// sample is treated as a unit vector, and the statistics are derived from
+/// the sum of those vectors.
+///
+/// By default, samples are angles in radians. Other periodic data, like the
+/// hour of the day or the day of the week, can be used directly by setting
+/// the period with `with_period`. All statistics that are angles are
+/// returned in the same unit as the samples.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct CircularStats {
+    period: f64,
+    size: u64,
+    sum_cos: f64,
+    sum_sin: f64,
+}
+
+impl CircularStats {
+    /// Create initial state for angles in radians.
+    pub fn new() -> CircularStats {
+        Default::default()
+    }
+
+    /// Create initial state for data that repeats every `period` units,
+    /// e.g., `360.0` for degrees or `24.0` for hours of the day.
+    pub fn with_period(period: f64) -> CircularStats {
+        assert!(period > 0.0, "period must be positive");
+        CircularStats { period, size: 0, sum_cos: 0.0, sum_sin: 0.0 }
+    }
+
+    /// Add a new sample.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        let theta = sample.to_f64().unwrap() * 2.0 * PI / self.period;
+        self.size += 1;
+        self.sum_cos += theta.cos();
+        self.sum_sin += theta.sin();
+    }
+
+    /// Returns the circular mean, in `[0, period)`.
+    ///
+    /// `None` is returned if there is no data or if the samples cancel out
+    /// exactly, in which case there is no mean direction.
+    pub fn mean(&self) -> Option<f64> {
+        if self.size == 0 || (self.sum_cos == 0.0 && self.sum_sin == 0.0) {
+            return None;
+        }
+        let theta = self.sum_sin.atan2(self.sum_cos);
+        let theta = if theta < 0.0 { theta + 2.0 * PI } else { theta };
+        let mean = theta * self.period / (2.0 * PI);
+        // A tiny negative angle rounds up to a full turn when it is wrapped.
+        Some(if mean < self.period { mean } else { 0.0 })
+    }
+
+    /// Returns the mean resultant length, in `[0, 1]`.
+    ///
+    /// This is `1` when all samples point in the same direction and close
+    /// to `0` when they are spread uniformly around the circle.
+    pub fn resultant_length(&self) -> f64 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        let n = self.size as f64;
+        let r = (self.sum_cos * self.sum_cos + self.sum_sin * self.sum_sin)
+                .sqrt() / n;
+        r.min(1.0)
+    }
+
+    /// Returns the circular variance, `1 - R`, in `[0, 1]`.
+    pub fn variance(&self) -> f64 {
+        1.0 - self.resultant_length()
+    }
+
+    /// Returns the circular standard deviation, `sqrt(-2 ln R)`.
+    ///
+    /// Unlike the circular variance, this is an angle, and for
+    /// concentrated data it is close to the ordinary standard deviation.
+    pub fn stddev(&self) -> f64 {
+        (-2.0 * self.resultant_length().ln()).sqrt()
+            * self.period / (2.0 * PI)
+    }
+
+    /// Returns the number of data points.
+    pub fn len(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl Commute for CircularStats {
+    fn merge(&mut self, v: CircularStats) {
+        assert_eq!(self.period, v.period);
+        self.size += v.size;
+        self.sum_cos += v.sum_cos;
+        self.sum_sin += v.sum_sin;
+    }
+}
+
+impl CommuteRef for CircularStats {
+    fn merge_ref(&mut self, v: &CircularStats) {
+        self.merge(*v);
+    }
+}
+
+impl Default for CircularStats {
+    fn default() -> CircularStats {
+        CircularStats::with_period(2.0 * PI)
+    }
+}
+
+impl fmt::Debug for CircularStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mean() {
+            Some(mean) => write!(f, "{:.10} +/- {:.10}", mean, self.stddev()),
+            None => write!(f, "N/A"),
+        }
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for CircularStats {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> CircularStats {
+        let mut v = CircularStats::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for CircularStats {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use super::CircularStats;
+    use Commute;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn mean_in_period(stats: &CircularStats, period: f64) -> f64 {
+        let mean = stats.mean().unwrap();
+        assert!(mean >= 0.0 && mean < period, "mean {} out of range", mean);
+        mean
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut degrees = CircularStats::with_period(360.0);
+        degrees.extend(vec![350, 10]);
+        let mean = mean_in_period(&degrees, 360.0);
+        assert!(close(mean, 0.0) || close(mean, 360.0));
+        assert!(close(degrees.resultant_length(), 10f64.to_radians().cos()));
+
+        let mut hours = CircularStats::with_period(24.0);
+        hours.extend(vec![22, 23, 1, 2]);
+        let mean = mean_in_period(&hours, 24.0);
+        assert!(close(mean, 0.0) || close(mean, 24.0));
+
+        let mut tiny = CircularStats::with_period(360.0);
+        tiny.extend(vec![-1e-14, 0.0]);
+        assert!(close(mean_in_period(&tiny, 360.0), 0.0));
+        let below: CircularStats = vec![-1e-300].into_iter().collect();
+        assert_eq!(below.mean(), Some(0.0));
+    }
+
+    #[test]
+    fn spread() {
+        let same: CircularStats = vec![1.0, 1.0, 1.0].into_iter().collect();
+        assert!(close(mean_in_period(&same, 2.0 * PI), 1.0));
+        assert!(close(same.variance(), 0.0));
+        assert!(close(same.stddev(), 0.0));
+
+        let mut opposite = CircularStats::with_period(360.0);
+        opposite.extend(vec![0, 90, 180, 270]);
+        assert!(opposite.resultant_length() < 1e-9);
+        assert!(close(opposite.variance(), 1.0));
+        assert_eq!(CircularStats::new().mean(), None);
+    }
+
+    #[test]
+    fn merge() {
+        let mut c1 = CircularStats::with_period(360.0);
+        c1.extend(vec![10, 20]);
+        let mut c2 = CircularStats::with_period(360.0);
+        c2.extend(vec![30]);
+        c1.merge(c2);
+        assert_eq!(c1.len(), 3);
+        assert!(close(mean_in_period(&c1, 360.0), 20.0));
+    }
+}
@@ -0,0 +1,99 @@
+use std::prelude::v1::*;
+
+use {Commute, merge_all};
+
+/// A percentile of every cohort of a grouped summary, compared with the
+/// percentile of all of the cohorts together.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct CohortPercentiles<K> {
+    /// The percentile of the merged cohorts.
+    pub overall: f64,
+    /// The percentile of every cohort that has one, in the order in which
+    /// the cohorts were given.
+    pub cohorts: Vec<Cohort<K>>,
+}
+
+/// The percentile of one cohort in `CohortPercentiles`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Cohort<K> {
+    /// The key of the cohort.
+    pub key: K,
+    /// The percentile of the cohort.
+    pub value: f64,
+    /// The percentile of the cohort less the overall percentile.
+    pub delta: f64,
+}
+
+/// Compare the `q`th quantile of every cohort of a grouped summary, e.g.,
+/// the P99 latency per region, with the quantile of all of them.
+///
+/// `groups` are the cohorts and their summaries, such as the entries of a
+/// `BTreeMap<K, TDigest>`, and `quantile` computes a quantile of a summary,
+/// e.g., `TDigest::quantile`. The overall quantile is computed from the
+/// merged summaries, not from the quantiles of the cohorts.
+///
+/// Cohorts without a quantile, such as empty ones, are left out. `None` is
+/// returned if the merged summary has no quantile either.
+pub fn compare_percentiles<'a, K, S, I, F>(
+    groups: I,
+    q: f64,
+    mut quantile: F,
+) -> Option<CohortPercentiles<K>>
+        where K: Clone + 'a,
+              S: Commute + Clone + 'a,
+              I: IntoIterator<Item=(&'a K, &'a S)>,
+              F: FnMut(&mut S, f64) -> Option<f64> {
+    let groups: Vec<(&K, &S)> = groups.into_iter().collect();
+    let mut merged = merge_all(groups.iter().map(|&(_, s)| s.clone()))?;
+    let overall = quantile(&mut merged, q)?;
+    let cohorts = groups
+        .into_iter()
+        .filter_map(|(key, summary)| {
+            quantile(&mut summary.clone(), q).map(|value| Cohort {
+                key: key.clone(),
+                value,
+                delta: value - overall,
+            })
+        })
+        .collect();
+    Some(CohortPercentiles { overall, cohorts })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use super::{Cohort, compare_percentiles};
+    use {TDigest, Unsorted};
+
+    #[test]
+    fn per_cohort_median() {
+        let mut groups: BTreeMap<&str, Unsorted<u32>> = BTreeMap::new();
+        groups.insert("a", (1..101).collect());
+        groups.insert("b", (101..201).collect());
+        groups.insert("empty", Unsorted::new());
+        let table =
+            compare_percentiles(&groups, 0.5, Unsorted::quantile).unwrap();
+        assert_eq!(table.overall, 100.5);
+        assert_eq!(table.cohorts, vec![
+            Cohort { key: "a", value: 50.5, delta: -50.0 },
+            Cohort { key: "b", value: 150.5, delta: 50.0 },
+        ]);
+    }
+
+    #[test]
+    fn sketches() {
+        let mut groups: BTreeMap<u8, TDigest> = BTreeMap::new();
+        groups.insert(1, (0..1000).collect());
+        groups.insert(2, (0..1000).map(|x| x * 10).collect());
+        let table =
+            compare_percentiles(&groups, 1.0, TDigest::quantile).unwrap();
+        assert_eq!(table.overall, 9990.0);
+        assert_eq!(table.cohorts[0].delta, 999.0 - 9990.0);
+        assert_eq!(table.cohorts[1].delta, 0.0);
+
+        let empty: BTreeMap<u8, TDigest> = BTreeMap::new();
+        assert!(compare_percentiles(&empty, 0.5, TDigest::quantile).is_none());
+    }
+}
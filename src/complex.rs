@@ -0,0 +1,154 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::fmt;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+use {Commute, CommuteRef};
+use online::OnlineStats;
+
+/// Online state for computing the mean and variance of complex numbers.
+///
+/// Samples are given as `(re, im)` pairs, so any complex number type can be
+/// used without this crate depending on it. This is useful for summarizing
+/// the output of an FFT, for example.
+///
+/// Statistics are available both component-wise, via `re` and `im`, and for
+/// the magnitudes of the samples, via `magnitude`. The complex variance is
+/// the mean squared distance of the samples from their mean.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ComplexStats {
+    re: OnlineStats,
+    im: OnlineStats,
+    magnitude: OnlineStats,
+}
+
+impl ComplexStats {
+    /// Create initial state.
+    pub fn new() -> ComplexStats {
+        Default::default()
+    }
+
+    /// Add a new sample with real part `re` and imaginary part `im`.
+    pub fn add<T: ToPrimitive>(&mut self, re: T, im: T) {
+        let (re, im) = (re.to_f64().unwrap(), im.to_f64().unwrap());
+        self.re.add(re);
+        self.im.add(im);
+        self.magnitude.add(re.hypot(im));
+    }
+
+    /// Returns the mean as an `(re, im)` pair.
+    pub fn mean(&self) -> (f64, f64) {
+        (self.re.mean(), self.im.mean())
+    }
+
+    /// Returns the complex variance, `E[|z - mean|^2]`.
+    ///
+    /// This is the sum of the variances of the real and imaginary parts.
+    pub fn variance(&self) -> f64 {
+        self.re.variance() + self.im.variance()
+    }
+
+    /// Returns the complex standard deviation.
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the statistics of the real parts.
+    pub fn re(&self) -> &OnlineStats {
+        &self.re
+    }
+
+    /// Returns the statistics of the imaginary parts.
+    pub fn im(&self) -> &OnlineStats {
+        &self.im
+    }
+
+    /// Returns the statistics of the magnitudes, `|z|`.
+    pub fn magnitude(&self) -> &OnlineStats {
+        &self.magnitude
+    }
+
+    /// Returns the number of data points.
+    pub fn len(&self) -> usize {
+        self.re.len()
+    }
+}
+
+impl Commute for ComplexStats {
+    fn merge(&mut self, v: ComplexStats) {
+        self.re.merge(v.re);
+        self.im.merge(v.im);
+        self.magnitude.merge(v.magnitude);
+    }
+}
+
+impl CommuteRef for ComplexStats {
+    fn merge_ref(&mut self, v: &ComplexStats) {
+        self.merge(*v);
+    }
+}
+
+impl Default for ComplexStats {
+    fn default() -> ComplexStats {
+        ComplexStats {
+            re: OnlineStats::new(),
+            im: OnlineStats::new(),
+            magnitude: OnlineStats::new(),
+        }
+    }
+}
+
+impl fmt::Debug for ComplexStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (re, im) = self.mean();
+        write!(f, "{:.10}{:+.10}i +/- {:.10}", re, im, self.stddev())
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<(T, T)> for ComplexStats {
+    fn from_iter<I: IntoIterator<Item=(T, T)>>(it: I) -> ComplexStats {
+        let mut v = ComplexStats::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<(T, T)> for ComplexStats {
+    fn extend<I: IntoIterator<Item=(T, T)>>(&mut self, it: I) {
+        for (re, im) in it {
+            self.add(re, im)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ComplexStats;
+    use Commute;
+
+    #[test]
+    fn moments() {
+        let stats: ComplexStats =
+            vec![(1, 0), (0, 1), (-1, 0), (0, -1)].into_iter().collect();
+        assert_eq!(stats.mean(), (0.0, 0.0));
+        assert_eq!(stats.variance(), 1.0);
+        assert_eq!(stats.magnitude().mean(), 1.0);
+        assert_eq!(stats.magnitude().variance(), 0.0);
+        assert_eq!(stats.re().variance(), 0.5);
+    }
+
+    #[test]
+    fn merge() {
+        let mut s1: ComplexStats = vec![(3.0, 4.0)].into_iter().collect();
+        let s2: ComplexStats = vec![(1.0, 2.0)].into_iter().collect();
+        s1.merge(s2);
+        assert_eq!(s1.len(), 2);
+        assert_eq!(s1.mean(), (2.0, 3.0));
+        assert_eq!(s1.variance(), 2.0);
+        assert_eq!(s1.magnitude().mean(), (5.0 + 5f64.sqrt()) / 2.0);
+    }
+}
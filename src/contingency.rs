@@ -0,0 +1,247 @@
+use std::prelude::v1::*;
+
+use std::collections::HashMap;
+use std::default::Default;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::{FromIterator, IntoIterator};
+
+use {Commute, CommuteRef};
+use distributions::chi_squared_sf;
+use frequency::{ChiSquaredResult, Frequencies};
+
+/// A commutative data structure for counting pairs of categorical values,
+/// i.e., a contingency table.
+///
+/// Every sample is a pair of a row value and a column value. Besides the
+/// count of every pair, the table reports how strongly the two are
+/// associated: the chi-squared test of independence, the phi coefficient,
+/// Cramér's V and Theil's U.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "A: Eq + Hash + ::serde::Serialize, \
+                 B: Eq + Hash + ::serde::Serialize",
+    deserialize = "A: Eq + Hash + ::serde::Deserialize<'de>, \
+                   B: Eq + Hash + ::serde::Deserialize<'de>"
+)))]
+pub struct Contingency<A, B> {
+    cells: HashMap<A, Frequencies<B>>,
+    rows: Frequencies<A>,
+    columns: Frequencies<B>,
+}
+
+impl<A: Eq + Hash + Clone, B: Eq + Hash + Clone> Contingency<A, B> {
+    /// Create an empty table.
+    pub fn new() -> Contingency<A, B> {
+        Default::default()
+    }
+
+    /// Add a sample of row value `a` and column value `b`.
+    pub fn add(&mut self, a: A, b: B) {
+        self.rows.add(a.clone());
+        self.columns.add(b.clone());
+        self.cells.entry(a).or_default().add(b);
+    }
+
+    /// Returns the number of samples of row value `a` and column value `b`.
+    pub fn count(&self, a: &A, b: &B) -> u64 {
+        self.cells.get(a).map_or(0, |row| row.count(b))
+    }
+
+    /// Returns the counts of the row values.
+    pub fn rows(&self) -> &Frequencies<A> {
+        &self.rows
+    }
+
+    /// Returns the counts of the column values.
+    pub fn columns(&self) -> &Frequencies<B> {
+        &self.columns
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.rows.total() as usize
+    }
+
+    /// Pearson's chi-squared test of independence of the row and column
+    /// values.
+    ///
+    /// `None` is returned if there are fewer than two row values or fewer
+    /// than two column values.
+    pub fn chi_squared(&self) -> Option<ChiSquaredResult> {
+        let (r, c) = (self.rows.len(), self.columns.len());
+        if r < 2 || c < 2 {
+            return None;
+        }
+        let n = self.rows.total() as f64;
+        let mut statistic = 0.0;
+        for (a, row) in &self.cells {
+            let row_total = self.rows.count(a) as f64;
+            for (b, &col_total) in &self.columns.data {
+                let e = row_total * col_total as f64 / n;
+                let d = row.count(b) as f64 - e;
+                statistic += d * d / e;
+            }
+        }
+        let df = ((r - 1) * (c - 1)) as u64;
+        Some(ChiSquaredResult {
+            statistic,
+            df,
+            p_value: chi_squared_sf(statistic, df as f64),
+        })
+    }
+
+    /// Returns the phi coefficient, `sqrt(chi^2 / n)`.
+    ///
+    /// For a table of two rows and two columns, this is the absolute value
+    /// of the correlation of the two binary variables. `None` is returned
+    /// when `chi_squared` returns `None`.
+    pub fn phi(&self) -> Option<f64> {
+        let n = self.rows.total() as f64;
+        self.chi_squared().map(|chi| (chi.statistic / n).sqrt())
+    }
+
+    /// Returns Cramér's V, the phi coefficient scaled to `[0, 1]` for
+    /// tables with more than two rows or columns.
+    ///
+    /// `None` is returned when `chi_squared` returns `None`.
+    pub fn cramers_v(&self) -> Option<f64> {
+        let k = ::std::cmp::min(self.rows.len(), self.columns.len()) as f64;
+        self.phi().map(|phi| phi / (k - 1.0).sqrt())
+    }
+
+    /// Returns Theil's U of the row values given the column values, in
+    /// `[0, 1]`.
+    ///
+    /// This is the share of the entropy of the row values that is removed
+    /// by knowing the column value: `0` if they are independent and `1` if
+    /// the column value determines the row value. Unlike Cramér's V, it is
+    /// not symmetric. `None` is returned if there is no data or if there is
+    /// only one row value.
+    pub fn theils_u(&self) -> Option<f64> {
+        let h_rows = self.rows.entropy_nats();
+        if h_rows.is_nan() || h_rows <= 0.0 {
+            return None;
+        }
+        let n = self.rows.total() as f64;
+        let h_joint: f64 = -self.cells
+            .values()
+            .flat_map(|row| row.data.values())
+            .map(|&count| {
+                let p = count as f64 / n;
+                p * p.ln()
+            })
+            .sum::<f64>();
+        let h_given = h_joint - self.columns.entropy_nats();
+        Some((h_rows - h_given) / h_rows)
+    }
+}
+
+impl<A, B> Commute for Contingency<A, B>
+        where A: Eq + Hash + Clone, B: Eq + Hash + Clone {
+    fn merge(&mut self, v: Contingency<A, B>) {
+        self.rows.merge(v.rows);
+        self.columns.merge(v.columns);
+        for (a, row) in v.cells {
+            self.cells.entry(a).or_default().merge(row);
+        }
+    }
+}
+
+impl<A, B> CommuteRef for Contingency<A, B>
+        where A: Eq + Hash + Clone, B: Eq + Hash + Clone {
+    fn merge_ref(&mut self, v: &Contingency<A, B>) {
+        self.rows.merge_ref(&v.rows);
+        self.columns.merge_ref(&v.columns);
+        for (a, row) in &v.cells {
+            self.cells
+                .entry(a.clone())
+                .or_default()
+                .merge_ref(row);
+        }
+    }
+}
+
+impl<A: Eq + Hash, B: Eq + Hash> Default for Contingency<A, B> {
+    fn default() -> Contingency<A, B> {
+        Contingency {
+            cells: HashMap::new(),
+            rows: Frequencies::default(),
+            columns: Frequencies::default(),
+        }
+    }
+}
+
+impl<A, B> fmt::Debug for Contingency<A, B>
+        where A: fmt::Debug + Eq + Hash, B: fmt::Debug + Eq + Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.cells)
+    }
+}
+
+impl<A, B> FromIterator<(A, B)> for Contingency<A, B>
+        where A: Eq + Hash + Clone, B: Eq + Hash + Clone {
+    fn from_iter<I: IntoIterator<Item=(A, B)>>(it: I) -> Contingency<A, B> {
+        let mut v = Contingency::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<A, B> Extend<(A, B)> for Contingency<A, B>
+        where A: Eq + Hash + Clone, B: Eq + Hash + Clone {
+    fn extend<I: IntoIterator<Item=(A, B)>>(&mut self, it: I) {
+        for (a, b) in it {
+            self.add(a, b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Contingency;
+    use Commute;
+
+    fn table(counts: &[(u8, char, usize)]) -> Contingency<u8, char> {
+        counts.iter()
+              .flat_map(|&(a, b, n)| vec![(a, b); n])
+              .collect()
+    }
+
+    #[test]
+    fn association() {
+        let t = table(&[(1, 'x', 10), (1, 'y', 20), (2, 'x', 30),
+                        (2, 'y', 40)]);
+        assert_eq!(t.len(), 100);
+        assert_eq!(t.count(&2, &'x'), 30);
+        assert_eq!(t.count(&3, &'x'), 0);
+        let chi = t.chi_squared().unwrap();
+        assert!((chi.statistic - 0.7936507936507936).abs() < 1e-12);
+        assert_eq!(chi.df, 1);
+        assert!((t.phi().unwrap() - 0.0890870806374748).abs() < 1e-12);
+        let v = t.cramers_v().unwrap();
+        assert!((v - 0.0890870806374748).abs() < 1e-12);
+        assert!((t.theils_u().unwrap() - 0.006583693329195444).abs() < 1e-12);
+    }
+
+    #[test]
+    fn perfect_association() {
+        let t = table(&[(1, 'x', 5), (2, 'y', 5), (3, 'z', 5)]);
+        assert_eq!(t.chi_squared().unwrap().df, 4);
+        assert!((t.cramers_v().unwrap() - 1.0).abs() < 1e-12);
+        assert!((t.theils_u().unwrap() - 1.0).abs() < 1e-12);
+        assert!(table(&[(1, 'x', 5), (1, 'y', 5)]).cramers_v().is_none());
+        assert!(Contingency::<u8, char>::new().theils_u().is_none());
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = table(&[(1, 'x', 10), (1, 'y', 20)]);
+        a.merge(table(&[(2, 'x', 30), (2, 'y', 40), (1, 'x', 1)]));
+        assert_eq!(a.count(&1, &'x'), 11);
+        assert_eq!(a.rows().count(&2), 70);
+        assert_eq!(a.columns().count(&'y'), 60);
+        assert_eq!(a.len(), 101);
+    }
+}
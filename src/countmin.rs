@@ -0,0 +1,235 @@
+use std::prelude::v1::*;
+
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use {Commute, CommuteRef};
+use hashing::{hash_with_seed, mix};
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// A commutative data structure for approximate per-key counts in a fixed
+/// amount of memory.
+///
+/// The sketch is a `depth x width` table of counters. Every sample adds one
+/// to a counter in each row, chosen by a different hash function, and the
+/// count of a key is the minimum of its counters. Collisions only add to
+/// counters, so counts never underestimate. With `width = ceil(e / eps)`
+/// and `depth = ceil(ln(1 / delta))`, a count overestimates by more than
+/// `eps * n` with probability at most `delta`.
+///
+/// The table is allocated once, when the sketch is created, so memory use
+/// does not depend on the number of distinct keys. Sketches with the same
+/// dimensions can be merged, e.g., across threads or enclaves.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct CountMinSketch<T> {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    len: u64,
+    _type: PhantomData<T>,
+}
+
+impl<T> fmt::Debug for CountMinSketch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CountMinSketch({}x{}, n={})",
+               self.depth, self.width, self.len)
+    }
+}
+
+impl<T: Hash> CountMinSketch<T> {
+    /// Create an empty sketch with `depth` rows of `width` counters.
+    ///
+    /// This panics if `width` or `depth` is `0`.
+    pub fn new(width: usize, depth: usize) -> CountMinSketch<T> {
+        assert!(width > 0 && depth > 0, "dimensions must be positive");
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            len: 0,
+            _type: PhantomData,
+        }
+    }
+
+    /// Create an empty sketch whose counts overestimate by more than
+    /// `epsilon` times the number of samples with probability at most
+    /// `delta`.
+    ///
+    /// This panics unless both `epsilon` and `delta` are in `(0, 1)`.
+    pub fn with_error(epsilon: f64, delta: f64) -> CountMinSketch<T> {
+        assert!(epsilon > 0.0 && epsilon < 1.0, "epsilon must be in (0, 1)");
+        assert!(delta > 0.0 && delta < 1.0, "delta must be in (0, 1)");
+        let width = (::std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil() as usize;
+        CountMinSketch::new(width, depth.max(1))
+    }
+
+    /// Add a sample to the sketch.
+    pub fn add(&mut self, v: T) {
+        self.add_count(&v, 1);
+    }
+
+    /// Add `count` samples of `v` to the sketch.
+    pub fn add_count(&mut self, v: &T, count: u64) {
+        self.len += count;
+        let hash = hash_with_seed(v, 0);
+        for row in 0..self.depth {
+            let i = self.index(hash, row);
+            self.counters[i] += count;
+        }
+    }
+
+    /// Returns the estimated number of samples of `v`.
+    ///
+    /// The estimate is never less than the true count.
+    pub fn count(&self, v: &T) -> u64 {
+        let hash = hash_with_seed(v, 0);
+        (0..self.depth)
+            .map(|row| self.counters[self.index(hash, row)])
+            .min()
+            .unwrap()
+    }
+
+    /// Returns the width of each row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the overestimate that a count exceeds with probability at
+    /// most `e^-depth`, which is `e / width` times the number of samples.
+    pub fn error_bound(&self) -> f64 {
+        ::std::f64::consts::E / self.width as f64 * self.len as f64
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn index(&self, hash: u64, row: usize) -> usize {
+        let h = mix(hash ^ mix(row as u64 + 1));
+        row * self.width + (h % self.width as u64) as usize
+    }
+}
+
+impl<T> Commute for CountMinSketch<T> {
+    fn merge(&mut self, v: CountMinSketch<T>) {
+        self.merge_ref(&v);
+    }
+}
+
+impl<T> CommuteRef for CountMinSketch<T> {
+    fn merge_ref(&mut self, v: &CountMinSketch<T>) {
+        assert_eq!((self.width, self.depth), (v.width, v.depth),
+                   "sketches must have the same dimensions");
+        for (c1, &c2) in self.counters.iter_mut().zip(&v.counters) {
+            *c1 += c2;
+        }
+        self.len += v.len;
+    }
+}
+
+/// Deserializing checks that the dimensions are positive and match the
+/// number of counters.
+#[cfg(feature = "serialize")]
+impl<'de, T> Deserialize<'de> for CountMinSketch<T> {
+    fn deserialize<D>(d: D) -> Result<CountMinSketch<T>, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            width: usize,
+            depth: usize,
+            counters: Vec<u64>,
+            len: u64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.width == 0 || parts.depth == 0 {
+            return Err(D::Error::custom("dimensions must be positive"));
+        }
+        if parts.width.checked_mul(parts.depth) != Some(parts.counters.len()) {
+            return Err(D::Error::custom(
+                "counters do not match the dimensions"));
+        }
+        Ok(CountMinSketch {
+            width: parts.width,
+            depth: parts.depth,
+            counters: parts.counters,
+            len: parts.len,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Hash> Extend<T> for CountMinSketch<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CountMinSketch;
+    use Commute;
+
+    #[test]
+    fn never_underestimates() {
+        let mut sketch = CountMinSketch::with_error(0.01, 0.01);
+        assert_eq!((sketch.width(), sketch.depth()), (272, 5));
+        for i in 0..5000u32 {
+            sketch.add(i % 1000);
+        }
+        sketch.add_count(&7, 100);
+        assert!(sketch.count(&7) >= 105);
+        let bound = sketch.error_bound();
+        let over = (0..1000u32).filter(|&i| {
+            let truth = if i == 7 { 105 } else { 5 };
+            let count = sketch.count(&i);
+            assert!(count >= truth);
+            (count - truth) as f64 > bound
+        }).count();
+        assert!(over <= 10);
+    }
+
+    #[test]
+    fn merge() {
+        let mut s1 = CountMinSketch::new(64, 4);
+        s1.extend(vec!["a", "b", "a"]);
+        let mut s2 = CountMinSketch::new(64, 4);
+        s2.extend(vec!["a", "c"]);
+        s1.merge(s2);
+        assert_eq!(s1.len(), 5);
+        assert!(s1.count(&"a") >= 3);
+        assert!(s1.count(&"c") >= 1);
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_dimensions() {
+        use serde_json;
+
+        let mut sketch = CountMinSketch::new(3, 2);
+        sketch.extend(vec!["a", "b", "a"]);
+        let json = serde_json::to_string(&sketch).unwrap();
+        let back: CountMinSketch<&str> = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.count(&"a"), back.len()), (sketch.count(&"a"), 3));
+
+        let bad = [
+            r#"{"width":0,"depth":2,"counters":[],"len":0}"#,
+            r#"{"width":3,"depth":2,"counters":[0,0,0,0,0],"len":0}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<CountMinSketch<u32>>(json).is_err());
+        }
+    }
+}
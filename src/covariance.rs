@@ -0,0 +1,503 @@
+use std::prelude::v1::*;
+
+use std::iter::IntoIterator;
+
+use {Commute, CommuteRef};
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// Online state for the mean and covariance matrix of vectors of samples.
+///
+/// This generalizes `OnlineBivariate` to any number of dimensions. The
+/// moments are updated with the pairwise formulas of Pébay, so they are
+/// computed in one pass and merged exactly. The covariances are population
+/// statistics, with `n` in the denominator.
+///
+/// Besides the covariances, the fourth-order co-moments of every pair of
+/// dimensions are kept, which estimate how noisy the covariances are. They
+/// are used by `ledoit_wolf` to shrink the covariance matrix, so memory use
+/// is a small multiple of the size of the matrix.
+///
+/// The Cholesky factor of the covariance matrix is remembered until the
+/// data is next modified, so scoring many vectors with `mahalanobis`
+/// between updates is cheap.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct CovarianceMatrix {
+    dim: usize,
+    len: u64,
+    mean: Vec<f64>,
+    /// The central co-moments of order `(p, q)` of dimensions `i` and `j`,
+    /// `sum((x_i - mean_i)^p * (x_j - mean_j)^q)`, at `i * dim + j`.
+    m11: Vec<f64>,
+    m21: Vec<f64>,
+    m22: Vec<f64>,
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    cholesky: Option<Option<Vec<f64>>>,
+}
+
+impl CovarianceMatrix {
+    /// Create initial state for vectors of `dim` samples.
+    ///
+    /// This panics if `dim` is `0`.
+    pub fn new(dim: usize) -> CovarianceMatrix {
+        assert!(dim > 0, "dimension must be positive");
+        CovarianceMatrix {
+            dim,
+            len: 0,
+            mean: vec![0.0; dim],
+            m11: vec![0.0; dim * dim],
+            m21: vec![0.0; dim * dim],
+            m22: vec![0.0; dim * dim],
+            cholesky: None,
+        }
+    }
+
+    /// Add a vector of samples.
+    ///
+    /// This panics if `sample` does not have `dim` elements.
+    pub fn add(&mut self, sample: &[f64]) {
+        assert_eq!(sample.len(), self.dim, "sample has the wrong dimension");
+        // A single sample has no central moments but its count.
+        self.combine(1, sample, |_, _, p, q| {
+            if p == 0 && q == 0 { 1.0 } else { 0.0 }
+        });
+    }
+
+    /// Returns the mean of each dimension.
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    /// Returns the covariance matrix, as rows.
+    ///
+    /// If there is no data, every covariance is `NaN`.
+    pub fn covariance(&self) -> Vec<Vec<f64>> {
+        let n = self.len as f64;
+        self.rows(|i, j| self.m11[i * self.dim + j] / n)
+    }
+
+    /// Returns the Pearson correlation matrix, as rows.
+    pub fn correlation(&self) -> Vec<Vec<f64>> {
+        let d = self.dim;
+        self.rows(|i, j| {
+            self.m11[i * d + j]
+                / (self.m11[i * d + i] * self.m11[j * d + j]).sqrt()
+        })
+    }
+
+    /// Returns the Ledoit–Wolf estimate of the covariance matrix, as rows.
+    ///
+    /// When there are few samples relative to the number of dimensions,
+    /// the sample covariance matrix is noisy and may not be invertible.
+    /// This shrinks it towards a multiple of the identity matrix with the
+    /// same trace, with the intensity from `shrinkage`, which minimizes the
+    /// expected squared error. The result is always well-conditioned.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn ledoit_wolf(&self) -> Option<Vec<Vec<f64>>> {
+        if self.len == 0 {
+            return None;
+        }
+        let (s, mu) = (self.covariance(), self.mean_variance());
+        let shrinkage = self.shrinkage().unwrap();
+        Some((0..self.dim).map(|i| {
+            (0..self.dim).map(|j| {
+                let target = if i == j { mu } else { 0.0 };
+                shrinkage * target + (1.0 - shrinkage) * s[i][j]
+            }).collect()
+        }).collect())
+    }
+
+    /// Returns the Ledoit–Wolf shrinkage intensity, in `[0, 1]`.
+    ///
+    /// This is the estimated variance of the sample covariances relative
+    /// to their squared distance from the target: `0` keeps the sample
+    /// covariance matrix, and `1` replaces it by the target.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn shrinkage(&self) -> Option<f64> {
+        if self.len == 0 {
+            return None;
+        }
+        let n = self.len as f64;
+        let mu = self.mean_variance();
+        let (mut distance, mut squares, mut fourth) = (0.0, 0.0, 0.0);
+        for i in 0..self.dim {
+            for j in 0..self.dim {
+                let k = i * self.dim + j;
+                let s = self.m11[k] / n;
+                let target = if i == j { mu } else { 0.0 };
+                distance += (s - target) * (s - target);
+                squares += s * s;
+                fourth += self.m22[k];
+            }
+        }
+        if distance == 0.0 {
+            // The sample covariance matrix is the target already.
+            return Some(0.0);
+        }
+        let noise = ((fourth / n - squares) / n).max(0.0);
+        Some(noise.min(distance) / distance)
+    }
+
+    /// Returns the Mahalanobis distance of `x` from the mean.
+    ///
+    /// This is `sqrt((x - mean)' * S^-1 * (x - mean))` for the covariance
+    /// matrix `S`, the number of standard deviations from the mean along
+    /// the direction of `x`, taking the correlations into account. Scores
+    /// of multivariate normal data follow a chi distribution with `dim`
+    /// degrees of freedom, so large scores flag outliers.
+    ///
+    /// `None` is returned if the covariance matrix is not positive
+    /// definite, e.g., if there are no more samples than dimensions. This
+    /// panics if `x` does not have `dim` elements.
+    pub fn mahalanobis(&mut self, x: &[f64]) -> Option<f64> {
+        assert_eq!(x.len(), self.dim, "vector has the wrong dimension");
+        if self.cholesky.is_none() {
+            self.cholesky = Some(cholesky(&self.covariance(), self.dim));
+        }
+        let (d, l) = match self.cholesky {
+            Some(Some(ref l)) => (self.dim, l),
+            _ => return None,
+        };
+        // Solve `L * z = x - mean` by forward substitution, so that the
+        // squared distance is `z' * z`.
+        let mut z = vec![0.0; d];
+        for i in 0..d {
+            let dot: f64 = (0..i).map(|k| l[i * d + k] * z[k]).sum();
+            z[i] = (x[i] - self.mean[i] - dot) / l[i * d + i];
+        }
+        Some(z.iter().map(|v| v * v).sum::<f64>().sqrt())
+    }
+
+    /// Returns the number of dimensions.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Returns the number of vectors of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns the mean of the variances, the scale of the shrinkage
+    /// target.
+    fn mean_variance(&self) -> f64 {
+        let trace: f64 = (0..self.dim)
+            .map(|i| self.m11[i * self.dim + i])
+            .sum();
+        trace / (self.len as f64 * self.dim as f64)
+    }
+
+    fn rows<F: Fn(usize, usize) -> f64>(&self, f: F) -> Vec<Vec<f64>> {
+        (0..self.dim).map(|i| (0..self.dim).map(|j| f(i, j)).collect())
+                     .collect()
+    }
+
+    /// Returns the co-moment of order `(p, q)` of dimensions `i` and `j`.
+    fn moment(&self, i: usize, j: usize, p: usize, q: usize) -> f64 {
+        let d = self.dim;
+        match (p, q) {
+            (0, 0) => self.len as f64,
+            (2, 0) => self.m11[i * d + i],
+            (0, 2) => self.m11[j * d + j],
+            (1, 1) => self.m11[i * d + j],
+            (2, 1) => self.m21[i * d + j],
+            (1, 2) => self.m21[j * d + i],
+            (2, 2) => self.m22[i * d + j],
+            // First-order central moments vanish.
+            _ => 0.0,
+        }
+    }
+
+    /// Combines the moments with those of `len` other samples with the
+    /// given mean and co-moments.
+    ///
+    /// With `f = len / n` for each side and `delta` the difference of the
+    /// means, the co-moment of order `(p, q)` of the union is the sum over
+    /// `a <= p` and `b <= q` of `C(p, a) * C(q, b)` times
+    /// `(-f_B * dx)^a * (-f_B * dy)^b * M^A_(p - a, q - b)` plus the same
+    /// term for `B` with `f_A * dx` and `f_A * dy`.
+    fn combine<F>(&mut self, len: u64, mean: &[f64], other: F)
+            where F: Fn(usize, usize, usize, usize) -> f64 {
+        if len == 0 {
+            return;
+        }
+        let d = self.dim;
+        let n = (self.len + len) as f64;
+        let (fa, fb) = (self.len as f64 / n, len as f64 / n);
+        let delta: Vec<f64> =
+            mean.iter().zip(&self.mean).map(|(b, a)| b - a).collect();
+        let (mut m11, mut m21, mut m22) =
+            (vec![0.0; d * d], vec![0.0; d * d], vec![0.0; d * d]);
+        for i in 0..d {
+            for j in 0..d {
+                let (dx, dy) = (delta[i], delta[j]);
+                let union = |p: usize, q: usize| {
+                    let mut sum = 0.0;
+                    for a in 0..p + 1 {
+                        for b in 0..q + 1 {
+                            let (pa, qb) = (p - a, q - b);
+                            let shift = |f: f64| {
+                                (f * dx).powi(a as i32)
+                                * (f * dy).powi(b as i32)
+                            };
+                            let c = binomial(p, a) * binomial(q, b);
+                            sum += c * (shift(-fb) * self.moment(i, j, pa, qb)
+                                        + shift(fa) * other(i, j, pa, qb));
+                        }
+                    }
+                    sum
+                };
+                let k = i * d + j;
+                m11[k] = union(1, 1);
+                m21[k] = union(2, 1);
+                m22[k] = union(2, 2);
+            }
+        }
+        for (m, &delta) in self.mean.iter_mut().zip(&delta) {
+            *m += fb * delta;
+        }
+        self.m11 = m11;
+        self.m21 = m21;
+        self.m22 = m22;
+        self.len += len;
+        self.cholesky = None;
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    if n == 2 && k == 1 { 2.0 } else { 1.0 }
+}
+
+/// Returns the lower triangular Cholesky factor `L` of the rows `a` of a
+/// `d x d` matrix, with `a = L * L'`, at `i * d + j`.
+///
+/// `None` is returned if the matrix is not positive definite.
+fn cholesky(a: &[Vec<f64>], d: usize) -> Option<Vec<f64>> {
+    let mut l = vec![0.0; d * d];
+    for i in 0..d {
+        for j in 0..i + 1 {
+            let dot: f64 = (0..j).map(|k| l[i * d + k] * l[j * d + k]).sum();
+            let v = a[i][j] - dot;
+            if i == j {
+                if v.is_nan() || v <= 0.0 {
+                    return None;
+                }
+                l[i * d + i] = v.sqrt();
+            } else {
+                l[i * d + j] = v / l[j * d + j];
+            }
+        }
+    }
+    Some(l)
+}
+
+impl Commute for CovarianceMatrix {
+    fn merge(&mut self, v: CovarianceMatrix) {
+        self.merge_ref(&v);
+    }
+}
+
+impl CommuteRef for CovarianceMatrix {
+    fn merge_ref(&mut self, v: &CovarianceMatrix) {
+        assert_eq!(self.dim, v.dim, "dimensions must be equal");
+        self.combine(v.len, &v.mean, |i, j, p, q| v.moment(i, j, p, q));
+    }
+}
+
+/// Matrices are equal if their moments are, whether or not the Cholesky
+/// factor of either is cached.
+impl PartialEq for CovarianceMatrix {
+    fn eq(&self, other: &CovarianceMatrix) -> bool {
+        self.dim == other.dim
+            && self.len == other.len
+            && self.mean == other.mean
+            && self.m11 == other.m11
+            && self.m21 == other.m21
+            && self.m22 == other.m22
+    }
+}
+
+/// Deserializing checks that the dimension is positive and matches the
+/// lengths of the mean and the matrices of co-moments.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for CovarianceMatrix {
+    fn deserialize<D>(d: D) -> Result<CovarianceMatrix, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            dim: usize,
+            len: u64,
+            mean: Vec<f64>,
+            m11: Vec<f64>,
+            m21: Vec<f64>,
+            m22: Vec<f64>,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.dim == 0 {
+            return Err(D::Error::custom("dimension must be positive"));
+        }
+        let size = Some(parts.m11.len());
+        if parts.mean.len() != parts.dim
+                || parts.dim.checked_mul(parts.dim) != size
+                || parts.m21.len() != parts.m11.len()
+                || parts.m22.len() != parts.m11.len() {
+            return Err(D::Error::custom("lengths do not match the dimension"));
+        }
+        Ok(CovarianceMatrix {
+            dim: parts.dim,
+            len: parts.len,
+            mean: parts.mean,
+            m11: parts.m11,
+            m21: parts.m21,
+            m22: parts.m22,
+            cholesky: None,
+        })
+    }
+}
+
+impl<V: AsRef<[f64]>> Extend<V> for CovarianceMatrix {
+    fn extend<I: IntoIterator<Item=V>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CovarianceMatrix;
+    use Commute;
+
+    fn data() -> Vec<Vec<f64>> {
+        vec![vec![1.0, 2.0, 0.5], vec![2.0, 1.0, -1.0],
+             vec![4.0, 5.0, 2.0], vec![3.0, 3.0, 0.0],
+             vec![0.0, 1.5, 1.0]]
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn covariance() {
+        let data = data();
+        let mut cov = CovarianceMatrix::new(3);
+        cov.extend(&data);
+        assert_eq!(cov.len(), 5);
+        assert!(close(cov.mean()[0], 2.0) && close(cov.mean()[1], 2.5));
+        let n = data.len() as f64;
+        let s = cov.covariance();
+        for i in 0..3 {
+            for j in 0..3 {
+                let (mi, mj) = (cov.mean()[i], cov.mean()[j]);
+                let expected: f64 = data.iter()
+                    .map(|x| (x[i] - mi) * (x[j] - mj))
+                    .sum();
+                assert!(close(s[i][j], expected / n));
+            }
+        }
+        assert!(close(cov.correlation()[1][1], 1.0));
+    }
+
+    #[test]
+    fn ledoit_wolf() {
+        let data = data();
+        let mut cov = CovarianceMatrix::new(3);
+        cov.extend(&data);
+        let (n, s) = (data.len() as f64, cov.covariance());
+        let mu = (s[0][0] + s[1][1] + s[2][2]) / 3.0;
+        // The shrinkage intensity from its definition, with the variance
+        // of the covariances computed from the data directly.
+        let (mut distance, mut noise) = (0.0, 0.0);
+        for i in 0..3 {
+            for j in 0..3 {
+                let target = if i == j { mu } else { 0.0 };
+                distance += (s[i][j] - target).powi(2);
+                let (mi, mj) = (cov.mean()[i], cov.mean()[j]);
+                noise += data.iter()
+                    .map(|x| ((x[i] - mi) * (x[j] - mj) - s[i][j]).powi(2))
+                    .sum::<f64>() / (n * n);
+            }
+        }
+        let expected = noise.min(distance) / distance;
+        let shrinkage = cov.shrinkage().unwrap();
+        assert!(close(shrinkage, expected) && shrinkage > 0.0);
+        let lw = cov.ledoit_wolf().unwrap();
+        assert!(close(lw[0][1], (1.0 - shrinkage) * s[0][1]));
+        assert!(close(lw[2][2], shrinkage * mu + (1.0 - shrinkage) * s[2][2]));
+        assert_eq!(CovarianceMatrix::new(2).ledoit_wolf(), None);
+    }
+
+    #[test]
+    fn mahalanobis() {
+        let data = data();
+        let mut cov = CovarianceMatrix::new(3);
+        cov.extend(&data[..3]);
+        // Three samples span a plane, so the matrix is singular.
+        assert_eq!(cov.mahalanobis(&[0.0, 0.0, 0.0]), None);
+        cov.extend(&data[3..]);
+        let mean = cov.mean().to_vec();
+        assert_eq!(cov.mahalanobis(&mean), Some(0.0));
+
+        // Uncorrelated dimensions scale by their standard deviations.
+        let mut cov = CovarianceMatrix::new(2);
+        cov.extend(vec![[1.0, 0.0], [-1.0, 0.0], [0.0, 3.0], [0.0, -3.0]]);
+        let sd = (0.5f64.sqrt(), 4.5f64.sqrt());
+        let score = cov.mahalanobis(&[1.0, 3.0]).unwrap();
+        assert!(close(score, (1.0 / (sd.0 * sd.0) + 9.0 / (sd.1 * sd.1))
+                             .sqrt()));
+        // The cached factor does not affect equality.
+        let mut uncached = CovarianceMatrix::new(2);
+        uncached.extend(vec![[1.0, 0.0], [-1.0, 0.0], [0.0, 3.0],
+                             [0.0, -3.0]]);
+        assert_eq!(cov, uncached);
+        // The cached factor is dropped when the data changes.
+        cov.add(&[5.0, 0.0]);
+        assert!(cov.mahalanobis(&[1.0, 3.0]).unwrap() < score);
+    }
+
+    #[test]
+    fn merge() {
+        let data = data();
+        let mut all = CovarianceMatrix::new(3);
+        all.extend(&data);
+        let mut c1 = CovarianceMatrix::new(3);
+        c1.extend(&data[..2]);
+        let mut c2 = CovarianceMatrix::new(3);
+        c2.extend(&data[2..]);
+        c1.merge(c2);
+        assert_eq!(c1.len(), all.len());
+        for k in 0..9 {
+            assert!(close(c1.m11[k], all.m11[k]));
+            assert!(close(c1.m21[k], all.m21[k]));
+            assert!(close(c1.m22[k], all.m22[k]));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_dimension() {
+        use serde_json;
+
+        let mut cov = CovarianceMatrix::new(2);
+        cov.extend(&[[1.0, 2.0], [3.0, 5.0]]);
+        let json = serde_json::to_string(&cov).unwrap();
+        assert_eq!(serde_json::from_str::<CovarianceMatrix>(&json).unwrap(),
+                   cov);
+
+        let bad = [
+            r#"{"dim":0,"len":0,"mean":[],"m11":[],"m21":[],"m22":[]}"#,
+            r#"{"dim":2,"len":0,"mean":[0.0],"m11":[0.0,0.0,0.0,0.0],
+                "m21":[0.0,0.0,0.0,0.0],"m22":[0.0,0.0,0.0,0.0]}"#,
+            r#"{"dim":2,"len":0,"mean":[0.0,0.0],"m11":[0.0,0.0,0.0,0.0],
+                "m21":[0.0,0.0,0.0],"m22":[0.0,0.0,0.0,0.0]}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<CovarianceMatrix>(json).is_err());
+        }
+    }
+}
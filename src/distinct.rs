@@ -0,0 +1,264 @@
+use std::prelude::v1::*;
+use std::collections::HashSet;
+use std::default::Default;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::{FromIterator, IntoIterator};
+use std::mem;
+
+use {Commute, CommuteRef};
+use hyperloglog::HyperLogLog;
+
+/// A commutative data structure for exact distinct counts.
+///
+/// Unlike `Unsorted::cardinality`, which deduplicates all of the data every
+/// time it is called, the set of distinct values is maintained as samples
+/// are added and merged. Asking for the cardinality is therefore `O(1)`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize",
+    deserialize = "T: Eq + Hash + ::serde::Deserialize<'de>"
+)))]
+pub struct Distinct<T> {
+    set: HashSet<T>,
+}
+
+impl<T: Eq + Hash> Distinct<T> {
+    /// Create an empty set of distinct values.
+    pub fn new() -> Distinct<T> {
+        Default::default()
+    }
+
+    /// Add a sample to the set.
+    pub fn add(&mut self, v: T) {
+        self.set.insert(v);
+    }
+
+    /// Returns true if `v` has been added.
+    pub fn contains(&self, v: &T) -> bool {
+        self.set.contains(v)
+    }
+
+    /// Return the cardinality (number of unique elements) of the data.
+    pub fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+
+    /// Returns the cardinality of the data.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+}
+
+impl<T: Eq + Hash> Commute for Distinct<T> {
+    fn merge(&mut self, v: Distinct<T>) {
+        self.set.extend(v.set);
+    }
+}
+
+impl<T: Eq + Hash + Clone> CommuteRef for Distinct<T> {
+    fn merge_ref(&mut self, v: &Distinct<T>) {
+        self.set.extend(v.set.iter().cloned());
+    }
+}
+
+impl<T: Eq + Hash> Default for Distinct<T> {
+    fn default() -> Distinct<T> {
+        Distinct { set: HashSet::new() }
+    }
+}
+
+impl<T: fmt::Debug + Eq + Hash> fmt::Debug for Distinct<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.set)
+    }
+}
+
+impl<T: Eq + Hash> FromIterator<T> for Distinct<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Distinct<T> {
+        let mut v = Distinct::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Eq + Hash> Extend<T> for Distinct<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// A commutative data structure for distinct counts in bounded memory.
+///
+/// Values are counted exactly, as in `Distinct`, until there are more than
+/// `limit` of them. Then the set of values is replaced by a `HyperLogLog`
+/// sketch, so memory stays bounded, and the cardinality becomes an
+/// estimate. `is_exact` reports which is the case.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize",
+    deserialize = "T: Eq + Hash + ::serde::Deserialize<'de>"
+)))]
+pub struct BoundedDistinct<T> {
+    limit: usize,
+    counts: Counts<T>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize",
+    deserialize = "T: Eq + Hash + ::serde::Deserialize<'de>"
+)))]
+enum Counts<T> {
+    Exact(HashSet<T>),
+    Approximate(HyperLogLog<T>),
+}
+
+impl<T: Eq + Hash> BoundedDistinct<T> {
+    /// Create an empty counter that is exact for up to `limit` distinct
+    /// values.
+    pub fn with_limit(limit: usize) -> BoundedDistinct<T> {
+        BoundedDistinct { limit, counts: Counts::Exact(HashSet::new()) }
+    }
+
+    /// Add a sample.
+    pub fn add(&mut self, v: T) {
+        let spill = match self.counts {
+            Counts::Exact(ref mut set) => {
+                set.insert(v);
+                set.len() > self.limit
+            }
+            Counts::Approximate(ref mut hll) => {
+                hll.add(v);
+                false
+            }
+        };
+        if spill {
+            self.spill();
+        }
+    }
+
+    /// Returns the number of distinct samples.
+    ///
+    /// This is exact if `is_exact` returns true, and an estimate otherwise.
+    pub fn cardinality(&self) -> u64 {
+        match self.counts {
+            Counts::Exact(ref set) => set.len() as u64,
+            Counts::Approximate(ref hll) => hll.cardinality(),
+        }
+    }
+
+    /// Returns true if the cardinality is an exact count.
+    pub fn is_exact(&self) -> bool {
+        match self.counts {
+            Counts::Exact(_) => true,
+            Counts::Approximate(_) => false,
+        }
+    }
+
+    /// Returns the largest number of distinct values that are counted
+    /// exactly.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Switches to approximate counting.
+    fn spill(&mut self) {
+        let hll = HyperLogLog::new();
+        match mem::replace(&mut self.counts, Counts::Approximate(hll)) {
+            Counts::Exact(set) => {
+                if let Counts::Approximate(ref mut hll) = self.counts {
+                    hll.extend(set);
+                }
+            }
+            approximate => self.counts = approximate,
+        }
+    }
+}
+
+impl<T: Eq + Hash> Commute for BoundedDistinct<T> {
+    fn merge(&mut self, v: BoundedDistinct<T>) {
+        match v.counts {
+            Counts::Exact(set) => self.extend(set),
+            Counts::Approximate(other) => {
+                self.spill();
+                if let Counts::Approximate(ref mut hll) = self.counts {
+                    hll.merge(other);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Eq + Hash> fmt::Debug for BoundedDistinct<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let approx = if self.is_exact() { "" } else { "~" };
+        write!(f, "BoundedDistinct({}{})", approx, self.cardinality())
+    }
+}
+
+impl<T: Eq + Hash> Extend<T> for BoundedDistinct<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BoundedDistinct, Distinct};
+    use Commute;
+
+    #[test]
+    fn cardinality() {
+        let mut d: Distinct<u32> = vec![1, 2, 2, 3, 3, 3].into_iter().collect();
+        assert_eq!(d.cardinality(), 3);
+        d.add(4);
+        assert_eq!(d.cardinality(), 4);
+        assert!(d.contains(&4));
+    }
+
+    #[test]
+    fn merge() {
+        let mut d1: Distinct<&str> = vec!["a", "b"].into_iter().collect();
+        let d2: Distinct<&str> = vec!["b", "c"].into_iter().collect();
+        d1.merge(d2);
+        assert_eq!(d1.cardinality(), 3);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut d = BoundedDistinct::with_limit(100);
+        d.extend((0..100u32).chain(0..100));
+        assert!(d.is_exact());
+        assert_eq!(d.cardinality(), 100);
+
+        d.extend(100..10000);
+        assert!(!d.is_exact());
+        let estimate = d.cardinality() as f64;
+        assert!((estimate - 10000.0).abs() < 300.0, "{}", estimate);
+    }
+
+    #[test]
+    fn bounded_merge() {
+        let mut d1 = BoundedDistinct::with_limit(10);
+        d1.extend(vec![1, 2, 3]);
+        let mut d2 = BoundedDistinct::with_limit(10);
+        d2.extend(vec![3, 4]);
+        d1.merge(d2);
+        assert!(d1.is_exact());
+        assert_eq!(d1.cardinality(), 4);
+
+        let mut big = BoundedDistinct::with_limit(10);
+        big.extend(0..1000);
+        d1.merge(big);
+        assert!(!d1.is_exact());
+        assert!((d1.cardinality() as f64 - 1000.0).abs() < 50.0);
+    }
+}
@@ -0,0 +1,191 @@
+use std::prelude::v1::*;
+
+use std::iter::IntoIterator;
+
+use num_traits::ToPrimitive;
+
+/// Online state for an exponentially weighted mean and variance.
+///
+/// Every sample's weight decays geometrically as newer samples arrive, so
+/// recent samples dominate. This is useful for monitoring, where the
+/// current level of a metric matters more than its level of all time.
+///
+/// The decay is either per sample, set with a smoothing factor (`new`) or
+/// a half-life in samples (`with_half_life`), or per unit of time, set with
+/// `with_time_half_life`, in which case samples are added with timestamps
+/// by `add_at`. The weights are normalized by their sum, so the first
+/// samples are not biased towards zero.
+///
+/// Like `SeriesStats`, this does not implement `Commute`, since the weights
+/// depend on the order of the samples.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct EwmaStats {
+    decay: f64,
+    time_half_life: Option<f64>,
+    last_time: Option<f64>,
+    weight: f64,
+    mean: f64,
+    variance: f64,
+    len: u64,
+}
+
+impl EwmaStats {
+    /// Create initial state where each new sample has weight `alpha`
+    /// relative to the current state.
+    ///
+    /// This panics if `alpha` is not in `(0, 1]`.
+    pub fn new(alpha: f64) -> EwmaStats {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha must be in (0, 1]");
+        EwmaStats::with_decay(1.0 - alpha, None)
+    }
+
+    /// Create initial state where a sample's weight halves after every
+    /// `half_life` newer samples.
+    ///
+    /// This panics if `half_life` is not positive.
+    pub fn with_half_life(half_life: f64) -> EwmaStats {
+        assert!(half_life > 0.0, "half life must be positive");
+        EwmaStats::with_decay((-1.0 / half_life).exp2(), None)
+    }
+
+    /// Create initial state where a sample's weight halves after every
+    /// `half_life` units of time.
+    ///
+    /// Samples must be added with `add_at`. This panics if `half_life` is
+    /// not positive.
+    pub fn with_time_half_life(half_life: f64) -> EwmaStats {
+        assert!(half_life > 0.0, "half life must be positive");
+        EwmaStats::with_decay(1.0, Some(half_life))
+    }
+
+    fn with_decay(decay: f64, time_half_life: Option<f64>) -> EwmaStats {
+        EwmaStats {
+            decay,
+            time_half_life,
+            last_time: None,
+            weight: 0.0,
+            mean: 0.0,
+            variance: 0.0,
+            len: 0,
+        }
+    }
+
+    /// Add a new sample.
+    ///
+    /// This panics if the state decays with time; use `add_at` instead.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        assert!(self.time_half_life.is_none(),
+                "samples must be added with a timestamp");
+        let decay = self.decay;
+        self.update(sample.to_f64().unwrap(), decay);
+    }
+
+    /// Add a new sample observed at `time`.
+    ///
+    /// Samples older than the newest sample so far are treated as if they
+    /// were observed at the same time as it. This panics unless the state
+    /// was created with `with_time_half_life`.
+    pub fn add_at<T: ToPrimitive>(&mut self, sample: T, time: f64) {
+        let half_life = self.time_half_life
+            .expect("state does not decay with time; use add instead");
+        let decay = match self.last_time {
+            Some(last) if time > last => {
+                self.last_time = Some(time);
+                (-(time - last) / half_life).exp2()
+            }
+            Some(_) => 1.0,
+            None => {
+                self.last_time = Some(time);
+                1.0
+            }
+        };
+        self.update(sample.to_f64().unwrap(), decay);
+    }
+
+    fn update(&mut self, x: f64, decay: f64) {
+        self.len += 1;
+        self.weight = self.weight * decay + 1.0;
+        let alpha = 1.0 / self.weight;
+        let delta = x - self.mean;
+        self.mean += alpha * delta;
+        self.variance = (1.0 - alpha) * (self.variance + alpha * delta * delta);
+    }
+
+    /// Returns the exponentially weighted mean.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the exponentially weighted variance.
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    /// Returns the exponentially weighted standard deviation.
+    pub fn stddev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for EwmaStats {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EwmaStats;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn follows_level() {
+        let mut stats = EwmaStats::new(0.5);
+        stats.extend(vec![10; 20]);
+        assert!(close(stats.mean(), 10.0));
+        assert!(close(stats.stddev(), 0.0));
+        stats.extend(vec![20; 20]);
+        assert!((stats.mean() - 20.0).abs() < 1e-3);
+        assert_eq!(stats.len(), 40);
+    }
+
+    #[test]
+    fn weights() {
+        // With a half-life of one sample, the weights are 1/2 and 1.
+        let mut stats = EwmaStats::with_half_life(1.0);
+        stats.extend(vec![0.0, 3.0]);
+        assert!(close(stats.mean(), 2.0));
+        assert!(close(stats.variance(), 2.0));
+
+        // `alpha = 1` keeps only the last sample.
+        let mut last = EwmaStats::new(1.0);
+        last.extend(vec![1, 2, 5]);
+        assert!(close(last.mean(), 5.0));
+    }
+
+    #[test]
+    fn time_decay() {
+        let mut stats = EwmaStats::with_time_half_life(10.0);
+        stats.add_at(0.0, 0.0);
+        stats.add_at(3.0, 10.0);
+        assert!(close(stats.mean(), 2.0));
+
+        // Samples at the same time have equal weights.
+        let mut same = EwmaStats::with_time_half_life(10.0);
+        same.add_at(1.0, 5.0);
+        same.add_at(3.0, 5.0);
+        same.add_at(5.0, 4.0);
+        assert!(close(same.mean(), 3.0));
+    }
+}
@@ -0,0 +1,193 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+
+use num_traits::ToPrimitive;
+
+use Commute;
+use distributions::{erfc, normal_quantile};
+use online::OnlineStats;
+
+/// A comparison of a treatment group against a control group.
+///
+/// Both groups are summarized by `OnlineStats`, so an experiment can be
+/// built up in a streaming fashion and merged across shards like any other
+/// accumulator. Conversion rates are handled by adding `0` or `1` for each
+/// subject: the mean is then the rate, and the variance is `p * (1 - p)`.
+///
+/// Inference uses the normal approximation to the difference of means with
+/// unpooled variances, which is appropriate for the large samples typical
+/// of product experiments.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Experiment {
+    control: OnlineStats,
+    treatment: OnlineStats,
+}
+
+impl Experiment {
+    /// Create an experiment from the data of each group.
+    pub fn new(control: OnlineStats, treatment: OnlineStats) -> Experiment {
+        Experiment { control, treatment }
+    }
+
+    /// Add a sample to the control group.
+    pub fn add_control<T: ToPrimitive>(&mut self, sample: T) {
+        self.control.add(sample);
+    }
+
+    /// Add a sample to the treatment group.
+    pub fn add_treatment<T: ToPrimitive>(&mut self, sample: T) {
+        self.treatment.add(sample);
+    }
+
+    /// Returns the statistics of the control group.
+    pub fn control(&self) -> &OnlineStats {
+        &self.control
+    }
+
+    /// Returns the statistics of the treatment group.
+    pub fn treatment(&self) -> &OnlineStats {
+        &self.treatment
+    }
+
+    /// Returns the difference of the treatment mean and the control mean.
+    pub fn difference(&self) -> f64 {
+        self.treatment.mean() - self.control.mean()
+    }
+
+    /// Returns the difference relative to the control mean, e.g., `0.05`
+    /// for a 5% improvement.
+    pub fn lift(&self) -> f64 {
+        self.difference() / self.control.mean()
+    }
+
+    /// Returns the standard error of the difference.
+    ///
+    /// This is `NaN` if either group has fewer than two samples.
+    pub fn std_err(&self) -> f64 {
+        (sample_variance(&self.control) / self.control.len() as f64
+         + sample_variance(&self.treatment) / self.treatment.len() as f64)
+        .sqrt()
+    }
+
+    /// Returns a two-sided confidence interval for the difference.
+    ///
+    /// `level` is the confidence level, e.g., `0.95`.
+    pub fn confidence_interval(&self, level: f64) -> (f64, f64) {
+        let z = normal_quantile(1.0 - (1.0 - level) / 2.0);
+        let (d, se) = (self.difference(), self.std_err());
+        (d - z * se, d + z * se)
+    }
+
+    /// Returns the two-sided p-value for the null hypothesis that the
+    /// groups have the same mean.
+    pub fn p_value(&self) -> f64 {
+        let z = self.difference().abs() / self.std_err();
+        erfc(z / ::std::f64::consts::SQRT_2)
+    }
+
+    /// Returns the number of samples needed in each group to detect an
+    /// absolute difference of `effect` with the given significance level
+    /// `alpha` and `power`, e.g., `0.05` and `0.8`.
+    ///
+    /// The variance of each group is estimated from the data collected so
+    /// far. `None` is returned if `effect` is zero or the variances cannot
+    /// be estimated yet.
+    pub fn required_sample_size(
+        &self,
+        effect: f64,
+        alpha: f64,
+        power: f64,
+    ) -> Option<u64> {
+        let var = sample_variance(&self.control)
+                  + sample_variance(&self.treatment);
+        if effect == 0.0 || !var.is_finite() {
+            return None;
+        }
+        let z = normal_quantile(1.0 - alpha / 2.0) + normal_quantile(power);
+        Some((z * z * var / (effect * effect)).ceil() as u64)
+    }
+}
+
+fn sample_variance(stats: &OnlineStats) -> f64 {
+    let n = stats.len() as f64;
+    if n < 2.0 {
+        return ::std::f64::NAN;
+    }
+    stats.variance() * n / (n - 1.0)
+}
+
+impl Commute for Experiment {
+    fn merge(&mut self, v: Experiment) {
+        self.control.merge(v.control);
+        self.treatment.merge(v.treatment);
+    }
+}
+
+impl Default for Experiment {
+    fn default() -> Experiment {
+        Experiment::new(OnlineStats::new(), OnlineStats::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Experiment;
+    use {Commute, OnlineStats};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    fn conversions(n: usize, converted: usize) -> OnlineStats {
+        (0..n).map(|i| if i < converted { 1 } else { 0 }).collect()
+    }
+
+    #[test]
+    fn proportions() {
+        let exp = Experiment::new(conversions(1000, 100),
+                                  conversions(1000, 130));
+        assert!(close(exp.difference(), 0.03));
+        assert!(close(exp.lift(), 0.3));
+        // The unpooled standard error, using sample variances.
+        let se = (0.1 * 0.9 / 999.0 + 0.13 * 0.87 / 999.0f64).sqrt();
+        assert!(close(exp.std_err(), se));
+        let (lo, hi) = exp.confidence_interval(0.95);
+        assert!(close(lo, 0.03 - 1.959963984540054 * se));
+        assert!(close(hi, 0.03 + 1.959963984540054 * se));
+        assert!(exp.p_value() > 0.03 && exp.p_value() < 0.04);
+
+        // A difference of many standard errors still has a positive
+        // p-value.
+        let far = Experiment::new(conversions(1000, 100),
+                                  conversions(1000, 500));
+        assert!(far.p_value() > 0.0 && far.p_value() < 1e-50);
+    }
+
+    #[test]
+    fn sample_size() {
+        let exp = Experiment::new(OnlineStats::from_slice(&[0, 2]),
+                                  OnlineStats::from_slice(&[0, 2]));
+        // Each group has a sample variance of 2, so with alpha = 0.05 and
+        // power = 0.8: (1.96 + 0.8416)^2 * 4 / 1 = 31.4.
+        assert_eq!(exp.required_sample_size(1.0, 0.05, 0.8), Some(32));
+        assert_eq!(exp.required_sample_size(0.0, 0.05, 0.8), None);
+        assert_eq!(Experiment::default().required_sample_size(1.0, 0.05, 0.8),
+                   None);
+    }
+
+    #[test]
+    fn merge() {
+        let mut e1 = Experiment::default();
+        e1.add_control(1);
+        e1.add_treatment(3);
+        let mut e2 = Experiment::default();
+        e2.add_control(3);
+        e2.add_treatment(5);
+        e1.merge(e2);
+        assert_eq!(e1.control().len(), 2);
+        assert_eq!(e1.difference(), 2.0);
+        assert!(close(e1.p_value(), 0.1572992));
+    }
+}
@@ -0,0 +1,668 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+use Commute;
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// The finest scale of the OpenTelemetry data model.
+const MAX_SCALE: i32 = 20;
+/// The coarsest scale of the OpenTelemetry data model.
+const MIN_SCALE: i32 = -10;
+/// The default maximum number of buckets for each sign.
+const DEFAULT_MAX_SIZE: usize = 160;
+
+/// A commutative histogram with exponentially growing buckets, as in the
+/// OpenTelemetry exponential histogram data model.
+///
+/// At scale `s`, bucket `i` holds the values in `(base^i, base^(i + 1)]`
+/// where `base = 2^(2^-s)`, so the relative error of every bucket is the
+/// same. Positive and negative values are kept in separate bucket ranges
+/// that share the scale, and zero has a bucket of its own.
+///
+/// The histogram starts at the finest scale, `20`, and whenever the values
+/// of one sign span more than its maximum number of buckets, the scale is
+/// lowered until they fit. Lowering the scale by one merges pairs of
+/// adjacent buckets.
+///
+/// Histograms convert to and from `ExponentialDataPoint`, which mirrors
+/// the OTLP `ExponentialHistogramDataPoint` message, so they can be
+/// exchanged with collectors without an adaptation layer.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct ExponentialHistogram {
+    scale: i32,
+    max_size: usize,
+    zero_threshold: f64,
+    zero_count: u64,
+    positive: Buckets,
+    negative: Buckets,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+/// The buckets of one sign in an OTLP exponential histogram data point.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ExponentialBuckets {
+    /// The index of the first bucket.
+    pub offset: i32,
+    /// The counts of consecutive buckets, starting at `offset`.
+    pub bucket_counts: Vec<u64>,
+}
+
+/// The fields of an OTLP `ExponentialHistogramDataPoint` that describe the
+/// distribution.
+///
+/// Attributes, timestamps and exemplars are left to the exporter.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ExponentialDataPoint {
+    /// The number of values.
+    pub count: u64,
+    /// The sum of the values.
+    pub sum: f64,
+    /// The resolution of the buckets.
+    pub scale: i32,
+    /// The number of values whose magnitude is at most `zero_threshold`.
+    pub zero_count: u64,
+    /// The buckets of the positive values.
+    pub positive: ExponentialBuckets,
+    /// The buckets of the negative values, indexed by magnitude.
+    pub negative: ExponentialBuckets,
+    /// The smallest value, if known.
+    pub min: Option<f64>,
+    /// The largest value, if known.
+    pub max: Option<f64>,
+    /// The width of the zero bucket.
+    pub zero_threshold: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+struct Buckets {
+    offset: i32,
+    counts: Vec<u64>,
+}
+
+impl ExponentialHistogram {
+    /// Create an empty histogram with up to 160 buckets for each sign.
+    pub fn new() -> ExponentialHistogram {
+        Default::default()
+    }
+
+    /// Create an empty histogram with up to `max_size` buckets for each
+    /// sign.
+    ///
+    /// This panics if `max_size` is less than `2`.
+    pub fn with_max_size(max_size: usize) -> ExponentialHistogram {
+        assert!(max_size >= 2, "max_size must be at least 2");
+        ExponentialHistogram {
+            scale: MAX_SCALE,
+            max_size,
+            zero_threshold: 0.0,
+            zero_count: 0,
+            positive: Buckets::default(),
+            negative: Buckets::default(),
+            count: 0,
+            sum: 0.0,
+            min: ::std::f64::INFINITY,
+            max: ::std::f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a sample to the histogram.
+    ///
+    /// Samples that are infinite or `NaN` are ignored, since they have no
+    /// bucket.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        let v = sample.to_f64().unwrap();
+        if !v.is_finite() {
+            return;
+        }
+        self.count += 1;
+        self.sum += v;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        if v.abs() <= self.zero_threshold {
+            self.zero_count += 1;
+            return;
+        }
+
+        let index = bucket_index(v.abs(), self.scale);
+        let change = {
+            let buckets = if v > 0.0 { &self.positive } else { &self.negative };
+            match buckets.range() {
+                None => 0,
+                Some((lo, hi)) => {
+                    scale_change(lo.min(index), hi.max(index), self.max_size)
+                }
+            }
+        };
+        self.downscale(change);
+        let index = bucket_index(v.abs(), self.scale);
+        if v > 0.0 {
+            self.positive.increment(index, 1);
+        } else {
+            self.negative.increment(index, 1);
+        }
+    }
+
+    /// Returns the resolution of the buckets.
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    /// Returns the number of values in the zero bucket.
+    pub fn zero_count(&self) -> u64 {
+        self.zero_count
+    }
+
+    /// Returns the sum of the samples.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the mean of the samples, or `None` if there is no data.
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum / self.count as f64)
+        }
+    }
+
+    /// Returns the smallest sample, or `None` if there is no data.
+    pub fn min(&self) -> Option<f64> {
+        if self.count == 0 { None } else { Some(self.min) }
+    }
+
+    /// Returns the largest sample, or `None` if there is no data.
+    pub fn max(&self) -> Option<f64> {
+        if self.count == 0 { None } else { Some(self.max) }
+    }
+
+    /// Returns the non-empty buckets as `(lower, upper, count)`, in
+    /// ascending order of value.
+    ///
+    /// Each bucket holds the values in `(lower, upper]`, or `[lower, upper)`
+    /// for negative buckets. The zero bucket is included as
+    /// `(-zero_threshold, zero_threshold)`.
+    pub fn buckets(&self) -> Vec<(f64, f64, u64)> {
+        let mut out = vec![];
+        for (i, &c) in self.negative.counts.iter().enumerate().rev() {
+            if c > 0 {
+                let index = self.negative.offset + i as i32;
+                out.push((-lower_boundary(index + 1, self.scale),
+                          -lower_boundary(index, self.scale), c));
+            }
+        }
+        if self.zero_count > 0 {
+            out.push((-self.zero_threshold, self.zero_threshold,
+                      self.zero_count));
+        }
+        for (i, &c) in self.positive.counts.iter().enumerate() {
+            if c > 0 {
+                let index = self.positive.offset + i as i32;
+                out.push((lower_boundary(index, self.scale),
+                          lower_boundary(index + 1, self.scale), c));
+            }
+        }
+        out
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Returns the histogram as an OTLP data point.
+    pub fn to_otlp(&self) -> ExponentialDataPoint {
+        ExponentialDataPoint {
+            count: self.count,
+            sum: self.sum,
+            scale: self.scale,
+            zero_count: self.zero_count,
+            positive: self.positive.to_otlp(),
+            negative: self.negative.to_otlp(),
+            min: self.min(),
+            max: self.max(),
+            zero_threshold: self.zero_threshold,
+        }
+    }
+
+    /// Create a histogram with up to `max_size` buckets for each sign from
+    /// an OTLP data point.
+    ///
+    /// If the data point has more buckets than fit, its scale is lowered.
+    /// `None` is returned if the scale is outside of `[-10, 20]`, if the
+    /// counts do not add up to `count` without overflowing, if the zero
+    /// threshold is negative, or if the buckets extend past the buckets of
+    /// the finite values at the scale.
+    pub fn from_otlp(
+        point: &ExponentialDataPoint,
+        max_size: usize,
+    ) -> Option<ExponentialHistogram> {
+        if point.scale < MIN_SCALE || point.scale > MAX_SCALE
+                || point.zero_threshold.is_nan()
+                || point.zero_threshold < 0.0 {
+            return None;
+        }
+        let total = point.positive.bucket_counts.iter()
+            .chain(&point.negative.bucket_counts)
+            .try_fold(point.zero_count, |sum, &c| sum.checked_add(c));
+        if total != Some(point.count) {
+            return None;
+        }
+        let (lo, hi) = index_range(point.scale);
+        for buckets in &[&point.positive, &point.negative] {
+            let last = buckets.offset as i64
+                       + buckets.bucket_counts.len() as i64 - 1;
+            if !buckets.bucket_counts.is_empty()
+                    && (buckets.offset < lo || last > hi as i64) {
+                return None;
+            }
+        }
+        let mut h = ExponentialHistogram::with_max_size(max_size);
+        h.scale = point.scale;
+        h.zero_threshold = point.zero_threshold;
+        h.zero_count = point.zero_count;
+        h.count = point.count;
+        h.sum = point.sum;
+        h.min = point.min.unwrap_or(::std::f64::INFINITY);
+        h.max = point.max.unwrap_or(::std::f64::NEG_INFINITY);
+        let mut positive = Buckets::from_otlp(&point.positive);
+        let mut negative = Buckets::from_otlp(&point.negative);
+        let change = positive.fit_change(max_size)
+            .max(negative.fit_change(max_size));
+        positive.downscale(change);
+        negative.downscale(change);
+        h.scale -= change;
+        h.positive = positive;
+        h.negative = negative;
+        Some(h)
+    }
+
+    /// Lowers the scale by `change`, merging buckets.
+    fn downscale(&mut self, change: i32) {
+        if change > 0 {
+            self.scale -= change;
+            self.positive.downscale(change);
+            self.negative.downscale(change);
+        }
+    }
+}
+
+impl Buckets {
+    /// Returns the lowest and highest index of the non-empty range.
+    fn range(&self) -> Option<(i32, i32)> {
+        if self.counts.is_empty() {
+            None
+        } else {
+            Some((self.offset, self.offset + self.counts.len() as i32 - 1))
+        }
+    }
+
+    fn increment(&mut self, index: i32, count: u64) {
+        if self.counts.is_empty() {
+            self.offset = index;
+            self.counts.push(count);
+            return;
+        }
+        if index < self.offset {
+            let grow = (self.offset - index) as usize;
+            let mut counts = vec![0; grow];
+            counts.extend_from_slice(&self.counts);
+            self.counts = counts;
+            self.offset = index;
+        }
+        let i = (index - self.offset) as usize;
+        if i >= self.counts.len() {
+            self.counts.resize(i + 1, 0);
+        }
+        self.counts[i] += count;
+    }
+
+    /// Returns how much the scale must be lowered to fit in `max_size`.
+    fn fit_change(&self, max_size: usize) -> i32 {
+        match self.range() {
+            None => 0,
+            Some((lo, hi)) => scale_change(lo, hi, max_size),
+        }
+    }
+
+    fn downscale(&mut self, change: i32) {
+        if change <= 0 || self.counts.is_empty() {
+            return;
+        }
+        let old = ::std::mem::replace(self, Buckets::default());
+        for (i, c) in old.counts.into_iter().enumerate() {
+            if c > 0 {
+                self.increment((old.offset + i as i32) >> change, c);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Buckets) {
+        for (i, c) in other.counts.into_iter().enumerate() {
+            if c > 0 {
+                self.increment(other.offset + i as i32, c);
+            }
+        }
+    }
+
+    fn to_otlp(&self) -> ExponentialBuckets {
+        ExponentialBuckets {
+            offset: self.offset,
+            bucket_counts: self.counts.clone(),
+        }
+    }
+
+    fn from_otlp(buckets: &ExponentialBuckets) -> Buckets {
+        // Empty buckets at either end carry no information.
+        let counts = &buckets.bucket_counts;
+        let start = match counts.iter().position(|&c| c > 0) {
+            None => return Buckets::default(),
+            Some(start) => start,
+        };
+        let end = counts.iter().rposition(|&c| c > 0).unwrap() + 1;
+        Buckets {
+            offset: buckets.offset + start as i32,
+            counts: counts[start..end].to_vec(),
+        }
+    }
+}
+
+/// Returns how much the scale must be lowered for the indexes `lo..=hi` to
+/// fit in `max_size` buckets.
+fn scale_change(lo: i32, hi: i32, max_size: usize) -> i32 {
+    let mut change = 0;
+    while ((hi >> change) - (lo >> change)) as usize + 1 > max_size {
+        change += 1;
+    }
+    change
+}
+
+/// Returns the base 2 exponent of a positive finite value and whether the
+/// value is an exact power of two.
+fn decompose(v: f64) -> (i32, bool) {
+    let bits = v.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let mantissa = bits & ((1 << 52) - 1);
+    if exponent == 0 {
+        // Subnormal values have no implicit leading bit.
+        let p = 63 - mantissa.leading_zeros() as i32;
+        (p - 1074, mantissa == 1 << p)
+    } else {
+        (exponent - 1023, mantissa == 0)
+    }
+}
+
+/// Returns the index of the bucket that holds the positive value `v`.
+///
+/// Exact powers of two are the upper boundaries of their buckets, which is
+/// computed exactly rather than with a logarithm.
+fn bucket_index(v: f64, scale: i32) -> i32 {
+    let (exponent, power_of_two) = decompose(v);
+    if scale <= 0 {
+        let exponent = if power_of_two { exponent - 1 } else { exponent };
+        exponent >> -scale
+    } else if power_of_two {
+        (exponent << scale) - 1
+    } else {
+        let factor = (scale as f64).exp2() / ::std::f64::consts::LN_2;
+        (v.ln() * factor).ceil() as i32 - 1
+    }
+}
+
+/// Returns the indexes of the buckets of the smallest and the largest
+/// positive finite values at `scale`.
+fn index_range(scale: i32) -> (i32, i32) {
+    (bucket_index(f64::from_bits(1), scale),
+     bucket_index(::std::f64::MAX, scale))
+}
+
+/// Returns the lower boundary of bucket `index`, `base^index`.
+fn lower_boundary(index: i32, scale: i32) -> f64 {
+    (index as f64 * (-scale as f64).exp2()).exp2()
+}
+
+impl Commute for ExponentialHistogram {
+    fn merge(&mut self, mut v: ExponentialHistogram) {
+        assert_eq!(self.zero_threshold, v.zero_threshold);
+        // Find the finest common scale at which both signs fit.
+        let mut scale = self.scale.min(v.scale);
+        loop {
+            let fits = [(&self.positive, &v.positive),
+                        (&self.negative, &v.negative)]
+                .iter()
+                .all(|&(a, b)| {
+                    let (da, db) = (self.scale - scale, v.scale - scale);
+                    let ranges = [a.range().map(|(l, h)| (l >> da, h >> da)),
+                                  b.range().map(|(l, h)| (l >> db, h >> db))];
+                    let mut r = ranges.iter().filter_map(|&r| r);
+                    match r.next() {
+                        None => true,
+                        Some(first) => {
+                            let (lo, hi) = r.fold(first, |(l1, h1), (l2, h2)| {
+                                (l1.min(l2), h1.max(h2))
+                            });
+                            scale_change(lo, hi, self.max_size) == 0
+                        }
+                    }
+                });
+            if fits {
+                break;
+            }
+            scale -= 1;
+        }
+        let (da, db) = (self.scale - scale, v.scale - scale);
+        self.downscale(da);
+        v.downscale(db);
+        self.positive.merge(v.positive);
+        self.negative.merge(v.negative);
+        self.zero_count += v.zero_count;
+        self.count += v.count;
+        self.sum += v.sum;
+        self.min = self.min.min(v.min);
+        self.max = self.max.max(v.max);
+    }
+}
+
+impl Default for ExponentialHistogram {
+    fn default() -> ExponentialHistogram {
+        ExponentialHistogram::with_max_size(DEFAULT_MAX_SIZE)
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for ExponentialHistogram {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> ExponentialHistogram {
+        let mut v = ExponentialHistogram::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for ExponentialHistogram {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+/// Deserializing checks the scale and the bucket counts against
+/// `max_size`, so a corrupt histogram is an error instead of a panic when
+/// it is updated or merged.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for ExponentialHistogram {
+    fn deserialize<D>(d: D) -> Result<ExponentialHistogram, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            scale: i32,
+            max_size: usize,
+            zero_threshold: f64,
+            zero_count: u64,
+            positive: Buckets,
+            negative: Buckets,
+            count: u64,
+            sum: f64,
+            min: f64,
+            max: f64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.max_size < 2 {
+            return Err(D::Error::custom("max_size must be at least 2"));
+        }
+        if parts.scale < MIN_SCALE || parts.scale > MAX_SCALE {
+            return Err(D::Error::custom("scale is out of range"));
+        }
+        if parts.positive.counts.len() > parts.max_size
+                || parts.negative.counts.len() > parts.max_size {
+            return Err(D::Error::custom("more buckets than max_size"));
+        }
+        Ok(ExponentialHistogram {
+            scale: parts.scale,
+            max_size: parts.max_size,
+            zero_threshold: parts.zero_threshold,
+            zero_count: parts.zero_count,
+            positive: parts.positive,
+            negative: parts.negative,
+            count: parts.count,
+            sum: parts.sum,
+            min: parts.min,
+            max: parts.max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExponentialBuckets, ExponentialHistogram, bucket_index};
+    use Commute;
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_max_size() {
+        use serde_json;
+
+        let mut hist = ExponentialHistogram::with_max_size(4);
+        hist.extend(vec![1.0, 10.0, 100.0, -3.0]);
+        let json = serde_json::to_string(&hist).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ExponentialHistogram>(&json).unwrap(),
+            hist);
+
+        let bad = json.replace("\"max_size\":4", "\"max_size\":1");
+        assert!(serde_json::from_str::<ExponentialHistogram>(&bad).is_err());
+        let bad = json.replace("\"max_size\":4", "\"max_size\":2");
+        assert!(serde_json::from_str::<ExponentialHistogram>(&bad).is_err());
+    }
+
+    #[test]
+    fn indexes() {
+        // At scale 0, bucket `i` is `(2^i, 2^(i + 1)]`.
+        assert_eq!(bucket_index(1.0, 0), -1);
+        assert_eq!(bucket_index(1.5, 0), 0);
+        assert_eq!(bucket_index(2.0, 0), 0);
+        assert_eq!(bucket_index(2.5, 0), 1);
+        assert_eq!(bucket_index(4.0, 1), 3);
+        assert_eq!(bucket_index(5.0, 1), 4);
+        assert_eq!(bucket_index(1024.0, -2), 2);
+        assert_eq!(bucket_index(1025.0, -2), 2);
+        assert_eq!(bucket_index(::std::f64::MIN_POSITIVE / 2.0, 0), -1024);
+    }
+
+    #[test]
+    fn downscales() {
+        let mut h = ExponentialHistogram::with_max_size(4);
+        h.extend(vec![1.5, 3.0, 6.0, 12.0]);
+        assert_eq!(h.scale(), 0);
+        h.add(24.0);
+        assert_eq!(h.scale(), -1);
+        h.extend(vec![0.0, -2.0]);
+        assert_eq!(h.len(), 7);
+        assert_eq!(h.zero_count(), 1);
+        assert_eq!(h.min(), Some(-2.0));
+        assert_eq!(h.max(), Some(24.0));
+
+        let buckets = h.buckets();
+        assert_eq!(buckets[0], (-4.0, -1.0, 1));
+        assert_eq!(buckets[1], (0.0, 0.0, 1));
+        assert_eq!(buckets[2], (1.0, 4.0, 2));
+        let total: u64 = buckets.iter().map(|b| b.2).sum();
+        assert_eq!(total, 7);
+    }
+
+    #[test]
+    fn otlp_round_trip() {
+        let mut h = ExponentialHistogram::with_max_size(20);
+        h.extend(vec![0.5, 1.0, 3.0, -7.0, 0.0, 100.0]);
+        let point = h.to_otlp();
+        assert_eq!(point.count, 6);
+        assert_eq!(point.zero_count, 1);
+        assert_eq!(ExponentialHistogram::from_otlp(&point, 20), Some(h));
+
+        // Importing into a smaller histogram lowers the scale.
+        let small = ExponentialHistogram::from_otlp(&point, 2).unwrap();
+        assert!(small.scale() < point.scale);
+        assert_eq!(small.to_otlp().positive.bucket_counts.iter()
+                        .sum::<u64>(), 4);
+
+        let mut bad = point.clone();
+        bad.count += 1;
+        assert_eq!(ExponentialHistogram::from_otlp(&bad, 20), None);
+        bad.count -= 1;
+        bad.positive = ExponentialBuckets { offset: 0, bucket_counts: vec![] };
+        bad.negative.bucket_counts = vec![0, 0];
+        bad.count = 1;
+        let zero = ExponentialHistogram::from_otlp(&bad, 20).unwrap();
+        assert_eq!(zero.buckets(), vec![(0.0, 0.0, 1)]);
+        // Counts that only add up by wrapping around are rejected.
+        bad.negative.bucket_counts = vec![u64::max_value(), 2];
+        bad.zero_count = 0;
+        assert_eq!(ExponentialHistogram::from_otlp(&bad, 20), None);
+        // So are buckets past the indexes of finite values.
+        bad.negative = ExponentialBuckets {
+            offset: ::std::i32::MAX,
+            bucket_counts: vec![1, 1],
+        };
+        bad.count = 2;
+        assert_eq!(ExponentialHistogram::from_otlp(&bad, 20), None);
+        bad.negative.offset = -1 << 30;
+        assert_eq!(ExponentialHistogram::from_otlp(&bad, 20), None);
+        bad.negative.offset = (1 << 30) - 2;
+        bad.scale = 20;
+        assert!(ExponentialHistogram::from_otlp(&bad, 20).is_some());
+        bad.negative.offset += 1;
+        assert_eq!(ExponentialHistogram::from_otlp(&bad, 20), None);
+    }
+
+    #[test]
+    fn index_range() {
+        assert_eq!(super::index_range(0), (-1075, 1023));
+        assert_eq!(super::index_range(20), ((-1074 << 20) - 1, (1 << 30) - 1));
+    }
+
+    #[test]
+    fn merge() {
+        let mut h1: ExponentialHistogram =
+            vec![1.5, 1.5, 3.0].into_iter().collect();
+        let h2: ExponentialHistogram =
+            vec![1000.0, 0.001].into_iter().collect();
+        let full: ExponentialHistogram =
+            vec![1.5, 1.5, 3.0, 1000.0, 0.001].into_iter().collect();
+        h1.merge(h2);
+        assert_eq!(h1.len(), 5);
+        assert_eq!(h1.scale(), full.scale());
+        assert_eq!(h1.buckets(), full.buckets());
+    }
+}
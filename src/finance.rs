@@ -0,0 +1,178 @@
+use std::prelude::v1::*;
+
+use num_traits::ToPrimitive;
+
+use online::OnlineStats;
+
+/// How the return between two consecutive prices is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Returns {
+    /// `p1 / p0 - 1`
+    Simple,
+    /// `ln(p1 / p0)`. Log returns add up over time.
+    Log,
+}
+
+/// Online state for statistics of the returns of a price series.
+///
+/// Returns are computed from consecutive prices as they are added, so this
+/// needs the prices in time order. Annualized statistics scale the per
+/// period statistics by the number of periods in a year, e.g., `252` for
+/// daily prices of a stock or `12` for monthly prices. Standard deviations
+/// use the sample variance, with `n - 1` in the denominator.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ReturnStats {
+    kind: Returns,
+    periods_per_year: f64,
+    target: f64,
+    last_price: Option<f64>,
+    returns: OnlineStats,
+    downside_sumsq: f64,
+}
+
+impl ReturnStats {
+    /// Create initial state for prices observed `periods_per_year` times a
+    /// year.
+    ///
+    /// The target return for the downside deviation and the Sortino ratio
+    /// is `0`.
+    pub fn new(kind: Returns, periods_per_year: f64) -> ReturnStats {
+        ReturnStats::with_target(kind, periods_per_year, 0.0)
+    }
+
+    /// Create initial state with a minimum acceptable return per period.
+    ///
+    /// Only returns below `target` count towards the downside deviation.
+    pub fn with_target(
+        kind: Returns,
+        periods_per_year: f64,
+        target: f64,
+    ) -> ReturnStats {
+        ReturnStats {
+            kind,
+            periods_per_year,
+            target,
+            last_price: None,
+            returns: OnlineStats::new(),
+            downside_sumsq: 0.0,
+        }
+    }
+
+    /// Add the next price of the series.
+    ///
+    /// The first price only sets the starting point; every later price adds
+    /// the return since the previous one.
+    pub fn add_price<T: ToPrimitive>(&mut self, price: T) {
+        let price = price.to_f64().unwrap();
+        if let Some(last) = self.last_price {
+            let r = match self.kind {
+                Returns::Simple => price / last - 1.0,
+                Returns::Log => (price / last).ln(),
+            };
+            self.add_return(r);
+        }
+        self.last_price = Some(price);
+    }
+
+    /// Add a return that was computed elsewhere.
+    pub fn add_return(&mut self, r: f64) {
+        self.returns.add(r);
+        if r < self.target {
+            self.downside_sumsq += (r - self.target) * (r - self.target);
+        }
+    }
+
+    /// Returns the statistics of the per period returns.
+    pub fn returns(&self) -> &OnlineStats {
+        &self.returns
+    }
+
+    /// Returns the mean return, annualized.
+    pub fn annualized_mean(&self) -> f64 {
+        self.returns.mean() * self.periods_per_year
+    }
+
+    /// Returns the standard deviation of the returns, annualized.
+    pub fn annualized_volatility(&self) -> f64 {
+        self.stddev() * self.periods_per_year.sqrt()
+    }
+
+    /// Returns the downside deviation of the returns per period.
+    ///
+    /// This is the root mean square of the shortfall of every return below
+    /// the target, where returns above the target count as zero.
+    pub fn downside_deviation(&self) -> f64 {
+        let n = self.returns.len() as f64;
+        (self.downside_sumsq / n).sqrt()
+    }
+
+    /// Returns the annualized Sharpe ratio, given the annual risk-free
+    /// rate.
+    pub fn sharpe_ratio(&self, risk_free: f64) -> f64 {
+        let excess = self.returns.mean() - risk_free / self.periods_per_year;
+        excess / self.stddev() * self.periods_per_year.sqrt()
+    }
+
+    /// Returns the annualized Sortino ratio.
+    ///
+    /// This is like the Sharpe ratio, but only penalizes returns below the
+    /// target.
+    pub fn sortino_ratio(&self) -> f64 {
+        (self.returns.mean() - self.target) / self.downside_deviation()
+            * self.periods_per_year.sqrt()
+    }
+
+    /// Returns the number of returns.
+    pub fn len(&self) -> usize {
+        self.returns.len()
+    }
+
+    fn stddev(&self) -> f64 {
+        let n = self.returns.len() as f64;
+        (self.returns.variance() * n / (n - 1.0)).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ReturnStats, Returns};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn simple_returns() {
+        let mut stats = ReturnStats::new(Returns::Simple, 12.0);
+        for &p in &[100.0, 110.0, 99.0, 108.9] {
+            stats.add_price(p);
+        }
+        // The returns are 10%, -10% and 10%.
+        assert_eq!(stats.len(), 3);
+        assert!(close(stats.returns().mean(), 0.1 / 3.0));
+        let sd = (0.04f64 / 3.0).sqrt();
+        assert!(close(stats.annualized_volatility(), sd * 12f64.sqrt()));
+        assert!(close(stats.sharpe_ratio(0.0),
+                      0.1 / 3.0 / sd * 12f64.sqrt()));
+        assert!(close(stats.downside_deviation(), (0.01f64 / 3.0).sqrt()));
+        assert!(close(stats.sortino_ratio(),
+                      0.1 / 3.0 / (0.01f64 / 3.0).sqrt() * 12f64.sqrt()));
+    }
+
+    #[test]
+    fn log_returns() {
+        let mut stats = ReturnStats::new(Returns::Log, 252.0);
+        for &p in &[50.0, 100.0, 50.0] {
+            stats.add_price(p);
+        }
+        assert!(close(stats.returns().mean(), 0.0));
+        assert!(close(stats.annualized_mean(), 0.0));
+
+        let mut target = ReturnStats::with_target(Returns::Log, 252.0, 1.0);
+        target.add_return(0.5);
+        target.add_return(2.0);
+        assert!(close(target.downside_deviation(), (0.125f64).sqrt()));
+    }
+}
@@ -0,0 +1,355 @@
+use std::prelude::v1::*;
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::hash::Hash;
+
+use Commute;
+use hashing::hash_with_seed;
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// What a `FixedFrequencies` table does with a new key when it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum WhenFull {
+    /// Drop samples of keys that are not already in the table.
+    Reject,
+    /// Evict the least frequent key (and its count) to make room.
+    EvictLeastFrequent,
+}
+
+/// A commutative data structure for frequency counts in a fixed amount of
+/// memory.
+///
+/// The table holds at most `capacity` distinct keys in an open addressing
+/// hash table that is allocated once, when it is created. Adding samples
+/// never allocates, which makes this suitable for `no_std` targets and SGX
+/// enclaves with a tight EPC budget. Once the table is full, samples of new
+/// keys are handled according to its `WhenFull` policy; the counts are
+/// exact as long as that never happens.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct FixedFrequencies<T> {
+    slots: Vec<Option<(T, u64)>>,
+    len: usize,
+    capacity: usize,
+    policy: WhenFull,
+    dropped: u64,
+}
+
+impl<T: fmt::Debug> fmt::Debug for FixedFrequencies<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+         .entries(self.slots.iter().filter_map(|s| {
+             s.as_ref().map(|(k, c)| (k, c))
+         }))
+         .finish()
+    }
+}
+
+impl<T: Eq + Hash> FixedFrequencies<T> {
+    /// Create a table that counts at most `capacity` distinct keys.
+    ///
+    /// This panics if `capacity` is `0`.
+    pub fn new(capacity: usize, policy: WhenFull) -> FixedFrequencies<T> {
+        assert!(capacity > 0, "capacity must be positive");
+        // Keep the load factor at or below 3/4 so probe sequences stay short.
+        let size = (capacity + capacity / 3 + 1).next_power_of_two();
+        FixedFrequencies {
+            slots: (0..size).map(|_| None).collect(),
+            len: 0,
+            capacity,
+            policy,
+            dropped: 0,
+        }
+    }
+
+    /// Add a sample to the frequency table.
+    ///
+    /// Returns false if the sample was dropped because the table is full.
+    pub fn add(&mut self, v: T) -> bool {
+        self.add_count(v, 1)
+    }
+
+    /// Return the number of occurrences of `v` in the data.
+    pub fn count(&self, v: &T) -> u64 {
+        match self.slots[self.find(v)] {
+            Some((_, c)) => c,
+            None => 0,
+        }
+    }
+
+    /// Returns the number of samples that were dropped or evicted because
+    /// the table was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns the maximum number of distinct keys in the table.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Return the cardinality (number of unique elements) in the table.
+    pub fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+
+    /// Returns the mode if one exists.
+    pub fn mode(&self) -> Option<&T> {
+        let counts = self.most_frequent();
+        if counts.is_empty()
+                || (counts.len() >= 2 && counts[0].1 == counts[1].1) {
+            None
+        } else {
+            Some(counts[0].0)
+        }
+    }
+
+    /// Return a `Vec` of elements and their corresponding counts in
+    /// descending order.
+    pub fn most_frequent(&self) -> Vec<(&T, u64)> {
+        let mut counts = self.entries();
+        counts.sort_by_key(|&(_, c)| Reverse(c));
+        counts
+    }
+
+    /// Return a `Vec` of elements and their corresponding counts in
+    /// ascending order.
+    pub fn least_frequent(&self) -> Vec<(&T, u64)> {
+        let mut counts = self.entries();
+        counts.sort_by_key(|&(_, c1)| c1);
+        counts
+    }
+
+    /// Returns the cardinality of the data.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn entries(&self) -> Vec<(&T, u64)> {
+        self.slots.iter()
+                  .filter_map(|s| s.as_ref().map(|&(ref k, c)| (k, c)))
+                  .collect()
+    }
+
+    fn add_count(&mut self, v: T, count: u64) -> bool {
+        let mut i = self.find(&v);
+        if let Some((_, ref mut c)) = self.slots[i] {
+            *c += count;
+            return true;
+        }
+        if self.len == self.capacity {
+            match self.policy {
+                WhenFull::Reject => {
+                    self.dropped += count;
+                    return false;
+                }
+                WhenFull::EvictLeastFrequent => {
+                    let (victim, evicted) = self.least_frequent_slot();
+                    self.dropped += evicted;
+                    self.remove_at(victim);
+                    i = self.find(&v);
+                }
+            }
+        }
+        self.slots[i] = Some((v, count));
+        self.len += 1;
+        true
+    }
+
+    fn ideal(&self, v: &T) -> usize {
+        (hash_with_seed(v, 0) as usize) & (self.slots.len() - 1)
+    }
+
+    /// Returns the slot holding `v`, or the empty slot where it belongs.
+    fn find(&self, v: &T) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut i = self.ideal(v);
+        loop {
+            match self.slots[i] {
+                None => return i,
+                Some((ref k, _)) if k == v => return i,
+                Some(_) => i = (i + 1) & mask,
+            }
+        }
+    }
+
+    fn least_frequent_slot(&self) -> (usize, u64) {
+        self.slots.iter()
+                  .enumerate()
+                  .filter_map(|(i, s)| s.as_ref().map(|&(_, c)| (i, c)))
+                  .min_by_key(|&(_, c)| c)
+                  .unwrap()
+    }
+
+    /// Remove the key in slot `i`, shifting back any keys in the same probe
+    /// sequence so that every key stays reachable without tombstones.
+    fn remove_at(&mut self, mut i: usize) {
+        let mask = self.slots.len() - 1;
+        self.slots[i] = None;
+        self.len -= 1;
+        let mut j = i;
+        loop {
+            j = (j + 1) & mask;
+            let ideal = match self.slots[j] {
+                None => return,
+                Some((ref k, _)) => self.ideal(k),
+            };
+            // The key in `j` can stay put if its ideal slot is cyclically
+            // within `(i, j]`.
+            let stays = if i <= j {
+                i < ideal && ideal <= j
+            } else {
+                i < ideal || ideal <= j
+            };
+            if !stays {
+                self.slots[i] = self.slots[j].take();
+                i = j;
+            }
+        }
+    }
+}
+
+impl<T: Eq + Hash> Commute for FixedFrequencies<T> {
+    fn merge(&mut self, v: FixedFrequencies<T>) {
+        self.dropped += v.dropped;
+        for (k, c) in v.slots.into_iter().flatten() {
+            self.add_count(k, c);
+        }
+    }
+}
+
+/// Deserializing rebuilds the table from its keys and their counts, and
+/// checks that the number of slots matches the capacity and that the keys
+/// are distinct and fit in the capacity.
+#[cfg(feature = "serialize")]
+impl<'de, T> Deserialize<'de> for FixedFrequencies<T>
+        where T: Eq + Hash + Deserialize<'de> {
+    fn deserialize<D>(d: D) -> Result<FixedFrequencies<T>, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts<T> {
+            slots: Vec<Option<(T, u64)>>,
+            capacity: usize,
+            policy: WhenFull,
+            dropped: u64,
+        }
+        let parts = Parts::deserialize(d)?;
+        // There are always more slots than the capacity, which bounds the
+        // size of the new table by the size of the input.
+        if parts.capacity == 0 || parts.capacity >= parts.slots.len() {
+            return Err(D::Error::custom("slots do not match the capacity"));
+        }
+        let mut table = FixedFrequencies::new(parts.capacity, parts.policy);
+        if table.slots.len() != parts.slots.len() {
+            return Err(D::Error::custom("slots do not match the capacity"));
+        }
+        table.dropped = parts.dropped;
+        for (k, c) in parts.slots.into_iter().filter_map(|s| s) {
+            if table.len == table.capacity {
+                return Err(D::Error::custom("more keys than the capacity"));
+            }
+            let i = table.find(&k);
+            if table.slots[i].is_some() {
+                return Err(D::Error::custom("keys are not distinct"));
+            }
+            table.slots[i] = Some((k, c));
+            table.len += 1;
+        }
+        Ok(table)
+    }
+}
+
+impl<T: Eq + Hash> Extend<T> for FixedFrequencies<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FixedFrequencies, WhenFull};
+    use Commute;
+
+    #[test]
+    fn exact_below_capacity() {
+        let mut counts = FixedFrequencies::new(8, WhenFull::Reject);
+        counts.extend(vec![1usize, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4]);
+        assert_eq!(counts.most_frequent()[0], (&2, 5));
+        assert_eq!(counts.least_frequent()[0], (&3, 1));
+        assert_eq!(counts.cardinality(), 4);
+        assert_eq!(counts.dropped(), 0);
+    }
+
+    #[test]
+    fn reject_when_full() {
+        let mut counts = FixedFrequencies::new(2, WhenFull::Reject);
+        assert!(counts.add("a"));
+        assert!(counts.add("b"));
+        assert!(!counts.add("c"));
+        assert!(counts.add("a"));
+        assert_eq!(counts.count(&"a"), 2);
+        assert_eq!(counts.count(&"c"), 0);
+        assert_eq!(counts.dropped(), 1);
+    }
+
+    #[test]
+    fn evict_when_full() {
+        let mut counts = FixedFrequencies::new(3, WhenFull::EvictLeastFrequent);
+        for k in 0..20u32 {
+            counts.add(k % 2);
+        }
+        for k in 0..100u32 {
+            assert!(counts.add(1000 + k));
+        }
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts.count(&0), 10);
+        assert_eq!(counts.count(&1), 10);
+        assert_eq!(counts.count(&1099), 1);
+        assert_eq!(counts.count(&1098), 0);
+        assert_eq!(counts.dropped(), 99);
+    }
+
+    #[test]
+    fn merge() {
+        let mut c1 = FixedFrequencies::new(4, WhenFull::Reject);
+        c1.extend(vec!["a", "b", "b"]);
+        let mut c2 = FixedFrequencies::new(4, WhenFull::Reject);
+        c2.extend(vec!["b", "c"]);
+        c1.merge(c2);
+        assert_eq!(c1.mode(), Some(&"b"));
+        assert_eq!(c1.count(&"b"), 3);
+        assert_eq!(c1.cardinality(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_slots() {
+        use serde_json;
+
+        let mut counts = FixedFrequencies::new(2, WhenFull::Reject);
+        counts.extend(vec![1u32, 2, 2, 3]);
+        let json = serde_json::to_string(&counts).unwrap();
+        let back: FixedFrequencies<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.most_frequent(), counts.most_frequent());
+        assert_eq!((back.dropped(), back.capacity()), (1, 2));
+
+        let bad = [
+            r#"{"slots":[null,null,null],"len":0,"capacity":2,
+                "policy":"Reject","dropped":0}"#,
+            r#"{"slots":[[1,1],[2,1]],"len":2,"capacity":1,
+                "policy":"Reject","dropped":0}"#,
+            r#"{"slots":[[1,1],null,[1,2],null],"len":2,"capacity":2,
+                "policy":"Reject","dropped":0}"#,
+        ];
+        for json in &bad {
+            assert!(
+                serde_json::from_str::<FixedFrequencies<u32>>(json).is_err());
+        }
+    }
+}
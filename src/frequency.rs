@@ -1,20 +1,434 @@
 
 use std::prelude::v1::*;
-use std::collections::hash_map::{HashMap, Entry};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::collections::hash_map::{self, HashMap, Entry, RandomState};
+use std::error;
 use std::fmt;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 use std::iter::{FromIterator, IntoIterator};
 use std::default::Default;
+use std::marker::PhantomData;
+use std::mem;
 
-use Commute;
+use num_traits::{FromPrimitive, NumCast, PrimInt, ToPrimitive, Unsigned};
+
+use {Commute, CommuteRef, total_cmp};
+use checkpoint::{Checkpoint, CheckpointError, Kind, seal, unseal};
+use distributions::{beta_quantile, chi_squared_sf};
+use rank::{RankMethod, rank};
+use records::Record;
+use topk::{self, TopK};
+use unsorted::nearest_rank;
+
+/// Compute the exact mode on a stream of hashable data.
+///
+/// This is like `mode`, but it counts values with a hash map instead of
+/// sorting them, so it takes `O(n)` time and works for types without an
+/// ordering, like enums.
+///
+/// If the data does not have a mode, then `None` is returned.
+pub fn mode_hashable<T, I>(it: I) -> Option<T>
+       where T: Eq + Hash, I: Iterator<Item=T> {
+    let mut modes = modes_hashable(it);
+    if modes.len() == 1 { modes.pop() } else { None }
+}
+
+/// Compute the modes on a stream of hashable data.
+///
+/// This is like `modes`, but it counts values with a hash map instead of
+/// sorting them. The modes are returned in the order in which they first
+/// appear in the stream.
+///
+/// If every value is distinct, then an empty `Vec` is returned.
+pub fn modes_hashable<T, I>(it: I) -> Vec<T>
+       where T: Eq + Hash, I: Iterator<Item=T> {
+    let mut counts: HashMap<T, (u64, usize)> = HashMap::new();
+    for (i, v) in it.enumerate() {
+        counts.entry(v).or_insert((0, i)).0 += 1;
+    }
+    let longest = counts.values().map(|&(c, _)| c).max().unwrap_or(0);
+    if longest < 2 {
+        return vec![];
+    }
+    let mut modes: Vec<(usize, T)> = counts.into_iter()
+        .filter(|&(_, (c, _))| c == longest)
+        .map(|(v, (_, first))| (first, v))
+        .collect();
+    modes.sort_by_key(|&(first, _)| first);
+    modes.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Options for `Frequencies::ranked`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RankOptions {
+    /// How values with equal counts are ranked, e.g., `RankMethod::Min` for
+    /// competition ranking (`1, 2, 2, 4`) or `RankMethod::Dense` for dense
+    /// ranking (`1, 2, 2, 3`).
+    pub ties: RankMethod,
+    /// If set, only the entries with a rank of at most this value are
+    /// returned.
+    pub limit: Option<usize>,
+}
+
+impl Default for RankOptions {
+    fn default() -> RankOptions {
+        RankOptions { ties: RankMethod::Min, limit: None }
+    }
+}
+
+/// How `Frequencies::mode_with` breaks ties between values with the
+/// largest count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeTies {
+    /// A tie means that there is no mode, as with `mode`.
+    None,
+    /// The smallest of the tied values is the mode.
+    Smallest,
+    /// The largest of the tied values is the mode.
+    Largest,
+}
+
+/// An entry of `Frequencies::ranked`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ranked<'a, T: 'a> {
+    /// The value that was counted.
+    pub value: &'a T,
+    /// The rank of the value, where the most frequent value has rank `1`.
+    pub rank: f64,
+    /// The number of occurrences of the value.
+    pub count: u64,
+    /// The share of the total count, in `[0, 1]`.
+    pub share: f64,
+}
+
+/// The result of `Frequencies::chi_squared_against` and
+/// `Contingency::chi_squared`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ChiSquaredResult {
+    /// Pearson's chi-squared statistic.
+    pub statistic: f64,
+    /// The degrees of freedom: one less than the number of categories for
+    /// a goodness-of-fit test, and `(rows - 1) * (columns - 1)` for a test
+    /// of independence.
+    pub df: u64,
+    /// The probability of a statistic at least as large if the data
+    /// follows the expected distribution.
+    pub p_value: f64,
+}
+
+/// How `Frequencies::kl_divergence` and `Frequencies::js_distance` treat
+/// values that were only seen in one of the tables.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Use the observed shares, so a value that is missing from one table
+    /// has a probability of `0` there.
+    None,
+    /// Add a pseudo-count to every value seen in either table, e.g., `1`
+    /// for Laplace smoothing. The pseudo-count must be positive.
+    Additive(f64),
+}
+
+/// An iterator over the values of a `Frequencies` table and their shares
+/// of the samples, from `Frequencies::probabilities`.
+pub struct Probabilities<'a, T: 'a, C: 'a = u64> {
+    iter: hash_map::Iter<'a, T, C>,
+    total: f64,
+}
+
+impl<'a, T, C: Count> Iterator for Probabilities<'a, T, C> {
+    type Item = (&'a T, f64);
+
+    fn next(&mut self) -> Option<(&'a T, f64)> {
+        self.iter.next().map(|(v, &c)| (v, widen(c) as f64 / self.total))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Bayesian estimates of the probabilities of the categories of a
+/// `Frequencies` table, from `Frequencies::dirichlet`.
+///
+/// The probabilities have a symmetric `Dirichlet(alpha)` prior over a known
+/// number of categories, so the posterior after the observed counts is
+/// also a Dirichlet distribution. Every category gets `alpha` extra counts,
+/// which keeps small samples from assigning a probability of `0` or `1`.
+pub struct Dirichlet<'a, T: 'a, C: 'a = u64, S: 'a = RandomState> {
+    freqs: &'a Frequencies<T, C, S>,
+    alpha: f64,
+    // The total count of the posterior, `total + categories * alpha`.
+    total: f64,
+}
+
+impl<'a, T, C, S> Dirichlet<'a, T, C, S>
+        where T: Eq + Hash, C: Count, S: BuildHasher {
+    /// Returns the posterior mean of the probability of `v`, which is
+    /// `(count + alpha) / (total + categories * alpha)`.
+    ///
+    /// Values that were not seen get the share of the prior alone.
+    pub fn probability(&self, v: &T) -> f64 {
+        let (a, b) = self.marginal(v);
+        a / (a + b)
+    }
+
+    /// Returns the equal-tailed credible interval of the probability of
+    /// `v`.
+    ///
+    /// `level` is the posterior probability of the interval, e.g., `0.95`.
+    /// This panics if `level` is not in `(0, 1)`.
+    pub fn credible_interval(&self, v: &T, level: f64) -> (f64, f64) {
+        assert!(level > 0.0 && level < 1.0, "level must be in (0, 1)");
+        let (a, b) = self.marginal(v);
+        let tail = (1.0 - level) / 2.0;
+        (beta_quantile(tail, a, b), beta_quantile(1.0 - tail, a, b))
+    }
+
+    /// Returns the posterior mean probabilities of the values that were
+    /// seen, in arbitrary order.
+    pub fn probabilities(&self) -> Vec<(&'a T, f64)> {
+        self.freqs.data.keys().map(|v| (v, self.probability(v))).collect()
+    }
+
+    /// Returns the parameters of the beta distribution of the probability
+    /// of `v`, the marginal of the Dirichlet posterior.
+    fn marginal(&self, v: &T) -> (f64, f64) {
+        let a = self.freqs.count(v) as f64 + self.alpha;
+        (a, self.total - a)
+    }
+}
+
+/// An iterator over the values of a `Frequencies` table and their counts
+/// in order of count, from `Frequencies::most_frequent_iter` or
+/// `Frequencies::least_frequent_iter`.
+///
+/// The entries are kept in a binary heap, so creating the iterator takes
+/// `O(n)` time and each entry takes `O(log n)` time, and taking only the
+/// first few entries never sorts the whole table.
+pub struct ByFrequency<'a, T: 'a> {
+    heap: BinaryHeap<Counted<'a, T>>,
+}
+
+impl<'a, T> Iterator for ByFrequency<'a, T> {
+    type Item = (&'a T, u64);
+
+    fn next(&mut self) -> Option<(&'a T, u64)> {
+        self.heap.pop().map(|e| (e.value, e.count))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+/// An entry of a heap of counts, ordered by `key` alone.
+///
+/// The key is the count for a max-heap of counts, or its bitwise
+/// complement for a min-heap.
+struct Counted<'a, T: 'a> {
+    key: u64,
+    count: u64,
+    value: &'a T,
+}
+
+impl<'a, T> Counted<'a, T> {
+    fn new(value: &'a T, count: u64, largest_first: bool) -> Counted<'a, T> {
+        let key = if largest_first { count } else { !count };
+        Counted { key, count, value }
+    }
+}
+
+impl<'a, T> PartialEq for Counted<'a, T> {
+    fn eq(&self, other: &Counted<'a, T>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<'a, T> Eq for Counted<'a, T> {}
+
+impl<'a, T> PartialOrd for Counted<'a, T> {
+    fn partial_cmp(&self, other: &Counted<'a, T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T> Ord for Counted<'a, T> {
+    fn cmp(&self, other: &Counted<'a, T>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// The error returned by `Frequencies::from_parts` for a zero count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZeroCountError {
+    /// The 0-based position of the offending pair in the input.
+    pub index: usize,
+}
+
+impl fmt::Display for ZeroCountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "zero count at position {}", self.index)
+    }
+}
+
+impl error::Error for ZeroCountError {
+    fn description(&self) -> &str {
+        "zero count"
+    }
+}
+
+/// Simple Good–Turing estimates of probabilities, from
+/// `Frequencies::good_turing`.
+///
+/// Good–Turing estimation moves some probability mass from the values that
+/// were seen to the values that were not, in proportion to the number of
+/// values that were seen once. The count of counts is smoothed with a
+/// log-linear fit, as in Gale and Sampson's "Good–Turing Frequency
+/// Estimation Without Tears", so rare counts get reliable estimates.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct GoodTuring {
+    unseen: f64,
+    probabilities: Vec<(u64, f64)>,
+}
+
+impl GoodTuring {
+    /// Returns the total probability of all values that were not seen.
+    pub fn unseen(&self) -> f64 {
+        self.unseen
+    }
+
+    /// Returns the probability of a single value that was seen `count`
+    /// times.
+    ///
+    /// If `count` is `0`, this is the total probability of all unseen
+    /// values, since their number is unknown. `None` is returned if no
+    /// value was seen exactly `count` times.
+    pub fn probability(&self, count: u64) -> Option<f64> {
+        if count == 0 {
+            return Some(self.unseen);
+        }
+        self.probabilities
+            .binary_search_by_key(&count, |&(r, _)| r)
+            .ok()
+            .map(|i| self.probabilities[i].1)
+    }
+
+    fn from_count_of_counts(counts: &[(u64, u64)]) -> GoodTuring {
+        let total: f64 = counts.iter().map(|&(r, n)| (r * n) as f64).sum();
+        let unseen = match counts.first() {
+            Some(&(1, n1)) => n1 as f64 / total,
+            _ => 0.0,
+        };
+
+        // Average the counts over the gaps between the observed counts,
+        // then fit `log(z) = a + b * log(r)`.
+        let mut points = Vec::with_capacity(counts.len());
+        for (i, &(r, n)) in counts.iter().enumerate() {
+            let q = if i == 0 { 0 } else { counts[i - 1].0 };
+            let t = match counts.get(i + 1) {
+                Some(&(t, _)) => t,
+                None => 2 * r - q,
+            };
+            let z = n as f64 / (0.5 * (t - q) as f64);
+            points.push(((r as f64).ln(), z.ln()));
+        }
+        let slope = fit_slope(&points);
+
+        let mut turing = true;
+        let mut adjusted = Vec::with_capacity(counts.len());
+        for (i, &(r, n)) in counts.iter().enumerate() {
+            let rf = r as f64;
+            let smoothed = match slope {
+                Some(b) => (rf + 1.0) * (1.0 + 1.0 / rf).powf(b),
+                None => rf,
+            };
+            let next = counts.get(i + 1).filter(|&&(r1, _)| r1 == r + 1);
+            let estimate = match next {
+                Some(&(_, n1)) if turing => {
+                    let (n, n1) = (n as f64, n1 as f64);
+                    let x = (rf + 1.0) * n1 / n;
+                    let sd = (rf + 1.0) * (n1 / (n * n) * (1.0 + n1 / n))
+                        .sqrt();
+                    if (x - smoothed).abs() > 1.96 * sd {
+                        x
+                    } else {
+                        turing = false;
+                        smoothed
+                    }
+                }
+                _ => {
+                    turing = false;
+                    smoothed
+                }
+            };
+            adjusted.push((r, n, estimate));
+        }
+
+        let norm: f64 = adjusted.iter().map(|&(_, n, e)| n as f64 * e).sum();
+        let probabilities = adjusted.into_iter()
+            .map(|(r, _, e)| (r, (1.0 - unseen) * e / norm))
+            .collect();
+        GoodTuring { unseen, probabilities }
+    }
+}
+
+/// Returns the slope of the least squares line through `points`, or `None`
+/// if it is undefined.
+fn fit_slope(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mx = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let my = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mx) * (p.0 - mx)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum();
+    if sxx > 0.0 { Some(sxy / sxx) } else { None }
+}
+
+/// The type of the counts of a `Frequencies` table: an unsigned integer of
+/// at most 64 bits, such as `u32`, `u64` or `usize`.
+///
+/// Counts are always reported as `u64`, so a smaller type only changes how
+/// much memory the table takes. Adding to a count beyond the maximum of its
+/// type panics.
+pub trait Count: PrimInt + Unsigned + fmt::Debug {}
+
+impl<C: PrimInt + Unsigned + fmt::Debug> Count for C {}
+
+pub fn widen<C: Count>(count: C) -> u64 {
+    count.to_u64().expect("count does not fit in u64")
+}
+
+fn narrow<C: Count>(count: u64) -> C {
+    NumCast::from(count).expect("count overflows the count type")
+}
+
+fn add_counts<C: Count>(c1: C, c2: C) -> C {
+    c1.checked_add(&c2).expect("count overflows the count type")
+}
 
 /// A commutative data structure for exact frequency counts.
+///
+/// By default, counts are `u64` and values are hashed with the standard
+/// library's `RandomState`. A smaller count type, e.g., `u32`, saves memory
+/// for tables with many distinct values, and a faster hasher can be given
+/// with `with_hasher`, as for `HashMap`.
 #[derive(Clone)]
-pub struct Frequencies<T> {
-    pub data: HashMap<T, u64>,
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize, C: ::serde::Serialize, \
+                 S: ::std::hash::BuildHasher",
+    deserialize = "T: Eq + Hash + ::serde::Deserialize<'de>, \
+                   C: ::serde::Deserialize<'de>, \
+                   S: ::std::hash::BuildHasher + Default"
+)))]
+pub struct Frequencies<T, C = u64, S = RandomState> {
+    pub data: HashMap<T, C, S>,
 }
 
-impl<T: fmt::Debug + Eq + Hash> fmt::Debug for Frequencies<T> {
+impl<T, C, S> fmt::Debug for Frequencies<T, C, S>
+        where T: fmt::Debug + Eq + Hash, C: Count, S: BuildHasher {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self.data)
     }
@@ -22,21 +436,103 @@ impl<T: fmt::Debug + Eq + Hash> fmt::Debug for Frequencies<T> {
 
 impl<T: Eq + Hash> Frequencies<T> {
     /// Create a new frequency table with no samples.
+    ///
+    /// Like `HashMap::new`, this uses the default count type and hasher, so
+    /// that they need not be spelled out. Use `Default::default` or
+    /// `with_hasher` for others.
     pub fn new() -> Frequencies<T> {
         Default::default()
     }
 
+    /// Create a frequency table from `(value, count)` pairs, e.g., counts
+    /// stored by another system.
+    ///
+    /// Counts for repeated values are added up. A count of zero is an error,
+    /// since the table never holds values that were not seen.
+    pub fn from_parts<I>(it: I) -> Result<Frequencies<T>, ZeroCountError>
+            where I: IntoIterator<Item=(T, u64)> {
+        Frequencies::from_parts_with_hasher(it, RandomState::new())
+    }
+}
+
+impl<T: Eq + Hash, C: Count, S: BuildHasher> Frequencies<T, C, S> {
+    /// Create a new frequency table with no samples that uses `hasher` to
+    /// hash values.
+    pub fn with_hasher(hasher: S) -> Frequencies<T, C, S> {
+        Frequencies { data: HashMap::with_hasher(hasher) }
+    }
+
+    /// Create a frequency table from `(value, count)` pairs like
+    /// `from_parts`, using `hasher` to hash values.
+    ///
+    /// This panics if a count overflows the count type.
+    pub fn from_parts_with_hasher<I>(
+        it: I,
+        hasher: S,
+    ) -> Result<Frequencies<T, C, S>, ZeroCountError>
+            where I: IntoIterator<Item=(T, u64)> {
+        let mut freqs = Frequencies::with_hasher(hasher);
+        for (index, (v, count)) in it.into_iter().enumerate() {
+            if count == 0 {
+                return Err(ZeroCountError { index });
+            }
+            freqs.add_count(v, count);
+        }
+        Ok(freqs)
+    }
+
     /// Add a sample to the frequency table.
     pub fn add(&mut self, v: T) {
+        self.add_count(v, 1);
+    }
+
+    /// Add `n` samples of `v`, e.g., from data that was already counted.
+    ///
+    /// Adding `0` samples does nothing. This panics if the count of `v`
+    /// would overflow the count type.
+    pub fn add_count(&mut self, v: T, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let n = narrow(n);
         match self.data.entry(v) {
-            Entry::Vacant(count) => { count.insert(1); },
-            Entry::Occupied(mut count) => { *count.get_mut() += 1; },
+            Entry::Vacant(count) => { count.insert(n); },
+            Entry::Occupied(mut count) => {
+                let count = count.get_mut();
+                *count = add_counts(*count, n);
+            },
+        }
+    }
+
+    /// Remove a sample of `v`, and return whether there was one.
+    pub fn remove(&mut self, v: &T) -> bool {
+        self.sub_count(v, 1) == 1
+    }
+
+    /// Remove up to `n` samples of `v`, and return the number removed.
+    ///
+    /// A value whose count drops to `0` is removed from the table, so it no
+    /// longer counts towards `cardinality`. This makes it possible to track
+    /// the frequencies of a sliding window by removing the samples that
+    /// leave it.
+    pub fn sub_count(&mut self, v: &T, n: u64) -> u64 {
+        let (removed, empty) = match self.data.get_mut(v) {
+            None => return 0,
+            Some(count) => {
+                let removed = n.min(widen(*count));
+                *count = *count - narrow(removed);
+                (removed, count.is_zero())
+            }
+        };
+        if empty {
+            self.data.remove(v);
         }
+        removed
     }
 
     /// Return the number of occurrences of `v` in the data.
     pub fn count(&self, v: &T) -> u64 {
-        self.data.get(v).map(|&v| v).unwrap_or(0)
+        self.data.get(v).map(|&v| widen(v)).unwrap_or(0)
     }
 
     /// Return the cardinality (number of unique elements) in the data.
@@ -60,7 +556,7 @@ impl<T: Eq + Hash> Frequencies<T> {
     /// descending order.
     pub fn most_frequent(&self) -> Vec<(&T, u64)> {
         let mut counts: Vec<_> = self.data.iter()
-                                          .map(|(k, &v)| (k, v))
+                                          .map(|(k, &v)| (k, widen(v)))
                                           .collect();
         counts.sort_by(|&(_, c1), &(_, c2)| c2.cmp(&c1));
         counts
@@ -70,44 +566,852 @@ impl<T: Eq + Hash> Frequencies<T> {
     /// ascending order.
     pub fn least_frequent(&self) -> Vec<(&T, u64)> {
         let mut counts: Vec<_> = self.data.iter()
-                                          .map(|(k, &v)| (k, v))
+                                          .map(|(k, &v)| (k, widen(v)))
                                           .collect();
         counts.sort_by(|&(_, c1), &(_, c2)| c1.cmp(&c2));
         counts
     }
 
+    /// Returns the `k` most frequent elements and their counts in
+    /// descending order of count.
+    ///
+    /// This is like `most_frequent` truncated to `k` entries, but it keeps
+    /// a heap of at most `k` entries instead of sorting the whole table, so
+    /// it takes `O(n log k)` time.
+    pub fn most_frequent_k(&self, k: usize) -> Vec<(&T, u64)> {
+        self.select_k(k, true)
+    }
+
+    /// Returns the `k` least frequent elements and their counts in
+    /// ascending order of count.
+    ///
+    /// Like `most_frequent_k`, this takes `O(n log k)` time.
+    pub fn least_frequent_k(&self, k: usize) -> Vec<(&T, u64)> {
+        self.select_k(k, false)
+    }
+
+    /// Returns an iterator over the elements and their counts in
+    /// descending order of count.
+    ///
+    /// Unlike `most_frequent`, the entries are not sorted up front, so
+    /// taking the first few is cheap even for huge tables.
+    pub fn most_frequent_iter<'a>(&'a self) -> ByFrequency<'a, T> {
+        self.by_frequency(true)
+    }
+
+    /// Returns an iterator over the elements and their counts in
+    /// ascending order of count.
+    pub fn least_frequent_iter<'a>(&'a self) -> ByFrequency<'a, T> {
+        self.by_frequency(false)
+    }
+
+    fn by_frequency<'a>(&'a self, largest_first: bool) -> ByFrequency<'a, T> {
+        let entries: Vec<Counted<'a, T>> = self.data
+            .iter()
+            .map(|(v, &c)| Counted::new(v, widen(c), largest_first))
+            .collect();
+        ByFrequency { heap: BinaryHeap::from(entries) }
+    }
+
+    fn select_k(&self, k: usize, largest: bool) -> Vec<(&T, u64)> {
+        if k == 0 {
+            return vec![];
+        }
+        // The heap is ordered the opposite way, so that its top is the
+        // worst of the selected entries, which is evicted first.
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (v, &c) in &self.data {
+            heap.push(Counted::new(v, widen(c), !largest));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|e| (e.value, e.count))
+            .collect()
+    }
+
     /// Returns the cardinality of the data.
     pub fn len(&self) -> usize {
         self.data.len()
     }
+
+    /// Returns the total number of samples.
+    ///
+    /// This sums the counts, so it takes `O(n)` time for `n` distinct
+    /// values.
+    pub fn total(&self) -> u64 {
+        self.data.values().map(|&c| widen(c)).sum()
+    }
+
+    /// Returns the values ranked by count, most frequent first.
+    ///
+    /// Unlike `most_frequent`, ties are handled explicitly according to
+    /// `options`, and every entry includes its share of the total count.
+    /// With `RankMethod::Ordinal`, tied values are ranked in an arbitrary
+    /// order.
+    pub fn ranked<'a>(&'a self, options: RankOptions) -> Vec<Ranked<'a, T>> {
+        let counts = self.most_frequent();
+        let keys: Vec<Reverse<u64>> =
+            counts.iter().map(|&(_, c)| Reverse(c)).collect();
+        let total = self.total() as f64;
+        let mut ranked: Vec<Ranked<'a, T>> = counts.iter()
+            .zip(rank(&keys, options.ties))
+            .map(|(&(v, c), r)| Ranked {
+                value: v,
+                rank: r,
+                count: c,
+                share: c as f64 / total,
+            })
+            .filter(|r| options.limit.map_or(true, |n| r.rank <= n as f64))
+            .collect();
+        ranked.sort_by(|a, b| total_cmp(&a.rank, &b.rank));
+        ranked
+    }
+
+    /// Returns a summary of the most common values for reporting.
+    ///
+    /// Every value whose share of the total count is greater than
+    /// `min_share` (e.g., `0.05` for 5%) is listed with its count and share,
+    /// in descending order of count. All other values are aggregated into a
+    /// final entry with a value of `None`, which is omitted if there are no
+    /// such values.
+    pub fn report(&self, min_share: f64) -> Vec<(Option<&T>, u64, f64)> {
+        let total = self.total() as f64;
+        let (mut other, mut report) = (0, vec![]);
+        for (v, count) in self.most_frequent() {
+            let share = count as f64 / total;
+            if share > min_share {
+                report.push((Some(v), count, share));
+            } else {
+                other += count;
+            }
+        }
+        if other > 0 {
+            report.push((None, other, other as f64 / total));
+        }
+        report
+    }
+
+    /// Returns the share of the samples that are equal to `v`, in `[0, 1]`.
+    ///
+    /// Values that were not seen, including every value when there is no
+    /// data, have a probability of `0`.
+    ///
+    /// The total is not cached, since `data` can be changed directly, so
+    /// this takes `O(n)` time for `n` distinct values. Use
+    /// `probabilities_of` or `probabilities` for many values.
+    pub fn probability(&self, v: &T) -> f64 {
+        let count = self.count(v);
+        if count == 0 { 0.0 } else { count as f64 / self.total() as f64 }
+    }
+
+    /// Returns the shares of the samples that are equal to each of
+    /// `values`, like `probability`, but summing the counts only once.
+    pub fn probabilities_of<'b, I>(&self, values: I) -> Vec<f64>
+            where I: IntoIterator<Item=&'b T>, T: 'b {
+        let total = self.total() as f64;
+        values
+            .into_iter()
+            .map(|v| {
+                let count = self.count(v);
+                if count == 0 { 0.0 } else { count as f64 / total }
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over the values and their shares of the samples,
+    /// i.e., the empirical distribution of the data, in arbitrary order.
+    pub fn probabilities<'a>(&'a self) -> Probabilities<'a, T, C> {
+        Probabilities { iter: self.data.iter(), total: self.total() as f64 }
+    }
+
+    /// Returns the Shannon entropy of the empirical distribution, in bits.
+    ///
+    /// If there is no data, the entropy is `0`.
+    pub fn entropy(&self) -> f64 {
+        self.entropy_nats() / ::std::f64::consts::LN_2
+    }
+
+    /// Returns the Shannon entropy of the empirical distribution, in nats.
+    ///
+    /// If there is no data, the entropy is `0`.
+    pub fn entropy_nats(&self) -> f64 {
+        -self.probabilities()
+             .map(|(_, p)| if p > 0.0 { p * p.ln() } else { 0.0 })
+             .sum::<f64>()
+    }
+
+    /// Pearson's chi-squared goodness-of-fit test of the data against an
+    /// expected distribution.
+    ///
+    /// `expected` maps every category to its expected weight, which may be
+    /// a probability or an expected count: the weights are normalized to
+    /// the total count of the data. Categories that were expected but not
+    /// seen count as zero.
+    ///
+    /// `None` is returned if there is no data, if there are fewer than two
+    /// categories, if any weight is not positive, or if a value was seen
+    /// that has no expected weight.
+    pub fn chi_squared_against<H: BuildHasher>(
+        &self,
+        expected: &HashMap<T, f64, H>,
+    ) -> Option<ChiSquaredResult> {
+        let total = self.total() as f64;
+        let weight: f64 = expected.values().sum();
+        if total == 0.0 || expected.len() < 2
+                || expected.values().any(|&w| w.is_nan() || w <= 0.0)
+                || self.data.keys().any(|v| !expected.contains_key(v)) {
+            return None;
+        }
+        let statistic = expected.iter().map(|(v, &w)| {
+            let e = total * w / weight;
+            let d = self.count(v) as f64 - e;
+            d * d / e
+        }).sum();
+        let df = expected.len() as u64 - 1;
+        Some(ChiSquaredResult {
+            statistic,
+            df,
+            p_value: chi_squared_sf(statistic, df as f64),
+        })
+    }
+
+    /// Returns the Kullback–Leibler divergence of the distribution of
+    /// `other` from the distribution of the data, in bits.
+    ///
+    /// This is the expected number of extra bits needed to encode samples
+    /// of this table with a code optimized for `other`. It is not
+    /// symmetric, and without smoothing it is infinite if a value of this
+    /// table was not seen in `other`. `None` is returned if either table
+    /// is empty.
+    pub fn kl_divergence(
+        &self,
+        other: &Frequencies<T, C, S>,
+        smoothing: Smoothing,
+    ) -> Option<f64> {
+        self.joint_probabilities(other, smoothing).map(|probs| {
+            probs.iter()
+                 .filter(|&&(p, _)| p > 0.0)
+                 .map(|&(p, q)| p * (p / q).log2())
+                 .sum()
+        })
+    }
+
+    /// Returns the Jensen–Shannon distance between the distributions of
+    /// the data and `other`.
+    ///
+    /// This is the square root of the Jensen–Shannon divergence in bits,
+    /// the mean KL divergence of both distributions from their average. It
+    /// is symmetric, finite even without smoothing, and in `[0, 1]`. `None`
+    /// is returned if either table is empty.
+    pub fn js_distance(
+        &self,
+        other: &Frequencies<T, C, S>,
+        smoothing: Smoothing,
+    ) -> Option<f64> {
+        let term = |p: f64, m: f64| {
+            if p > 0.0 { p * (p / m).log2() } else { 0.0 }
+        };
+        self.joint_probabilities(other, smoothing).map(|probs| {
+            let js: f64 = probs.iter().map(|&(p, q)| {
+                let m = (p + q) / 2.0;
+                (term(p, m) + term(q, m)) / 2.0
+            }).sum();
+            // Rounding can make the divergence of equal tables negative.
+            js.max(0.0).sqrt()
+        })
+    }
+
+    /// Returns the probabilities of every value seen in either table, in
+    /// this table and in `other`.
+    fn joint_probabilities(
+        &self,
+        other: &Frequencies<T, C, S>,
+        smoothing: Smoothing,
+    ) -> Option<Vec<(f64, f64)>> {
+        let alpha = match smoothing {
+            Smoothing::None => 0.0,
+            Smoothing::Additive(alpha) => {
+                assert!(alpha > 0.0, "pseudo-count must be positive");
+                alpha
+            }
+        };
+        let (n1, n2) = (self.total() as f64, other.total() as f64);
+        if n1 == 0.0 || n2 == 0.0 {
+            return None;
+        }
+        let keys: Vec<&T> = self.data.keys()
+            .chain(other.data.keys().filter(|k| !self.data.contains_key(k)))
+            .collect();
+        let k = keys.len() as f64;
+        Some(keys.into_iter().map(|v| {
+            ((self.count(v) as f64 + alpha) / (n1 + alpha * k),
+             (other.count(v) as f64 + alpha) / (n2 + alpha * k))
+        }).collect())
+    }
+
+    /// Returns Bayesian estimates of the probabilities of `categories`
+    /// categories with a symmetric `Dirichlet(alpha)` prior, e.g., `1` for
+    /// a uniform prior or `0.5` for Jeffreys' prior.
+    ///
+    /// `categories` counts the values that could occur, including those
+    /// that were not seen; if every category was seen, it is
+    /// `cardinality()`. This panics if `alpha` is not positive or if fewer
+    /// categories than seen values are given.
+    pub fn dirichlet<'a>(
+        &'a self,
+        alpha: f64,
+        categories: u64,
+    ) -> Dirichlet<'a, T, C, S> {
+        assert!(alpha > 0.0, "alpha must be positive");
+        assert!(categories > 0 && categories >= self.cardinality(),
+                "categories must include every seen value");
+        let total = self.total() as f64 + categories as f64 * alpha;
+        Dirichlet { freqs: self, alpha, total }
+    }
+
+    /// Returns the frequencies of the counts: for every count `k` that
+    /// occurs, the number of values that occurred exactly `k` times.
+    ///
+    /// The pairs `(k, n_k)` are in ascending order of `k`. This is the
+    /// input to Good–Turing estimation, and describes how popularity is
+    /// distributed among the values, e.g., how many values are singletons.
+    pub fn count_of_counts(&self) -> Vec<(u64, u64)> {
+        let mut counts: BTreeMap<u64, u64> = BTreeMap::new();
+        for &c in self.data.values() {
+            *counts.entry(widen(c)).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+
+    /// Returns simple Good–Turing estimates of the probabilities of seen
+    /// and unseen values.
+    ///
+    /// Unlike the observed shares, these reserve probability for values
+    /// that were never seen, which is the share of values seen once, and
+    /// discount the probabilities of rare values. `None` is returned if
+    /// there is no data.
+    pub fn good_turing(&self) -> Option<GoodTuring> {
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(GoodTuring::from_count_of_counts(&self.count_of_counts()))
+    }
 }
 
-impl<T: Eq + Hash> Commute for Frequencies<T> {
-    fn merge(&mut self, v: Frequencies<T>) {
+impl<T, C, S> Frequencies<T, C, S>
+        where T: Eq + Hash + Ord, C: Count, S: BuildHasher {
+    /// Returns the mode, breaking ties between values with the largest
+    /// count according to `ties`.
+    ///
+    /// `None` is returned if there is no data, or if there is a tie and
+    /// `ties` is `ModeTies::None`.
+    pub fn mode_with(&self, ties: ModeTies) -> Option<&T> {
+        let max = match self.data.values().max() {
+            None => return None,
+            Some(&max) => max,
+        };
+        let modes = self.data.iter().filter(|&(_, &c)| c == max)
+                                    .map(|(v, _)| v);
+        match ties {
+            ModeTies::None => self.mode(),
+            ModeTies::Smallest => modes.min(),
+            ModeTies::Largest => modes.max(),
+        }
+    }
+
+    /// Returns the median of the data.
+    ///
+    /// This is the low median, `quantile(0.5)`, so it is always one of the
+    /// counted values and needs no arithmetic on them. For numbers,
+    /// `weighted_median` interpolates between the two middle values
+    /// instead.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn median(&self) -> Option<&T> {
+        self.quantile(0.5)
+    }
+
+    /// Returns the `q`th quantile of the data, e.g., `0.99` for the 99th
+    /// percentile.
+    ///
+    /// This is the smallest value `v` such that at least a fraction `q` of
+    /// the samples are less than or equal to `v`, found by accumulating the
+    /// counts in ascending order of value, so it only sorts the distinct
+    /// values. Like `Unsorted::quantile_function`, no interpolation is done.
+    ///
+    /// `None` is returned if there is no data or if `q` is not in `[0, 1]`.
+    pub fn quantile(&self, q: f64) -> Option<&T> {
+        let total = self.total();
+        if total == 0 || !(q >= 0.0 && q <= 1.0) {
+            return None;
+        }
+        let rank = nearest_rank(q, total);
+        let mut entries: Vec<(&T, u64)> =
+            self.data.iter().map(|(v, &c)| (v, widen(c))).collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        let mut seen = 0;
+        for (v, c) in entries {
+            seen += c;
+            if seen >= rank {
+                return Some(v);
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl<T, C, S> Frequencies<T, C, S>
+        where T: Eq + Hash + ToPrimitive, C: Count, S: BuildHasher {
+    /// Returns the mean of the data, treating the counts as weights.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn weighted_mean(&self) -> Option<f64> {
+        let total = self.total_weight();
+        if total == 0.0 {
+            return None;
+        }
+        let sum: f64 = self.data.iter().map(|(k, &c)| {
+            k.to_f64().unwrap() * widen(c) as f64
+        }).sum();
+        Some(sum / total)
+    }
+
+    /// Returns the population variance of the data, treating the counts as
+    /// weights.
+    ///
+    /// This is the same variance that `OnlineStats` computes when every
+    /// value is added as many times as it was counted. `None` is returned
+    /// if there is no data.
+    pub fn weighted_variance(&self) -> Option<f64> {
+        let mean = self.weighted_mean()?;
+        let sumsq: f64 = self.data.iter().map(|(k, &c)| {
+            let d = k.to_f64().unwrap() - mean;
+            d * d * widen(c) as f64
+        }).sum();
+        Some(sumsq / self.total_weight())
+    }
+
+    /// Returns the median of the data, treating the counts as weights.
+    ///
+    /// This is the same median that `Unsorted` computes when every value is
+    /// added as many times as it was counted: when the total count is even,
+    /// the two middle values are averaged. `None` is returned if there is
+    /// no data.
+    pub fn weighted_median(&self) -> Option<f64> {
+        let mut entries: Vec<(f64, u64)> = self.data.iter()
+            .filter(|&(_, &c)| !c.is_zero())
+            .map(|(k, &c)| (k.to_f64().unwrap(), widen(c)))
+            .collect();
+        entries.sort_unstable_by(|a, b| total_cmp(&a.0, &b.0));
+        let total: u64 = entries.iter().map(|&(_, c)| c).sum();
+        if total == 0 {
+            return None;
+        }
+        let hi = value_at_rank(&entries, total / 2);
+        if total % 2 == 1 {
+            Some(hi)
+        } else {
+            Some((value_at_rank(&entries, total / 2 - 1) + hi) / 2.0)
+        }
+    }
+
+    fn total_weight(&self) -> f64 {
+        self.data.values().map(|&c| widen(c) as f64).sum()
+    }
+}
+
+/// Returns the value at the 0-based `rank` of sorted `(value, count)` pairs.
+fn value_at_rank(entries: &[(f64, u64)], rank: u64) -> f64 {
+    let mut seen = 0;
+    for &(v, c) in entries {
+        seen += c;
+        if rank < seen {
+            return v;
+        }
+    }
+    unreachable!()
+}
+
+impl<T, C, S> Commute for Frequencies<T, C, S>
+        where T: Eq + Hash, C: Count, S: BuildHasher {
+    fn merge(&mut self, v: Frequencies<T, C, S>) {
         for (k, v2) in v.data.into_iter() {
             match self.data.entry(k) {
                 Entry::Vacant(v1) => { v1.insert(v2); }
-                Entry::Occupied(mut v1) => { *v1.get_mut() += v2; }
+                Entry::Occupied(mut v1) => {
+                    let v1 = v1.get_mut();
+                    *v1 = add_counts(*v1, v2);
+                }
+            }
+        }
+    }
+}
+
+impl<T, C, S> CommuteRef for Frequencies<T, C, S>
+        where T: Eq + Hash + Clone, C: Count, S: BuildHasher {
+    fn merge_ref(&mut self, v: &Frequencies<T, C, S>) {
+        for (k, &v2) in v.data.iter() {
+            let v1 = self.data.entry(k.clone()).or_insert(C::zero());
+            *v1 = add_counts(*v1, v2);
+        }
+    }
+}
+
+/// Counts are written as `u64` whatever the count type, so a checkpoint can
+/// be restored into a table with another count type, as long as its counts
+/// fit.
+impl<T, C, S> Checkpoint for Frequencies<T, C, S>
+        where T: Eq + Hash + Record, C: Count, S: BuildHasher + Default {
+    fn to_checkpoint(&self) -> Vec<u8> {
+        // Hash map iteration order is arbitrary, so sort the encoded entries
+        // to make equal tables produce equal checkpoints.
+        let mut entries: Vec<Vec<u8>> = self.data.iter().map(|(k, &v)| {
+            let mut entry = vec![];
+            k.write_le(&mut entry);
+            widen(v).write_le(&mut entry);
+            entry
+        }).collect();
+        entries.sort();
+
+        let mut out = vec![];
+        (entries.len() as u64).write_le(&mut out);
+        for entry in entries {
+            out.extend_from_slice(&entry);
+        }
+        seal(Kind::Frequencies, out)
+    }
+
+    fn from_checkpoint(
+        bytes: &[u8],
+    ) -> Result<Frequencies<T, C, S>, CheckpointError> {
+        let mut r = unseal(Kind::Frequencies, bytes)?;
+        let mut freqs = Frequencies::default();
+        for _ in 0..r.read::<u64>()? {
+            let (k, v) = (r.read()?, r.read::<u64>()?);
+            let v = match NumCast::from(v) {
+                None => return Err(CheckpointError::Malformed),
+                Some(v) => v,
+            };
+            if freqs.data.insert(k, v).is_some() {
+                return Err(CheckpointError::Malformed);
             }
         }
+        r.finish()?;
+        Ok(freqs)
     }
 }
 
-impl<T: Eq + Hash> Default for Frequencies<T> {
-    fn default() -> Frequencies<T> {
-        Frequencies { data: HashMap::new() }
+impl<T, C, S> Default for Frequencies<T, C, S>
+        where T: Eq + Hash, C: Count, S: BuildHasher + Default {
+    fn default() -> Frequencies<T, C, S> {
+        Frequencies::with_hasher(S::default())
     }
 }
 
-impl<T: Eq + Hash> FromIterator<T> for Frequencies<T> {
-    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Frequencies<T> {
-        let mut v = Frequencies::new();
+impl<T, C, S> FromIterator<T> for Frequencies<T, C, S>
+        where T: Eq + Hash, C: Count, S: BuildHasher + Default {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Frequencies<T, C, S> {
+        let mut v = Frequencies::default();
         v.extend(it);
         v
     }
 }
 
-impl<T: Eq + Hash> Extend<T> for Frequencies<T> {
+impl<T, C, S> Extend<T> for Frequencies<T, C, S>
+        where T: Eq + Hash, C: Count, S: BuildHasher {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// A commutative data structure for frequency counts in bounded memory.
+///
+/// Values are counted exactly, as in `Frequencies`, until there are more
+/// than `limit` distinct values. Then the table is replaced by a `TopK`
+/// summary of the `limit` most frequent values, so memory stays bounded,
+/// and counts become estimates. `is_exact` reports which is the case.
+///
+/// Merging two approximate tables with different limits panics.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize",
+    deserialize = "T: Eq + Hash + Clone + ::serde::Deserialize<'de>"
+)))]
+pub struct BoundedFrequencies<T> {
+    limit: usize,
+    counts: Counts<T>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize",
+    deserialize = "T: Eq + Hash + Clone + ::serde::Deserialize<'de>"
+)))]
+enum Counts<T> {
+    Exact(Frequencies<T>),
+    Approximate(TopK<T>),
+}
+
+impl<T: Eq + Hash + Clone> BoundedFrequencies<T> {
+    /// Create an empty table that is exact for up to `limit` distinct
+    /// values.
+    ///
+    /// This panics if `limit` is `0`.
+    pub fn with_limit(limit: usize) -> BoundedFrequencies<T> {
+        assert!(limit > 0, "limit must be positive");
+        BoundedFrequencies {
+            limit,
+            counts: Counts::Exact(Frequencies::new()),
+        }
+    }
+
+    /// Add a sample.
+    pub fn add(&mut self, v: T) {
+        let spill = match self.counts {
+            Counts::Exact(ref mut freqs) => {
+                freqs.add(v);
+                freqs.len() > self.limit
+            }
+            Counts::Approximate(ref mut top) => {
+                top.add(v);
+                false
+            }
+        };
+        if spill {
+            self.spill();
+        }
+    }
+
+    /// Returns the count of `v`.
+    ///
+    /// This is exact if `is_exact` returns true. Otherwise it is an upper
+    /// bound for a tracked value, and `0` for a value that is not tracked.
+    pub fn count(&self, v: &T) -> u64 {
+        match self.counts {
+            Counts::Exact(ref freqs) => freqs.count(v),
+            Counts::Approximate(ref top) => top.count(v),
+        }
+    }
+
+    /// Returns the `k` most frequent values and their counts in descending
+    /// order of count.
+    pub fn most_frequent_k(&self, k: usize) -> Vec<(&T, u64)> {
+        match self.counts {
+            Counts::Exact(ref freqs) => freqs.most_frequent_k(k),
+            Counts::Approximate(ref top) => top.top(k),
+        }
+    }
+
+    /// Returns the total number of samples.
+    pub fn total(&self) -> u64 {
+        match self.counts {
+            Counts::Exact(ref freqs) => freqs.total(),
+            Counts::Approximate(ref top) => top.len() as u64,
+        }
+    }
+
+    /// Returns true if the counts are exact.
+    pub fn is_exact(&self) -> bool {
+        match self.counts {
+            Counts::Exact(_) => true,
+            Counts::Approximate(_) => false,
+        }
+    }
+
+    /// Returns the largest number of distinct values that are counted
+    /// exactly.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Switches to approximate counting.
+    fn spill(&mut self) {
+        let empty = Counts::Exact(Frequencies::new());
+        self.counts = match mem::replace(&mut self.counts, empty) {
+            Counts::Exact(freqs) => {
+                Counts::Approximate(topk::from_counts(self.limit, freqs.data))
+            }
+            approximate => approximate,
+        };
+    }
+}
+
+impl<T: Eq + Hash + Clone> Commute for BoundedFrequencies<T> {
+    fn merge(&mut self, v: BoundedFrequencies<T>) {
+        match v.counts {
+            Counts::Exact(freqs) => {
+                let spill = match self.counts {
+                    Counts::Exact(ref mut mine) => {
+                        mine.merge(freqs);
+                        mine.len() > self.limit
+                    }
+                    Counts::Approximate(ref mut top) => {
+                        top.merge(topk::from_counts(self.limit, freqs.data));
+                        false
+                    }
+                };
+                if spill {
+                    self.spill();
+                }
+            }
+            Counts::Approximate(other) => {
+                self.spill();
+                if let Counts::Approximate(ref mut top) = self.counts {
+                    top.merge(other);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone + fmt::Debug> fmt::Debug for BoundedFrequencies<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.counts {
+            Counts::Exact(ref freqs) => write!(f, "{:?}", freqs),
+            Counts::Approximate(ref top) => write!(f, "~{:?}", top),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> Extend<T> for BoundedFrequencies<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// A commutative data structure for exact frequency counts of integers in a
+/// small, bounded range.
+///
+/// This offers the same ranking and merging operations as `Frequencies`,
+/// but counts are stored in a flat array indexed by value instead of a hash
+/// map. For domains like `u8`, `u16` or a known range of small integers,
+/// this is much faster and far more cache friendly.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct DenseFrequencies<T> {
+    min: i64,
+    counts: Vec<u64>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: fmt::Debug + ToPrimitive + FromPrimitive> fmt::Debug
+        for DenseFrequencies<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+impl<T: ToPrimitive + FromPrimitive> DenseFrequencies<T> {
+    /// Create a new frequency table for values in the inclusive range
+    /// `[min, max]`.
+    ///
+    /// This panics if `min` is greater than `max`, or if either does not
+    /// fit in an `i64`, e.g., a `u64` above `i64::MAX`.
+    pub fn new(min: T, max: T) -> DenseFrequencies<T> {
+        let min = min.to_i64().expect("min does not fit in an i64");
+        let max = max.to_i64().expect("max does not fit in an i64");
+        assert!(min <= max, "the range of a frequency table is empty");
+        DenseFrequencies {
+            min,
+            counts: vec![0; (max - min + 1) as usize],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add a sample to the frequency table.
+    ///
+    /// This panics if the sample is outside the range of the table. Values
+    /// that do not fit in an `i64` are always outside of it.
+    pub fn add(&mut self, v: T) {
+        let i = self.index(&v).expect("sample outside of frequency table");
+        self.counts[i] += 1;
+    }
+
+    /// Return the number of occurrences of `v` in the data.
+    pub fn count(&self, v: &T) -> u64 {
+        self.index(v).map(|i| self.counts[i]).unwrap_or(0)
+    }
+
+    /// Return the cardinality (number of unique elements) in the data.
+    pub fn cardinality(&self) -> u64 {
+        self.len() as u64
+    }
+
+    /// Returns the mode if one exists.
+    pub fn mode(&self) -> Option<T> {
+        let counts = self.most_frequent();
+        if counts.is_empty()
+                || (counts.len() >= 2 && counts[0].1 == counts[1].1) {
+            None
+        } else {
+            counts.into_iter().next().map(|(v, _)| v)
+        }
+    }
+
+    /// Return a `Vec` of elements and their corresponding counts in
+    /// descending order.
+    ///
+    /// Elements with equal counts are in ascending order of value.
+    pub fn most_frequent(&self) -> Vec<(T, u64)> {
+        let mut counts = self.entries();
+        counts.sort_by_key(|&(_, c)| Reverse(c));
+        counts
+    }
+
+    /// Return a `Vec` of elements and their corresponding counts in
+    /// ascending order.
+    ///
+    /// Elements with equal counts are in ascending order of value.
+    pub fn least_frequent(&self) -> Vec<(T, u64)> {
+        let mut counts = self.entries();
+        counts.sort_by_key(|&(_, c1)| c1);
+        counts
+    }
+
+    /// Returns the cardinality of the data.
+    pub fn len(&self) -> usize {
+        self.counts.iter().filter(|&&c| c > 0).count()
+    }
+
+    fn index(&self, v: &T) -> Option<usize> {
+        let i = v.to_i64().and_then(|v| v.checked_sub(self.min))?;
+        if i >= 0 && (i as usize) < self.counts.len() {
+            Some(i as usize)
+        } else {
+            None
+        }
+    }
+
+    fn entries(&self) -> Vec<(T, u64)> {
+        self.counts.iter()
+                   .enumerate()
+                   .filter(|&(_, &c)| c > 0)
+                   .map(|(i, &c)| {
+                       (T::from_i64(self.min + i as i64).unwrap(), c)
+                   })
+                   .collect()
+    }
+}
+
+impl<T> Commute for DenseFrequencies<T> {
+    fn merge(&mut self, v: DenseFrequencies<T>) {
+        assert_eq!((self.min, self.counts.len()), (v.min, v.counts.len()));
+        for (c1, c2) in self.counts.iter_mut().zip(v.counts) {
+            *c1 += c2;
+        }
+    }
+}
+
+impl<T: ToPrimitive + FromPrimitive> Extend<T> for DenseFrequencies<T> {
     fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
         for sample in it {
             self.add(sample);
@@ -117,7 +1421,13 @@ impl<T: Eq + Hash> Extend<T> for Frequencies<T> {
 
 #[cfg(test)]
 mod test {
-    use super::Frequencies;
+    use super::{BoundedFrequencies, DenseFrequencies, Frequencies, ModeTies,
+                RankOptions, Smoothing, ZeroCountError, mode_hashable,
+                modes_hashable};
+    use std::collections::HashMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+    use rank::RankMethod;
+    use Commute;
 
     #[test]
     fn ranked() {
@@ -126,4 +1436,394 @@ mod test {
         assert_eq!(counts.most_frequent()[0], (&2, 5));
         assert_eq!(counts.least_frequent()[0], (&3, 1));
     }
+
+    #[test]
+    fn from_parts() {
+        let mut counts = Frequencies::from_parts(
+            vec![("a", 3), ("b", 1), ("a", 2)]).unwrap();
+        assert_eq!(counts.count(&"a"), 5);
+        assert_eq!(counts.total(), 6);
+
+        counts.merge(vec!["b", "c"].into_iter().collect());
+        assert_eq!(counts.count(&"b"), 2);
+        assert_eq!(counts.cardinality(), 3);
+
+        let err = Frequencies::from_parts(vec![("a", 1), ("b", 0)]);
+        assert_eq!(err.unwrap_err(), ZeroCountError { index: 1 });
+    }
+
+    #[test]
+    fn hashable_modes() {
+        assert_eq!(mode_hashable(vec!["b", "a", "b"].into_iter()), Some("b"));
+        assert_eq!(mode_hashable(vec!["a", "b", "a", "b"].into_iter()), None);
+        assert_eq!(modes_hashable(vec![3, 1, 1, 3, 2].into_iter()),
+                   vec![3, 1]);
+        assert_eq!(modes_hashable(vec![1, 2, 3].into_iter()), vec![]);
+        assert_eq!(mode_hashable(Vec::<u8>::new().into_iter()), None);
+    }
+
+    #[test]
+    fn ranked_ties() {
+        let mut counts = Frequencies::new();
+        counts.extend(vec!["a", "a", "a", "b", "b", "c", "c", "d"]);
+        let ranks = |ties| -> Vec<(&str, f64)> {
+            counts.ranked(RankOptions { ties, limit: None })
+                  .into_iter().map(|r| (*r.value, r.rank)).collect()
+        };
+        let competition = ranks(RankMethod::Min);
+        assert_eq!(competition[0], ("a", 1.0));
+        assert_eq!(competition[1].1, 2.0);
+        assert_eq!(competition[2].1, 2.0);
+        assert_eq!(competition[3], ("d", 4.0));
+        assert_eq!(ranks(RankMethod::Dense)[3], ("d", 3.0));
+
+        let top = counts.ranked(RankOptions { limit: Some(2),
+                                              ..Default::default() });
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].share, 0.375);
+    }
+
+    #[test]
+    fn report() {
+        let mut counts = Frequencies::new();
+        counts.extend(vec!["a"; 6]);
+        counts.extend(vec!["b"; 3]);
+        counts.extend(vec!["c", "d", "e"]);
+        assert_eq!(counts.total(), 12);
+        assert_eq!(counts.report(0.2), vec![
+            (Some(&"a"), 6, 0.5),
+            (Some(&"b"), 3, 0.25),
+            (None, 3, 0.25),
+        ]);
+        assert_eq!(counts.report(0.0).len(), 5);
+        assert!(Frequencies::<u8>::new().report(0.1).is_empty());
+    }
+
+    #[test]
+    fn count_of_counts() {
+        let mut counts = Frequencies::new();
+        counts.extend(vec!["a", "a", "a", "b", "c", "d", "d", "e"]);
+        assert_eq!(counts.count_of_counts(), vec![(1, 3), (2, 1), (3, 1)]);
+        assert!(Frequencies::<u8>::new().count_of_counts().is_empty());
+    }
+
+    #[test]
+    fn entropy() {
+        let mut counts = Frequencies::new();
+        counts.extend(vec!["a", "a", "b", "c"]);
+        assert_eq!(counts.probability(&"a"), 0.5);
+        assert_eq!(counts.probability(&"z"), 0.0);
+        assert_eq!(counts.probabilities_of(&["c", "z", "a"]),
+                   vec![0.25, 0.0, 0.5]);
+        assert!((counts.entropy() - 1.5).abs() < 1e-12);
+        assert!((counts.entropy_nats() - 1.5 * 2f64.ln()).abs() < 1e-12);
+        let total: f64 = counts.probabilities().map(|(_, p)| p).sum();
+        assert_eq!(total, 1.0);
+
+        let empty: Frequencies<u8> = Frequencies::new();
+        assert_eq!(empty.entropy(), 0.0);
+        assert_eq!(empty.probability(&1), 0.0);
+        assert_eq!(empty.probabilities_of(&[1]), vec![0.0]);
+    }
+
+    #[test]
+    fn chi_squared() {
+        let mut rolls = Frequencies::new();
+        for (face, &n) in [5, 8, 9, 8, 10, 20].iter().enumerate() {
+            rolls.extend(vec![face + 1; n]);
+        }
+        let fair: HashMap<usize, f64> =
+            (1..7).map(|face| (face, 1.0 / 6.0)).collect();
+        let test = rolls.chi_squared_against(&fair).unwrap();
+        assert!((test.statistic - 13.4).abs() < 1e-9);
+        assert_eq!(test.df, 5);
+        assert!(test.p_value > 0.019 && test.p_value < 0.021);
+
+        // Weights are normalized, so expected counts work too.
+        let counts: HashMap<usize, f64> =
+            (1..7).map(|face| (face, 10.0)).collect();
+        let same = rolls.chi_squared_against(&counts).unwrap();
+        assert!((same.statistic - test.statistic).abs() < 1e-9);
+
+        let partial: HashMap<usize, f64> =
+            (1..6).map(|face| (face, 1.0)).collect();
+        assert_eq!(rolls.chi_squared_against(&partial), None);
+    }
+
+    #[test]
+    fn divergence() {
+        let p: Frequencies<&str> = vec!["a", "a", "b", "b"].into_iter()
+                                                            .collect();
+        let q: Frequencies<&str> = vec!["a", "b", "b", "b"].into_iter()
+                                                            .collect();
+        let kl = p.kl_divergence(&q, Smoothing::None).unwrap();
+        let expected = 0.5 * (0.5f64 / 0.25).log2()
+                       + 0.5 * (0.5f64 / 0.75).log2();
+        assert!((kl - expected).abs() < 1e-12);
+        assert_eq!(p.kl_divergence(&p, Smoothing::None), Some(0.0));
+        assert_eq!(p.js_distance(&p, Smoothing::None), Some(0.0));
+
+        let r: Frequencies<&str> = vec!["c"].into_iter().collect();
+        assert_eq!(p.kl_divergence(&r, Smoothing::None),
+                   Some(::std::f64::INFINITY));
+        assert!(p.kl_divergence(&r, Smoothing::Additive(1.0))
+                 .unwrap().is_finite());
+        // Disjoint distributions are as far apart as possible.
+        assert_eq!(p.js_distance(&r, Smoothing::None), Some(1.0));
+        let d1 = p.js_distance(&q, Smoothing::None).unwrap();
+        let d2 = q.js_distance(&p, Smoothing::None).unwrap();
+        assert!((d1 - d2).abs() < 1e-12 && d1 > 0.0 && d1 < 1.0);
+        assert_eq!(p.kl_divergence(&Frequencies::new(), Smoothing::None),
+                   None);
+    }
+
+    #[test]
+    fn good_turing() {
+        let mut counts = Frequencies::new();
+        for (i, &n) in [1, 1, 1, 1, 1, 2, 2, 3, 5, 8].iter().enumerate() {
+            counts.extend(vec![i; n]);
+        }
+        let gt = counts.good_turing().unwrap();
+        // Five of the 25 samples were seen once.
+        assert_eq!(gt.unseen(), 0.2);
+        assert_eq!(gt.probability(0), Some(0.2));
+        assert_eq!(gt.probability(4), None);
+
+        let p1 = gt.probability(1).unwrap();
+        assert!(p1 < 1.0 / 25.0);
+        assert!(p1 < gt.probability(2).unwrap());
+        let total: f64 = counts.count_of_counts().iter()
+            .map(|&(r, n)| n as f64 * gt.probability(r).unwrap())
+            .sum();
+        assert!((total + gt.unseen() - 1.0).abs() < 1e-12);
+        assert_eq!(Frequencies::<u8>::new().good_turing(), None);
+    }
+
+    #[test]
+    fn dirichlet() {
+        let counts: Frequencies<&str> =
+            vec!["a", "a", "a", "b"].into_iter().collect();
+        let post = counts.dirichlet(1.0, 3);
+        assert!((post.probability(&"a") - 4.0 / 7.0).abs() < 1e-12);
+        assert!((post.probability(&"c") - 1.0 / 7.0).abs() < 1e-12);
+        let seen: f64 = post.probabilities().iter().map(|&(_, p)| p).sum();
+        assert!((seen - 6.0 / 7.0).abs() < 1e-12);
+        // The unseen category has a Beta(1, 6) marginal, whose CDF is
+        // `1 - (1 - x)^6`.
+        let (lo, hi) = post.credible_interval(&"c", 0.95);
+        assert!((lo - (1.0 - 0.975f64.powf(1.0 / 6.0))).abs() < 1e-9);
+        assert!((hi - (1.0 - 0.025f64.powf(1.0 / 6.0))).abs() < 1e-9);
+    }
+
+    #[test]
+    fn add_and_remove_counts() {
+        let mut counts = Frequencies::new();
+        counts.add_count("a", 3);
+        counts.add_count("b", 0);
+        counts.add("b");
+        assert_eq!((counts.count(&"a"), counts.cardinality()), (3, 2));
+        assert!(counts.remove(&"b"));
+        assert!(!counts.remove(&"b"));
+        assert_eq!(counts.cardinality(), 1);
+        assert_eq!(counts.sub_count(&"a", 2), 2);
+        assert_eq!(counts.sub_count(&"a", 5), 1);
+        assert_eq!((counts.total(), counts.cardinality()), (0, 0));
+        assert_eq!(counts.mode(), None);
+    }
+
+    #[test]
+    fn select_k() {
+        let mut counts = Frequencies::new();
+        for i in 0..100u32 {
+            counts.extend(vec![i; (i * 7 % 100 + 1) as usize]);
+        }
+        let all = counts.most_frequent();
+        let top = counts.most_frequent_k(10);
+        assert_eq!(top.len(), 10);
+        assert_eq!(top.iter().map(|&(_, c)| c).collect::<Vec<_>>(),
+                   all[..10].iter().map(|&(_, c)| c).collect::<Vec<_>>());
+        assert_eq!(top[0], (&57, 100));
+        assert_eq!(counts.least_frequent_k(2), vec![(&0, 1), (&43, 2)]);
+        assert_eq!(counts.most_frequent_k(0), vec![]);
+        assert_eq!(counts.most_frequent_k(1000).len(), 100);
+
+        let mut it = counts.most_frequent_iter();
+        assert_eq!(it.size_hint(), (100, Some(100)));
+        assert_eq!(it.next(), Some((&57, 100)));
+        assert_eq!(it.next(), Some((&14, 99)));
+        assert_eq!(counts.least_frequent_iter().take(2).collect::<Vec<_>>(),
+                   vec![(&0, 1), (&43, 2)]);
+    }
+
+    #[test]
+    fn order_statistics() {
+        let counts: Frequencies<&str> =
+            vec!["b", "a", "c", "b", "a", "d"].into_iter().collect();
+        assert_eq!(counts.mode_with(ModeTies::None), None);
+        assert_eq!(counts.mode_with(ModeTies::Smallest), Some(&"a"));
+        assert_eq!(counts.mode_with(ModeTies::Largest), Some(&"b"));
+        // The sorted samples are `a a b b c d`.
+        assert_eq!(counts.median(), Some(&"b"));
+        assert_eq!(counts.quantile(0.0), Some(&"a"));
+        assert_eq!(counts.quantile(1.0 / 3.0), Some(&"a"));
+        assert_eq!(counts.quantile(0.7), Some(&"c"));
+        assert_eq!(counts.quantile(1.0), Some(&"d"));
+        assert_eq!(counts.quantile(1.5), None);
+        // `0.07 * 100` is slightly more than `7`.
+        let counts: Frequencies<u32> = (1..101).collect();
+        assert_eq!(counts.quantile(0.07), Some(&7));
+        assert_eq!(Frequencies::<u8>::new().median(), None);
+        assert_eq!(Frequencies::<u8>::new().mode_with(ModeTies::Largest),
+                   None);
+    }
+
+    #[test]
+    fn weighted() {
+        let mut counts = Frequencies::new();
+        counts.extend(vec![2u32, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(counts.weighted_mean(), Some(5.0));
+        assert_eq!(counts.weighted_variance(), Some(4.0));
+        assert_eq!(counts.weighted_median(), Some(4.5));
+        counts.add(9);
+        assert_eq!(counts.weighted_median(), Some(5.0));
+        let empty: Frequencies<u32> = Frequencies::new();
+        assert_eq!(empty.weighted_mean(), None);
+        assert_eq!(empty.weighted_median(), None);
+    }
+
+    /// The 64-bit FNV-1a hash.
+    struct Fnv(u64);
+
+    impl Default for Fnv {
+        fn default() -> Fnv {
+            Fnv(0xcbf29ce484222325)
+        }
+    }
+
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 ^ b as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+
+    #[test]
+    fn count_type_and_hasher() {
+        let mut counts: Frequencies<&str, u32, BuildHasherDefault<Fnv>> =
+            vec!["a", "b", "b", "c", "c", "c"].into_iter().collect();
+        assert_eq!(counts.most_frequent(), vec![(&"c", 3), (&"b", 2),
+                                                (&"a", 1)]);
+        counts.add_count("a", 10);
+        assert_eq!(counts.sub_count(&"b", 5), 2);
+        assert_eq!((counts.count(&"a"), counts.total()), (11, 14));
+
+        let mut other = Frequencies::with_hasher(Default::default());
+        other.add_count("c", u32::max_value() as u64 - 3);
+        counts.merge(other);
+        assert_eq!(counts.count(&"c"), u32::max_value() as u64);
+        assert_eq!(counts.mode(), Some(&"c"));
+    }
+
+    #[test]
+    fn count_type_conversions() {
+        use {Checkpoint, OnlineStats, Unsorted};
+
+        let small: Frequencies<u32, u16, BuildHasherDefault<Fnv>> =
+            Frequencies::from_parts_with_hasher(vec![(1, 3), (2, 5)],
+                                                Default::default())
+                .unwrap();
+        let wide: Frequencies<u32> =
+            Checkpoint::from_checkpoint(&small.to_checkpoint()).unwrap();
+        assert_eq!((wide.count(&1), wide.count(&2)), (3, 5));
+        let mut big = wide.clone();
+        big.add_count(1, 1 << 16);
+        let narrow: Result<Frequencies<u32, u16>, _> =
+            Checkpoint::from_checkpoint(&big.to_checkpoint());
+        assert!(narrow.is_err());
+
+        let mut fair: HashMap<u32, f64, BuildHasherDefault<Fnv>> =
+            HashMap::default();
+        fair.insert(1, 1.0);
+        fair.insert(2, 1.0);
+        assert!(small.chi_squared_against(&fair).unwrap().statistic > 0.0);
+        assert_eq!(OnlineStats::from(small.clone()).mean(), 1.625);
+        assert_eq!(Unsorted::from(small).len(), 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn count_overflow() {
+        let mut counts: Frequencies<u8, u8> = Frequencies::default();
+        counts.add_count(1, 200);
+        counts.add_count(1, 100);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut small = BoundedFrequencies::with_limit(3);
+        small.extend(vec!["a", "b", "a", "c", "b", "a"]);
+        assert!(small.is_exact());
+        assert_eq!(small.most_frequent_k(1), vec![(&"a", 3)]);
+
+        let mut big = BoundedFrequencies::with_limit(3);
+        big.extend(vec!["a", "a", "a", "b", "b", "c", "c", "d"]);
+        assert!(!big.is_exact());
+        assert_eq!(big.total(), 8);
+        assert_eq!(big.count(&"a"), 3);
+        assert_eq!(big.count(&"d"), 0);
+
+        small.merge(big);
+        assert!(!small.is_exact());
+        assert_eq!(small.total(), 14);
+        assert_eq!(small.most_frequent_k(2), vec![(&"a", 6), (&"b", 4)]);
+    }
+
+    #[test]
+    fn dense_ranked() {
+        let mut counts = DenseFrequencies::new(0u8, 255);
+        counts.extend(vec![1u8, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4]);
+        assert_eq!(counts.most_frequent()[0], (2, 5));
+        assert_eq!(counts.least_frequent()[0], (3, 1));
+        assert_eq!(counts.cardinality(), 4);
+        assert_eq!(counts.mode(), Some(2));
+        assert_eq!(counts.count(&200), 0);
+    }
+
+    #[test]
+    fn dense_merge() {
+        let mut c1 = DenseFrequencies::new(-2i16, 2);
+        c1.extend(vec![-2, 0, 0]);
+        let mut c2 = DenseFrequencies::new(-2i16, 2);
+        c2.extend(vec![-2, -2, 2]);
+        c1.merge(c2);
+        assert_eq!(c1.most_frequent(), vec![(-2, 3), (0, 2), (2, 1)]);
+        assert_eq!(c1.mode(), Some(-2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn dense_out_of_range() {
+        DenseFrequencies::new(0u16, 10).add(11);
+    }
+
+    #[test]
+    fn dense_wide_values() {
+        let mut counts = DenseFrequencies::new(0u64, 10);
+        counts.add(3);
+        assert_eq!(counts.count(&u64::max_value()), 0);
+        let counts = DenseFrequencies::new(i64::max_value() - 1,
+                                           i64::max_value());
+        assert_eq!(counts.count(&i64::min_value()), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "max does not fit in an i64")]
+    fn dense_range_too_wide() {
+        DenseFrequencies::new(0u64, u64::max_value());
+    }
 }
@@ -0,0 +1,64 @@
+use std::hash::{Hash, Hasher};
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// A seedable hasher whose output is stable across runs and processes.
+///
+/// The standard library's default hasher is randomly keyed, which makes it
+/// useless for sketches and samples that are built in different places and
+/// compared or merged later. This is FNV-1a followed by the SplitMix64
+/// finalizer, which spreads the entropy of short keys across all 64 bits.
+///
+/// Note that `Hash` implementations for integers write their bytes in
+/// native order, so hashes are only stable between machines with the same
+/// endianness and pointer width.
+#[derive(Clone, Debug)]
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub fn with_seed(seed: u64) -> StableHasher {
+        StableHasher { state: FNV_OFFSET ^ mix(seed) }
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.state ^= b as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        mix(self.state)
+    }
+}
+
+/// Hash `value` with a `StableHasher` using the given seed.
+pub fn hash_with_seed<T: Hash + ?Sized>(value: &T, seed: u64) -> u64 {
+    let mut hasher = StableHasher::with_seed(seed);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The SplitMix64 finalizer.
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::hash_with_seed;
+
+    #[test]
+    fn stable() {
+        assert_eq!(hash_with_seed("abc", 0), hash_with_seed("abc", 0));
+        assert!(hash_with_seed("abc", 0) != hash_with_seed("abc", 1));
+        assert!(hash_with_seed(&1u64, 0) != hash_with_seed(&2u64, 0));
+    }
+}
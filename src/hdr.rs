@@ -0,0 +1,406 @@
+use std::prelude::v1::*;
+
+use std::cmp;
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use {Commute, CommuteRef};
+use unsorted::nearest_rank;
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// The default number of significant decimal digits of an `HdrHistogram`.
+const DEFAULT_SIGNIFICANT_FIGURES: u8 = 3;
+
+/// A commutative histogram of integers with a bounded relative error over
+/// a high dynamic range, as in HdrHistogram.
+///
+/// Values are counted in buckets whose width doubles with every power of
+/// two, and every power of two is split into the same number of linear
+/// sub-buckets. With `d` significant figures, the width of a bucket is at
+/// most `10^-d` times the values in it, whether they are microseconds or
+/// minutes, so latencies can be recorded with one histogram for the whole
+/// range of `u64`. Small values are counted exactly.
+///
+/// Buckets are allocated up to the largest recorded value, so memory use
+/// grows with the logarithm of the range of the data.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct HdrHistogram {
+    sigfigs: u8,
+    /// The number of bits of the values that are kept exactly.
+    bits: u32,
+    counts: Vec<u64>,
+    len: u64,
+    min: u64,
+    max: u64,
+}
+
+impl HdrHistogram {
+    /// Create an empty histogram with 3 significant figures.
+    pub fn new() -> HdrHistogram {
+        HdrHistogram::with_significant_figures(DEFAULT_SIGNIFICANT_FIGURES)
+    }
+
+    /// Create an empty histogram that keeps `sigfigs` significant decimal
+    /// digits of every value.
+    ///
+    /// This panics if `sigfigs` is not in `[1, 5]`.
+    pub fn with_significant_figures(sigfigs: u8) -> HdrHistogram {
+        assert!(sigfigs >= 1 && sigfigs <= 5,
+                "significant figures must be in [1, 5]");
+        // Every sub-bucket must be at most `10^-sigfigs` of its lowest
+        // value, which is at least `2^(bits - 1)`.
+        let exact = 2 * 10u64.pow(sigfigs as u32);
+        let bits = 64 - (exact - 1).leading_zeros();
+        HdrHistogram {
+            sigfigs,
+            bits,
+            counts: vec![],
+            len: 0,
+            min: ::std::u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Record a value.
+    pub fn record(&mut self, v: u64) {
+        self.record_n(v, 1);
+    }
+
+    /// Record `count` occurrences of a value.
+    pub fn record_n(&mut self, v: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let i = self.index(v);
+        if i >= self.counts.len() {
+            self.counts.resize(i + 1, 0);
+        }
+        self.counts[i] += count;
+        self.len += count;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+    }
+
+    /// Record a value, correcting for coordinated omission.
+    ///
+    /// When values are latencies measured once every `expected_interval`,
+    /// a stall of `v` also delays the measurements that should have been
+    /// taken during it, so only the one slow value would be recorded. This
+    /// also records the values those measurements would have seen: `v -
+    /// expected_interval`, `v - 2 * expected_interval` and so on, down to
+    /// `expected_interval`. An interval of `0` only records `v`.
+    ///
+    /// The missing values are counted a bucket at a time, so this takes
+    /// time proportional to the number of buckets they span rather than to
+    /// `v / expected_interval`.
+    pub fn record_with_expected_interval(
+        &mut self,
+        v: u64,
+        expected_interval: u64,
+    ) {
+        self.record(v);
+        let step = expected_interval;
+        let top = match v.checked_sub(step) {
+            Some(top) if step > 0 && top >= step => top,
+            _ => return,
+        };
+        // The smallest missing value that is at least `step`.
+        let mut lo = step + top % step;
+        loop {
+            let hi = cmp::min(self.highest_equivalent(self.index(lo)), top);
+            let n = (hi - lo) / step + 1;
+            self.record_n(lo, n);
+            // This is at most `top + step`, which is `v`.
+            let next = lo + n * step;
+            if next > top {
+                break;
+            }
+            lo = next;
+        }
+    }
+
+    /// Returns the value at quantile `q`, e.g., `0.99` for the 99th
+    /// percentile.
+    ///
+    /// This is the largest value that is equivalent to the sample at rank
+    /// `ceil(q * n)`, capped at the maximum, so it is within the relative
+    /// error of the histogram. `None` is returned if there is no data or if
+    /// `q` is not in `[0, 1]`.
+    pub fn value_at_quantile(&self, q: f64) -> Option<u64> {
+        if self.len == 0 || !(q >= 0.0 && q <= 1.0) {
+            return None;
+        }
+        let rank = nearest_rank(q, self.len);
+        let mut seen = 0;
+        for (i, &c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                let v = self.highest_equivalent(i);
+                return Some(v.min(self.max).max(self.min));
+            }
+        }
+        unreachable!()
+    }
+
+    /// Returns the smallest recorded value.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn min(&self) -> Option<u64> {
+        if self.len == 0 { None } else { Some(self.min) }
+    }
+
+    /// Returns the largest recorded value.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn max(&self) -> Option<u64> {
+        if self.len == 0 { None } else { Some(self.max) }
+    }
+
+    /// Returns the number of significant decimal digits that are kept.
+    pub fn significant_figures(&self) -> u8 {
+        self.sigfigs
+    }
+
+    /// Returns the number of recorded values.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns the index of the bucket of `v`.
+    ///
+    /// Values below `2^bits` have buckets of their own. Above, the shift
+    /// `s` of `v` keeps its top `bits` bits, and the `2^(bits - 1)` buckets
+    /// for each shift follow the buckets for the previous shift.
+    fn index(&self, v: u64) -> usize {
+        let shift = self.shift(v);
+        ((shift as u64) << (self.bits - 1)) as usize + (v >> shift) as usize
+    }
+
+    fn shift(&self, v: u64) -> u32 {
+        (64 - v.leading_zeros()).saturating_sub(self.bits)
+    }
+
+    /// Returns the largest value in the bucket with index `i`.
+    fn highest_equivalent(&self, i: usize) -> u64 {
+        let exact = 1usize << self.bits;
+        if i < exact {
+            return i as u64;
+        }
+        let half = exact >> 1;
+        let shift = ((i - half) / half) as u32;
+        let sub = (i - (shift as usize) * half) as u64;
+        // The last bucket ends at `u64::MAX`, which `(sub + 1) << shift`
+        // would overflow.
+        (sub << shift) + ((1u64 << shift) - 1)
+    }
+}
+
+impl Commute for HdrHistogram {
+    fn merge(&mut self, v: HdrHistogram) {
+        self.merge_ref(&v);
+    }
+}
+
+impl CommuteRef for HdrHistogram {
+    fn merge_ref(&mut self, v: &HdrHistogram) {
+        assert_eq!(self.sigfigs, v.sigfigs,
+                   "histograms must have the same significant figures");
+        if v.counts.len() > self.counts.len() {
+            self.counts.resize(v.counts.len(), 0);
+        }
+        for (c1, &c2) in self.counts.iter_mut().zip(&v.counts) {
+            *c1 += c2;
+        }
+        self.len += v.len;
+        self.min = self.min.min(v.min);
+        self.max = self.max.max(v.max);
+    }
+}
+
+/// Deserializing checks that the layout of the buckets matches the
+/// significant figures, and that the counts agree with the length and the
+/// extremes.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for HdrHistogram {
+    fn deserialize<D>(d: D) -> Result<HdrHistogram, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            sigfigs: u8,
+            bits: u32,
+            counts: Vec<u64>,
+            len: u64,
+            min: u64,
+            max: u64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.sigfigs < 1 || parts.sigfigs > 5 {
+            return Err(D::Error::custom(
+                "significant figures must be in [1, 5]"));
+        }
+        let mut hist = HdrHistogram::with_significant_figures(parts.sigfigs);
+        if parts.bits != hist.bits {
+            return Err(D::Error::custom(
+                "bits do not match the significant figures"));
+        }
+        if parts.counts.len() > hist.index(::std::u64::MAX) + 1 {
+            return Err(D::Error::custom("more buckets than values"));
+        }
+        let mut total = Some(0u64);
+        for &c in &parts.counts {
+            total = total.and_then(|t| t.checked_add(c));
+        }
+        if total != Some(parts.len) {
+            return Err(D::Error::custom("counts do not add up to the length"));
+        }
+        let extremes = parts.min <= parts.max
+            && hist.index(parts.max) < parts.counts.len();
+        if parts.len > 0 && !extremes {
+            return Err(D::Error::custom("min and max do not fit the counts"));
+        }
+        hist.counts = parts.counts;
+        hist.len = parts.len;
+        hist.min = parts.min;
+        hist.max = parts.max;
+        Ok(hist)
+    }
+}
+
+impl Default for HdrHistogram {
+    fn default() -> HdrHistogram {
+        HdrHistogram::new()
+    }
+}
+
+impl FromIterator<u64> for HdrHistogram {
+    fn from_iter<I: IntoIterator<Item=u64>>(it: I) -> HdrHistogram {
+        let mut v = HdrHistogram::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl Extend<u64> for HdrHistogram {
+    fn extend<I: IntoIterator<Item=u64>>(&mut self, it: I) {
+        for v in it {
+            self.record(v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HdrHistogram;
+    use Commute;
+
+    #[test]
+    fn exact_small_values() {
+        let hist: HdrHistogram = (1..1001).collect();
+        assert_eq!(hist.value_at_quantile(0.5), Some(500));
+        assert_eq!(hist.value_at_quantile(0.0), Some(1));
+        assert_eq!(hist.value_at_quantile(1.0), Some(1000));
+        assert_eq!((hist.min(), hist.max()), (Some(1), Some(1000)));
+        assert_eq!(hist.value_at_quantile(1.5), None);
+        assert_eq!(hist.value_at_quantile(::std::f64::NAN), None);
+        // `0.07 * 100` is slightly more than `7`.
+        let hist: HdrHistogram = (1..101).collect();
+        assert_eq!(hist.value_at_quantile(0.07), Some(7));
+    }
+
+    #[test]
+    fn relative_error() {
+        let mut hist = HdrHistogram::with_significant_figures(2);
+        // Microseconds up to minutes.
+        let values: Vec<u64> = (0..60).map(|i| 1u64 << (i / 2)).collect();
+        hist.extend(values.iter().cloned());
+        for (i, &v) in values.iter().enumerate() {
+            let q = (i + 1) as f64 / values.len() as f64;
+            let found = hist.value_at_quantile(q).unwrap();
+            assert!(found >= v && (found - v) as f64 <= 0.01 * v as f64,
+                    "{} {}", v, found);
+        }
+        hist.record(::std::u64::MAX);
+        assert_eq!(hist.value_at_quantile(1.0), Some(::std::u64::MAX));
+    }
+
+    #[test]
+    fn coordinated_omission() {
+        let mut hist = HdrHistogram::new();
+        for _ in 0..7 {
+            hist.record_with_expected_interval(10, 100);
+        }
+        // One stall of a second, while measuring every 100.
+        hist.record_with_expected_interval(1000, 100);
+        assert_eq!(hist.len(), 17);
+        assert_eq!(hist.value_at_quantile(0.5), Some(200));
+        assert_eq!(hist.value_at_quantile(0.75), Some(600));
+        assert_eq!(hist.max(), Some(1000));
+
+        let mut plain = HdrHistogram::new();
+        plain.record_with_expected_interval(1000, 0);
+        assert_eq!(plain.len(), 1);
+    }
+
+    #[test]
+    fn coordinated_omission_by_bucket() {
+        for &(v, step) in &[(12345, 7), (5000, 3), (99, 50), (100, 50),
+                            (3, 2), (1 << 20, 1)] {
+            let mut hist = HdrHistogram::with_significant_figures(2);
+            hist.record_with_expected_interval(v, step);
+            let mut one_by_one = HdrHistogram::with_significant_figures(2);
+            one_by_one.record(v);
+            let mut missing = v - step;
+            while missing >= step {
+                one_by_one.record(missing);
+                missing -= step;
+            }
+            assert_eq!(hist, one_by_one, "{} every {}", v, step);
+        }
+
+        let mut hist = HdrHistogram::new();
+        hist.record_with_expected_interval(1 << 40, 1);
+        assert_eq!(hist.len(), 1 << 40);
+        assert_eq!((hist.min(), hist.max()), (Some(1), Some(1 << 40)));
+        hist.record_with_expected_interval(::std::u64::MAX, 1 << 62);
+        assert_eq!(hist.len(), (1 << 40) + 3);
+    }
+
+    #[test]
+    fn merge() {
+        let mut h1: HdrHistogram = (0..500).map(|i| i * 1000).collect();
+        let h2: HdrHistogram = (500..1000).map(|i| i * 1000).collect();
+        let all: HdrHistogram = (0..1000).map(|i| i * 1000).collect();
+        h1.merge(h2);
+        assert_eq!(h1, all);
+        assert!(HdrHistogram::new().value_at_quantile(0.5).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_buckets() {
+        use serde_json;
+
+        let hist: HdrHistogram = vec![1, 2, 2].into_iter().collect();
+        let json = serde_json::to_string(&hist).unwrap();
+        assert_eq!(serde_json::from_str::<HdrHistogram>(&json).unwrap(), hist);
+        let empty = serde_json::to_string(&HdrHistogram::new()).unwrap();
+        assert_eq!(serde_json::from_str::<HdrHistogram>(&empty).unwrap(),
+                   HdrHistogram::new());
+
+        let bad = [
+            r#"{"sigfigs":0,"bits":2,"counts":[],"len":0,"min":0,"max":0}"#,
+            r#"{"sigfigs":3,"bits":4,"counts":[0,1],"len":1,"min":1,
+                "max":1}"#,
+            r#"{"sigfigs":3,"bits":11,"counts":[0,1],"len":2,"min":1,
+                "max":1}"#,
+            r#"{"sigfigs":3,"bits":11,"counts":[0,1],"len":1,"min":1,
+                "max":5}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<HdrHistogram>(json).is_err());
+        }
+    }
+}
@@ -0,0 +1,1077 @@
+use std::prelude::v1::*;
+
+use num_traits::ToPrimitive;
+
+use {Commute, CommuteRef, total_cmp};
+use unsorted::quantile_on_sorted;
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// A commutative data structure for counting samples in bins.
+///
+/// The bins are given by their edges: bin `i` covers `[edges[i],
+/// edges[i + 1])`, except that the last bin also includes its upper edge.
+/// Samples outside of the bins are counted separately as underflow or
+/// overflow, and `NaN` samples are ignored.
+///
+/// Histograms can only be merged with histograms with the same edges.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Histogram {
+    edges: Vec<f64>,
+    counts: Vec<u64>,
+    // The number of samples equal to each edge, which are also counted in
+    // `counts`, for the upper-inclusive buckets of `to_cumulative_buckets`.
+    on_edges: Vec<u64>,
+    underflow: u64,
+    overflow: u64,
+    sum: f64,
+}
+
+/// The buckets of a histogram with cumulative counts, as in a Prometheus
+/// histogram.
+///
+/// Bucket `i` counts the samples that are at most `bounds[i]`, like the
+/// `le` label of Prometheus. An OTLP explicit-bucket `HistogramDataPoint`
+/// instead has per-bucket counts, which are the differences of consecutive
+/// `counts`, and no `+Inf` bound.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct CumulativeBuckets {
+    /// The upper bounds of the buckets, in increasing order. The last bound
+    /// is `+Inf`.
+    pub bounds: Vec<f64>,
+    /// The number of samples in every bucket and the buckets below it.
+    pub counts: Vec<u64>,
+    /// The number of samples, which is also the count of the `+Inf` bucket.
+    pub count: u64,
+    /// The sum of the samples.
+    pub sum: f64,
+}
+
+impl Histogram {
+    /// Create an empty histogram of `bins` bins of equal width covering
+    /// `[lo, hi]`.
+    ///
+    /// If the range is too narrow for `bins` distinct edges, e.g., only a
+    /// few ulps wide, the edges that round to the same value are merged, so
+    /// the histogram has fewer bins.
+    ///
+    /// This panics if `bins` is `0` or `lo` is not less than `hi`.
+    pub fn new(lo: f64, hi: f64, bins: usize) -> Histogram {
+        assert!(bins > 0, "a histogram needs at least one bin");
+        assert!(lo < hi, "lo must be less than hi");
+        let width = (hi - lo) / bins as f64;
+        let mut edges = vec![lo];
+        for i in 1..bins {
+            let edge = lo + i as f64 * width;
+            if edge > edges[edges.len() - 1] && edge < hi {
+                edges.push(edge);
+            }
+        }
+        edges.push(hi);
+        Histogram::with_edges(edges)
+    }
+
+    /// Create an empty histogram with the given bin edges.
+    ///
+    /// This panics if there are fewer than two edges or if they are not
+    /// strictly increasing.
+    pub fn with_edges(edges: Vec<f64>) -> Histogram {
+        assert!(edges.len() >= 2, "a histogram needs at least two edges");
+        assert!(edges.windows(2).all(|w| w[0] < w[1]),
+                "edges must be strictly increasing");
+        Histogram {
+            counts: vec![0; edges.len() - 1],
+            on_edges: vec![0; edges.len()],
+            edges,
+            underflow: 0,
+            overflow: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Create a histogram of `data` with bins of equal width covering its
+    /// range, chosen from the data.
+    ///
+    /// The bins are chosen by the Freedman–Diaconis rule, or by Sturges'
+    /// formula if that gives more bins, as for data with many repeated
+    /// values. `NaN` samples are ignored, and `None` is returned if there is
+    /// no other data.
+    pub fn auto<T: ToPrimitive>(data: &[T]) -> Option<Histogram> {
+        let mut sorted: Vec<f64> = data
+            .iter()
+            .map(|x| x.to_f64().unwrap())
+            .filter(|x| !x.is_nan())
+            .collect();
+        sorted.sort_by(total_cmp);
+        let fd = bin_count_on_sorted(BinRule::FreedmanDiaconis, &sorted);
+        let rule = if fd < bin_count_on_sorted(BinRule::Sturges, &sorted) {
+            BinRule::Sturges
+        } else {
+            BinRule::FreedmanDiaconis
+        };
+        histogram_on_sorted(rule, &sorted)
+    }
+
+    /// Add a sample to the histogram.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        let x = sample.to_f64().unwrap();
+        if x.is_nan() {
+            return;
+        }
+        self.sum += x;
+        match bin(&self.edges, x) {
+            Some(i) => {
+                self.counts[i] += 1;
+                if x == self.edges[i] {
+                    self.on_edges[i] += 1;
+                } else if x == self.edges[i + 1] {
+                    self.on_edges[i + 1] += 1;
+                }
+            }
+            None if x < self.edges[0] => self.underflow += 1,
+            None => self.overflow += 1,
+        }
+    }
+
+    /// Returns the number of samples in each bin.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the bin edges.
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    /// Returns the range `(lo, hi)` covered by bin `i`.
+    ///
+    /// This panics if `i` is not less than the number of bins.
+    pub fn bin_range(&self, i: usize) -> (f64, f64) {
+        (self.edges[i], self.edges[i + 1])
+    }
+
+    /// Returns the number of bins.
+    pub fn bins(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns the number of samples below the first edge.
+    pub fn underflow(&self) -> u64 {
+        self.underflow
+    }
+
+    /// Returns the number of samples above the last edge.
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    /// Returns the number of samples, including underflow and overflow.
+    pub fn len(&self) -> usize {
+        (self.counts.iter().sum::<u64>() + self.underflow + self.overflow)
+            as usize
+    }
+
+    /// Returns the sum of the samples, including underflow and overflow.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns the buckets with cumulative counts, for exporting to
+    /// Prometheus or OpenTelemetry.
+    ///
+    /// Every edge is the upper bound of a bucket, so the first bucket holds
+    /// the underflow and the `+Inf` bucket holds all samples. Buckets
+    /// include their upper bound, so a sample that is equal to an edge is
+    /// counted in the bucket of that edge, even though the bins of the
+    /// histogram include their lower edge instead.
+    pub fn to_cumulative_buckets(&self) -> CumulativeBuckets {
+        let mut bounds = self.edges.clone();
+        bounds.push(::std::f64::INFINITY);
+        let last = self.counts.len();
+        let mut counts = Vec::with_capacity(bounds.len());
+        let mut total = self.underflow;
+        for (i, &on_edge) in self.on_edges.iter().enumerate() {
+            // The last bin already includes its upper edge.
+            counts.push(if i < last { total + on_edge } else { total });
+            if i < last {
+                total += self.counts[i];
+            }
+        }
+        counts.push(total + self.overflow);
+        CumulativeBuckets {
+            bounds,
+            counts,
+            count: total + self.overflow,
+            sum: self.sum,
+        }
+    }
+
+    /// Returns a histogram with coarser bins, whose edges are a subset of
+    /// the edges of this histogram, e.g., every other edge.
+    ///
+    /// Every bin is counted in the new bin that contains it, and bins
+    /// outside of the new edges are counted as underflow or overflow. This
+    /// panics if `edges` are not valid for `with_edges` or if any of them
+    /// is not an edge of this histogram.
+    pub fn rebin(&self, edges: Vec<f64>) -> Histogram {
+        let mut hist = Histogram::with_edges(edges);
+        let at: Vec<usize> = hist.edges.iter().map(|e| {
+            self.edges.iter()
+                .position(|x| x == e)
+                .expect("new edges must be edges of the histogram")
+        }).collect();
+        for (c, w) in hist.counts.iter_mut().zip(at.windows(2)) {
+            *c = self.counts[w[0]..w[1]].iter().sum();
+        }
+        for (on_edge, &i) in hist.on_edges.iter_mut().zip(&at) {
+            *on_edge = self.on_edges[i];
+        }
+        let (first, last) = (at[0], at[at.len() - 1]);
+        hist.underflow =
+            self.underflow + self.counts[..first].iter().sum::<u64>();
+        hist.overflow =
+            self.overflow + self.counts[last..].iter().sum::<u64>();
+        // The new last bin includes its upper edge, unless it was the upper
+        // edge of this histogram, where it was already included.
+        if last < self.counts.len() {
+            let n = hist.counts.len();
+            hist.counts[n - 1] += self.on_edges[last];
+            hist.overflow -= self.on_edges[last];
+        }
+        hist.sum = self.sum;
+        hist
+    }
+
+    /// Subtract the counts of another histogram with the same edges, e.g.,
+    /// an earlier snapshot of the same stream or a background
+    /// distribution.
+    ///
+    /// This panics if the histograms do not have the same edges, or if any
+    /// count of `other` is larger than the count it is subtracted from.
+    pub fn subtract(&mut self, other: &Histogram) {
+        assert_eq!(self.edges, other.edges,
+                   "histograms must have the same bins");
+        let sub = |c1: u64, c2: u64| {
+            c1.checked_sub(c2).expect("cannot subtract more samples than \
+                                       the histogram has")
+        };
+        let counts: Vec<u64> = self.counts.iter().zip(&other.counts)
+            .map(|(&c1, &c2)| sub(c1, c2))
+            .collect();
+        let on_edges: Vec<u64> = self.on_edges.iter().zip(&other.on_edges)
+            .map(|(&c1, &c2)| sub(c1, c2))
+            .collect();
+        self.underflow = sub(self.underflow, other.underflow);
+        self.overflow = sub(self.overflow, other.overflow);
+        self.counts = counts;
+        self.on_edges = on_edges;
+        self.sum -= other.sum;
+    }
+
+    /// Returns the density of every bin: its share of the samples in the
+    /// bins divided by its width, so that the density integrates to `1`.
+    ///
+    /// Underflow and overflow are not included. `None` is returned if there
+    /// are no samples in the bins.
+    pub fn density(&self) -> Option<Vec<f64>> {
+        self.shares().map(|shares| {
+            shares.into_iter()
+                  .zip(self.edges.windows(2))
+                  .map(|(p, w)| p / (w[1] - w[0]))
+                  .collect()
+        })
+    }
+
+    /// Returns the overlap of the distributions of two histograms with the
+    /// same edges, in `[0, 1]`.
+    ///
+    /// This is the sum over the bins of the smaller of the two shares of
+    /// the samples in the bins: `1` for identical distributions and `0` for
+    /// distributions without common bins. Underflow and overflow are not
+    /// included. `None` is returned if either histogram has no samples in
+    /// its bins, and this panics if the edges are not the same.
+    pub fn overlap(&self, other: &Histogram) -> Option<f64> {
+        assert_eq!(self.edges, other.edges,
+                   "histograms must have the same bins");
+        match (self.shares(), other.shares()) {
+            (Some(p), Some(q)) => {
+                Some(p.iter().zip(&q).map(|(&p, &q)| p.min(q)).sum())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the earth mover's distance between the distributions of two
+    /// histograms, in the units of the samples.
+    ///
+    /// This is the 1-Wasserstein distance: the least amount of probability
+    /// times distance that has to be moved to turn one distribution into
+    /// the other, where the samples of each bin are spread evenly across
+    /// it. Unlike `overlap`, it grows with how far the distributions are
+    /// apart, and the histograms may have different edges. Underflow and
+    /// overflow are not included. `None` is returned if either histogram
+    /// has no samples in its bins.
+    ///
+    /// Both histograms are swept once, so this takes time linear in the
+    /// number of bins.
+    pub fn earth_movers_distance(&self, other: &Histogram) -> Option<f64> {
+        let (p, q) = match (self.shares(), other.shares()) {
+            (Some(p), Some(q)) => (p, q),
+            _ => return None,
+        };
+        let edges = merge_edges(&self.edges, &other.edges);
+        let mut cdf1 = Sweep { hist: self, shares: &p, bin: 0, below: 0.0 };
+        let mut cdf2 = Sweep { hist: other, shares: &q, bin: 0, below: 0.0 };
+        // The difference of the two distribution functions is linear
+        // between consecutive edges, so integrate its absolute value
+        // exactly.
+        let (mut d0, mut distance) = (0.0f64, 0.0);
+        for w in edges.windows(2) {
+            let d1 = cdf1.at(w[1]) - cdf2.at(w[1]);
+            let (a, b) = (d0.abs(), d1.abs());
+            distance += if d0 * d1 >= 0.0 {
+                (w[1] - w[0]) * (a + b) / 2.0
+            } else {
+                (w[1] - w[0]) * (a * a + b * b) / (2.0 * (a + b))
+            };
+            d0 = d1;
+        }
+        Some(distance)
+    }
+
+    /// Returns the share of the samples in the bins of every bin.
+    fn shares(&self) -> Option<Vec<f64>> {
+        let total: u64 = self.counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        Some(self.counts.iter().map(|&c| c as f64 / total as f64).collect())
+    }
+
+}
+
+/// The distribution function of the bins of a histogram, with the samples
+/// of each bin spread evenly across it, evaluated at increasing points in
+/// one pass over the bins.
+struct Sweep<'a> {
+    hist: &'a Histogram,
+    shares: &'a [f64],
+    bin: usize,
+    below: f64,
+}
+
+impl<'a> Sweep<'a> {
+    /// Returns the share of the samples that are at most `x`, which must
+    /// not be less than in the previous call.
+    fn at(&mut self, x: f64) -> f64 {
+        let edges = &self.hist.edges;
+        while self.bin < self.shares.len() && x >= edges[self.bin + 1] {
+            self.below += self.shares[self.bin];
+            self.bin += 1;
+        }
+        if self.bin == self.shares.len() || x <= edges[self.bin] {
+            return self.below;
+        }
+        let (lo, hi) = (edges[self.bin], edges[self.bin + 1]);
+        self.below + self.shares[self.bin] * (x - lo) / (hi - lo)
+    }
+}
+
+/// Returns the union of two sorted lists of edges, in sorted order.
+fn merge_edges(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut edges = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let x = if j == b.len() || (i < a.len() && a[i] <= b[j]) {
+            i += 1;
+            a[i - 1]
+        } else {
+            j += 1;
+            b[j - 1]
+        };
+        if edges.last() != Some(&x) {
+            edges.push(x);
+        }
+    }
+    edges
+}
+
+/// Returns the bin of `edges` that a sample `x`, which is not `NaN`, falls
+/// into, or `None` if it is outside of the bins.
+fn bin(edges: &[f64], x: f64) -> Option<usize> {
+    let last = edges.len() - 1;
+    if x < edges[0] || x > edges[last] {
+        None
+    } else if x == edges[last] {
+        Some(last - 1)
+    } else {
+        // The number of edges that are at most `x`, which is at least 1.
+        let i = match edges.binary_search_by(|e| e.partial_cmp(&x).unwrap()) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        Some(i - 1)
+    }
+}
+
+impl Commute for Histogram {
+    fn merge(&mut self, v: Histogram) {
+        self.merge_ref(&v);
+    }
+}
+
+impl CommuteRef for Histogram {
+    fn merge_ref(&mut self, v: &Histogram) {
+        assert_eq!(self.edges, v.edges, "histograms must have the same bins");
+        for (c1, &c2) in self.counts.iter_mut().zip(&v.counts) {
+            *c1 += c2;
+        }
+        for (c1, &c2) in self.on_edges.iter_mut().zip(&v.on_edges) {
+            *c1 += c2;
+        }
+        self.underflow += v.underflow;
+        self.overflow += v.overflow;
+        self.sum += v.sum;
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for Histogram {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// Deserializing checks the edges like `with_edges`, so a corrupt histogram
+/// is an error instead of a panic when it is updated.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for Histogram {
+    fn deserialize<D>(d: D) -> Result<Histogram, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            edges: Vec<f64>,
+            counts: Vec<u64>,
+            on_edges: Vec<u64>,
+            underflow: u64,
+            overflow: u64,
+            sum: f64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.edges.len() < 2
+                || !parts.edges.windows(2).all(|w| w[0] < w[1]) {
+            return Err(D::Error::custom(
+                "edges must be at least two strictly increasing values"));
+        }
+        if parts.counts.len() != parts.edges.len() - 1 {
+            return Err(D::Error::custom(
+                "there must be one count for every bin"));
+        }
+        if parts.on_edges.len() != parts.edges.len() {
+            return Err(D::Error::custom(
+                "there must be one count of samples for every edge"));
+        }
+        Ok(Histogram {
+            edges: parts.edges,
+            counts: parts.counts,
+            on_edges: parts.on_edges,
+            underflow: parts.underflow,
+            overflow: parts.overflow,
+            sum: parts.sum,
+        })
+    }
+}
+
+/// A commutative data structure for counting pairs of samples in a grid of
+/// bins, e.g., request size against latency for a heatmap.
+///
+/// The x and y bins are given by their edges, independently of each other,
+/// as for `Histogram`. The marginal histogram of each coordinate is kept
+/// too, so it includes the pairs whose other coordinate is outside of its
+/// bins. Pairs with a `NaN` coordinate are ignored.
+///
+/// Histograms can only be merged with histograms with the same edges.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Histogram2d {
+    x: Histogram,
+    y: Histogram,
+    counts: Vec<u64>,
+    outside: u64,
+}
+
+impl Histogram2d {
+    /// Create an empty histogram of `xbins` by `ybins` bins of equal width
+    /// covering `[xlo, xhi]` by `[ylo, yhi]`.
+    ///
+    /// This panics if either number of bins is `0` or either range is
+    /// empty.
+    pub fn new(xlo: f64, xhi: f64, xbins: usize,
+               ylo: f64, yhi: f64, ybins: usize) -> Histogram2d {
+        Histogram2d::from_marginals(Histogram::new(xlo, xhi, xbins),
+                                    Histogram::new(ylo, yhi, ybins))
+    }
+
+    /// Create an empty histogram with the given x and y bin edges.
+    ///
+    /// This panics if either edges are invalid for `Histogram::with_edges`.
+    pub fn with_edges(xedges: Vec<f64>, yedges: Vec<f64>) -> Histogram2d {
+        Histogram2d::from_marginals(Histogram::with_edges(xedges),
+                                    Histogram::with_edges(yedges))
+    }
+
+    fn from_marginals(x: Histogram, y: Histogram) -> Histogram2d {
+        Histogram2d {
+            counts: vec![0; x.bins() * y.bins()],
+            x,
+            y,
+            outside: 0,
+        }
+    }
+
+    /// Add a pair of samples to the histogram.
+    pub fn add<T: ToPrimitive, U: ToPrimitive>(&mut self, x: T, y: U) {
+        let (x, y) = (x.to_f64().unwrap(), y.to_f64().unwrap());
+        if x.is_nan() || y.is_nan() {
+            return;
+        }
+        self.x.add(x);
+        self.y.add(y);
+        match (bin(&self.x.edges, x), bin(&self.y.edges, y)) {
+            (Some(i), Some(j)) => self.counts[i * self.y.bins() + j] += 1,
+            _ => self.outside += 1,
+        }
+    }
+
+    /// Returns the number of pairs in x bin `i` and y bin `j`.
+    ///
+    /// This panics if `i` or `j` is out of range.
+    pub fn count(&self, i: usize, j: usize) -> u64 {
+        assert!(i < self.x.bins() && j < self.y.bins(), "bin out of range");
+        self.counts[i * self.y.bins() + j]
+    }
+
+    /// Returns the number of pairs in each bin, in rows of y bins for each
+    /// x bin.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the number of x bins and the number of y bins.
+    pub fn bins(&self) -> (usize, usize) {
+        (self.x.bins(), self.y.bins())
+    }
+
+    /// Returns the histogram of the x samples.
+    pub fn x_marginal(&self) -> &Histogram {
+        &self.x
+    }
+
+    /// Returns the histogram of the y samples.
+    pub fn y_marginal(&self) -> &Histogram {
+        &self.y
+    }
+
+    /// Returns the number of pairs with either sample outside of its bins.
+    pub fn outside(&self) -> u64 {
+        self.outside
+    }
+
+    /// Returns the number of pairs, including those outside of the bins.
+    pub fn len(&self) -> usize {
+        self.x.len()
+    }
+}
+
+impl Commute for Histogram2d {
+    fn merge(&mut self, v: Histogram2d) {
+        self.merge_ref(&v);
+    }
+}
+
+impl CommuteRef for Histogram2d {
+    fn merge_ref(&mut self, v: &Histogram2d) {
+        self.x.merge_ref(&v.x);
+        self.y.merge_ref(&v.y);
+        for (c1, &c2) in self.counts.iter_mut().zip(&v.counts) {
+            *c1 += c2;
+        }
+        self.outside += v.outside;
+    }
+}
+
+impl<T: ToPrimitive, U: ToPrimitive> Extend<(T, U)> for Histogram2d {
+    fn extend<I: IntoIterator<Item=(T, U)>>(&mut self, it: I) {
+        for (x, y) in it {
+            self.add(x, y);
+        }
+    }
+}
+
+/// Deserializing checks the marginal histograms and that there is a count
+/// for every pair of bins.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for Histogram2d {
+    fn deserialize<D>(d: D) -> Result<Histogram2d, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            x: Histogram,
+            y: Histogram,
+            counts: Vec<u64>,
+            outside: u64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.counts.len() != parts.x.bins() * parts.y.bins() {
+            return Err(D::Error::custom(
+                "there must be one count for every pair of bins"));
+        }
+        Ok(Histogram2d {
+            x: parts.x,
+            y: parts.y,
+            counts: parts.counts,
+            outside: parts.outside,
+        })
+    }
+}
+
+/// Returns a histogram of sorted data, with bins of equal width covering
+/// the range of its finite values as chosen by `rule`.
+///
+/// Infinite values are counted as underflow or overflow, and `NaN` values
+/// are ignored. `None` is returned if there is no finite data.
+pub fn histogram_on_sorted<T>(rule: BinRule, data: &[T]) -> Option<Histogram>
+        where T: ToPrimitive {
+    let finite: Vec<f64> = data
+        .iter()
+        .map(|x| x.to_f64().unwrap())
+        .filter(|x| x.is_finite())
+        .collect();
+    let bins = bin_count_on_sorted(rule, &finite)?;
+    let (lo, hi) = (finite[0], finite[finite.len() - 1]);
+    let mut hist = if lo < hi {
+        Histogram::new(lo, hi, bins)
+    } else {
+        Histogram::new(lo - 0.5, lo + 0.5, 1)
+    };
+    hist.extend(data.iter().map(|x| x.to_f64().unwrap()));
+    Some(hist)
+}
+
+/// A rule for choosing the number and width of histogram bins from data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinRule {
+    /// Sturges' formula: `ceil(log2(n)) + 1` bins.
+    ///
+    /// This assumes the data is approximately normal and tends to produce
+    /// too few bins for large data sets.
+    Sturges,
+    /// The Freedman–Diaconis rule: bins of width `2 * IQR * n^(-1/3)`.
+    ///
+    /// This is robust to outliers.
+    FreedmanDiaconis,
+    /// Scott's rule: bins of width `3.49 * sd * n^(-1/3)`.
+    Scott,
+    /// Doane's formula, which extends Sturges' formula with a correction
+    /// for the skewness of the data.
+    Doane,
+}
+
+/// Returns the number of bins that `rule` selects for sorted data.
+///
+/// There are never more bins than samples, which bounds the rules based on
+/// the bin width when most of the data is concentrated in a narrow range.
+/// `None` is returned if there is no data.
+pub fn bin_count_on_sorted<T>(rule: BinRule, data: &[T]) -> Option<usize>
+        where T: ToPrimitive {
+    if data.is_empty() {
+        return None;
+    }
+    let n = data.len() as f64;
+    let range = data[data.len() - 1].to_f64().unwrap()
+                - data[0].to_f64().unwrap();
+    let count = match rule {
+        BinRule::Sturges => n.log2().ceil() + 1.0,
+        BinRule::Doane => {
+            if data.len() < 3 {
+                n.log2().ceil() + 1.0
+            } else {
+                let sigma = (6.0 * (n - 2.0) / ((n + 1.0) * (n + 3.0))).sqrt();
+                let g1 = skewness(data);
+                (1.0 + n.log2() + (1.0 + g1.abs() / sigma).log2()).ceil()
+            }
+        }
+        BinRule::FreedmanDiaconis | BinRule::Scott => {
+            let width = bin_width_rule(rule, data);
+            if width > 0.0 { (range / width).ceil() } else { 1.0 }
+        }
+    };
+    Some(count.min(n).max(1.0) as usize)
+}
+
+/// Returns the bin width that `rule` selects for sorted data.
+///
+/// For rules that choose a number of bins, the width is the range of the
+/// data divided by that number. `None` is returned if there is no data.
+pub fn bin_width_on_sorted<T>(rule: BinRule, data: &[T]) -> Option<f64>
+        where T: ToPrimitive {
+    if data.is_empty() {
+        return None;
+    }
+    Some(match rule {
+        BinRule::FreedmanDiaconis | BinRule::Scott => {
+            bin_width_rule(rule, data)
+        }
+        BinRule::Sturges | BinRule::Doane => {
+            let range = data[data.len() - 1].to_f64().unwrap()
+                        - data[0].to_f64().unwrap();
+            range / bin_count_on_sorted(rule, data).unwrap() as f64
+        }
+    })
+}
+
+fn bin_width_rule<T: ToPrimitive>(rule: BinRule, data: &[T]) -> f64 {
+    let n = data.len() as f64;
+    match rule {
+        BinRule::FreedmanDiaconis => {
+            let iqr = quantile_on_sorted(data, 0.75).unwrap()
+                      - quantile_on_sorted(data, 0.25).unwrap();
+            2.0 * iqr * n.powf(-1.0 / 3.0)
+        }
+        BinRule::Scott => {
+            let (_, var) = moments(data);
+            3.49 * var.sqrt() * n.powf(-1.0 / 3.0)
+        }
+        BinRule::Sturges | BinRule::Doane => unreachable!(),
+    }
+}
+
+fn moments<T: ToPrimitive>(data: &[T]) -> (f64, f64) {
+    let n = data.len() as f64;
+    let mean = data.iter().map(|x| x.to_f64().unwrap()).sum::<f64>() / n;
+    let var = data.iter()
+                  .map(|x| (x.to_f64().unwrap() - mean).powi(2))
+                  .sum::<f64>() / n;
+    (mean, var)
+}
+
+fn skewness<T: ToPrimitive>(data: &[T]) -> f64 {
+    let (mean, var) = moments(data);
+    if var == 0.0 {
+        return 0.0;
+    }
+    let n = data.len() as f64;
+    let m3 = data.iter()
+                 .map(|x| (x.to_f64().unwrap() - mean).powi(3))
+                 .sum::<f64>() / n;
+    m3 / var.powf(1.5)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BinRule, Histogram, Histogram2d};
+    use unsorted::Unsorted;
+    use {Commute, CommuteRef};
+
+    #[test]
+    fn fixed_width() {
+        let mut hist = Histogram::new(0.0, 10.0, 5);
+        hist.extend(vec![0.0, 1.9, 2.0, 5.5, 10.0, -1.0, 10.5]);
+        assert_eq!(hist.counts(), &[2, 1, 1, 0, 1]);
+        assert_eq!(hist.bin_range(1), (2.0, 4.0));
+        assert_eq!((hist.underflow(), hist.overflow()), (1, 1));
+        assert_eq!(hist.len(), 7);
+    }
+
+    #[test]
+    fn ulp_wide_range() {
+        let eps = ::std::f64::EPSILON;
+        let hist = Histogram::new(1.0, 1.0 + 2.0 * eps, 3);
+        assert_eq!(hist.edges(), &[1.0, 1.0 + eps, 1.0 + 2.0 * eps]);
+
+        let mut data: Unsorted<f64> =
+            vec![1.0, 1.0 + eps, 1.0 + 2.0 * eps].into_iter().collect();
+        let hist = data.histogram(BinRule::Sturges).unwrap();
+        assert!(hist.edges().windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(hist.counts(), &[1, 2]);
+        assert_eq!((hist.underflow(), hist.overflow()), (0, 0));
+    }
+
+    #[test]
+    fn edges() {
+        let mut hist = Histogram::with_edges(vec![0.0, 1.0, 10.0, 100.0]);
+        hist.extend(vec![0.5, 1.0, 9.0, 50.0, 100.0]);
+        assert_eq!(hist.counts(), &[1, 2, 2]);
+
+        let mut other = Histogram::with_edges(vec![0.0, 1.0, 10.0, 100.0]);
+        other.add(0.1);
+        hist.merge(other);
+        assert_eq!(hist.counts(), &[2, 2, 2]);
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_edges() {
+        use serde_json;
+
+        let mut hist = Histogram::new(0.0, 1.0, 2);
+        hist.extend(vec![0.2, 0.7, 2.0]);
+        let json = serde_json::to_string(&hist).unwrap();
+        assert_eq!(serde_json::from_str::<Histogram>(&json).unwrap(), hist);
+
+        let bad = [
+            r#"{"edges":[0.0],"counts":[],"on_edges":[0],"underflow":0,
+                "overflow":0,"sum":0.0}"#,
+            r#"{"edges":[1.0,0.0],"counts":[1],"on_edges":[0,0],
+                "underflow":0,"overflow":0,"sum":0.5}"#,
+            r#"{"edges":[0.0,1.0],"counts":[1,2],"on_edges":[0,0],
+                "underflow":0,"overflow":0,"sum":1.5}"#,
+            r#"{"edges":[0.0,1.0],"counts":[1],"on_edges":[0],
+                "underflow":0,"overflow":0,"sum":0.5}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<Histogram>(json).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_grid() {
+        use serde_json;
+
+        let mut hist = Histogram2d::new(0.0, 1.0, 2, 0.0, 1.0, 3);
+        hist.add(0.2, 0.5);
+        let json = serde_json::to_string(&hist).unwrap();
+        assert_eq!(serde_json::from_str::<Histogram2d>(&json).unwrap(), hist);
+        let bad = json.replace("[0,1,0,0,0,0]", "[0,1,0]");
+        assert!(serde_json::from_str::<Histogram2d>(&bad).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_different_bins() {
+        let mut hist = Histogram::new(0.0, 1.0, 2);
+        hist.merge(Histogram::new(0.0, 1.0, 3));
+    }
+
+    #[test]
+    fn rebin() {
+        let mut hist = Histogram::new(0.0, 8.0, 8);
+        hist.extend(vec![-1.0, 0.5, 1.5, 2.5, 3.5, 3.7, 6.5, 8.0]);
+        let coarse = hist.rebin(vec![0.0, 2.0, 6.0]);
+        assert_eq!(coarse.counts(), &[2, 3]);
+        assert_eq!((coarse.underflow(), coarse.overflow()), (1, 2));
+        assert_eq!(coarse.len(), hist.len());
+    }
+
+    #[test]
+    fn cumulative_buckets() {
+        use std::f64::INFINITY;
+
+        let mut hist = Histogram::with_edges(vec![0.0, 1.0, 5.0]);
+        hist.extend(vec![-1.0, 0.0, 0.5, 1.0, 4.0, 5.0, 9.0]);
+        let buckets = hist.to_cumulative_buckets();
+        assert_eq!(buckets.bounds, vec![0.0, 1.0, 5.0, INFINITY]);
+        // Samples on an edge are in the bucket of that edge, like `le`.
+        assert_eq!(buckets.counts, vec![2, 4, 6, 7]);
+        assert_eq!(buckets.count, 7);
+        assert_eq!(buckets.sum, 18.5);
+        assert_eq!(hist.rebin(vec![0.0, 1.0]).to_cumulative_buckets().counts,
+                   vec![2, 4, 7]);
+
+        let mut other = Histogram::with_edges(vec![0.0, 1.0, 5.0]);
+        other.add(2.0);
+        hist.merge(other.clone());
+        assert_eq!(hist.sum(), 20.5);
+        hist.subtract(&other);
+        assert_eq!(hist.sum(), 18.5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rebin_to_other_edges() {
+        Histogram::new(0.0, 8.0, 8).rebin(vec![0.0, 2.5]);
+    }
+
+    #[test]
+    fn two_dimensional() {
+        let mut a = Histogram2d::new(0.0, 10.0, 2, 0.0, 1.0, 4);
+        a.extend(vec![(1.0, 0.1), (6.0, 0.9), (6.0, 0.8), (11.0, 0.3)]);
+        let mut b = Histogram2d::with_edges(vec![0.0, 5.0, 10.0],
+                                            vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        b.extend(vec![(2.0, 0.1), (3.0, 2.0), (::std::f64::NAN, 0.5)]);
+        a.merge(b);
+        assert_eq!(a.bins(), (2, 4));
+        assert_eq!(a.len(), 6);
+        assert_eq!(a.count(0, 0), 2);
+        assert_eq!(a.count(1, 3), 2);
+        assert_eq!(a.counts(), &[2, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(a.outside(), 2);
+        assert_eq!(a.x_marginal().counts(), &[3, 2]);
+        assert_eq!(a.x_marginal().overflow(), 1);
+        assert_eq!(a.y_marginal().counts(), &[2, 1, 0, 2]);
+        assert_eq!(a.y_marginal().overflow(), 1);
+    }
+
+    #[test]
+    fn subtract() {
+        let mut hist = Histogram::new(0.0, 4.0, 2);
+        hist.extend(vec![0.5, 1.0, 3.0, 5.0]);
+        let mut before = Histogram::new(0.0, 4.0, 2);
+        before.extend(vec![0.5, 5.0]);
+        hist.subtract(&before);
+        assert_eq!((hist.counts(), hist.overflow()), (&[1, 1][..], 0));
+        hist.merge_ref(&before);
+        assert_eq!((hist.counts(), hist.overflow()), (&[2, 1][..], 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn subtract_too_many() {
+        let mut hist = Histogram::new(0.0, 4.0, 2);
+        let mut other = Histogram::new(0.0, 4.0, 2);
+        other.add(1.0);
+        hist.subtract(&other);
+    }
+
+    #[test]
+    fn density() {
+        let mut hist = Histogram::with_edges(vec![0.0, 1.0, 3.0]);
+        hist.extend(vec![0.5, 1.5, 2.0, 2.5, 9.0]);
+        assert_eq!(hist.density(), Some(vec![0.25, 0.375]));
+        assert_eq!(Histogram::new(0.0, 1.0, 2).density(), None);
+    }
+
+    #[test]
+    fn overlap_and_distance() {
+        let mut h1 = Histogram::new(0.0, 4.0, 4);
+        h1.extend(vec![0.5, 0.5, 1.5, 1.5]);
+        let mut h2 = Histogram::new(0.0, 4.0, 4);
+        h2.extend(vec![1.5, 1.5, 2.5, 2.5]);
+        assert_eq!(h1.overlap(&h2), Some(0.5));
+        assert_eq!(h1.overlap(&h1), Some(1.0));
+        // Half of the samples move two bins.
+        assert_eq!(h1.earth_movers_distance(&h2), Some(1.0));
+        assert_eq!(h2.earth_movers_distance(&h1), Some(1.0));
+        assert_eq!(h1.earth_movers_distance(&h1), Some(0.0));
+
+        let mut h3 = Histogram::new(0.0, 4.0, 4);
+        h3.add(3.5);
+        assert_eq!(h1.overlap(&h3), Some(0.0));
+        assert_eq!(h1.earth_movers_distance(&h3), Some(2.5));
+        assert_eq!(h1.overlap(&Histogram::new(0.0, 4.0, 4)), None);
+    }
+
+    #[test]
+    fn distance_between_different_edges() {
+        let mut h1 = Histogram::new(0.0, 1.0, 1);
+        h1.add(0.5);
+        let mut h2 = Histogram::new(1.0, 2.0, 2);
+        h2.extend(vec![1.2, 1.7]);
+        let d = h1.earth_movers_distance(&h2).unwrap();
+        assert!((d - 1.0).abs() < 1e-12);
+        assert_eq!(h2.earth_movers_distance(&h2), Some(0.0));
+        assert_eq!(h1.earth_movers_distance(&Histogram::new(0.0, 1.0, 1)),
+                   None);
+    }
+
+    #[test]
+    fn from_unsorted() {
+        let mut data: Unsorted<u32> = (1..9).collect();
+        let hist = data.histogram(BinRule::Sturges).unwrap();
+        assert_eq!(hist.bins(), 4);
+        assert_eq!(hist.counts(), &[2, 2, 2, 2]);
+        assert_eq!(hist.edges()[4], 8.0);
+
+        let mut constant: Unsorted<u32> = vec![3, 3].into_iter().collect();
+        let hist = constant.histogram(BinRule::FreedmanDiaconis).unwrap();
+        assert_eq!(hist.counts(), &[2]);
+        assert!(Unsorted::<u32>::new().histogram(BinRule::Scott).is_none());
+    }
+
+    #[test]
+    fn sturges() {
+        let mut data: Unsorted<u32> = (1..9).collect();
+        assert_eq!(data.bin_count(BinRule::Sturges), Some(4));
+        assert_eq!(data.bin_width(BinRule::Sturges), Some(7.0 / 4.0));
+    }
+
+    #[test]
+    fn freedman_diaconis() {
+        let mut data: Unsorted<u32> =
+            vec![0, 1, 2, 3, 4, 5, 6, 9].into_iter().collect();
+        let width = data.bin_width(BinRule::FreedmanDiaconis).unwrap();
+        assert!((width - 3.5).abs() < 1e-9);
+        assert_eq!(data.bin_count(BinRule::FreedmanDiaconis), Some(3));
+    }
+
+    #[test]
+    fn doane_counts_skew() {
+        let mut symmetric: Unsorted<u32> = (0..100).collect();
+        let mut skewed: Unsorted<u32> =
+            (0..100).map(|x| x * x * x).collect();
+        assert!(skewed.bin_count(BinRule::Doane).unwrap()
+                > symmetric.bin_count(BinRule::Doane).unwrap());
+    }
+
+    #[test]
+    fn auto() {
+        let mut data: Vec<u32> = (0..1000).collect();
+        data.push(10050);
+        let hist = Histogram::auto(&data).unwrap();
+        assert_eq!(hist.bins(), 101);
+        assert_eq!(hist.len(), 1001);
+
+        let repeated = [1u32, 1, 1, 1, 1, 1, 1, 2, 9];
+        assert_eq!(Histogram::auto(&repeated).unwrap().bins(), 5);
+        assert!(Histogram::auto(&[::std::f64::NAN]).is_none());
+    }
+
+    #[test]
+    fn auto_ulp_wide_range() {
+        let eps = ::std::f64::EPSILON;
+        let data: Vec<f64> = (0..100).map(|i| 1.0 + (i % 4) as f64 * eps)
+                                     .collect();
+        let hist = Histogram::auto(&data).unwrap();
+        assert!(hist.edges().windows(2).all(|w| w[0] < w[1]));
+        assert!(hist.bins() <= 3);
+        assert_eq!(hist.counts().iter().sum::<u64>(), 100);
+        assert_eq!((hist.underflow(), hist.overflow()), (0, 0));
+    }
+
+    #[test]
+    fn constant_data() {
+        let mut data: Unsorted<u32> = vec![5, 5, 5].into_iter().collect();
+        assert_eq!(data.bin_count(BinRule::Scott), Some(1));
+        assert_eq!(Unsorted::<u32>::new().bin_count(BinRule::Sturges), None);
+    }
+
+    #[test]
+    fn non_finite_data() {
+        use std::f64::{INFINITY, NAN, NEG_INFINITY};
+
+        let mut data: Unsorted<f64> =
+            vec![NEG_INFINITY, 1.0, 2.0, 3.0, 4.0, INFINITY, NAN]
+                .into_iter().collect();
+        let hist = data.histogram(BinRule::Sturges).unwrap();
+        assert_eq!(hist.edges()[0], 1.0);
+        assert_eq!(hist.edges()[hist.bins()], 4.0);
+        assert_eq!((hist.underflow(), hist.overflow()), (1, 1));
+        assert_eq!(hist.len(), 6);
+        let mut none: Unsorted<f64> = vec![INFINITY].into_iter().collect();
+        assert!(none.histogram(BinRule::Sturges).is_none());
+    }
+
+    #[test]
+    fn bins_bounded_by_samples() {
+        let mut data: Unsorted<f64> =
+            (0..10).map(|x| x as f64).chain(vec![1e6]).collect();
+        assert_eq!(data.bin_count(BinRule::FreedmanDiaconis), Some(11));
+        assert_eq!(data.histogram(BinRule::FreedmanDiaconis).unwrap().bins(),
+                   11);
+    }
+}
@@ -0,0 +1,411 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::{FromIterator, IntoIterator};
+use std::marker::PhantomData;
+use std::mem;
+
+use {Commute, CommuteRef};
+use hashing::hash_with_seed;
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// The default precision of a `HyperLogLog`.
+const DEFAULT_PRECISION: u8 = 14;
+
+/// A commutative data structure for approximate distinct counts in fixed
+/// space.
+///
+/// A HyperLogLog with precision `p` keeps `2^p` one byte registers, no
+/// matter how many samples are added, and estimates the cardinality with a
+/// relative standard error of about `1.04 / sqrt(2^p)`. The default
+/// precision of `14` uses 16KiB for an error of about `0.8%`.
+///
+/// Sketches start out sparse, storing only the registers that are set, and
+/// switch to the full register array once that is smaller. This keeps the
+/// many small sketches of, e.g., per-key distinct counts cheap.
+///
+/// Samples are hashed with a stable hasher, so sketches built on different
+/// machines (with the same endianness) can be merged, which gives the
+/// distinct count of the union of their data.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct HyperLogLog<T> {
+    precision: u8,
+    registers: Registers,
+    _type: PhantomData<T>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+enum Registers {
+    /// The non-zero registers as `(index, rank)`, sorted by index.
+    Sparse(Vec<(u32, u8)>),
+    Dense(Vec<u8>),
+}
+
+impl<T> fmt::Debug for HyperLogLog<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HyperLogLog(p={}, ~{})", self.precision, self.cardinality())
+    }
+}
+
+impl<T> HyperLogLog<T> {
+    /// Create an empty sketch with a precision of `14`.
+    pub fn new() -> HyperLogLog<T> {
+        HyperLogLog::with_precision(DEFAULT_PRECISION)
+    }
+
+    /// Create an empty sketch with `2^precision` registers.
+    ///
+    /// This panics if `precision` is not in `[4, 18]`.
+    pub fn with_precision(precision: u8) -> HyperLogLog<T> {
+        assert!(precision >= 4 && precision <= 18,
+                "precision must be in [4, 18]");
+        HyperLogLog {
+            precision,
+            registers: Registers::Sparse(vec![]),
+            _type: PhantomData,
+        }
+    }
+
+    /// Returns the estimated number of distinct samples.
+    pub fn cardinality(&self) -> u64 {
+        let m = self.size() as f64;
+        let (sum, zeros) = match self.registers {
+            Registers::Sparse(ref sparse) => {
+                let zeros = self.size() - sparse.len();
+                let sum = sparse.iter()
+                    .fold(zeros as f64, |sum, &(_, r)| sum + rank_weight(r));
+                (sum, zeros)
+            }
+            Registers::Dense(ref dense) => {
+                let zeros = dense.iter().filter(|&&r| r == 0).count();
+                (dense.iter().map(|&r| rank_weight(r)).sum(), zeros)
+            }
+        };
+        let estimate = alpha(self.size()) * m * m / sum;
+        // The raw estimate is biased for small cardinalities, where linear
+        // counting of the empty registers is more accurate.
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    /// Returns the precision of the sketch.
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Returns the expected relative standard error of the estimate.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / (self.size() as f64).sqrt()
+    }
+
+    /// Returns a sketch of the union of the data of both sketches.
+    ///
+    /// This is the same as merging them, without consuming either.
+    pub fn union(&self, other: &HyperLogLog<T>) -> HyperLogLog<T> {
+        let mut union = HyperLogLog::with_precision(self.precision);
+        union.merge_ref(self);
+        union.merge_ref(other);
+        union
+    }
+
+    /// Returns the estimated number of distinct samples in both sketches.
+    ///
+    /// This uses inclusion–exclusion, `|A| + |B| - |A ∪ B|`, so the absolute
+    /// error is on the order of the error of the union. Estimates of small
+    /// intersections of large sets are therefore very noisy.
+    pub fn intersection_cardinality(&self, other: &HyperLogLog<T>) -> u64 {
+        let union = self.union(other).cardinality();
+        (self.cardinality() + other.cardinality()).saturating_sub(union)
+    }
+
+    /// Returns the estimated Jaccard similarity of the data of both
+    /// sketches, `|A ∩ B| / |A ∪ B|`.
+    ///
+    /// If both sketches are empty, `0` is returned, as with
+    /// `MinHash::jaccard`.
+    pub fn jaccard(&self, other: &HyperLogLog<T>) -> f64 {
+        let union = self.union(other).cardinality();
+        if union == 0 {
+            return 0.0;
+        }
+        let both = self.cardinality() + other.cardinality();
+        (both.saturating_sub(union) as f64 / union as f64).min(1.0)
+    }
+
+    /// Returns true if no samples have been added.
+    pub fn is_empty(&self) -> bool {
+        match self.registers {
+            Registers::Sparse(ref sparse) => sparse.is_empty(),
+            Registers::Dense(ref dense) => dense.iter().all(|&r| r == 0),
+        }
+    }
+
+    /// Returns true if the sketch still uses the sparse representation.
+    pub fn is_sparse(&self) -> bool {
+        match self.registers {
+            Registers::Sparse(_) => true,
+            Registers::Dense(_) => false,
+        }
+    }
+
+    /// Returns the number of registers.
+    fn size(&self) -> usize {
+        1 << self.precision
+    }
+
+    /// Raises register `index` to at least `rank`.
+    fn set(&mut self, index: u32, rank: u8) {
+        let upgrade = match self.registers {
+            Registers::Sparse(ref mut sparse) => {
+                match sparse.binary_search_by_key(&index, |&(i, _)| i) {
+                    Ok(i) => {
+                        if rank > sparse[i].1 {
+                            sparse[i].1 = rank;
+                        }
+                    }
+                    Err(i) => sparse.insert(i, (index, rank)),
+                }
+                sparse.len() * mem::size_of::<(u32, u8)>() > self.size()
+            }
+            Registers::Dense(ref mut dense) => {
+                let r = &mut dense[index as usize];
+                if rank > *r {
+                    *r = rank;
+                }
+                false
+            }
+        };
+        if upgrade {
+            self.densify();
+        }
+    }
+
+    /// Switches to the dense representation.
+    fn densify(&mut self) {
+        let dense = match self.registers {
+            Registers::Dense(_) => return,
+            Registers::Sparse(ref sparse) => {
+                let mut dense = vec![0; self.size()];
+                for &(i, r) in sparse {
+                    dense[i as usize] = r;
+                }
+                dense
+            }
+        };
+        self.registers = Registers::Dense(dense);
+    }
+}
+
+impl<T: Hash> HyperLogLog<T> {
+    /// Add a sample to the sketch.
+    pub fn add(&mut self, v: T) {
+        let hash = hash_with_seed(&v, 0);
+        let p = self.precision as u32;
+        let index = (hash >> (64 - p)) as u32;
+        // The sentinel bit caps the rank for hashes whose remaining bits
+        // are all zero.
+        let rest = (hash << p) | (1 << (p - 1));
+        self.set(index, rest.leading_zeros() as u8 + 1);
+    }
+}
+
+/// Returns the contribution of a register to the harmonic mean, `2^-rank`.
+fn rank_weight(rank: u8) -> f64 {
+    (-(rank as f64)).exp2()
+}
+
+/// The bias correction constant for `m` registers.
+fn alpha(m: usize) -> f64 {
+    match m {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        m => 0.7213 / (1.0 + 1.079 / m as f64),
+    }
+}
+
+impl<T> Commute for HyperLogLog<T> {
+    fn merge(&mut self, v: HyperLogLog<T>) {
+        self.merge_ref(&v);
+    }
+}
+
+impl<T> CommuteRef for HyperLogLog<T> {
+    fn merge_ref(&mut self, v: &HyperLogLog<T>) {
+        assert_eq!(self.precision, v.precision);
+        match v.registers {
+            Registers::Sparse(ref sparse) => {
+                for &(i, r) in sparse {
+                    self.set(i, r);
+                }
+            }
+            Registers::Dense(ref dense) => {
+                self.densify();
+                for (i, &r) in dense.iter().enumerate() {
+                    self.set(i as u32, r);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for HyperLogLog<T> {
+    fn default() -> HyperLogLog<T> {
+        HyperLogLog::new()
+    }
+}
+
+impl<T: Hash> FromIterator<T> for HyperLogLog<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> HyperLogLog<T> {
+        let mut v = HyperLogLog::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Hash> Extend<T> for HyperLogLog<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// Deserializing checks the precision and the registers, so a corrupt
+/// sketch is an error instead of a panic in a later query or merge.
+#[cfg(feature = "serialize")]
+impl<'de, T> Deserialize<'de> for HyperLogLog<T> {
+    fn deserialize<D>(d: D) -> Result<HyperLogLog<T>, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            precision: u8,
+            registers: Registers,
+            _type: PhantomData<()>,
+        }
+        let parts = Parts::deserialize(d)?;
+        let precision = parts.precision;
+        if precision < 4 || precision > 18 {
+            return Err(D::Error::custom("precision must be in [4, 18]"));
+        }
+        let size = 1usize << precision;
+        let valid = match parts.registers {
+            Registers::Sparse(ref sparse) => {
+                sparse.windows(2).all(|w| w[0].0 < w[1].0)
+                && sparse.last().map_or(true, |&(i, _)| (i as usize) < size)
+            }
+            Registers::Dense(ref dense) => dense.len() == size,
+        };
+        if !valid {
+            return Err(D::Error::custom(
+                "registers do not match the precision"));
+        }
+        Ok(HyperLogLog {
+            precision: precision,
+            registers: parts.registers,
+            _type: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HyperLogLog;
+    use Commute;
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_invariants() {
+        use serde_json;
+
+        let mut hll = HyperLogLog::with_precision(4);
+        hll.extend(0..100u32);
+        let json = serde_json::to_string(&hll).unwrap();
+        let back: HyperLogLog<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.cardinality(), hll.cardinality());
+
+        let bad = [
+            r#"{"precision":30,"registers":{"Sparse":[]},"_type":null}"#,
+            r#"{"precision":4,"registers":{"Dense":[0,1]},"_type":null}"#,
+            r#"{"precision":4,"registers":{"Sparse":[[16,1]]},"_type":null}"#,
+            r#"{"precision":4,"registers":{"Sparse":[[2,1],[1,1]]},
+                "_type":null}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<HyperLogLog<u32>>(json).is_err());
+        }
+    }
+
+    fn within(estimate: u64, actual: u64, error: f64) -> bool {
+        (estimate as f64 - actual as f64).abs() <= error * actual as f64
+    }
+
+    #[test]
+    fn estimates() {
+        let hll: HyperLogLog<u64> = (0..100000).chain(0..100000).collect();
+        assert!(within(hll.cardinality(), 100000, 0.03),
+                "{}", hll.cardinality());
+
+        let small: HyperLogLog<&str> =
+            vec!["a", "b", "c", "a"].into_iter().collect();
+        assert_eq!(small.cardinality(), 3);
+        assert!(HyperLogLog::<u8>::new().is_empty());
+        assert_eq!(HyperLogLog::<u8>::new().cardinality(), 0);
+    }
+
+    #[test]
+    fn merge() {
+        let mut h1: HyperLogLog<u64> = (0..60000).collect();
+        let h2: HyperLogLog<u64> = (40000..100000).collect();
+        h1.merge(h2);
+        assert!(within(h1.cardinality(), 100000, 0.03),
+                "{}", h1.cardinality());
+    }
+
+    #[test]
+    fn set_operations() {
+        let a: HyperLogLog<u64> = (0..60000).collect();
+        let b: HyperLogLog<u64> = (40000..100000).collect();
+        assert!(within(a.union(&b).cardinality(), 100000, 0.03));
+        let both = a.intersection_cardinality(&b);
+        assert!(within(both, 20000, 0.2), "{}", both);
+        assert!((a.jaccard(&b) - 0.2).abs() < 0.04, "{}", a.jaccard(&b));
+        assert!((a.jaccard(&a) - 1.0).abs() < 1e-9);
+
+        let empty = HyperLogLog::<u64>::new();
+        assert_eq!(empty.jaccard(&empty), 0.0);
+        assert_eq!(a.intersection_cardinality(&empty), 0);
+    }
+
+    #[test]
+    fn sparse() {
+        let mut hll = HyperLogLog::with_precision(12);
+        hll.extend(0..100u64);
+        assert!(hll.is_sparse());
+        assert!(within(hll.cardinality(), 100, 0.02));
+
+        let mut dense = HyperLogLog::with_precision(12);
+        dense.extend(0..5000u64);
+        assert!(!dense.is_sparse());
+        hll.merge(dense);
+        assert!(!hll.is_sparse());
+        assert!(within(hll.cardinality(), 5000, 0.05));
+    }
+
+    #[test]
+    fn precision() {
+        let mut hll = HyperLogLog::with_precision(8);
+        hll.extend(0..10000u64);
+        assert!(within(hll.cardinality(), 10000, 0.2));
+        assert!((hll.relative_error() - 0.065).abs() < 1e-9);
+    }
+}
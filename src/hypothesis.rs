@@ -0,0 +1,396 @@
+use std::prelude::v1::*;
+
+use num_traits::ToPrimitive;
+
+use total_cmp;
+use distributions::{chi_squared_sf, erfc, f_sf};
+use rank::{RankMethod, rank};
+
+/// The result of a hypothesis test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct TestResult {
+    /// The test statistic.
+    pub statistic: f64,
+    /// The probability of a statistic at least as extreme under the null
+    /// hypothesis.
+    pub p_value: f64,
+}
+
+/// The Wald–Wolfowitz runs test for randomness of a sequence.
+///
+/// Every value is classified as above or below the median of the sequence,
+/// and values equal to the median are dropped. Too few runs of consecutive
+/// values on the same side suggest a trend or positive autocorrelation, and
+/// too many suggest oscillation. The statistic is the number of runs,
+/// standardized by its mean and variance under randomness, and the p-value
+/// is two-sided, from the normal approximation.
+///
+/// `None` is returned if there are no values on one side of the median, or
+/// if the number of runs has no variance, e.g., with one value on each side.
+pub fn runs_test<T: ToPrimitive>(data: &[T]) -> Option<TestResult> {
+    if data.is_empty() {
+        return None;
+    }
+    let xs: Vec<f64> = data.iter().map(|x| x.to_f64().unwrap()).collect();
+    let median = median(&xs);
+
+    let (mut above, mut below, mut runs) = (0u64, 0u64, 0u64);
+    let mut last = None;
+    for &x in &xs {
+        let side = if x > median {
+            above += 1;
+            true
+        } else if x < median {
+            below += 1;
+            false
+        } else {
+            continue;
+        };
+        if last != Some(side) {
+            runs += 1;
+            last = Some(side);
+        }
+    }
+    if above == 0 || below == 0 {
+        return None;
+    }
+    let (n1, n2) = (above as f64, below as f64);
+    let n = n1 + n2;
+    let mean = 2.0 * n1 * n2 / n + 1.0;
+    let var = 2.0 * n1 * n2 * (2.0 * n1 * n2 - n) / (n * n * (n - 1.0));
+    if var.is_nan() || var <= 0.0 {
+        return None;
+    }
+    let z = (runs as f64 - mean) / var.sqrt();
+    Some(TestResult {
+        statistic: z,
+        p_value: erfc(z.abs() / ::std::f64::consts::SQRT_2),
+    })
+}
+
+/// The center that deviations are measured from in `levene_test`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeveneCenter {
+    /// The mean of each group, as in Levene's original test.
+    Mean,
+    /// The median of each group, as in the Brown–Forsythe test, which is
+    /// robust to skewed data.
+    Median,
+}
+
+/// Levene's test for equality of variances across groups.
+///
+/// Each sample is replaced by its absolute deviation from the center of its
+/// group, and the statistic is the one-way ANOVA F statistic of the
+/// deviations, with `k - 1` and `N - k` degrees of freedom for `k` groups
+/// and `N` samples. A small p-value means that the variances differ, which
+/// violates the assumptions of ANOVA and the pooled t-test.
+///
+/// `None` is returned if there are fewer than two groups, any group is
+/// empty, there are no more samples than groups, or all deviations within
+/// every group are equal.
+pub fn levene_test<T: ToPrimitive>(
+    groups: &[&[T]],
+    center: LeveneCenter,
+) -> Option<TestResult> {
+    let k = groups.len();
+    if k < 2 || groups.iter().any(|g| g.is_empty()) {
+        return None;
+    }
+    let deviations: Vec<Vec<f64>> = groups.iter().map(|g| {
+        let xs: Vec<f64> = g.iter().map(|x| x.to_f64().unwrap()).collect();
+        let c = match center {
+            LeveneCenter::Mean => mean(&xs),
+            LeveneCenter::Median => median(&xs),
+        };
+        xs.iter().map(|x| (x - c).abs()).collect()
+    }).collect();
+
+    let n: usize = deviations.iter().map(|z| z.len()).sum();
+    if n <= k {
+        return None;
+    }
+    let total: f64 = deviations.iter().map(|z| z.iter().sum::<f64>()).sum();
+    let grand = total / n as f64;
+    let (mut between, mut within) = (0.0, 0.0);
+    for z in &deviations {
+        let m = mean(z);
+        between += z.len() as f64 * (m - grand) * (m - grand);
+        within += z.iter().map(|x| (x - m) * (x - m)).sum::<f64>();
+    }
+    if within == 0.0 {
+        return None;
+    }
+    let (d1, d2) = ((k - 1) as f64, (n - k) as f64);
+    let w = (between / d1) / (within / d2);
+    Some(TestResult { statistic: w, p_value: f_sf(w, d1, d2) })
+}
+
+/// The Kruskal–Wallis test of whether groups of samples come from the same
+/// distribution.
+///
+/// This is the rank-based counterpart of the one-way ANOVA: the samples of
+/// all groups are ranked together, and the statistic `H` measures how much
+/// the mean ranks of the groups differ, corrected for ties. The p-value is
+/// from the chi-squared approximation with `k - 1` degrees of freedom for
+/// `k` groups, which is accurate when every group has at least five
+/// samples.
+///
+/// `None` is returned if there are fewer than two groups, any group is
+/// empty, or all samples are equal.
+pub fn kruskal_wallis<T: PartialOrd>(groups: &[&[T]]) -> Option<TestResult> {
+    let k = groups.len();
+    if k < 2 || groups.iter().any(|g| g.is_empty()) {
+        return None;
+    }
+    let pooled: Vec<&T> = groups.iter().flat_map(|g| g.iter()).collect();
+    let ranks = rank(&pooled, RankMethod::Average);
+    let n = pooled.len() as f64;
+
+    let (mut h, mut start) = (0.0, 0);
+    for g in groups {
+        let sum: f64 = ranks[start..start + g.len()].iter().sum();
+        h += sum * sum / g.len() as f64;
+        start += g.len();
+    }
+    h = 12.0 / (n * (n + 1.0)) * h - 3.0 * (n + 1.0);
+
+    // Tied samples, and only tied samples, have equal ranks.
+    let mut sorted = ranks.clone();
+    sorted.sort_by(total_cmp);
+    let mut ties = 0.0;
+    let mut i = 0;
+    while i < sorted.len() {
+        let mut j = i + 1;
+        while j < sorted.len() && sorted[j] == sorted[i] {
+            j += 1;
+        }
+        let t = (j - i) as f64;
+        ties += t * t * t - t;
+        i = j;
+    }
+    let correction = 1.0 - ties / (n * n * n - n);
+    if correction.is_nan() || correction <= 0.0 {
+        return None;
+    }
+    h /= correction;
+    Some(TestResult {
+        statistic: h,
+        p_value: chi_squared_sf(h, (k - 1) as f64),
+    })
+}
+
+/// A method of adjusting p-values for multiple comparisons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Adjustment {
+    /// The Bonferroni correction, which controls the family-wise error
+    /// rate by multiplying every p-value by the number of tests.
+    Bonferroni,
+    /// Holm's step-down method, which controls the family-wise error rate
+    /// like Bonferroni, but rejects at least as many hypotheses.
+    Holm,
+    /// The Benjamini–Hochberg procedure, which controls the false discovery
+    /// rate, the expected share of false positives among the rejections.
+    BenjaminiHochberg,
+}
+
+/// Returns the adjusted p-values of a family of tests, in the same order as
+/// `p_values`.
+///
+/// Rejecting the hypotheses whose adjusted p-value is at most `alpha`
+/// controls the error rate of `method` at `alpha`. The adjusted values are
+/// the same as those of R's `p.adjust`.
+///
+/// `NaN` p-values are not counted as tests, and their adjusted p-values
+/// are `NaN`, so they are never rejected. This is also what `p.adjust`
+/// does with missing p-values.
+pub fn adjust_p_values(p_values: &[f64], method: Adjustment) -> Vec<f64> {
+    let mut order: Vec<usize> =
+        (0..p_values.len()).filter(|&i| !p_values[i].is_nan()).collect();
+    order.sort_by(|&i, &j| total_cmp(&p_values[i], &p_values[j]));
+    let m = order.len() as f64;
+    let mut adjusted = vec![::std::f64::NAN; p_values.len()];
+    match method {
+        Adjustment::Bonferroni => {
+            for &i in &order {
+                adjusted[i] = (m * p_values[i]).min(1.0);
+            }
+        }
+        Adjustment::Holm => {
+            let mut running = 0.0f64;
+            for (rank, &i) in order.iter().enumerate() {
+                running = running.max((m - rank as f64) * p_values[i]);
+                adjusted[i] = running.min(1.0);
+            }
+        }
+        Adjustment::BenjaminiHochberg => {
+            let mut running = 1.0f64;
+            for (rank, &i) in order.iter().enumerate().rev() {
+                running = running.min(m / (rank + 1) as f64 * p_values[i]);
+                adjusted[i] = running;
+            }
+        }
+    }
+    adjusted
+}
+
+/// Returns whether each hypothesis is rejected at level `alpha` after
+/// adjusting the p-values with `method`, in the same order as `p_values`.
+pub fn reject_hypotheses(
+    p_values: &[f64],
+    method: Adjustment,
+    alpha: f64,
+) -> Vec<bool> {
+    adjust_p_values(p_values, method).into_iter().map(|p| p <= alpha).collect()
+}
+
+fn mean(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+/// Returns the median of non-empty data.
+fn median(xs: &[f64]) -> f64 {
+    let mut sorted = xs.to_vec();
+    sorted.sort_by(total_cmp);
+    let n = sorted.len();
+    if n % 2 == 0 {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    } else {
+        sorted[n / 2]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Adjustment, LeveneCenter, adjust_p_values, kruskal_wallis,
+                levene_test, reject_hypotheses, runs_test};
+    use unsorted::Unsorted;
+
+    #[test]
+    fn runs() {
+        // Alternating values have the most runs possible.
+        let alternating: Vec<u32> = (0..40).map(|i| i % 2).collect();
+        let test = runs_test(&alternating).unwrap();
+        assert!(test.statistic > 5.0);
+        assert!(test.p_value < 1e-6);
+        // Far in the tail the p-value is tiny but still positive.
+        let alternating: Vec<u32> = (0..400).map(|i| i % 2).collect();
+        let p = runs_test(&alternating).unwrap().p_value;
+        assert!(p > 0.0 && p < 1e-50);
+
+        // A trend has the fewest.
+        let trend: Vec<u32> = (0..40).collect();
+        let test = runs_test(&trend).unwrap();
+        assert!(test.statistic < -5.0);
+
+        let random = [5, 2, 8, 1, 9, 4, 3, 7, 6, 0, 2, 8, 5, 3, 9, 1];
+        assert!(runs_test(&random).unwrap().p_value > 0.05);
+        assert_eq!(runs_test(&[1, 1, 1]), None);
+        assert_eq!(runs_test(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn levene() {
+        let a = [10.0, 11.0, 9.0, 10.5, 9.5, 10.2, 9.8];
+        let b = [4.0, 18.0, 10.0, 1.0, 16.0, 7.0, 14.0];
+        let test = levene_test(&[&a[..], &b[..]], LeveneCenter::Mean);
+        assert!(test.unwrap().p_value < 0.01);
+
+        // Shifting a group does not change its spread.
+        let same = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let shifted = [11.0, 12.0, 13.0, 14.0, 15.0];
+        let test = levene_test(&[&same[..], &shifted[..]],
+                               LeveneCenter::Median).unwrap();
+        assert_eq!((test.statistic, test.p_value), (0.0, 1.0));
+
+        // The deviations from the medians are `2, 1, 0, 0, 3` and
+        // `6, 4, 0, 3, 7`.
+        let g1 = [4, 5, 6, 6, 9];
+        let g2 = [1, 3, 7, 10, 14];
+        let test = levene_test(&[&g1[..], &g2[..]], LeveneCenter::Median)
+            .unwrap();
+        assert!((test.statistic - 19.6 * 8.0 / 36.8).abs() < 1e-9);
+        assert!(test.p_value > 0.05 && test.p_value < 0.1);
+        assert_eq!(levene_test(&[&g1[..]], LeveneCenter::Mean), None);
+        assert!(levene_test(&[&same[..], &same[..1]],
+                            LeveneCenter::Mean).is_some());
+    }
+
+    #[test]
+    fn kruskal_wallis_test() {
+        let x = [2.9, 3.0, 2.5, 2.6, 3.2];
+        let y = [3.8, 2.7, 4.0, 2.4];
+        let z = [2.8, 3.4, 3.7, 2.2, 2.0];
+        let test = kruskal_wallis(&[&x[..], &y[..], &z[..]]).unwrap();
+        assert!((test.statistic - 0.7714286).abs() < 1e-6);
+        assert!((test.p_value - 0.68).abs() < 0.005);
+
+        // The rank sums are `15.5` and `39.5`, and five pairs are tied.
+        let a = [1, 1, 2, 2, 3];
+        let b = [3, 4, 4, 5, 5];
+        let tied = kruskal_wallis(&[&a[..], &b[..]]).unwrap();
+        let h = 12.0 / 110.0 * (15.5 * 15.5 + 39.5 * 39.5) / 5.0 - 33.0;
+        assert!((tied.statistic - h / (1.0 - 30.0 / 990.0)).abs() < 1e-9);
+
+        let groups: Vec<Unsorted<u8>> = vec![
+            vec![1, 2, 3].into_iter().collect(),
+            vec![7, 8, 9].into_iter().collect(),
+        ];
+        let test = Unsorted::kruskal_wallis(&groups).unwrap();
+        assert!(test.p_value < 0.05);
+        assert_eq!(kruskal_wallis(&[&[1, 1][..], &[1][..]]), None);
+    }
+
+    #[test]
+    fn adjustments() {
+        let p = [0.01, 0.04, 0.03, 0.005, 0.5];
+        let close = |a: Vec<f64>, b: &[f64]| {
+            a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-12)
+        };
+        assert!(close(adjust_p_values(&p, Adjustment::Bonferroni),
+                      &[0.05, 0.2, 0.15, 0.025, 1.0]));
+        assert!(close(adjust_p_values(&p, Adjustment::Holm),
+                      &[0.04, 0.09, 0.09, 0.025, 0.5]));
+        assert!(close(adjust_p_values(&p, Adjustment::BenjaminiHochberg),
+                      &[0.025, 0.05, 0.05, 0.025, 0.5]));
+        assert_eq!(reject_hypotheses(&p, Adjustment::BenjaminiHochberg, 0.05),
+                   vec![true, true, true, true, false]);
+        assert_eq!(reject_hypotheses(&p, Adjustment::Holm, 0.05),
+                   vec![true, false, false, true, false]);
+        assert!(adjust_p_values(&[], Adjustment::Holm).is_empty());
+    }
+
+    #[test]
+    fn adjustments_skip_nan() {
+        use std::f64::NAN;
+
+        let mut p: Vec<f64> = (0..25).map(|i| i as f64 / 100.0).collect();
+        p[3] = NAN;
+        p[11] = NAN;
+        for &method in &[Adjustment::Bonferroni, Adjustment::Holm,
+                         Adjustment::BenjaminiHochberg] {
+            let adjusted = adjust_p_values(&p, method);
+            assert!(adjusted[3].is_nan() && adjusted[11].is_nan());
+            assert!(adjusted.iter()
+                            .enumerate()
+                            .all(|(i, &a)| i == 3 || i == 11 || a >= p[i]));
+        }
+        // Only the 23 p-values that are numbers are counted as tests.
+        assert_eq!(adjust_p_values(&p, Adjustment::Bonferroni)[1], 0.23);
+        assert!(!reject_hypotheses(&p, Adjustment::Holm, 1.0)[3]);
+    }
+
+    #[test]
+    fn nan_samples() {
+        use std::f64::NAN;
+
+        let mut a: Vec<f64> = (0..30).map(|x| x as f64).collect();
+        a[7] = NAN;
+        let b: Vec<f64> = (20..50).map(|x| x as f64).collect();
+        assert!(kruskal_wallis(&[&a[..], &b[..]]).is_some());
+        assert!(runs_test(&a).is_some());
+        assert!(levene_test(&[&a[..], &b[..]], LeveneCenter::Median)
+                    .is_some());
+    }
+}
@@ -0,0 +1,159 @@
+use std::prelude::v1::*;
+
+use std::f64::consts::PI;
+
+use num_traits::ToPrimitive;
+
+use total_cmp;
+use online::OnlineStats;
+use unsorted::quantile_on_sorted;
+
+/// A rule for choosing the bandwidth of a kernel density estimate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bandwidth {
+    /// Silverman's rule of thumb: `0.9 * min(sd, IQR / 1.34) * n^(-1/5)`.
+    ///
+    /// This is more robust than Scott's rule when the data is skewed or
+    /// has outliers.
+    Silverman,
+    /// Scott's rule: `1.06 * sd * n^(-1/5)`.
+    ///
+    /// This is optimal when the data is normally distributed.
+    Scott,
+    /// A bandwidth chosen by the caller.
+    Fixed(f64),
+}
+
+/// A kernel density estimate using a Gaussian kernel.
+#[derive(Clone, Debug)]
+pub struct KernelDensity {
+    samples: Vec<f64>,
+    bandwidth: f64,
+}
+
+impl KernelDensity {
+    /// Fit a kernel density estimate to the given samples.
+    ///
+    /// Samples that are not finite, like `NaN`, are ignored. `None` is
+    /// returned if there are fewer than two finite samples or if the
+    /// selected bandwidth is not positive (e.g., all samples are equal).
+    pub fn new<T: ToPrimitive>(
+        samples: &[T],
+        bandwidth: Bandwidth,
+    ) -> Option<KernelDensity> {
+        let mut samples: Vec<f64> = samples
+            .iter()
+            .map(|x| x.to_f64().unwrap())
+            .filter(|x| x.is_finite())
+            .collect();
+        if samples.len() < 2 {
+            return None;
+        }
+        samples.sort_unstable_by(total_cmp);
+
+        let n = samples.len() as f64;
+        let stats = OnlineStats::from_slice(&samples);
+        let sd = (stats.variance() * n / (n - 1.0)).sqrt();
+        let bandwidth = match bandwidth {
+            Bandwidth::Fixed(h) => h,
+            Bandwidth::Scott => 1.06 * sd * n.powf(-0.2),
+            Bandwidth::Silverman => {
+                let iqr = quantile_on_sorted(&samples, 0.75).unwrap()
+                          - quantile_on_sorted(&samples, 0.25).unwrap();
+                let spread = if iqr > 0.0 { sd.min(iqr / 1.34) } else { sd };
+                0.9 * spread * n.powf(-0.2)
+            }
+        };
+        if bandwidth.is_nan() || bandwidth <= 0.0 {
+            return None;
+        }
+        Some(KernelDensity { samples, bandwidth })
+    }
+
+    /// Returns the bandwidth of the kernel.
+    pub fn bandwidth(&self) -> f64 {
+        self.bandwidth
+    }
+
+    /// Returns the estimated probability density at `x`.
+    pub fn density(&self, x: f64) -> f64 {
+        let h = self.bandwidth;
+        let sum: f64 = self.samples.iter().map(|&xi| {
+            let u = (x - xi) / h;
+            (-0.5 * u * u).exp()
+        }).sum();
+        sum / (self.samples.len() as f64 * h * (2.0 * PI).sqrt())
+    }
+
+    /// Evaluates the density at `points` evenly spaced values from `min` to
+    /// `max` inclusive.
+    ///
+    /// Each element of the returned `Vec` is a pair of the point and the
+    /// estimated density at that point.
+    pub fn grid(&self, min: f64, max: f64, points: usize) -> Vec<(f64, f64)> {
+        let step = if points > 1 {
+            (max - min) / (points - 1) as f64
+        } else {
+            0.0
+        };
+        (0..points).map(|i| {
+            let x = min + step * i as f64;
+            (x, self.density(x))
+        }).collect()
+    }
+
+    /// Evaluates the density on a grid of `points` values that covers the
+    /// samples plus three bandwidths on either side.
+    pub fn auto_grid(&self, points: usize) -> Vec<(f64, f64)> {
+        let lo = self.samples[0] - 3.0 * self.bandwidth;
+        let hi = self.samples[self.samples.len() - 1] + 3.0 * self.bandwidth;
+        self.grid(lo, hi, points)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bandwidth, KernelDensity};
+
+    #[test]
+    fn scott_bandwidth() {
+        let kde = KernelDensity::new(&[1.0, 2.0, 3.0, 4.0], Bandwidth::Scott)
+            .unwrap();
+        let sd = (5.0f64 / 3.0).sqrt();
+        assert!((kde.bandwidth() - 1.06 * sd * 4f64.powf(-0.2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn density_integrates_to_one() {
+        let kde = KernelDensity::new(&[1u32, 2, 2, 3, 7, 8],
+                                     Bandwidth::Silverman).unwrap();
+        let grid = kde.auto_grid(2001);
+        let step = grid[1].0 - grid[0].0;
+        let area: f64 = grid.iter().map(|&(_, d)| d * step).sum();
+        assert!((area - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn degenerate() {
+        assert!(KernelDensity::new(&[1.0], Bandwidth::Scott).is_none());
+        assert!(KernelDensity::new(&[2.0, 2.0], Bandwidth::Silverman)
+                .is_none());
+        assert!(KernelDensity::new(&[1.0, ::std::f64::NAN], Bandwidth::Scott)
+                .is_none());
+    }
+
+    #[test]
+    fn non_finite_samples() {
+        use std::f64::{INFINITY, NAN};
+
+        let finite: Vec<f64> = (0..40).map(|x| (x * x % 17) as f64).collect();
+        let mut samples = finite.clone();
+        samples.extend(vec![NAN, INFINITY, NAN]);
+        samples.swap(3, 40);
+        let kde = KernelDensity::new(&samples, Bandwidth::Silverman).unwrap();
+        let expected = KernelDensity::new(&finite, Bandwidth::Silverman)
+            .unwrap();
+        assert_eq!(kde.bandwidth(), expected.bandwidth());
+        assert_eq!(kde.density(5.0), expected.density(5.0));
+    }
+}
@@ -0,0 +1,301 @@
+use std::prelude::v1::*;
+
+use std::iter::IntoIterator;
+
+use Commute;
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// A commutative summary of multivariate data by `k` weighted centroids,
+/// from online k-means clustering.
+///
+/// The first `k` samples become the centroids. After that, every sample
+/// moves its nearest centroid towards it by `1 / count` of the distance,
+/// so each centroid is the mean of the samples assigned to it, as in
+/// MacQueen's online k-means. `add_batch` assigns a whole mini-batch to the
+/// centroids before moving any of them, which is less sensitive to the
+/// order of the samples.
+///
+/// This takes one pass and `O(k * dim)` memory, so it is meant for coarse
+/// summaries of large data, e.g., to find the dominant modes of a stream,
+/// rather than for exact clustering. Summaries are merged by pooling their
+/// centroids and repeatedly replacing the closest pair by its weighted
+/// mean until `k` are left.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct KMeans {
+    k: usize,
+    dim: usize,
+    centroids: Vec<Vec<f64>>,
+    counts: Vec<u64>,
+}
+
+impl KMeans {
+    /// Create an empty summary with at most `k` centroids of `dim`
+    /// dimensions.
+    ///
+    /// This panics if `k` or `dim` is `0`.
+    pub fn new(k: usize, dim: usize) -> KMeans {
+        assert!(k > 0 && dim > 0, "k and dimension must be positive");
+        KMeans {
+            k,
+            dim,
+            centroids: Vec::with_capacity(k),
+            counts: Vec::with_capacity(k),
+        }
+    }
+
+    /// Add a sample, moving its nearest centroid towards it.
+    ///
+    /// This panics if `sample` does not have `dim` elements.
+    pub fn add(&mut self, sample: &[f64]) {
+        if !self.seed(sample) {
+            let i = self.nearest(sample).unwrap();
+            self.update(i, sample);
+        }
+    }
+
+    /// Add a mini-batch of samples.
+    ///
+    /// Every sample is assigned to its nearest centroid before the
+    /// centroids are moved. This panics if a sample does not have `dim`
+    /// elements.
+    pub fn add_batch<I, V>(&mut self, batch: I)
+            where I: IntoIterator<Item=V>, V: AsRef<[f64]> {
+        let mut assigned = vec![];
+        for sample in batch {
+            let sample = sample.as_ref();
+            if !self.seed(sample) {
+                assigned.push((self.nearest(sample).unwrap(),
+                               sample.to_vec()));
+            }
+        }
+        for (i, sample) in assigned {
+            self.update(i, &sample);
+        }
+    }
+
+    /// Returns the index of the centroid nearest to `x`.
+    ///
+    /// `None` is returned if there is no data. This panics if `x` does not
+    /// have `dim` elements.
+    pub fn nearest(&self, x: &[f64]) -> Option<usize> {
+        assert_eq!(x.len(), self.dim, "sample has the wrong dimension");
+        let mut best = None;
+        for (i, c) in self.centroids.iter().enumerate() {
+            let d = squared_distance(c, x);
+            if best.map_or(true, |(_, min)| d < min) {
+                best = Some((i, d));
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    /// Returns the centroids.
+    ///
+    /// There are fewer than `k` centroids until `k` samples were added.
+    pub fn centroids(&self) -> &[Vec<f64>] {
+        &self.centroids
+    }
+
+    /// Returns the number of samples assigned to each centroid.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the maximum number of centroids.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the number of dimensions.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.counts.iter().sum::<u64>() as usize
+    }
+
+    /// Makes `sample` a centroid if there are fewer than `k`, and returns
+    /// whether it did.
+    fn seed(&mut self, sample: &[f64]) -> bool {
+        assert_eq!(sample.len(), self.dim, "sample has the wrong dimension");
+        if self.centroids.len() < self.k {
+            self.centroids.push(sample.to_vec());
+            self.counts.push(1);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn update(&mut self, i: usize, sample: &[f64]) {
+        self.counts[i] += 1;
+        let eta = 1.0 / self.counts[i] as f64;
+        for (c, &x) in self.centroids[i].iter_mut().zip(sample) {
+            *c += eta * (x - *c);
+        }
+    }
+}
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+impl Commute for KMeans {
+    fn merge(&mut self, v: KMeans) {
+        assert_eq!((self.k, self.dim), (v.k, v.dim),
+                   "summaries must have the same k and dimension");
+        self.centroids.extend(v.centroids);
+        self.counts.extend(v.counts);
+        while self.centroids.len() > self.k {
+            let mut closest = (0, 1, ::std::f64::INFINITY);
+            for i in 0..self.centroids.len() {
+                for j in i + 1..self.centroids.len() {
+                    let d = squared_distance(&self.centroids[i],
+                                             &self.centroids[j]);
+                    if d < closest.2 {
+                        closest = (i, j, d);
+                    }
+                }
+            }
+            let (i, j, _) = closest;
+            let (c, n) = (self.centroids.swap_remove(j),
+                          self.counts.swap_remove(j));
+            let n1 = self.counts[i] as f64;
+            let w = n as f64 / (n1 + n as f64);
+            for (c1, &c2) in self.centroids[i].iter_mut().zip(&c) {
+                *c1 += w * (c2 - *c1);
+            }
+            self.counts[i] += n;
+        }
+    }
+}
+
+/// Deserializing checks that there are at most `k` centroids, that each
+/// has `dim` dimensions and that each has samples assigned to it.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for KMeans {
+    fn deserialize<D>(d: D) -> Result<KMeans, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            k: usize,
+            dim: usize,
+            centroids: Vec<Vec<f64>>,
+            counts: Vec<u64>,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.k == 0 || parts.dim == 0 {
+            return Err(D::Error::custom("k and dimension must be positive"));
+        }
+        if parts.centroids.len() > parts.k
+                || parts.counts.len() != parts.centroids.len() {
+            return Err(D::Error::custom("centroids do not match the counts"));
+        }
+        if parts.centroids.iter().any(|c| c.len() != parts.dim) {
+            return Err(D::Error::custom("centroid has the wrong dimension"));
+        }
+        if parts.counts.iter().any(|&n| n == 0) {
+            return Err(D::Error::custom("centroid has no samples"));
+        }
+        Ok(KMeans {
+            k: parts.k,
+            dim: parts.dim,
+            centroids: parts.centroids,
+            counts: parts.counts,
+        })
+    }
+}
+
+impl<V: AsRef<[f64]>> Extend<V> for KMeans {
+    fn extend<I: IntoIterator<Item=V>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KMeans;
+    use Commute;
+
+    /// Samples around `(0, 0)`, `(10, 0)` and `(0, 10)`, interleaved.
+    fn clusters(n: usize) -> Vec<[f64; 2]> {
+        let offsets = [-0.5, 0.5, 0.25, -0.25];
+        (0..n).map(|i| {
+            let e = offsets[i / 3 % 4];
+            match i % 3 {
+                0 => [e, -e],
+                1 => [10.0 + e, e],
+                _ => [-e, 10.0 + e],
+            }
+        }).collect()
+    }
+
+    fn sorted_centroids(km: &KMeans) -> Vec<(i64, i64)> {
+        let mut cs: Vec<(i64, i64)> = km.centroids()
+            .iter()
+            .map(|c| (c[0].round() as i64, c[1].round() as i64))
+            .collect();
+        cs.sort();
+        cs
+    }
+
+    #[test]
+    fn finds_clusters() {
+        let mut km = KMeans::new(3, 2);
+        km.extend(clusters(300));
+        assert_eq!(sorted_centroids(&km), vec![(0, 0), (0, 10), (10, 0)]);
+        assert_eq!(km.counts(), &[100, 100, 100]);
+        assert_eq!(km.len(), 300);
+        assert_eq!(km.nearest(&[9.0, 1.0]), Some(1));
+
+        let mut batched = KMeans::new(3, 2);
+        for batch in clusters(300).chunks(30) {
+            batched.add_batch(batch);
+        }
+        assert_eq!(sorted_centroids(&batched), sorted_centroids(&km));
+        assert_eq!(KMeans::new(2, 2).nearest(&[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn merge() {
+        let data = clusters(300);
+        let mut k1 = KMeans::new(3, 2);
+        k1.extend(&data[..150]);
+        let mut k2 = KMeans::new(3, 2);
+        k2.extend(&data[150..]);
+        k1.merge(k2);
+        assert_eq!(k1.centroids().len(), 3);
+        assert_eq!(k1.len(), 300);
+        assert_eq!(sorted_centroids(&k1), vec![(0, 0), (0, 10), (10, 0)]);
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_centroids() {
+        use serde_json;
+
+        let mut km = KMeans::new(3, 2);
+        km.extend(clusters(30));
+        let json = serde_json::to_string(&km).unwrap();
+        let back: KMeans = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.centroids(), back.counts()),
+                   (km.centroids(), km.counts()));
+
+        let bad = [
+            r#"{"k":2,"dim":2,"centroids":[[0.0,0.0],[1.0]],"counts":[1,1]}"#,
+            r#"{"k":1,"dim":1,"centroids":[[0.0],[1.0]],"counts":[1,1]}"#,
+            r#"{"k":2,"dim":1,"centroids":[[0.0]],"counts":[1,1]}"#,
+            r#"{"k":2,"dim":1,"centroids":[[0.0]],"counts":[0]}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<KMeans>(json).is_err());
+        }
+    }
+}
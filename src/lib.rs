@@ -5,30 +5,117 @@
 #[macro_use]
 extern crate sgx_tstd as std;
 extern crate num_traits;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+#[cfg(feature = "serialize")]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(all(test, feature = "serialize"))]
+extern crate serde_json;
 
 use std::prelude::v1::*;
 
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::btree_map;
+use std::collections::hash_map;
 use std::hash;
 use num_traits::ToPrimitive;
 
-pub use frequency::Frequencies;
-pub use minmax::MinMax;
+pub use bayes::{BetaBinomial, NormalInverseGamma};
+pub use benford::{BenfordTest, Conformity, benford_test, leading_digits};
+pub use bivariate::OnlineBivariate;
+pub use checkpoint::{Checkpoint, CheckpointError};
+pub use circular::CircularStats;
+pub use cohort::{Cohort, CohortPercentiles, compare_percentiles};
+pub use complex::ComplexStats;
+pub use contingency::Contingency;
+pub use countmin::CountMinSketch;
+pub use covariance::CovarianceMatrix;
+pub use distinct::{BoundedDistinct, Distinct};
+pub use ewma::EwmaStats;
+pub use experiment::Experiment;
+pub use exponential::{ExponentialBuckets, ExponentialDataPoint,
+                      ExponentialHistogram};
+pub use finance::{ReturnStats, Returns};
+pub use fixed::{FixedFrequencies, WhenFull};
+pub use frequency::{BoundedFrequencies, ByFrequency, ChiSquaredResult, Count,
+                    DenseFrequencies, Dirichlet, Frequencies, GoodTuring,
+                    ModeTies, Probabilities, RankOptions, Ranked, Smoothing,
+                    ZeroCountError, mode_hashable, modes_hashable};
+pub use hdr::HdrHistogram;
+pub use histogram::{BinRule, CumulativeBuckets, Histogram, Histogram2d};
+pub use hyperloglog::HyperLogLog;
+pub use hypothesis::{Adjustment, LeveneCenter, TestResult, adjust_p_values,
+                     kruskal_wallis, levene_test, reject_hypotheses,
+                     runs_test};
+pub use kde::{Bandwidth, KernelDensity};
+pub use kmeans::KMeans;
+pub use majority::{KMajority, Majority};
+pub use minhash::MinHash;
+pub use minmax::{ArgMinMax, MinMax};
 pub use online::{OnlineStats, stddev, variance, mean};
-pub use unsorted::{Unsorted, median, mode, modes};
+pub use power::{proportion_test_detectable_effect, proportion_test_power,
+                proportion_test_sample_size, t_test_detectable_effect,
+                t_test_power, t_test_sample_size};
+pub use random::{RandomSource, SplitMix};
+#[cfg(all(target_arch = "x86_64", target_feature = "rdrand"))]
+pub use random::Rdrand;
+pub use rank::{RankMethod, percent_rank, rank, spearman};
+pub use records::{Record, Records, records};
+pub use regression::{QuantileRegression, durbin_watson};
+pub use reservoir::Reservoir;
+pub use sample::HashSampler;
+pub use sequential::{Decision, MixtureSprt, Sprt};
+pub use series::SeriesStats;
+pub use survival::{KaplanMeier, SurvivalStep};
+pub use tdigest::TDigest;
+pub use theta::ThetaSketch;
+pub use topk::{TopK, Weight};
+pub use transform::{OutOfRange, Rescale, Standardize, normalize,
+                    standardize};
+pub use trending::Trending;
+pub use unsorted::{BoundedUnsorted, Unsorted, median, mode, modes,
+                   quantile_normalize};
+pub use window::{WindowMinMax, WindowStats};
 
 /// Partial wraps a type that satisfies `PartialOrd` and implements `Ord`.
 ///
 /// This allows types like `f64` to be used in data structures that require
-/// `Ord`. When an ordering is not defined, an arbitrary order is returned.
-#[derive(Clone, PartialEq, PartialOrd)]
+/// `Ord`. Values without an ordering, like `NaN`, sort after all others, as
+/// with `total_cmp`.
+#[derive(Clone, PartialEq)]
 struct Partial<T>(pub T);
 
 impl<T: PartialEq> Eq for Partial<T> {}
 
+impl<T: PartialOrd> PartialOrd for Partial<T> {
+    fn partial_cmp(&self, other: &Partial<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl<T: PartialOrd> Ord for Partial<T> {
     fn cmp(&self, other: &Partial<T>) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Less)
+        total_cmp(&self.0, &other.0)
+    }
+}
+
+/// Compares two values in a total order for sorting.
+///
+/// Values that are not comparable with themselves, like `NaN`, are equal to
+/// each other and greater than all other values. Unlike
+/// `partial_cmp(..).unwrap_or(..)`, this is consistent, which the sorts of
+/// the standard library require.
+fn total_cmp<T: PartialOrd>(a: &T, b: &T) -> Ordering {
+    match a.partial_cmp(b) {
+        Some(ordering) => ordering,
+        None => {
+            let nan = |x: &T| x.partial_cmp(x).is_none();
+            nan(a).cmp(&nan(b))
+        }
     }
 }
 
@@ -69,6 +156,15 @@ pub trait Commute : Sized {
     }
 }
 
+/// Defines an interface for types that can be merged with a borrowed value.
+///
+/// This is equivalent to merging a clone of `other`, but it does not require
+/// cloning `other` first.
+pub trait CommuteRef : Commute {
+    /// Merges the value `other` into `self` without consuming it.
+    fn merge_ref(&mut self, other: &Self);
+}
+
 /// Merges all items in the stream.
 ///
 /// If the stream is empty, `None` is returned.
@@ -79,6 +175,25 @@ pub fn merge_all<T: Commute, I: Iterator<Item=T>>(mut it: I) -> Option<T> {
     }
 }
 
+/// Merges all items in the stream without consuming them.
+///
+/// This is useful for summarizing accumulators that are owned elsewhere,
+/// e.g., the values of a map, without moving them out. Only the first item
+/// is cloned. If the stream is empty, `None` is returned.
+pub fn merge_all_ref<'a, T, I>(mut it: I) -> Option<T>
+        where T: CommuteRef + Clone + 'a, I: Iterator<Item=&'a T> {
+    match it.next() {
+        None => None,
+        Some(first) => {
+            let mut init = first.clone();
+            for v in it {
+                init.merge_ref(v);
+            }
+            Some(init)
+        }
+    }
+}
+
 impl<T: Commute> Commute for Option<T> {
     fn merge(&mut self, other: Option<T>) {
         match *self {
@@ -120,16 +235,141 @@ impl<T: Commute> Commute for Vec<T> {
     }
 }
 
+impl<T: CommuteRef> CommuteRef for Vec<T> {
+    fn merge_ref(&mut self, other: &Vec<T>) {
+        assert_eq!(self.len(), other.len());
+        for (v1, v2) in self.iter_mut().zip(other.iter()) {
+            v1.merge_ref(v2);
+        }
+    }
+}
+
+/// Per-key accumulators are merged by taking the union of their keys. Values
+/// with the same key are merged with each other.
+impl<K, V, S> Commute for HashMap<K, V, S>
+        where K: Eq + hash::Hash, V: Commute, S: hash::BuildHasher {
+    fn merge(&mut self, other: HashMap<K, V, S>) {
+        for (k, v2) in other {
+            match self.entry(k) {
+                hash_map::Entry::Vacant(v1) => { v1.insert(v2); }
+                hash_map::Entry::Occupied(mut v1) => {
+                    v1.get_mut().merge(v2);
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S> CommuteRef for HashMap<K, V, S>
+        where K: Eq + hash::Hash + Clone,
+              V: CommuteRef + Clone,
+              S: hash::BuildHasher {
+    fn merge_ref(&mut self, other: &HashMap<K, V, S>) {
+        for (k, v2) in other {
+            match self.entry(k.clone()) {
+                hash_map::Entry::Vacant(v1) => { v1.insert(v2.clone()); }
+                hash_map::Entry::Occupied(mut v1) => {
+                    v1.get_mut().merge_ref(v2);
+                }
+            }
+        }
+    }
+}
+
+/// Per-key accumulators are merged by taking the union of their keys. Values
+/// with the same key are merged with each other.
+impl<K: Ord, V: Commute> Commute for BTreeMap<K, V> {
+    fn merge(&mut self, other: BTreeMap<K, V>) {
+        for (k, v2) in other {
+            match self.entry(k) {
+                btree_map::Entry::Vacant(v1) => { v1.insert(v2); }
+                btree_map::Entry::Occupied(mut v1) => {
+                    v1.get_mut().merge(v2);
+                }
+            }
+        }
+    }
+}
+
+impl<K, V> CommuteRef for BTreeMap<K, V>
+        where K: Ord + Clone, V: CommuteRef + Clone {
+    fn merge_ref(&mut self, other: &BTreeMap<K, V>) {
+        for (k, v2) in other {
+            match self.entry(k.clone()) {
+                btree_map::Entry::Vacant(v1) => { v1.insert(v2.clone()); }
+                btree_map::Entry::Occupied(mut v1) => {
+                    v1.get_mut().merge_ref(v2);
+                }
+            }
+        }
+    }
+}
+
+mod bayes;
+mod benford;
+mod bivariate;
+mod checkpoint;
+mod circular;
+mod cohort;
+mod complex;
+mod contingency;
+mod countmin;
+mod covariance;
+mod distinct;
+mod distributions;
+mod ewma;
+mod experiment;
+mod exponential;
+mod finance;
+mod fixed;
 mod frequency;
+mod hashing;
+mod hdr;
+mod histogram;
+mod hyperloglog;
+mod hypothesis;
+mod kde;
+mod kmeans;
+mod majority;
+mod minhash;
 mod minmax;
 mod online;
+mod power;
+mod random;
+mod rank;
+mod records;
+mod regression;
+mod reservoir;
+mod sample;
+mod sequential;
+mod series;
+mod survival;
+mod tdigest;
+mod theta;
+mod topk;
+mod transform;
+mod trending;
 mod unsorted;
+mod window;
 
 #[cfg(test)]
 mod test {
     use Commute;
     use unsorted::Unsorted;
 
+    #[test]
+    fn total_order() {
+        use std::f64::NAN;
+        use super::total_cmp;
+
+        let mut xs: Vec<f64> = (0..40).map(|i| (i * 7 % 40) as f64).collect();
+        xs[3] = NAN;
+        xs[17] = NAN;
+        xs.sort_by(total_cmp);
+        assert!(xs[38].is_nan() && xs[39].is_nan());
+        assert!(xs[..38].windows(2).all(|w| w[0] < w[1]));
+    }
+
     #[test]
     fn options() {
         let v1: Unsorted<usize> = vec![2, 1, 3, 2].into_iter().collect();
@@ -138,4 +378,539 @@ mod test {
         merged.merge(Some(v2));
         assert_eq!(merged.unwrap().mode(), Some(5));
     }
+
+    #[test]
+    fn keyed() {
+        use std::collections::{BTreeMap, HashMap};
+        use OnlineStats;
+
+        let mut m1 = HashMap::new();
+        m1.insert("a", OnlineStats::from_slice(&[1, 2]));
+        m1.insert("b", OnlineStats::from_slice(&[5]));
+        let mut m2 = HashMap::new();
+        m2.insert("a", OnlineStats::from_slice(&[3]));
+        m2.insert("c", OnlineStats::from_slice(&[7]));
+        m1.merge(m2);
+        assert_eq!(m1.len(), 3);
+        assert_eq!(m1["a"].mean(), 2.0);
+        assert_eq!(m1["c"].len(), 1);
+
+        let mut b1: BTreeMap<u8, Unsorted<u8>> = BTreeMap::new();
+        b1.insert(1, vec![1, 1].into_iter().collect());
+        let mut b2 = BTreeMap::new();
+        b2.insert(1, vec![2].into_iter().collect());
+        b2.insert(2, vec![3].into_iter().collect());
+        // `BTreeMap` may gain an inherent `merge`, so be explicit.
+        Commute::merge(&mut b1, b2);
+        assert_eq!(b1.get_mut(&1).unwrap().len(), 3);
+        assert_eq!(b1.keys().cloned().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn merge_all_ref() {
+        use std::collections::HashMap;
+        use {MinMax, OnlineStats};
+
+        let mut by_key = HashMap::new();
+        by_key.insert("a", OnlineStats::from_slice(&[1, 2, 3]));
+        by_key.insert("b", OnlineStats::from_slice(&[2, 4, 6]));
+        let total = super::merge_all_ref(by_key.values()).unwrap();
+        assert_eq!(total.len(), 6);
+        assert_eq!(total.mean(), 3.0);
+        assert_eq!(by_key.len(), 2);
+
+        let mxs: Vec<MinMax<i32>> = vec![
+            vec![3, 1].into_iter().collect(),
+            vec![7].into_iter().collect(),
+        ];
+        let mx = super::merge_all_ref(mxs.iter()).unwrap();
+        assert_eq!((mx.min(), mx.max()), (Some(&1), Some(&7)));
+        assert!(super::merge_all_ref::<Unsorted<u8>, _>([].iter())
+                .is_none());
+    }
+}
+
+#[cfg(all(test, feature = "serialize"))]
+mod serde_test {
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+    use serde_json;
+
+    use Commute;
+
+    fn roundtrip<T: Serialize + DeserializeOwned>(v: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(v).unwrap()).unwrap()
+    }
+
+    fn assert_close(got: &[f64], expected: &[f64]) {
+        assert_eq!(got.len(), expected.len());
+        for (&g, &e) in got.iter().zip(expected) {
+            assert!(g == e || (g - e).abs() <= 1e-9 * e.abs().max(1.0),
+                    "{:?} != {:?}", got, expected);
+        }
+    }
+
+    /// Checks that merging deserialized states gives the same answers as
+    /// merging the originals, and that the merge itself round trips.
+    ///
+    /// States are compared through `observe` rather than their encoding,
+    /// since hash tables may encode in any order.
+    fn merge_roundtrip<T, F>(a: T, b: T, observe: F)
+            where T: Commute + Clone + Serialize + DeserializeOwned,
+                  F: Fn(&mut T) -> Vec<f64> {
+        let mut expected = a.clone();
+        expected.merge(b.clone());
+        let mut merged = roundtrip(&a);
+        merged.merge(roundtrip(&b));
+        assert_close(&observe(&mut merged), &observe(&mut expected));
+        assert_close(&observe(&mut roundtrip(&expected)),
+                     &observe(&mut expected));
+    }
+
+    /// Checks that a deserialized state keeps giving the same answers as
+    /// the original after more samples are added to both.
+    fn resume_roundtrip<T, G, F>(v: T, more: G, observe: F)
+            where T: Serialize + DeserializeOwned,
+                  G: Fn(&mut T),
+                  F: Fn(&mut T) -> Vec<f64> {
+        let mut resumed = roundtrip(&v);
+        let mut expected = v;
+        assert_close(&observe(&mut resumed), &observe(&mut expected));
+        more(&mut resumed);
+        more(&mut expected);
+        assert_close(&observe(&mut resumed), &observe(&mut expected));
+    }
+
+    const XS: &'static [f64] = &[3.0, 1.5, 4.0, 1.0, 5.5, 9.0, 2.5, 6.0];
+    const YS: &'static [f64] = &[2.0, 7.0, 1.0, 8.0, 2.5, 8.5, 3.0, 0.5];
+
+    fn pairs(xs: &[f64], ys: &[f64]) -> Vec<(f64, f64)> {
+        xs.iter().cloned().zip(ys.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn online() {
+        use OnlineStats;
+
+        merge_roundtrip(OnlineStats::from_slice(XS),
+                        OnlineStats::from_slice(YS),
+                        |s| vec![s.len() as f64, s.mean(), s.variance()]);
+    }
+
+    #[test]
+    fn minmax() {
+        use {ArgMinMax, MinMax};
+
+        let a: MinMax<u32> = vec![4, 2, 9].into_iter().collect();
+        let b: MinMax<u32> = vec![7, 1].into_iter().collect();
+        merge_roundtrip(a, b, |m| vec![
+            *m.min().unwrap() as f64, *m.max().unwrap() as f64,
+            m.len() as f64,
+        ]);
+
+        let a: ArgMinMax<u32> = vec![4, 2, 9].into_iter().collect();
+        resume_roundtrip(a, |m| m.extend(vec![1, 12]), |m| vec![
+            m.argmin().unwrap() as f64, m.argmax().unwrap() as f64,
+            *m.min().unwrap() as f64, *m.max().unwrap() as f64,
+        ]);
+    }
+
+    #[test]
+    fn unsorted() {
+        use Unsorted;
+
+        let a: Unsorted<u32> = vec![5, 1, 4, 4, 8].into_iter().collect();
+        let b: Unsorted<u32> = vec![2, 9, 4].into_iter().collect();
+        merge_roundtrip(a, b, |u| vec![
+            u.len() as f64, u.median().unwrap(), u.quantile(0.9).unwrap(),
+            u.mode().unwrap() as f64,
+        ]);
+    }
+
+    #[test]
+    fn frequencies() {
+        use {DenseFrequencies, Frequencies};
+
+        let a: Frequencies<u32> = vec![1, 1, 2, 3].into_iter().collect();
+        let b: Frequencies<u32> = vec![2, 2, 4].into_iter().collect();
+        merge_roundtrip(a, b, |f| vec![
+            f.count(&1) as f64, f.count(&2) as f64, f.count(&4) as f64,
+            f.cardinality() as f64, f.total() as f64,
+        ]);
+
+        let mut a = DenseFrequencies::new(0i32, 9);
+        a.extend(vec![1, 1, 2, 9]);
+        let mut b = DenseFrequencies::new(0i32, 9);
+        b.extend(vec![2, 2, 0]);
+        merge_roundtrip(a, b, |f| vec![
+            f.count(&0) as f64, f.count(&1) as f64, f.count(&2) as f64,
+            f.mode().unwrap() as f64, f.len() as f64,
+        ]);
+
+        let f: Frequencies<u32> =
+            vec![1, 2, 3, 4, 4, 5, 5, 6, 6, 6].into_iter().collect();
+        let gt = f.good_turing().unwrap();
+        let back = roundtrip(&gt);
+        assert_close(&[back.unseen(), back.probability(2).unwrap()],
+                     &[gt.unseen(), gt.probability(2).unwrap()]);
+    }
+
+    #[test]
+    fn histograms() {
+        use {ExponentialHistogram, HdrHistogram, Histogram, Histogram2d};
+
+        let mut a = Histogram::new(0.0, 10.0, 5);
+        a.extend(XS.iter().cloned());
+        let mut b = Histogram::new(0.0, 10.0, 5);
+        b.extend(YS.iter().cloned().chain(vec![-1.0, 11.0]));
+        merge_roundtrip(a, b, |h| {
+            let mut obs: Vec<f64> =
+                h.counts().iter().map(|&c| c as f64).collect();
+            obs.push(h.underflow() as f64);
+            obs.push(h.overflow() as f64);
+            obs.push(h.sum());
+            obs
+        });
+        let mut h = Histogram::new(0.0, 4.0, 2);
+        h.extend(vec![1.0, 2.5, 7.0]);
+        let buckets = h.to_cumulative_buckets();
+        assert_eq!(roundtrip(&buckets), buckets);
+
+        let mut a = Histogram2d::new(0.0, 10.0, 3, 0.0, 10.0, 2);
+        a.extend(XS.iter().cloned().zip(YS.iter().cloned()));
+        let mut b = Histogram2d::new(0.0, 10.0, 3, 0.0, 10.0, 2);
+        b.extend(YS.iter().cloned().zip(XS.iter().cloned()));
+        merge_roundtrip(a, b, |h| {
+            let mut obs: Vec<f64> =
+                h.counts().iter().map(|&c| c as f64).collect();
+            obs.push(h.outside() as f64);
+            obs.push(h.x_marginal().sum());
+            obs
+        });
+
+        let a: HdrHistogram = (1..1000).collect();
+        let b: HdrHistogram = (500..5000).collect();
+        merge_roundtrip(a, b, |h| vec![
+            h.value_at_quantile(0.5).unwrap() as f64,
+            h.value_at_quantile(0.99).unwrap() as f64,
+            h.min().unwrap() as f64, h.max().unwrap() as f64,
+            h.len() as f64,
+        ]);
+
+        let mut a = ExponentialHistogram::with_max_size(8);
+        a.extend(XS.iter().cloned().chain(vec![0.0, -2.0]));
+        let mut b = ExponentialHistogram::with_max_size(8);
+        b.extend(YS.iter().map(|&y| y * 100.0));
+        merge_roundtrip(a, b, |h| {
+            let mut obs = vec![
+                h.scale() as f64, h.zero_count() as f64, h.sum(),
+                h.min().unwrap(), h.max().unwrap(), h.len() as f64,
+            ];
+            for (lo, hi, count) in h.buckets() {
+                obs.extend(vec![lo, hi, count as f64]);
+            }
+            obs
+        });
+        let mut h = ExponentialHistogram::with_max_size(8);
+        h.extend(vec![1.0, 0.5, -4.0, 0.0]);
+        let point = h.to_otlp();
+        assert_eq!(roundtrip(&point), point);
+    }
+
+    #[test]
+    fn sketches() {
+        use {CountMinSketch, HyperLogLog, MinHash, TDigest, ThetaSketch};
+
+        let mut a = HyperLogLog::with_precision(10);
+        a.extend(0..500u32);
+        let mut b = HyperLogLog::with_precision(10);
+        b.extend(250..2000u32);
+        merge_roundtrip(a, b, |h| vec![h.cardinality() as f64]);
+
+        let a: TDigest = (0..1000).collect();
+        let b: TDigest = (500..3000).collect();
+        merge_roundtrip(a, b, |t| vec![
+            t.quantile(0.1).unwrap(), t.quantile(0.5).unwrap(),
+            t.quantile(0.99).unwrap(),
+        ]);
+
+        let mut a = CountMinSketch::new(64, 4);
+        a.extend(vec![1u32, 1, 2, 7]);
+        let mut b = CountMinSketch::new(64, 4);
+        b.extend(vec![7u32, 7, 3]);
+        merge_roundtrip(a, b, |c| vec![
+            c.count(&1) as f64, c.count(&7) as f64, c.count(&9) as f64,
+            c.len() as f64,
+        ]);
+
+        let mut a = MinHash::with_len(32);
+        a.extend(0..40u32);
+        let mut b = MinHash::with_len(32);
+        b.extend(20..70u32);
+        merge_roundtrip(a, b, |m| {
+            m.signature().iter().map(|&h| h as f64).collect()
+        });
+
+        let mut a = ThetaSketch::with_nominal_entries(16);
+        a.extend(0..100u32);
+        let mut b = ThetaSketch::with_nominal_entries(16);
+        b.extend(50..300u32);
+        merge_roundtrip(a, b, |t| vec![
+            t.estimate(), t.theta(), t.retained() as f64,
+        ]);
+    }
+
+    #[test]
+    fn counters() {
+        use {
+            BoundedDistinct, Distinct, FixedFrequencies, KMajority,
+            Majority, TopK, Trending, WhenFull,
+        };
+
+        let a: Distinct<u32> = vec![1, 2, 3].into_iter().collect();
+        let b: Distinct<u32> = vec![3, 4].into_iter().collect();
+        merge_roundtrip(a, b, |d| vec![
+            d.cardinality() as f64, d.contains(&4) as u8 as f64,
+        ]);
+
+        let mut a = BoundedDistinct::with_limit(4);
+        a.extend(vec![1u32, 2, 3]);
+        let mut b = BoundedDistinct::with_limit(4);
+        b.extend(vec![3u32, 4, 5]);
+        merge_roundtrip(a, b, |d| vec![
+            d.cardinality() as f64, d.is_exact() as u8 as f64,
+        ]);
+
+        let mut a = FixedFrequencies::new(3, WhenFull::Reject);
+        a.extend(vec![1u32, 1, 2]);
+        let mut b = FixedFrequencies::new(3, WhenFull::Reject);
+        b.extend(vec![2u32, 3, 3, 3]);
+        merge_roundtrip(a, b, |f| vec![
+            f.count(&1) as f64, f.count(&2) as f64, f.count(&3) as f64,
+            f.dropped() as f64,
+        ]);
+        assert_eq!(roundtrip(&WhenFull::EvictLeastFrequent),
+                   WhenFull::EvictLeastFrequent);
+
+        let a: Majority<u32> = vec![1, 1, 2].into_iter().collect();
+        let b: Majority<u32> = vec![1, 3].into_iter().collect();
+        merge_roundtrip(a, b, |m| vec![
+            *m.candidate().unwrap() as f64, m.len() as f64,
+        ]);
+
+        let mut a = KMajority::new(3);
+        a.extend(vec![1u32, 1, 2, 5]);
+        let mut b = KMajority::new(3);
+        b.extend(vec![2u32, 2, 1]);
+        merge_roundtrip(a, b, |m| {
+            let mut c: Vec<f64> =
+                m.candidates().into_iter().map(|&v| v as f64).collect();
+            c.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            c
+        });
+
+        // Distinct counts and enough capacity keep the answers free of
+        // ties, which hash tables may break either way.
+        let mut a = TopK::new(8);
+        a.extend(vec![1u32, 1, 1, 2, 2, 3]);
+        let mut b = TopK::new(8);
+        b.extend(vec![1u32, 2, 4, 4, 4, 4]);
+        merge_roundtrip(a, b, |t| {
+            let mut obs = vec![];
+            for (&v, count) in t.top(4) {
+                obs.extend(vec![v as f64, count as f64]);
+            }
+            obs
+        });
+
+        let mut a = Trending::new(8, 10.0);
+        a.extend(vec![(1u32, 0.0), (1, 1.0), (2, 2.0)]);
+        let mut b = Trending::new(8, 10.0);
+        b.extend(vec![(2u32, 3.0), (3, 4.0), (1, 5.0)]);
+        merge_roundtrip(a, b, |t| vec![
+            t.score(&1, 6.0), t.score(&2, 6.0), t.score(&3, 6.0),
+        ]);
+    }
+
+    #[test]
+    fn multivariate() {
+        use {
+            CircularStats, ComplexStats, CovarianceMatrix, KMeans,
+            OnlineBivariate,
+        };
+
+        let mut a = OnlineBivariate::new();
+        a.extend(pairs(&XS[..4], &YS[..4]));
+        let mut b = OnlineBivariate::new();
+        b.extend(pairs(&XS[4..], &YS[4..]));
+        merge_roundtrip(a, b, |s| vec![
+            s.mean_x(), s.mean_y(), s.covariance(), s.slope(),
+            s.len() as f64,
+        ]);
+
+        let mut a = ComplexStats::new();
+        a.extend(pairs(&XS[..4], &YS[..4]));
+        let mut b = ComplexStats::new();
+        b.extend(pairs(&XS[4..], &YS[4..]));
+        merge_roundtrip(a, b, |s| vec![s.mean().0, s.mean().1, s.variance()]);
+
+        let a: CircularStats = XS.iter().cloned().collect();
+        let b: CircularStats = YS.iter().cloned().collect();
+        merge_roundtrip(a, b, |s| vec![
+            s.mean().unwrap(), s.resultant_length(), s.len() as f64,
+        ]);
+
+        let rows = |xs: &[f64]| -> Vec<[f64; 2]> {
+            xs.iter().map(|&x| [x, x * x - 3.0]).collect()
+        };
+        let mut a = CovarianceMatrix::new(2);
+        a.extend(rows(XS));
+        let mut b = CovarianceMatrix::new(2);
+        b.extend(rows(YS));
+        merge_roundtrip(a, b, |c| {
+            let mut obs = c.mean().to_vec();
+            for row in c.covariance() {
+                obs.extend(row);
+            }
+            obs.push(c.mahalanobis(&[1.0, 1.0]).unwrap());
+            obs
+        });
+
+        let mut a = KMeans::new(2, 1);
+        a.extend(vec![[0.0], [0.5], [10.0]]);
+        let mut b = KMeans::new(2, 1);
+        b.extend(vec![[9.5], [1.0], [11.0]]);
+        merge_roundtrip(a, b, |k| {
+            let mut obs: Vec<f64> =
+                k.centroids().iter().map(|c| c[0]).collect();
+            obs.extend(k.counts().iter().map(|&n| n as f64));
+            obs
+        });
+    }
+
+    #[test]
+    fn inference() {
+        use {
+            BetaBinomial, ChiSquaredResult, Decision, Experiment,
+            KaplanMeier, MixtureSprt, NormalInverseGamma, OnlineStats, Sprt,
+            SurvivalStep, TestResult,
+        };
+
+        let mut a = BetaBinomial::new();
+        a.add_trials(3, 10);
+        let mut b = BetaBinomial::with_prior(1.0, 1.0);
+        b.add_trials(7, 12);
+        merge_roundtrip(a, b, |p| vec![
+            p.successes() as f64, p.trials() as f64, p.posterior_mean(),
+        ]);
+
+        let mut a = NormalInverseGamma::new(0.0, 1.0, 1.0, 1.0);
+        a.extend(XS.iter().cloned());
+        let mut b = NormalInverseGamma::new(0.0, 1.0, 1.0, 1.0);
+        b.extend(YS.iter().cloned());
+        merge_roundtrip(a, b, |p| {
+            let (mu, kappa, alpha, beta) = p.posterior();
+            vec![mu, kappa, alpha, beta, p.len() as f64]
+        });
+
+        let a = Experiment::new(OnlineStats::from_slice(XS),
+                                OnlineStats::from_slice(YS));
+        let b = Experiment::new(OnlineStats::from_slice(YS),
+                                OnlineStats::from_slice(&[4, 5, 6]));
+        merge_roundtrip(a, b, |e| vec![e.difference(), e.std_err()]);
+
+        let mut a = KaplanMeier::new();
+        a.extend(vec![(1.0, false), (3.0, true), (4.0, false)]);
+        let mut b = KaplanMeier::new();
+        b.extend(vec![(2.0, false), (5.0, false), (6.0, true)]);
+        merge_roundtrip(a, b, |km| vec![
+            km.survival(3.5), km.median().unwrap(), km.len() as f64,
+        ]);
+
+        let mut sprt = Sprt::bernoulli(0.5, 0.7, 0.05, 0.2);
+        sprt.extend(vec![true, false, true]);
+        resume_roundtrip(sprt, |s| s.extend(vec![true, true]), |s| vec![
+            s.log_likelihood_ratio(), s.len() as f64,
+        ]);
+
+        let mut msprt = MixtureSprt::new(0.0, 1.0, 1.0, 0.05);
+        msprt.extend(vec![0.5, 1.5, -0.25]);
+        resume_roundtrip(msprt, |s| s.extend(vec![2.0, 1.0]), |s| vec![
+            s.p_value(), s.mean().unwrap(), s.len() as f64,
+        ]);
+
+        assert_eq!(roundtrip(&Decision::RejectNull), Decision::RejectNull);
+        let result = TestResult { statistic: 2.5, p_value: 0.125 };
+        assert_eq!(roundtrip(&result), result);
+        let result = ChiSquaredResult {
+            statistic: 6.25, df: 3, p_value: 0.0625,
+        };
+        assert_eq!(roundtrip(&result), result);
+        let step = SurvivalStep {
+            time: 2.0, at_risk: 8, events: 1, survival: 0.875,
+            std_err: 0.125,
+        };
+        assert_eq!(roundtrip(&step), step);
+    }
+
+    #[test]
+    fn sequences() {
+        use {
+            EwmaStats, HashSampler, QuantileRegression, Reservoir,
+            ReturnStats, Returns, SeriesStats, WindowMinMax, WindowStats,
+        };
+
+        let mut a = Reservoir::with_seed(4, 7);
+        a.extend(0..20u32);
+        let mut b = Reservoir::with_seed(4, 9);
+        b.extend(100..130u32);
+        merge_roundtrip(a, b, |r| {
+            r.sample().iter().map(|&v| v as f64).collect()
+        });
+
+        let mut ewma = EwmaStats::with_half_life(4.0);
+        ewma.extend(XS.iter().cloned());
+        resume_roundtrip(ewma, |s| s.extend(YS.iter().cloned()), |s| vec![
+            s.mean(), s.variance(), s.len() as f64,
+        ]);
+
+        let mut returns = ReturnStats::new(Returns::Log, 252.0);
+        for &p in &[100.0, 102.0, 99.0, 101.0] {
+            returns.add_price(p);
+        }
+        resume_roundtrip(returns, |s| s.add_price(104.0), |s| vec![
+            s.annualized_mean(), s.annualized_volatility(), s.len() as f64,
+        ]);
+        assert_eq!(roundtrip(&Returns::Simple), Returns::Simple);
+
+        let mut series = SeriesStats::new();
+        series.extend(XS.iter().cloned());
+        resume_roundtrip(series, |s| s.extend(YS.iter().cloned()), |s| vec![
+            s.max_drawdown(), s.longest_increasing_run() as f64,
+            s.peak().unwrap(), s.len() as f64,
+        ]);
+
+        let mut window = WindowStats::new(4);
+        window.extend(XS.iter().cloned());
+        resume_roundtrip(window, |s| s.extend(vec![7.0, 8.0]), |s| vec![
+            s.mean(), s.variance(), s.len() as f64,
+        ]);
+
+        let mut window = WindowMinMax::new(3);
+        window.extend(vec![4u32, 1, 8, 3]);
+        resume_roundtrip(window, |s| s.extend(vec![9, 2]), |s| vec![
+            *s.min().unwrap() as f64, *s.max().unwrap() as f64,
+        ]);
+
+        let sampler = HashSampler::with_seed(0.5, 3);
+        let back = roundtrip(&sampler);
+        for key in 0..50u32 {
+            assert_eq!(back.contains(&key), sampler.contains(&key));
+        }
+
+        let fit = QuantileRegression::fit(XS, YS, 0.5).unwrap();
+        let back = roundtrip(&fit);
+        assert_close(&[back.intercept(), back.slope(), back.predict(3.0)],
+                     &[fit.intercept(), fit.slope(), fit.predict(3.0)]);
+    }
 }
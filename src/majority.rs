@@ -0,0 +1,265 @@
+use std::prelude::v1::*;
+
+use std::borrow::Borrow;
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use Commute;
+
+/// A commutative data structure for finding the majority element of a
+/// stream in constant space.
+///
+/// This is the Boyer–Moore majority vote algorithm. If some value occurs in
+/// more than half of the samples, it is guaranteed to be the candidate.
+/// The converse does not hold: when there is no majority, the candidate is
+/// an arbitrary sample. Use `verify` with a second pass over the data to
+/// confirm it.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Majority<T> {
+    candidate: Option<T>,
+    count: u64,
+    len: u64,
+}
+
+impl<T: PartialEq> Majority<T> {
+    /// Create an empty majority vote.
+    pub fn new() -> Majority<T> {
+        Default::default()
+    }
+
+    /// Add a sample to the vote.
+    pub fn add(&mut self, v: T) {
+        self.len += 1;
+        if self.count == 0 {
+            self.candidate = Some(v);
+            self.count = 1;
+        } else if self.candidate.as_ref() == Some(&v) {
+            self.count += 1;
+        } else {
+            self.count -= 1;
+        }
+    }
+
+    /// Returns the only value that can be a majority element.
+    ///
+    /// `None` is returned if there certainly is no majority element.
+    pub fn candidate(&self) -> Option<&T> {
+        if self.count == 0 { None } else { self.candidate.as_ref() }
+    }
+
+    /// Returns the majority element, confirmed by a second pass over the
+    /// same data.
+    pub fn verify<I>(&self, it: I) -> Option<&T>
+            where I: IntoIterator, <I as IntoIterator>::Item: Borrow<T> {
+        let candidate = self.candidate()?;
+        let (mut n, mut count) = (0u64, 0u64);
+        for v in it {
+            n += 1;
+            if v.borrow() == candidate {
+                count += 1;
+            }
+        }
+        if count > n / 2 { Some(candidate) } else { None }
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<T: PartialEq> Commute for Majority<T> {
+    fn merge(&mut self, v: Majority<T>) {
+        self.len += v.len;
+        if self.candidate == v.candidate {
+            self.count += v.count;
+        } else if v.count > self.count {
+            self.candidate = v.candidate;
+            self.count = v.count - self.count;
+        } else {
+            self.count -= v.count;
+        }
+    }
+}
+
+impl<T> Default for Majority<T> {
+    fn default() -> Majority<T> {
+        Majority { candidate: None, count: 0, len: 0 }
+    }
+}
+
+impl<T: PartialEq> FromIterator<T> for Majority<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Majority<T> {
+        let mut v = Majority::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: PartialEq> Extend<T> for Majority<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// A commutative data structure for finding every value that occurs in more
+/// than `1/k` of the samples.
+///
+/// This is the Misra–Gries generalization of the majority vote. It keeps at
+/// most `k - 1` counters, and every value that occurs more than `n / k`
+/// times is guaranteed to be among the candidates. As with `Majority`, a
+/// second pass with `verify` removes the false positives.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct KMajority<T> {
+    k: usize,
+    counters: Vec<(T, u64)>,
+    len: u64,
+}
+
+impl<T: PartialEq> KMajority<T> {
+    /// Create an empty vote for values occurring in more than `1/k` of the
+    /// samples.
+    ///
+    /// This panics if `k < 2`.
+    pub fn new(k: usize) -> KMajority<T> {
+        assert!(k >= 2, "k must be at least 2");
+        KMajority { k, counters: Vec::with_capacity(k - 1), len: 0 }
+    }
+
+    /// Add a sample to the vote.
+    pub fn add(&mut self, v: T) {
+        self.len += 1;
+        if let Some(c) = self.counters.iter_mut().find(|c| c.0 == v) {
+            c.1 += 1;
+            return;
+        }
+        if self.counters.len() < self.k - 1 {
+            self.counters.push((v, 1));
+        } else {
+            for c in &mut self.counters {
+                c.1 -= 1;
+            }
+            self.counters.retain(|c| c.1 > 0);
+        }
+    }
+
+    /// Returns the values that can occur in more than `1/k` of the samples.
+    pub fn candidates(&self) -> Vec<&T> {
+        self.counters.iter().map(|c| &c.0).collect()
+    }
+
+    /// Returns the values that occur in more than `1/k` of the samples,
+    /// confirmed by a second pass over the same data.
+    pub fn verify<I>(&self, it: I) -> Vec<&T>
+            where I: IntoIterator, <I as IntoIterator>::Item: Borrow<T> {
+        let mut counts = vec![0u64; self.counters.len()];
+        let mut n = 0u64;
+        for v in it {
+            n += 1;
+            let v = v.borrow();
+            if let Some(i) = self.counters.iter().position(|c| &c.0 == v) {
+                counts[i] += 1;
+            }
+        }
+        let k = self.k as u64;
+        self.counters.iter()
+                     .zip(counts)
+                     .filter(|&(_, count)| count * k > n)
+                     .map(|(c, _)| &c.0)
+                     .collect()
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<T: PartialEq> Commute for KMajority<T> {
+    fn merge(&mut self, v: KMajority<T>) {
+        assert_eq!(self.k, v.k);
+        self.len += v.len;
+        for (x, count) in v.counters {
+            match self.counters.iter().position(|c| c.0 == x) {
+                Some(i) => self.counters[i].1 += count,
+                None => self.counters.push((x, count)),
+            }
+        }
+        // Subtracting the k-th largest count from every counter leaves at
+        // most `k - 1` positive counters and keeps the guarantee intact.
+        if self.counters.len() >= self.k {
+            let mut counts: Vec<u64> =
+                self.counters.iter().map(|c| c.1).collect();
+            counts.sort_unstable_by(|a, b| b.cmp(a));
+            let cut = counts[self.k - 1];
+            for c in &mut self.counters {
+                c.1 = c.1.saturating_sub(cut);
+            }
+            self.counters.retain(|c| c.1 > 0);
+        }
+    }
+}
+
+impl<T: PartialEq> Extend<T> for KMajority<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KMajority, Majority};
+    use Commute;
+
+    #[test]
+    fn majority() {
+        let data = vec![1, 2, 1, 3, 1, 1, 2];
+        let vote: Majority<u8> = data.iter().cloned().collect();
+        assert_eq!(vote.candidate(), Some(&1));
+        assert_eq!(vote.verify(&data), Some(&1));
+
+        let data = vec![1, 2, 3, 3, 2];
+        let vote: Majority<u8> = data.iter().cloned().collect();
+        assert_eq!(vote.verify(&data), None);
+        assert_eq!(Majority::<u8>::new().candidate(), None);
+    }
+
+    #[test]
+    fn majority_merge() {
+        let mut v1: Majority<&str> = vec!["a", "b", "a"].into_iter().collect();
+        let v2: Majority<&str> =
+            vec!["a", "c", "a", "a"].into_iter().collect();
+        v1.merge(v2);
+        assert_eq!(v1.candidate(), Some(&"a"));
+        assert_eq!(v1.len(), 7);
+    }
+
+    #[test]
+    fn k_majority() {
+        let data = vec![1, 2, 3, 1, 2, 4, 1, 2, 5, 6];
+        let mut vote = KMajority::new(4);
+        vote.extend(data.iter().cloned());
+        let mut found: Vec<u8> =
+            vote.verify(&data).into_iter().cloned().collect();
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+    }
+
+    #[test]
+    fn k_majority_merge() {
+        let data = vec![1, 2, 3, 1, 2, 4, 1, 2, 5, 1, 1, 7];
+        let mut v1 = KMajority::new(3);
+        v1.extend(data[..6].iter().cloned());
+        let mut v2 = KMajority::new(3);
+        v2.extend(data[6..].iter().cloned());
+        v1.merge(v2);
+        assert!(v1.candidates().len() <= 2);
+        assert_eq!(v1.verify(&data), vec![&1]);
+    }
+}
@@ -0,0 +1,174 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::{FromIterator, IntoIterator};
+use std::marker::PhantomData;
+
+use {Commute, CommuteRef};
+use hashing::{hash_with_seed, mix};
+
+/// The default number of hashes in a `MinHash` signature.
+const DEFAULT_SIGNATURE_LEN: usize = 128;
+
+/// A commutative data structure for estimating the Jaccard similarity of
+/// sets.
+///
+/// The signature keeps, for each of `k` hash functions, the smallest hash
+/// of any sample. Two sets agree on a given minimum with probability equal
+/// to their Jaccard similarity, so the fraction of agreeing positions
+/// estimates it with a standard error of at most `1 / (2 sqrt(k))`.
+///
+/// Merging two signatures gives the signature of the union of their data,
+/// so signatures can be built per shard. Samples are hashed with a stable
+/// hasher, so signatures built on different machines (with the same
+/// endianness) are comparable.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct MinHash<T> {
+    mins: Vec<u64>,
+    _type: PhantomData<T>,
+}
+
+impl<T> fmt::Debug for MinHash<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MinHash(k={})", self.mins.len())
+    }
+}
+
+impl<T> MinHash<T> {
+    /// Create an empty signature with 128 hashes.
+    pub fn new() -> MinHash<T> {
+        MinHash::with_len(DEFAULT_SIGNATURE_LEN)
+    }
+
+    /// Create an empty signature with `k` hashes.
+    ///
+    /// This panics if `k` is `0`.
+    pub fn with_len(k: usize) -> MinHash<T> {
+        assert!(k > 0, "signature length must be positive");
+        MinHash { mins: vec![::std::u64::MAX; k], _type: PhantomData }
+    }
+
+    /// Returns the estimated Jaccard similarity of the data of both
+    /// signatures, `|A ∩ B| / |A ∪ B|`.
+    ///
+    /// If both signatures are empty, `0` is returned, as with
+    /// `HyperLogLog::jaccard`.
+    ///
+    /// This panics if the signatures have different lengths.
+    pub fn jaccard(&self, other: &MinHash<T>) -> f64 {
+        assert_eq!(self.mins.len(), other.mins.len());
+        if self.is_empty() && other.is_empty() {
+            return 0.0;
+        }
+        let same = self.mins.iter()
+            .zip(&other.mins)
+            .filter(|&(a, b)| a == b)
+            .count();
+        same as f64 / self.mins.len() as f64
+    }
+
+    /// Returns the signature: the smallest hash for each hash function.
+    pub fn signature(&self) -> &[u64] {
+        &self.mins
+    }
+
+    /// Returns the number of hashes in the signature.
+    pub fn signature_len(&self) -> usize {
+        self.mins.len()
+    }
+
+    /// Returns true if no samples have been added.
+    pub fn is_empty(&self) -> bool {
+        self.mins.iter().all(|&m| m == ::std::u64::MAX)
+    }
+}
+
+impl<T: Hash> MinHash<T> {
+    /// Add a sample to the signature.
+    pub fn add(&mut self, v: T) {
+        // Deriving every hash from one hash of the sample is much cheaper
+        // than hashing the sample `k` times.
+        let hash = hash_with_seed(&v, 0);
+        for (i, min) in self.mins.iter_mut().enumerate() {
+            let h = mix(hash ^ mix(i as u64 + 1));
+            if h < *min {
+                *min = h;
+            }
+        }
+    }
+}
+
+impl<T> Commute for MinHash<T> {
+    fn merge(&mut self, v: MinHash<T>) {
+        self.merge_ref(&v);
+    }
+}
+
+impl<T> CommuteRef for MinHash<T> {
+    fn merge_ref(&mut self, v: &MinHash<T>) {
+        assert_eq!(self.mins.len(), v.mins.len());
+        for (m1, &m2) in self.mins.iter_mut().zip(&v.mins) {
+            if m2 < *m1 {
+                *m1 = m2;
+            }
+        }
+    }
+}
+
+impl<T> Default for MinHash<T> {
+    fn default() -> MinHash<T> {
+        MinHash::new()
+    }
+}
+
+impl<T: Hash> FromIterator<T> for MinHash<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> MinHash<T> {
+        let mut v = MinHash::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Hash> Extend<T> for MinHash<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MinHash;
+    use Commute;
+
+    #[test]
+    fn similarity() {
+        let a: MinHash<u32> = (0..1000).collect();
+        let b: MinHash<u32> = (500..1500).collect();
+        // The true similarity is 500 / 1500.
+        assert!((a.jaccard(&b) - 1.0 / 3.0).abs() < 0.15);
+        assert_eq!(a.jaccard(&a), 1.0);
+
+        let c: MinHash<u32> = (5000..6000).collect();
+        assert!(a.jaccard(&c) < 0.05);
+
+        let empty = MinHash::<u32>::new();
+        assert_eq!(empty.jaccard(&empty), 0.0);
+        assert_eq!(empty.jaccard(&a), 0.0);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a: MinHash<&str> = vec!["x", "y"].into_iter().collect();
+        let b: MinHash<&str> = vec!["z"].into_iter().collect();
+        let all: MinHash<&str> = vec!["z", "y", "x"].into_iter().collect();
+        a.merge(b);
+        assert_eq!(a.signature(), all.signature());
+        assert!(MinHash::<u8>::with_len(4).is_empty());
+        assert_eq!(all.signature_len(), 128);
+    }
+}
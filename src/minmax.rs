@@ -1,16 +1,24 @@
 
 use std::prelude::v1::*;
 
+use std::cmp::{self, Ordering};
 use std::default::Default;
 use std::fmt;
 use std::iter::{FromIterator, IntoIterator};
 
-use Commute;
+use num_traits::ToPrimitive;
+
+use {Commute, CommuteRef};
+use checkpoint::{Checkpoint, CheckpointError, Kind, seal, unseal,
+                 write_option};
+use records::Record;
+use transform::{OutOfRange, Rescale, min_max_scale};
 
 /// A commutative data structure for tracking minimum and maximum values.
 ///
 /// This also stores the number of samples.
 #[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct MinMax<T> {
     len: u64,
     min: Option<T>,
@@ -23,6 +31,19 @@ impl<T: PartialOrd + Clone> MinMax<T> {
         Default::default()
     }
 
+    /// Initializes the state from a known minimum, maximum and number of
+    /// samples.
+    ///
+    /// This makes range summaries from external metadata, e.g., the column
+    /// statistics of a Parquet file, mergeable with local state.
+    ///
+    /// This panics if `min > max` or `len` is `0`.
+    pub fn from_parts(min: T, max: T, len: u64) -> MinMax<T> {
+        assert!(min <= max, "min must not be greater than max");
+        assert!(len > 0, "a range requires at least one sample");
+        MinMax { len, min: Some(min), max: Some(max) }
+    }
+
     /// Add a sample to the data.
     pub fn add(&mut self, sample: T) {
         self.len += 1;
@@ -52,6 +73,76 @@ impl<T: PartialOrd + Clone> MinMax<T> {
     pub fn len(&self) -> usize {
         self.len as usize
     }
+
+    /// Returns true if `x` is within the range of the data, inclusive.
+    ///
+    /// If there is no data, then `false` is returned.
+    pub fn contains(&self, x: &T) -> bool {
+        match (&self.min, &self.max) {
+            (Some(min), Some(max)) => min <= x && x <= max,
+            _ => false,
+        }
+    }
+
+    /// Returns `x` limited to the range of the data.
+    ///
+    /// Values below the minimum return the minimum and values above the
+    /// maximum return the maximum. If there is no data, then `None` is
+    /// returned.
+    pub fn clamp<'a>(&'a self, x: &'a T) -> Option<&'a T> {
+        match (&self.min, &self.max) {
+            (Some(min), _) if x < min => Some(min),
+            (_, Some(max)) if x > max => Some(max),
+            (&Some(_), &Some(_)) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive> MinMax<T> {
+    /// Map `x` linearly from the range of the data to `[lo, hi]`.
+    ///
+    /// `policy` determines what happens to values outside of the range of
+    /// the data. If all of the data is equal, `lo` is returned. `None` is
+    /// returned if and only if the number of samples is `0`.
+    pub fn scale<U: ToPrimitive>(
+        &self,
+        x: U,
+        lo: f64,
+        hi: f64,
+        policy: OutOfRange,
+    ) -> Option<f64> {
+        self.range().map(|fitted| {
+            min_max_scale(x.to_f64().unwrap(), fitted, (lo, hi), policy)
+        })
+    }
+
+    /// Rescale a stream of values from the range of the data to `[lo, hi]`.
+    ///
+    /// This is useful for applying the range fitted on one data set (e.g.,
+    /// training data) to another. `None` is returned if and only if the
+    /// number of samples is `0`.
+    pub fn rescale<I>(
+        &self,
+        it: I,
+        lo: f64,
+        hi: f64,
+        policy: OutOfRange,
+    ) -> Option<Rescale<I::IntoIter>>
+            where I: IntoIterator, <I as IntoIterator>::Item: ToPrimitive {
+        self.range().map(|fitted| {
+            Rescale::new(it.into_iter(), fitted, (lo, hi), policy)
+        })
+    }
+
+    fn range(&self) -> Option<(f64, f64)> {
+        match (&self.min, &self.max) {
+            (Some(min), Some(max)) => {
+                Some((min.to_f64().unwrap(), max.to_f64().unwrap()))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl<T: PartialOrd> Commute for MinMax<T> {
@@ -66,6 +157,42 @@ impl<T: PartialOrd> Commute for MinMax<T> {
     }
 }
 
+impl<T: PartialOrd + Clone> CommuteRef for MinMax<T> {
+    fn merge_ref(&mut self, v: &MinMax<T>) {
+        self.len += v.len;
+        if self.min.is_none() || (v.min.is_some() && v.min < self.min) {
+            self.min = v.min.clone();
+        }
+        if self.max.is_none() || (v.max.is_some() && v.max > self.max) {
+            self.max = v.max.clone();
+        }
+    }
+}
+
+impl<T: PartialOrd + Record> Checkpoint for MinMax<T> {
+    fn to_checkpoint(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.len.write_le(&mut out);
+        write_option(self.min.as_ref(), &mut out);
+        write_option(self.max.as_ref(), &mut out);
+        seal(Kind::MinMax, out)
+    }
+
+    fn from_checkpoint(bytes: &[u8]) -> Result<MinMax<T>, CheckpointError> {
+        let mut r = unseal(Kind::MinMax, bytes)?;
+        let mx = MinMax {
+            len: r.read()?,
+            min: r.read_option()?,
+            max: r.read_option()?,
+        };
+        r.finish()?;
+        if mx.min.is_none() != mx.max.is_none() {
+            return Err(CheckpointError::Malformed);
+        }
+        Ok(mx)
+    }
+}
+
 impl<T: PartialOrd> Default for MinMax<T> {
     fn default() -> MinMax<T> {
         MinMax {
@@ -104,9 +231,192 @@ impl<T: PartialOrd + Clone> Extend<T> for MinMax<T> {
     }
 }
 
+/// A data structure for tracking the minimum and maximum values and the
+/// positions in the stream where they occurred.
+///
+/// Positions count from `0` for the first sample. If the minimum or maximum
+/// occurs more than once, the position of its first occurrence is kept.
+///
+/// A state created with `new` or `collect` counts positions from its own
+/// first sample. When it is merged into another state, it is taken to follow
+/// the samples already there, so its positions are offset by that count.
+///
+/// To track chunks of a stream separately and merge them in any order,
+/// create the state of each chunk with `with_start`, giving the position of
+/// its first sample in the whole stream. Its positions are then absolute and
+/// are kept as they are by `merge`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ArgMinMax<T> {
+    len: u64,
+    next: u64,
+    absolute: bool,
+    min: Option<(T, u64)>,
+    max: Option<(T, u64)>,
+}
+
+impl<T: PartialOrd + Clone> ArgMinMax<T> {
+    /// Create an empty state where min and max values do not exist.
+    pub fn new() -> ArgMinMax<T> {
+        Default::default()
+    }
+
+    /// Create an empty state whose first sample is at position `start` in
+    /// the stream.
+    pub fn with_start(start: usize) -> ArgMinMax<T> {
+        ArgMinMax {
+            next: start as u64,
+            absolute: true,
+            ..Default::default()
+        }
+    }
+
+    /// Add a sample to the data.
+    pub fn add(&mut self, sample: T) {
+        let i = self.next;
+        self.len += 1;
+        self.next += 1;
+        if self.min.as_ref().map(|v| sample < v.0).unwrap_or(true) {
+            self.min = Some((sample.clone(), i));
+        }
+        if self.max.as_ref().map(|v| sample > v.0).unwrap_or(true) {
+            self.max = Some((sample, i));
+        }
+    }
+
+    /// Returns the minimum of the data set.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    pub fn min(&self) -> Option<&T> {
+        self.min.as_ref().map(|v| &v.0)
+    }
+
+    /// Returns the maximum of the data set.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    pub fn max(&self) -> Option<&T> {
+        self.max.as_ref().map(|v| &v.0)
+    }
+
+    /// Returns the position of the first occurrence of the minimum.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    pub fn argmin(&self) -> Option<usize> {
+        self.min.as_ref().map(|v| v.1 as usize)
+    }
+
+    /// Returns the position of the first occurrence of the maximum.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    pub fn argmax(&self) -> Option<usize> {
+        self.max.as_ref().map(|v| v.1 as usize)
+    }
+
+    /// Returns the number of data points.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<T: PartialOrd> Commute for ArgMinMax<T> {
+    fn merge(&mut self, v: ArgMinMax<T>) {
+        let offset = if v.absolute { 0 } else { self.next };
+        self.len += v.len;
+        self.next = cmp::max(self.next, v.next + offset);
+        self.absolute |= v.absolute;
+        if let Some((min, i)) = v.min {
+            let i = i + offset;
+            let replace = match self.min {
+                None => true,
+                Some(ref cur) => replaces(&min, i, cur, Ordering::Less),
+            };
+            if replace {
+                self.min = Some((min, i));
+            }
+        }
+        if let Some((max, i)) = v.max {
+            let i = i + offset;
+            let replace = match self.max {
+                None => true,
+                Some(ref cur) => replaces(&max, i, cur, Ordering::Greater),
+            };
+            if replace {
+                self.max = Some((max, i));
+            }
+        }
+    }
+}
+
+/// Whether `x` at position `i` should replace the extreme `cur`, which is
+/// the case if it is further in the direction `ord`, or equal and earlier.
+fn replaces<T: PartialOrd>(x: &T, i: u64, cur: &(T, u64), ord: Ordering)
+                          -> bool {
+    match x.partial_cmp(&cur.0) {
+        Some(o) if o == ord => true,
+        Some(Ordering::Equal) => i < cur.1,
+        _ => false,
+    }
+}
+
+impl<T: PartialOrd + Clone> CommuteRef for ArgMinMax<T> {
+    fn merge_ref(&mut self, v: &ArgMinMax<T>) {
+        self.merge(v.clone());
+    }
+}
+
+impl<T> From<ArgMinMax<T>> for MinMax<T> {
+    fn from(v: ArgMinMax<T>) -> MinMax<T> {
+        MinMax {
+            len: v.len,
+            min: v.min.map(|v| v.0),
+            max: v.max.map(|v| v.0),
+        }
+    }
+}
+
+impl<T: PartialOrd> Default for ArgMinMax<T> {
+    fn default() -> ArgMinMax<T> {
+        ArgMinMax {
+            len: 0,
+            next: 0,
+            absolute: false,
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ArgMinMax<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.min, &self.max) {
+            (&Some((ref min, i)), &Some((ref max, j))) => {
+                write!(f, "[{:?} at {}, {:?} at {}]", min, i, max, j)
+            }
+            (&None, &None) => write!(f, "N/A"),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone> FromIterator<T> for ArgMinMax<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> ArgMinMax<T> {
+        let mut v = ArgMinMax::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: PartialOrd + Clone> Extend<T> for ArgMinMax<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::MinMax;
+    use super::{ArgMinMax, MinMax};
     use Commute;
 
     #[test]
@@ -127,4 +437,86 @@ mod test {
         assert_eq!(mx1.min(), Some(&1u32));
         assert_eq!(mx1.max(), Some(&10u32));
     }
+
+    #[test]
+    fn from_parts() {
+        let mut imported = MinMax::from_parts(10, 20, 100);
+        assert!(imported.contains(&10));
+        assert!(imported.contains(&20));
+        assert!(!imported.contains(&21));
+        assert_eq!(imported.clamp(&5), Some(&10));
+        assert_eq!(imported.clamp(&25), Some(&20));
+        assert_eq!(imported.clamp(&15), Some(&15));
+
+        imported.merge(vec![3, 12].into_iter().collect());
+        assert_eq!(imported.min(), Some(&3));
+        assert_eq!(imported.len(), 102);
+        assert!(!MinMax::<u8>::new().contains(&0));
+        assert_eq!(MinMax::<u8>::new().clamp(&0), None);
+    }
+
+    #[test]
+    fn minmax_u128() {
+        let big = 1u128 << 100;
+        let minmax: MinMax<u128> =
+            vec![big + 1, big, big + 2].into_iter().collect();
+        assert_eq!(minmax.min(), Some(&big));
+        assert_eq!(minmax.max(), Some(&(big + 2)));
+    }
+
+    #[test]
+    fn argminmax() {
+        let mx: ArgMinMax<u32> =
+            vec![4u32, 1, 7, 1, 7, 3].into_iter().collect();
+        assert_eq!((mx.min(), mx.argmin()), (Some(&1), Some(1)));
+        assert_eq!((mx.max(), mx.argmax()), (Some(&7), Some(2)));
+        assert_eq!(ArgMinMax::<u32>::new().argmin(), None);
+
+        let minmax: MinMax<u32> = mx.into();
+        assert_eq!((minmax.min(), minmax.max()), (Some(&1), Some(&7)));
+        assert_eq!(minmax.len(), 6);
+    }
+
+    #[test]
+    fn argminmax_merge() {
+        let data = [5, 3, 8, 0, 9, 0, 2];
+        let chunk = |start: usize, end: usize| {
+            let mut mx = ArgMinMax::with_start(start);
+            mx.extend(data[start..end].iter().cloned());
+            mx
+        };
+        let mut mx = chunk(0, 3);
+        mx.merge(ArgMinMax::new());
+        mx.merge(chunk(3, 5));
+        mx.merge(chunk(5, 7));
+        assert_eq!((mx.argmin(), mx.argmax(), mx.len()),
+                   (Some(3), Some(4), 7));
+
+        // The order of the merges doesn't matter, and the earlier of the
+        // tied minimums is kept.
+        let mut rev = chunk(5, 7);
+        rev.merge(chunk(3, 5));
+        rev.merge(chunk(0, 3));
+        assert_eq!((rev.argmin(), rev.argmax(), rev.len()),
+                   (Some(3), Some(4), 7));
+
+        let mut empty = ArgMinMax::new();
+        empty.merge(mx);
+        assert_eq!((empty.argmin(), empty.argmax()), (Some(3), Some(4)));
+        // Samples added after a merge follow the merged chunks.
+        empty.add(-1);
+        assert_eq!(empty.argmin(), Some(7));
+    }
+
+    #[test]
+    fn argminmax_merge_collected() {
+        let mut mx: ArgMinMax<i32> = vec![5, 3, 8].into_iter().collect();
+        mx.merge(vec![0, 9, 0].into_iter().collect());
+        mx.merge(ArgMinMax::new());
+        mx.merge(vec![2].into_iter().collect());
+        assert_eq!((mx.argmin(), mx.argmax(), mx.len()),
+                   (Some(3), Some(4), 7));
+        mx.add(-1);
+        assert_eq!(mx.argmin(), Some(7));
+    }
 }
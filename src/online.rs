@@ -2,11 +2,17 @@
 use std::prelude::v1::*;
 use std::default::Default;
 use std::fmt;
+use std::hash::{BuildHasher, Hash};
 use std::iter::{FromIterator, IntoIterator};
 
 use num_traits::ToPrimitive;
 
-use Commute;
+use {Commute, CommuteRef};
+use frequency::{Count, Frequencies, widen};
+use histogram::Histogram;
+use checkpoint::{Checkpoint, CheckpointError, Kind, seal, unseal};
+use records::Record;
+use transform::{Standardize, z_score};
 
 /// Compute the standard deviation of a stream in constant space.
 pub fn stddev<I>(it: I) -> f64
@@ -27,11 +33,18 @@ pub fn mean<I>(it: I) -> f64
 }
 
 /// Online state for computing mean, variance and standard deviation.
+///
+/// The third and fourth central moments are tracked as well, for skewness
+/// and kurtosis.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct OnlineStats {
     size: u64,
     mean: f64,
     variance: f64,
+    m3: f64,
+    m4: f64,
+    sigma_counts: Option<[u64; 3]>,
 }
 
 impl OnlineStats {
@@ -42,11 +55,72 @@ impl OnlineStats {
         Default::default()
     }
 
+    /// Create initial state that also counts how many samples fall within
+    /// one, two and three standard deviations of the mean.
+    ///
+    /// Each sample is compared to the running mean and standard deviation
+    /// at the time it is added (including the sample itself). For a large
+    /// sample from a normal distribution, the fractions should be close to
+    /// 68%, 95% and 99.7%, which makes this a cheap sanity check for
+    /// normality that does not retain the data.
+    pub fn with_sigma_counts() -> OnlineStats {
+        OnlineStats { sigma_counts: Some([0; 3]), ..OnlineStats::new() }
+    }
+
+    /// Initializes the state from a known count, mean and population
+    /// variance.
+    ///
+    /// This makes summaries computed elsewhere, e.g., with SQL's `AVG` and
+    /// `VAR_POP`, mergeable with local state. A sample variance (like SQL's
+    /// `VAR_SAMP`) must first be scaled by `(count - 1) / count`.
+    ///
+    /// The third and fourth moments are not known, so they are `NaN`, as in
+    /// checkpoints from before version 3. The skewness and kurtosis of the
+    /// result, and of anything it is merged into, are therefore `NaN`.
+    pub fn from_parts(count: u64, mean: f64, variance: f64) -> OnlineStats {
+        OnlineStats {
+            size: count,
+            mean,
+            variance,
+            m3: ::std::f64::NAN,
+            m4: ::std::f64::NAN,
+            ..OnlineStats::new()
+        }
+    }
+
     /// Initializes variance from a sample.
     pub fn from_slice<T: ToPrimitive>(samples: &[T]) -> OnlineStats {
         samples.iter().map(|n| n.to_f64().unwrap()).collect()
     }
 
+    /// Initializes the state from a sample using an exact two-pass method.
+    ///
+    /// The mean is computed first with compensated summation, then the
+    /// variance is computed from the deviations around it with the
+    /// corrected two-pass algorithm. This is more accurate than adding the
+    /// samples one at a time, at the cost of requiring all of the data up
+    /// front.
+    pub fn from_slice_exact<T: ToPrimitive>(samples: &[T]) -> OnlineStats {
+        if samples.is_empty() {
+            return OnlineStats::new();
+        }
+        let n = samples.len() as f64;
+        let xs = || samples.iter().map(|x| x.to_f64().unwrap());
+        let mean = kahan_sum(xs()) / n;
+        let sumsq = kahan_sum(xs().map(|x| (x - mean) * (x - mean)));
+        // In exact arithmetic, the deviations sum to zero. Subtracting their
+        // actual sum corrects for the rounding error in the mean.
+        let sumdev = kahan_sum(xs().map(|x| x - mean));
+        OnlineStats {
+            size: samples.len() as u64,
+            mean,
+            variance: (sumsq - sumdev * sumdev / n) / n,
+            m3: kahan_sum(xs().map(|x| (x - mean).powi(3))),
+            m4: kahan_sum(xs().map(|x| (x - mean).powi(4))),
+            sigma_counts: None,
+        }
+    }
+
     /// Return the current mean.
     pub fn mean(&self) -> f64 {
         self.mean
@@ -62,6 +136,34 @@ impl OnlineStats {
         self.variance
     }
 
+    /// Returns the sum of the squared deviations from the mean, often
+    /// called `M2`.
+    ///
+    /// This is the population variance times the number of samples.
+    pub fn m2(&self) -> f64 {
+        self.variance * self.size as f64
+    }
+
+    /// Returns the skewness of the data.
+    ///
+    /// This is the population skewness, `m3 / m2^(3/2)`, where `mk` is the
+    /// `k`th central moment. It is `0` for symmetric data and positive when
+    /// the right tail is longer. If the variance is zero, `NaN` is returned.
+    pub fn skewness(&self) -> f64 {
+        let n = self.size as f64;
+        n.sqrt() * self.m3 / self.m2().powf(1.5)
+    }
+
+    /// Returns the excess kurtosis of the data.
+    ///
+    /// This is the population kurtosis, `m4 / m2^2`, minus `3`, so that it
+    /// is `0` for a normal distribution and positive for heavier tails. If
+    /// the variance is zero, `NaN` is returned.
+    pub fn kurtosis(&self) -> f64 {
+        let (n, m2) = (self.size as f64, self.m2());
+        n * self.m4 / (m2 * m2) - 3.0
+    }
+
     /// Add a new sample.
     pub fn add<T: ToPrimitive>(&mut self, sample: T) {
         let sample = sample.to_f64().unwrap();
@@ -70,10 +172,33 @@ impl OnlineStats {
         let oldmean = self.mean;
         let prevq = self.variance * (self.size as f64);
 
+        // The higher moments are updated first since they depend on the old
+        // lower moments. See Pébay, "Formulas for Robust, One-Pass Parallel
+        // Computation of Covariances and Arbitrary-Order Statistical
+        // Moments" (2008).
+        let n = (self.size + 1) as f64;
+        let delta = sample - oldmean;
+        let delta_n = delta / n;
+        let term = delta * delta_n * (n - 1.0);
+        self.m4 += term * delta_n * delta_n * (n * n - 3.0 * n + 3.0)
+                   + 6.0 * delta_n * delta_n * prevq
+                   - 4.0 * delta_n * self.m3;
+        self.m3 += term * delta_n * (n - 2.0) - 3.0 * delta_n * prevq;
+
         self.size += 1;
         self.mean += (sample - oldmean) / (self.size as f64);
         self.variance = (prevq + (sample - oldmean) * (sample - self.mean))
                         / (self.size as f64);
+
+        if let Some(ref mut counts) = self.sigma_counts {
+            let dev = (sample - self.mean).abs();
+            let sd = self.variance.sqrt();
+            for (k, count) in counts.iter_mut().enumerate() {
+                if dev <= (k + 1) as f64 * sd {
+                    *count += 1;
+                }
+            }
+        }
     }
 
     /// Add a new NULL value to the population.
@@ -87,6 +212,59 @@ impl OnlineStats {
     pub fn len(&self) -> usize {
         self.size as usize
     }
+
+    /// Returns the number of samples that fell within one, two and three
+    /// standard deviations of the mean when they were added.
+    ///
+    /// `None` is returned unless the state was created with
+    /// `with_sigma_counts`.
+    pub fn sigma_counts(&self) -> Option<[u64; 3]> {
+        self.sigma_counts
+    }
+
+    /// Returns the fractions of samples that fell within one, two and three
+    /// standard deviations of the mean when they were added.
+    ///
+    /// `None` is returned unless the state was created with
+    /// `with_sigma_counts`, or if there is no data.
+    pub fn sigma_fractions(&self) -> Option<[f64; 3]> {
+        if self.size == 0 {
+            return None;
+        }
+        let n = self.size as f64;
+        self.sigma_counts.map(|c| {
+            [c[0] as f64 / n, c[1] as f64 / n, c[2] as f64 / n]
+        })
+    }
+
+    /// Returns the standard score of `x`: its distance from the mean in
+    /// standard deviations.
+    ///
+    /// If the standard deviation is zero, `0` is returned.
+    pub fn z_score<T: ToPrimitive>(&self, x: T) -> f64 {
+        z_score(x.to_f64().unwrap(), self.mean, self.stddev())
+    }
+
+    /// Standardize a stream of values using these statistics.
+    ///
+    /// This is useful for applying the mean and standard deviation fitted
+    /// on one data set (e.g., training data) to another.
+    pub fn standardize<I>(&self, it: I) -> Standardize<I::IntoIter>
+            where I: IntoIterator, <I as IntoIterator>::Item: ToPrimitive {
+        Standardize::new(it.into_iter(), self)
+    }
+}
+
+/// Sums the values with Kahan's compensated summation.
+fn kahan_sum<I: Iterator<Item=f64>>(it: I) -> f64 {
+    let (mut sum, mut comp) = (0.0, 0.0);
+    for x in it {
+        let y = x - comp;
+        let t = sum + y;
+        comp = (t - sum) - y;
+        sum = t;
+    }
+    sum
 }
 
 impl Commute for OnlineStats {
@@ -99,18 +277,145 @@ impl Commute for OnlineStats {
                    / (s1 + s2))
                   +
                   ((s1 * s2 * meandiffsq) / ((s1 + s2) * (s1 + s2)));
+        if s1 + s2 > 0.0 {
+            let n = s1 + s2;
+            let delta = v.mean - self.mean;
+            let (q1, q2) = (self.m2(), v.m2());
+            let m3 = self.m3 + v.m3
+                     + delta * meandiffsq * s1 * s2 * (s1 - s2) / (n * n)
+                     + 3.0 * delta * (s1 * q2 - s2 * q1) / n;
+            let m4 = self.m4 + v.m4
+                     + meandiffsq * meandiffsq * s1 * s2
+                       * (s1 * s1 - s1 * s2 + s2 * s2) / (n * n * n)
+                     + 6.0 * meandiffsq * (s1 * s1 * q2 + s2 * s2 * q1)
+                       / (n * n)
+                     + 4.0 * delta * (s1 * v.m3 - s2 * self.m3) / n;
+            self.m3 = m3;
+            self.m4 = m4;
+        }
+        self.sigma_counts = match (self.sigma_counts, v.sigma_counts) {
+            (Some(c1), Some(c2)) => {
+                Some([c1[0] + c2[0], c1[1] + c2[1], c1[2] + c2[2]])
+            }
+            // Merging with empty state must not lose the counts.
+            (c1, _) if v.size == 0 => c1,
+            (_, c2) if self.size == 0 => c2,
+            _ => None,
+        };
         self.size += v.size;
         self.mean = mean;
         self.variance = var;
     }
 }
 
+impl CommuteRef for OnlineStats {
+    fn merge_ref(&mut self, v: &OnlineStats) {
+        self.merge(*v);
+    }
+}
+
+impl Checkpoint for OnlineStats {
+    fn to_checkpoint(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.size.write_le(&mut out);
+        self.mean.write_le(&mut out);
+        self.variance.write_le(&mut out);
+        self.m3.write_le(&mut out);
+        self.m4.write_le(&mut out);
+        match self.sigma_counts {
+            None => out.push(0),
+            Some(counts) => {
+                out.push(1);
+                for c in &counts {
+                    c.write_le(&mut out);
+                }
+            }
+        }
+        seal(Kind::OnlineStats, out)
+    }
+
+    fn from_checkpoint(bytes: &[u8]) -> Result<OnlineStats, CheckpointError> {
+        let mut r = unseal(Kind::OnlineStats, bytes)?;
+        let mut stats = OnlineStats {
+            size: r.read()?,
+            mean: r.read()?,
+            variance: r.read()?,
+            m3: ::std::f64::NAN,
+            m4: ::std::f64::NAN,
+            sigma_counts: None,
+        };
+        // Version 2 added the sigma counts, and version 3 the higher
+        // moments, which remain unknown for older checkpoints.
+        if r.version() >= 3 {
+            stats.m3 = r.read()?;
+            stats.m4 = r.read()?;
+        }
+        if r.version() >= 2 {
+            match r.read::<u8>()? {
+                0 => {}
+                1 => {
+                    stats.sigma_counts =
+                        Some([r.read()?, r.read()?, r.read()?]);
+                }
+                _ => return Err(CheckpointError::Malformed),
+            }
+        }
+        r.finish()?;
+        Ok(stats)
+    }
+}
+
+impl<T, C, S> From<Frequencies<T, C, S>> for OnlineStats
+        where T: Eq + Hash + ToPrimitive, C: Count, S: BuildHasher {
+    /// Summarize a frequency table, weighting every value by its count.
+    fn from(freqs: Frequencies<T, C, S>) -> OnlineStats {
+        let mut stats = OnlineStats::new();
+        for (x, count) in freqs.data {
+            stats.merge(repeated(x.to_f64().unwrap(), widen(count)));
+        }
+        stats
+    }
+}
+
+impl From<Histogram> for OnlineStats {
+    /// Approximately summarize a histogram, taking every sample to be at
+    /// the midpoint of its bin.
+    ///
+    /// Underflow and overflow samples are left out, since they have no bin.
+    fn from(hist: Histogram) -> OnlineStats {
+        let mut stats = OnlineStats::new();
+        for (i, &count) in hist.counts().iter().enumerate() {
+            let (lo, hi) = hist.bin_range(i);
+            stats.merge(repeated(lo + (hi - lo) / 2.0, count));
+        }
+        stats
+    }
+}
+
+/// Returns the statistics of `count` samples equal to `x`.
+fn repeated(x: f64, count: u64) -> OnlineStats {
+    if count == 0 {
+        return OnlineStats::new();
+    }
+    OnlineStats {
+        size: count,
+        mean: x,
+        variance: 0.0,
+        m3: 0.0,
+        m4: 0.0,
+        sigma_counts: None,
+    }
+}
+
 impl Default for OnlineStats {
     fn default() -> OnlineStats {
         OnlineStats {
             size: 0,
             mean: 0.0,
             variance: 0.0,
+            m3: 0.0,
+            m4: 0.0,
+            sigma_counts: None,
         }
     }
 }
@@ -168,4 +473,89 @@ mod test {
         assert_eq!(expected.stddev(),
                    merge_all(vars.into_iter()).unwrap().stddev());
     }
+
+    #[test]
+    fn from_parts() {
+        let local = OnlineStats::from_slice(&[1, 2, 3]);
+        let mut remote = OnlineStats::from_parts(3, 4.0, 2.0 / 3.0);
+        assert_eq!(remote.m2(), 2.0);
+        assert!(remote.skewness().is_nan() && remote.kurtosis().is_nan());
+        remote.merge(local);
+        assert!(remote.skewness().is_nan() && remote.kurtosis().is_nan());
+        let expected = OnlineStats::from_slice(&[1, 2, 3, 3, 4, 5]);
+        assert_eq!(remote.len(), 6);
+        assert!((remote.mean() - expected.mean()).abs() < 1e-12);
+        assert!((remote.variance() - expected.variance()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sigma_counts() {
+        let mut stats = OnlineStats::with_sigma_counts();
+        stats.extend(vec![10, 10, 10, 10, 11, 9, 10, 10, 30]);
+        assert_eq!(stats.sigma_counts(), Some([6, 7, 9]));
+        let fractions = stats.sigma_fractions().unwrap();
+        assert_eq!(fractions[2], 1.0);
+        assert_eq!(OnlineStats::new().sigma_counts(), None);
+
+        let mut merged = OnlineStats::new();
+        merged.merge(stats);
+        assert_eq!(merged.sigma_counts(), Some([6, 7, 9]));
+        merged.merge(OnlineStats::from_slice(&[1, 2]));
+        assert_eq!(merged.sigma_counts(), None);
+    }
+
+    #[test]
+    fn moments() {
+        let data = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let stats = OnlineStats::from_slice(&data);
+        // The third and fourth central moments are 5.25 and 44.5, and the
+        // variance is 4.
+        assert!((stats.skewness() - 0.65625).abs() < 1e-12);
+        assert!((stats.kurtosis() - (44.5 / 16.0 - 3.0)).abs() < 1e-12);
+
+        let exact = OnlineStats::from_slice_exact(&data);
+        assert!((exact.skewness() - stats.skewness()).abs() < 1e-12);
+        assert!((exact.kurtosis() - stats.kurtosis()).abs() < 1e-12);
+
+        let mut merged = OnlineStats::from_slice(&data[..3]);
+        merged.merge(OnlineStats::from_slice(&data[3..]));
+        assert!((merged.skewness() - stats.skewness()).abs() < 1e-12);
+        assert!((merged.kurtosis() - stats.kurtosis()).abs() < 1e-12);
+        assert!(OnlineStats::from_slice(&[1, 1]).skewness().is_nan());
+    }
+
+    #[test]
+    fn from_frequencies() {
+        use Frequencies;
+
+        let data = [2u32, 4, 4, 4, 5, 5, 7, 9];
+        let freqs: Frequencies<u32> = data.iter().cloned().collect();
+        let stats = OnlineStats::from(freqs);
+        assert_eq!(stats.len(), 8);
+        assert!((stats.mean() - 5.0).abs() < 1e-12);
+        assert!((stats.variance() - 4.0).abs() < 1e-12);
+        assert!((stats.skewness() - 0.65625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn from_histogram() {
+        use Histogram;
+
+        let mut hist = Histogram::new(0.0, 10.0, 5);
+        hist.extend(vec![0.5, 1.5, 4.0, 9.0, 12.0]);
+        let stats = OnlineStats::from(hist);
+        assert_eq!(stats.len(), 4);
+        assert!((stats.mean() - 4.0).abs() < 1e-12);
+        assert!((stats.variance() - 11.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn exact_variance() {
+        let data = [1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0];
+        let exact = OnlineStats::from_slice_exact(&data);
+        assert_eq!(exact.len(), 4);
+        assert_eq!(exact.mean(), 1e9 + 10.0);
+        assert_eq!(exact.variance(), 22.5);
+        assert_eq!(OnlineStats::from_slice_exact::<f64>(&[]).len(), 0);
+    }
 }
@@ -0,0 +1,165 @@
+use distributions::{normal_cdf, normal_quantile};
+
+/// Returns the power of a two-sided, two-sample t-test with `n` samples in
+/// each group to detect a standardized effect of `effect`, at significance
+/// level `alpha`.
+///
+/// The effect is Cohen's `d`, the difference of the means divided by the
+/// common standard deviation. The power is computed from the normal
+/// approximation with Guenther's correction, as in `t_test_sample_size`.
+pub fn t_test_power(effect: f64, n: u64, alpha: f64) -> f64 {
+    let n = guenther(n, alpha).max(0.0);
+    two_sided_power(effect * (n / 2.0).sqrt(), alpha)
+}
+
+/// Returns the number of samples needed in each group of a two-sided,
+/// two-sample t-test to detect a standardized effect of `effect` with the
+/// given significance level `alpha` and `power`, e.g., `0.05` and `0.8`.
+///
+/// The normal approximation is adjusted with Guenther's correction, which
+/// makes it accurate for the t distribution down to small samples. `None`
+/// is returned if `effect` is zero.
+pub fn t_test_sample_size(effect: f64, alpha: f64, power: f64) -> Option<u64> {
+    if effect == 0.0 {
+        return None;
+    }
+    let za = normal_quantile(1.0 - alpha / 2.0);
+    let z = za + normal_quantile(power);
+    let n = 2.0 * z * z / (effect * effect) + za * za / 4.0;
+    Some(n.ceil() as u64)
+}
+
+/// Returns the smallest standardized effect that a two-sided, two-sample
+/// t-test with `n` samples in each group detects with the given
+/// significance level `alpha` and `power`.
+///
+/// This uses Guenther's correction, as in `t_test_sample_size`. `None` is
+/// returned if `n` is too small for it, which is only the case for one or
+/// two samples at the usual significance levels.
+pub fn t_test_detectable_effect(n: u64, alpha: f64, power: f64) -> Option<f64> {
+    let n = guenther(n, alpha);
+    if n.is_nan() || n <= 0.0 {
+        return None;
+    }
+    let z = normal_quantile(1.0 - alpha / 2.0) + normal_quantile(power);
+    Some(z * (2.0 / n).sqrt())
+}
+
+/// Returns the number of samples of the normal approximation that matches
+/// a t-test with `n` samples, by Guenther's correction.
+fn guenther(n: u64, alpha: f64) -> f64 {
+    let za = normal_quantile(1.0 - alpha / 2.0);
+    n as f64 - za * za / 4.0
+}
+
+/// Returns the power of a two-sided test of the difference of two
+/// proportions, `p1` and `p2`, with `n` samples in each group at
+/// significance level `alpha`.
+///
+/// This uses Cohen's arcsine transformation of the proportions, whose
+/// variance does not depend on the proportions themselves.
+pub fn proportion_test_power(p1: f64, p2: f64, n: u64, alpha: f64) -> f64 {
+    two_sided_power(cohens_h(p1, p2) * (n as f64 / 2.0).sqrt(), alpha)
+}
+
+/// Returns the number of samples needed in each group of a two-sided test
+/// to detect a change of a proportion from `p1` to `p2` with the given
+/// significance level `alpha` and `power`.
+///
+/// `None` is returned if `p1` and `p2` are equal.
+pub fn proportion_test_sample_size(
+    p1: f64,
+    p2: f64,
+    alpha: f64,
+    power: f64,
+) -> Option<u64> {
+    let h = cohens_h(p1, p2);
+    if h == 0.0 {
+        return None;
+    }
+    let z = normal_quantile(1.0 - alpha / 2.0) + normal_quantile(power);
+    Some((2.0 * z * z / (h * h)).ceil() as u64)
+}
+
+/// Returns the smallest increase of a proportion from `p1` that a
+/// two-sided test with `n` samples in each group detects with the given
+/// significance level `alpha` and `power`.
+///
+/// `None` is returned if `n` is zero or if no proportion up to `1` can be
+/// detected.
+pub fn proportion_test_detectable_effect(
+    p1: f64,
+    n: u64,
+    alpha: f64,
+    power: f64,
+) -> Option<f64> {
+    if n == 0 {
+        return None;
+    }
+    let z = normal_quantile(1.0 - alpha / 2.0) + normal_quantile(power);
+    let phi = p1.sqrt().asin() + z * (2.0 / n as f64).sqrt() / 2.0;
+    if phi > ::std::f64::consts::FRAC_PI_2 {
+        return None;
+    }
+    Some(phi.sin().powi(2) - p1)
+}
+
+/// Returns Cohen's effect size `h` for the difference of two proportions.
+fn cohens_h(p1: f64, p2: f64) -> f64 {
+    2.0 * p2.sqrt().asin() - 2.0 * p1.sqrt().asin()
+}
+
+/// Returns the power of a two-sided z-test whose statistic has mean
+/// `shift` under the alternative.
+fn two_sided_power(shift: f64, alpha: f64) -> f64 {
+    let za = normal_quantile(1.0 - alpha / 2.0);
+    normal_cdf(shift.abs() - za) + normal_cdf(-shift.abs() - za)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{proportion_test_detectable_effect, proportion_test_power,
+                proportion_test_sample_size, t_test_detectable_effect,
+                t_test_power, t_test_sample_size};
+
+    #[test]
+    fn t_test() {
+        // The textbook answer for a medium effect.
+        assert_eq!(t_test_sample_size(0.5, 0.05, 0.8), Some(64));
+        let power = t_test_power(0.5, 64, 0.05);
+        assert!(power > 0.8 && power < 0.81);
+        let d = t_test_detectable_effect(64, 0.05, power).unwrap();
+        assert!((d - 0.5).abs() < 1e-6);
+        assert_eq!(t_test_sample_size(0.0, 0.05, 0.8), None);
+        assert!((t_test_power(0.0, 100, 0.05) - 0.05).abs() < 1e-6);
+        assert_eq!(t_test_detectable_effect(0, 0.05, 0.8), None);
+    }
+
+    #[test]
+    fn t_test_round_trip() {
+        for &effect in &[0.2, 0.5, 0.8, 1.5] {
+            for &(alpha, power) in &[(0.05, 0.8), (0.01, 0.9)] {
+                let n = t_test_sample_size(effect, alpha, power).unwrap();
+                assert!(t_test_power(effect, n, alpha) >= power);
+                assert!(t_test_power(effect, n - 1, alpha) < power);
+                let d = t_test_detectable_effect(n, alpha, power).unwrap();
+                assert!(d <= effect);
+                let d = t_test_detectable_effect(n - 1, alpha, power).unwrap();
+                assert!(d > effect);
+            }
+        }
+    }
+
+    #[test]
+    fn proportions() {
+        assert_eq!(proportion_test_sample_size(0.5, 0.6, 0.05, 0.8),
+                   Some(388));
+        let power = proportion_test_power(0.5, 0.6, 388, 0.05);
+        assert!(power > 0.8 && power < 0.801);
+        let delta =
+            proportion_test_detectable_effect(0.5, 388, 0.05, power).unwrap();
+        assert!((delta - 0.1).abs() < 1e-6);
+        assert_eq!(proportion_test_detectable_effect(0.99, 2, 0.05, 0.8),
+                   None);
+    }
+}
@@ -0,0 +1,82 @@
+use std::prelude::v1::*;
+
+use hashing::mix;
+
+/// A source of random numbers for the accumulators that sample, like
+/// `Reservoir`.
+///
+/// This has the shape of `rand_core::RngCore::next_u64`, so any generator
+/// can be plugged in with a small wrapper: a seeded one for reproducible
+/// tests, or `Rdrand` inside an SGX enclave, where asking the host for
+/// randomness needs an ocall.
+///
+/// This is not `RngCore` itself so that the crate keeps building with only
+/// the SGX ports it already depends on, and `rand_core` is not one of them.
+/// Samplers only need `next_u64`, so a wrapper around an `RngCore` is one
+/// line.
+pub trait RandomSource {
+    /// Returns the next random number.
+    fn next_u64(&mut self) -> u64;
+}
+
+/// The default random source: a SplitMix64 generator.
+///
+/// It is fast and small, and the same seed always gives the same numbers,
+/// but it is not suitable for cryptography.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SplitMix(u64);
+
+impl SplitMix {
+    /// Create a generator from `seed`.
+    pub fn new(seed: u64) -> SplitMix {
+        SplitMix(seed)
+    }
+}
+
+impl RandomSource for SplitMix {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.0)
+    }
+}
+
+/// A random source that reads the CPU's `RDRAND` instruction.
+///
+/// The numbers come from the CPU's hardware generator, so an SGX enclave
+/// can draw them without an ocall, but they cannot be reproduced. This is
+/// only available when compiling for x86-64 with the `rdrand` target
+/// feature, e.g., with `-C target-feature=+rdrand`.
+#[cfg(all(target_arch = "x86_64", target_feature = "rdrand"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rdrand;
+
+#[cfg(all(target_arch = "x86_64", target_feature = "rdrand"))]
+impl RandomSource for Rdrand {
+    /// Returns the next random number, retrying while the hardware
+    /// generator is exhausted.
+    ///
+    /// This panics if the generator fails many times in a row, which means
+    /// that it is broken.
+    fn next_u64(&mut self) -> u64 {
+        use std::arch::x86_64::_rdrand64_step;
+
+        let mut v = 0;
+        for _ in 0..RDRAND_RETRIES {
+            // This is safe because the `rdrand` feature is enabled.
+            if unsafe { _rdrand64_step(&mut v) } == 1 {
+                return v;
+            }
+        }
+        panic!("RDRAND failed {} times in a row", RDRAND_RETRIES);
+    }
+}
+
+/// The number of times that `RDRAND` is retried, as recommended by Intel.
+#[cfg(all(target_arch = "x86_64", target_feature = "rdrand"))]
+const RDRAND_RETRIES: usize = 10;
+
+/// Returns a random number in `[0, n)`.
+pub fn below<R: RandomSource>(rng: &mut R, n: u64) -> u64 {
+    ((rng.next_u64() as u128 * n as u128) >> 64) as u64
+}
@@ -0,0 +1,147 @@
+use std::prelude::v1::*;
+
+use total_cmp;
+use bivariate::OnlineBivariate;
+
+/// A method for assigning ranks to tied values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RankMethod {
+    /// Tied values get the mean of the ranks they span, e.g., `1, 2.5, 2.5,
+    /// 4`. This is the method used by most rank-based statistics.
+    Average,
+    /// Tied values get the lowest rank they span, e.g., `1, 2, 2, 4`.
+    Min,
+    /// Tied values get the highest rank they span, e.g., `1, 3, 3, 4`.
+    Max,
+    /// Like `Min`, but the next distinct value gets the next rank, e.g.,
+    /// `1, 2, 2, 3`.
+    Dense,
+    /// Tied values get distinct ranks in the order they appear in the data,
+    /// e.g., `1, 2, 3, 4`.
+    Ordinal,
+}
+
+/// Returns the rank of each value in `data`, in the same order as `data`.
+///
+/// Ranks start at `1` for the smallest value. Ties are broken according to
+/// `method`. Values without an ordering, like `NaN`, rank after all other
+/// values in the order they appear, and are not tied with each other.
+pub fn rank<T: PartialOrd>(data: &[T], method: RankMethod) -> Vec<f64> {
+    // A stable sort keeps ties in the order they appear, which is what
+    // `Ordinal` needs.
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    order.sort_by(|&i, &j| total_cmp(&data[i], &data[j]));
+
+    let mut ranks = vec![0.0; data.len()];
+    let (mut start, mut dense) = (0, 0);
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && data[order[end]] == data[order[start]] {
+            end += 1;
+        }
+        dense += 1;
+        for (k, &i) in order[start..end].iter().enumerate() {
+            ranks[i] = match method {
+                RankMethod::Average => (start + end + 1) as f64 / 2.0,
+                RankMethod::Min => (start + 1) as f64,
+                RankMethod::Max => end as f64,
+                RankMethod::Dense => dense as f64,
+                RankMethod::Ordinal => (start + k + 1) as f64,
+            };
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Returns the percentile rank of each value in `data`, from `0` to `100`.
+///
+/// The percentile rank of a value is the percentage of the data below it,
+/// counting tied values as half below: `100 * (below + equal / 2) / n`.
+/// This is computed from the average ranks. The result has an
+/// approximately uniform distribution, which is useful for building
+/// features with uniform marginals or percentile-based indicators.
+pub fn percent_rank<T: PartialOrd>(data: &[T]) -> Vec<f64> {
+    let n = data.len() as f64;
+    rank(data, RankMethod::Average)
+        .into_iter()
+        .map(|r| 100.0 * (r - 0.5) / n)
+        .collect()
+}
+
+/// Returns Spearman's rank correlation coefficient of `xs` and `ys`, in
+/// `[-1, 1]`.
+///
+/// This is Pearson's correlation of the average ranks of the values, so it
+/// measures how well a monotonic function describes the relationship,
+/// without assuming it is linear. `None` is returned if there are fewer
+/// than two pairs or if all values of either side are tied.
+///
+/// This panics if `xs` and `ys` have different lengths.
+pub fn spearman<T, U>(xs: &[T], ys: &[U]) -> Option<f64>
+        where T: PartialOrd, U: PartialOrd {
+    assert_eq!(xs.len(), ys.len(), "samples must have the same length");
+    let pairs: OnlineBivariate = rank(xs, RankMethod::Average)
+        .into_iter()
+        .zip(rank(ys, RankMethod::Average))
+        .collect();
+    let r = pairs.pearson_correlation();
+    if r.is_nan() { None } else { Some(r) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RankMethod, percent_rank, rank, spearman};
+
+    #[test]
+    fn tie_methods() {
+        let data = [30, 10, 20, 20];
+        assert_eq!(rank(&data, RankMethod::Average), vec![4.0, 1.0, 2.5, 2.5]);
+        assert_eq!(rank(&data, RankMethod::Min), vec![4.0, 1.0, 2.0, 2.0]);
+        assert_eq!(rank(&data, RankMethod::Max), vec![4.0, 1.0, 3.0, 3.0]);
+        assert_eq!(rank(&data, RankMethod::Dense), vec![3.0, 1.0, 2.0, 2.0]);
+        assert_eq!(rank(&data, RankMethod::Ordinal), vec![4.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn percent_ranks() {
+        assert_eq!(percent_rank(&[10, 20, 30, 40]),
+                   vec![12.5, 37.5, 62.5, 87.5]);
+        assert_eq!(percent_rank(&[5, 5]), vec![50.0, 50.0]);
+        assert_eq!(percent_rank::<u8>(&[]), vec![]);
+    }
+
+    #[test]
+    fn empty_and_distinct() {
+        assert_eq!(rank::<f64>(&[], RankMethod::Average), vec![]);
+        assert_eq!(rank(&[2.5, -1.0, 7.0], RankMethod::Average),
+                   vec![2.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn nan_ranks_last() {
+        use std::f64::NAN;
+
+        let mut data: Vec<f64> = (0..30).map(|i| (29 - i) as f64).collect();
+        data[4] = NAN;
+        data[20] = NAN;
+        let ranks = rank(&data, RankMethod::Average);
+        assert_eq!((ranks[4], ranks[20]), (29.0, 30.0));
+        assert_eq!(ranks[0], 28.0);
+        assert_eq!(ranks[29], 1.0);
+    }
+
+    #[test]
+    fn spearman_correlation() {
+        let xs: Vec<f64> = (1..25).map(|x| x as f64).collect();
+        // Any increasing function is perfectly rank correlated.
+        let cubes: Vec<f64> = xs.iter().map(|x| x * x * x).collect();
+        assert_eq!(spearman(&xs, &cubes), Some(1.0));
+        let reversed: Vec<f64> = xs.iter().map(|x| -x).collect();
+        assert_eq!(spearman(&xs, &reversed), Some(-1.0));
+        assert!((spearman(&[1, 2, 3, 4], &[1, 3, 2, 4]).unwrap() - 0.8).abs()
+                < 1e-12);
+        assert_eq!(spearman(&[1, 2, 3], &[5, 5, 5]), None);
+        assert_eq!(spearman::<u8, u8>(&[], &[]), None);
+    }
+}
@@ -0,0 +1,155 @@
+use std::prelude::v1::*;
+
+use std::marker::PhantomData;
+use std::slice;
+
+/// A fixed-width numeric type that can be decoded from little-endian bytes.
+pub trait Record: Sized {
+    /// The number of bytes in one encoded value.
+    const SIZE: usize;
+
+    /// Decode a value from exactly `SIZE` little-endian bytes.
+    fn from_le(bytes: &[u8]) -> Self;
+
+    /// Append the `SIZE` little-endian bytes of this value to `out`.
+    fn write_le(&self, out: &mut Vec<u8>);
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn write_le(v: u64, size: usize, out: &mut Vec<u8>) {
+    for i in 0..size {
+        out.push((v >> (8 * i)) as u8);
+    }
+}
+
+macro_rules! impl_record {
+    ($($ty:ty => $size:expr, |$v:ident| $decode:expr, |$x:ident| $encode:expr;)*) => {$(
+        impl Record for $ty {
+            const SIZE: usize = $size;
+
+            fn from_le(bytes: &[u8]) -> $ty {
+                let $v = read_le(&bytes[..$size]);
+                $decode
+            }
+
+            fn write_le(&self, out: &mut Vec<u8>) {
+                let $x = *self;
+                write_le($encode, $size, out);
+            }
+        }
+    )*}
+}
+
+impl_record! {
+    u8 => 1, |v| v as u8, |x| x as u64;
+    u16 => 2, |v| v as u16, |x| x as u64;
+    u32 => 4, |v| v as u32, |x| x as u64;
+    u64 => 8, |v| v, |x| x;
+    i8 => 1, |v| v as i8, |x| x as u64;
+    i16 => 2, |v| v as i16, |x| x as u64;
+    i32 => 4, |v| v as i32, |x| x as u64;
+    i64 => 8, |v| v as i64, |x| x as u64;
+    f32 => 4, |v| f32::from_bits(v as u32), |x| x.to_bits() as u64;
+    f64 => 8, |v| f64::from_bits(v), |x| x.to_bits();
+}
+
+/// Iterate over the fixed-width little-endian records in `bytes`.
+///
+/// No copy of the data is made, so this works well with a memory-mapped
+/// file: statistics over a multi-gigabyte dump of `f64` values can be
+/// computed in constant space by collecting the iterator into e.g.
+/// `OnlineStats`. If the length of `bytes` is not a multiple of the record
+/// size, the trailing partial record is skipped and is available from
+/// `Records::remainder`.
+///
+/// ```
+/// use stats::{OnlineStats, records};
+///
+/// let mut bytes = vec![];
+/// for &x in &[1.0f64, 2.0, 3.0] {
+///     let bits = x.to_bits();
+///     for i in 0..8 {
+///         bytes.push((bits >> (8 * i)) as u8);
+///     }
+/// }
+/// let stats: OnlineStats = records::<f64>(&bytes).collect();
+/// assert_eq!(stats.mean(), 2.0);
+/// ```
+pub fn records<'a, T: Record>(bytes: &'a [u8]) -> Records<'a, T> {
+    let whole = bytes.len() - bytes.len() % T::SIZE;
+    Records {
+        chunks: bytes[..whole].chunks(T::SIZE),
+        remainder: &bytes[whole..],
+        _marker: PhantomData,
+    }
+}
+
+/// An iterator over fixed-width little-endian records.
+///
+/// This is created by the `records` function.
+#[derive(Clone, Debug)]
+pub struct Records<'a, T> {
+    chunks: slice::Chunks<'a, u8>,
+    remainder: &'a [u8],
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T> Records<'a, T> {
+    /// Returns the trailing bytes that do not form a complete record.
+    pub fn remainder(&self) -> &'a [u8] {
+        self.remainder
+    }
+}
+
+impl<'a, T: Record> Iterator for Records<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.chunks.next().map(T::from_le)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a, T: Record> ExactSizeIterator for Records<'a, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::records;
+
+    #[test]
+    fn decode() {
+        let bytes = [1, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff,
+                     0xff, 0xff, 0xff, 0xff, 7];
+        let rs = records::<u64>(&bytes);
+        assert_eq!(rs.len(), 2);
+        assert_eq!(rs.remainder(), &[7]);
+        assert_eq!(rs.collect::<Vec<_>>(), vec![1, ::std::u64::MAX]);
+        assert_eq!(records::<i64>(&bytes).nth(1), Some(-1));
+        assert_eq!(records::<u16>(&bytes).next(), Some(1));
+    }
+
+    #[test]
+    fn roundtrip() {
+        use super::Record;
+
+        let mut bytes = vec![];
+        (-3i16).write_le(&mut bytes);
+        1.5f32.write_le(&mut bytes);
+        assert_eq!(bytes.len(), 6);
+        assert_eq!(records::<i16>(&bytes[..2]).next(), Some(-3));
+        assert_eq!(records::<f32>(&bytes[2..]).next(), Some(1.5));
+    }
+
+    #[test]
+    fn floats() {
+        let bits = 2.5f64.to_bits();
+        let bytes: Vec<u8> = (0..8).map(|i| (bits >> (8 * i)) as u8).collect();
+        assert_eq!(records::<f64>(&bytes).collect::<Vec<_>>(), vec![2.5]);
+    }
+}
@@ -0,0 +1,241 @@
+use std::prelude::v1::*;
+
+use num_traits::ToPrimitive;
+
+/// A simple linear model of a conditional quantile.
+///
+/// The model is `y = intercept + slope * x`.
+///
+/// Least squares regression models the conditional mean. Quantile
+/// regression minimizes the pinball loss instead, which models any
+/// conditional quantile, e.g., how the 90th percentile of latency grows
+/// with request size. For `tau = 0.5`, this is least absolute deviations
+/// regression, which is also far less sensitive to outliers than least
+/// squares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct QuantileRegression {
+    tau: f64,
+    intercept: f64,
+    slope: f64,
+}
+
+impl QuantileRegression {
+    /// Fit a model of the `tau` quantile of `y` given `x`.
+    ///
+    /// The fit uses iteratively reweighted least squares, starting from the
+    /// least squares solution.
+    ///
+    /// `None` is returned if `x` and `y` differ in length, if `tau` is not
+    /// in `(0, 1)`, or if there are fewer than two distinct values of `x`.
+    pub fn fit<X, Y>(x: &[X], y: &[Y], tau: f64) -> Option<QuantileRegression>
+            where X: ToPrimitive, Y: ToPrimitive {
+        if x.len() != y.len() || !(tau > 0.0 && tau < 1.0) {
+            return None;
+        }
+        let x: Vec<f64> = x.iter().map(|v| v.to_f64().unwrap()).collect();
+        let y: Vec<f64> = y.iter().map(|v| v.to_f64().unwrap()).collect();
+        let (mut intercept, mut slope) =
+            weighted_least_squares(&x, &y, |_| 1.0)?;
+
+        // Residuals of (nearly) zero would get (nearly) infinite weight, so
+        // the weights are capped relative to the spread of `y`.
+        let spread = y.iter().fold(0.0f64, |m, &v| m.max(v.abs()));
+        let floor = 1e-10 * spread.max(1.0);
+        for _ in 0..MAX_ITERATIONS {
+            let (a, b) = (intercept, slope);
+            let fit = weighted_least_squares(&x, &y, |i| {
+                let r = y[i] - a - b * x[i];
+                let w = if r > 0.0 { tau } else { 1.0 - tau };
+                w / r.abs().max(floor)
+            });
+            match fit {
+                None => break,
+                Some((a2, b2)) => {
+                    intercept = a2;
+                    slope = b2;
+                }
+            }
+            let delta = (intercept - a).abs() + (slope - b).abs();
+            if delta <= 1e-12 * (1.0 + intercept.abs() + slope.abs()) {
+                break;
+            }
+        }
+        Some(QuantileRegression {
+            tau,
+            intercept,
+            slope,
+        })
+    }
+
+    /// Returns the quantile that is modeled.
+    pub fn tau(&self) -> f64 {
+        self.tau
+    }
+
+    /// Returns the intercept of the fitted line.
+    pub fn intercept(&self) -> f64 {
+        self.intercept
+    }
+
+    /// Returns the slope of the fitted line.
+    pub fn slope(&self) -> f64 {
+        self.slope
+    }
+
+    /// Returns the predicted `tau` quantile of `y` at `x`.
+    pub fn predict(&self, x: f64) -> f64 {
+        self.intercept + self.slope * x
+    }
+
+    /// Returns the residuals `y - predict(x)` of the model on data, in
+    /// order.
+    ///
+    /// This panics if `x` and `y` differ in length.
+    pub fn residuals<X, Y>(&self, x: &[X], y: &[Y]) -> Vec<f64>
+            where X: ToPrimitive, Y: ToPrimitive {
+        assert_eq!(x.len(), y.len());
+        x.iter().zip(y).map(|(x, y)| {
+            y.to_f64().unwrap() - self.predict(x.to_f64().unwrap())
+        }).collect()
+    }
+}
+
+/// Returns the Durbin–Watson statistic of a sequence of regression
+/// residuals.
+///
+/// The statistic is the sum of squared differences of consecutive residuals
+/// divided by the sum of squared residuals, and is close to `2(1 - r)`,
+/// where `r` is the lag-1 autocorrelation of the residuals. Values near `2`
+/// indicate no autocorrelation, values towards `0` positive and values
+/// towards `4` negative autocorrelation. The residuals are consumed in one
+/// pass, so they can be streamed while a model is evaluated.
+///
+/// `None` is returned if there are fewer than two residuals or if all of
+/// them are zero.
+pub fn durbin_watson<T, I>(residuals: I) -> Option<f64>
+        where T: ToPrimitive, I: IntoIterator<Item=T> {
+    let (mut num, mut den, mut len) = (0.0, 0.0, 0);
+    let mut last: Option<f64> = None;
+    for r in residuals {
+        let r = r.to_f64().unwrap();
+        if let Some(prev) = last {
+            num += (r - prev) * (r - prev);
+        }
+        den += r * r;
+        len += 1;
+        last = Some(r);
+    }
+    if len < 2 || den == 0.0 {
+        return None;
+    }
+    Some(num / den)
+}
+
+const MAX_ITERATIONS: usize = 1000;
+
+/// Fits `y = a + b * x` by weighted least squares and returns `(a, b)`.
+///
+/// `None` is returned if the system is singular, e.g., when all weighted
+/// values of `x` are equal.
+fn weighted_least_squares<F>(x: &[f64], y: &[f64], weight: F)
+        -> Option<(f64, f64)> where F: Fn(usize) -> f64 {
+    let (mut sw, mut swx, mut swy, mut xmax) = (0.0, 0.0, 0.0, 0.0f64);
+    for i in 0..x.len() {
+        let w = weight(i);
+        sw += w;
+        swx += w * x[i];
+        swy += w * y[i];
+        xmax = xmax.max(x[i].abs());
+    }
+    // Working with `x` and `y` centered on their weighted means keeps the
+    // test for singularity independent of the units and offset of `x`.
+    let (mx, my) = (swx / sw, swy / sw);
+    let (mut sxx, mut sxy) = (0.0, 0.0);
+    for i in 0..x.len() {
+        let (w, dx) = (weight(i), x[i] - mx);
+        sxx += w * dx * dx;
+        sxy += w * dx * (y[i] - my);
+    }
+    // The deviations from the mean are only known to a precision relative
+    // to the magnitude of `x`, so smaller ones count as zero.
+    let tol = 1e-12 * xmax;
+    if sxx.is_nan() || sxx <= sw * tol * tol {
+        return None;
+    }
+    let b = sxy / sxx;
+    Some((my - b * mx, b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QuantileRegression, durbin_watson, weighted_least_squares};
+
+    #[test]
+    fn median_ignores_outliers() {
+        let x: Vec<f64> = (0..11).map(|i| i as f64).collect();
+        let mut y: Vec<f64> = x.iter().map(|x| 2.0 * x + 1.0).collect();
+        y[10] = 1000.0;
+        let fit = QuantileRegression::fit(&x, &y, 0.5).unwrap();
+        assert!((fit.slope() - 2.0).abs() < 1e-4);
+        assert!((fit.intercept() - 1.0).abs() < 1e-4);
+        assert!((fit.predict(4.0) - 9.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn upper_quantile() {
+        let (mut x, mut y) = (vec![], vec![]);
+        for i in 0..20 {
+            for u in 0..10 {
+                x.push(i);
+                y.push(i + u);
+            }
+        }
+        let fit = QuantileRegression::fit(&x, &y, 0.9).unwrap();
+        assert!((fit.slope() - 1.0).abs() < 1e-3);
+        assert!(fit.intercept() > 7.99 && fit.intercept() < 9.01);
+
+        // 90% of the points should lie on or below the line.
+        let below = x.iter().zip(&y)
+            .filter(|&(&x, &y)| y as f64 <= fit.predict(x as f64) + 1e-6)
+            .count();
+        assert!(below >= 180);
+    }
+
+    #[test]
+    fn degenerate() {
+        assert_eq!(QuantileRegression::fit(&[1, 1], &[2, 3], 0.5), None);
+        assert_eq!(QuantileRegression::fit(&[1, 2], &[2, 3], 1.0), None);
+        assert_eq!(QuantileRegression::fit(&[1, 2], &[2], 0.5), None);
+    }
+
+    #[test]
+    fn small_spacing() {
+        let x: Vec<f64> = (0..10).map(|i| i as f64 * 1e-7).collect();
+        let y: Vec<f64> = x.iter().map(|x| 3.0 * x + 2.0).collect();
+        let (a, b) = weighted_least_squares(&x, &y, |_| 1.0).unwrap();
+        assert!((a - 2.0).abs() < 1e-9 && (b - 3.0).abs() < 1e-6);
+
+        let fit = QuantileRegression::fit(&x, &y, 0.5).unwrap();
+        assert!((fit.slope() - 3.0).abs() < 1e-3);
+        assert_eq!(weighted_least_squares(&[0.1; 3], &[1.0, 2.0, 3.0],
+                                          |i| [1.0, 3.0, 7.0][i]), None);
+    }
+
+    #[test]
+    fn durbin_watson_statistic() {
+        let alternating: Vec<f64> =
+            (0..100).map(|i| if i % 2 == 0 { 1.0 } else { -1.0 }).collect();
+        assert!(durbin_watson(alternating).unwrap() > 3.9);
+        let drifting: Vec<f64> =
+            (0..100).map(|i| (i as f64 / 20.0).sin()).collect();
+        assert!(durbin_watson(drifting).unwrap() < 0.1);
+        assert_eq!(durbin_watson(vec![1, -1, 1]), Some(8.0 / 3.0));
+        assert_eq!(durbin_watson(vec![1.0]), None);
+
+        let x = [0, 1, 2, 3];
+        let fit = QuantileRegression::fit(&x, &[1, 3, 5, 7], 0.5).unwrap();
+        let residuals = fit.residuals(&x, &[1, 4, 5, 8]);
+        assert!((residuals[1] - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,223 @@
+use std::prelude::v1::*;
+
+use std::fmt;
+
+use Commute;
+use random::{RandomSource, SplitMix, below};
+
+/// A commutative data structure for a uniform random sample of a stream
+/// in a fixed amount of memory.
+///
+/// This is reservoir sampling (Algorithm R). The first `capacity` samples
+/// are kept, and the `n`th sample after them replaces a random kept sample
+/// with probability `capacity / n`, so every sample of the stream is kept
+/// with the same probability whatever the length of the stream.
+///
+/// By default, random numbers come from a small generator with a fixed
+/// seed, so the same stream always gives the same sample. `with_seed` starts
+/// the generator from another seed, and any other `RandomSource`, like
+/// `Rdrand` in an SGX enclave, can be given with `with_rng`.
+/// The sample can be converted to `Unsorted` for quantiles, or
+/// collected into `OnlineStats` for the mean and variance.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Reservoir<T, R = SplitMix> {
+    sample: Vec<T>,
+    capacity: usize,
+    seen: u64,
+    #[cfg_attr(feature = "serialize", serde(rename = "state"))]
+    rng: R,
+}
+
+impl<T: fmt::Debug, R> fmt::Debug for Reservoir<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Reservoir({:?}, n={})", self.sample, self.seen)
+    }
+}
+
+impl<T> Reservoir<T> {
+    /// Create an empty reservoir that keeps at most `capacity` samples and
+    /// draws random numbers from the seed `0`.
+    ///
+    /// This panics if `capacity` is `0`.
+    pub fn new(capacity: usize) -> Reservoir<T> {
+        Reservoir::with_seed(capacity, 0)
+    }
+
+    /// Create an empty reservoir that keeps at most `capacity` samples and
+    /// draws random numbers from `seed`.
+    ///
+    /// This panics if `capacity` is `0`.
+    pub fn with_seed(capacity: usize, seed: u64) -> Reservoir<T> {
+        Reservoir::with_rng(capacity, SplitMix::new(seed))
+    }
+}
+
+impl<T, R: RandomSource> Reservoir<T, R> {
+    /// Create an empty reservoir that keeps at most `capacity` samples and
+    /// draws random numbers from `rng`.
+    ///
+    /// This panics if `capacity` is `0`.
+    pub fn with_rng(capacity: usize, rng: R) -> Reservoir<T, R> {
+        assert!(capacity > 0, "capacity must be positive");
+        Reservoir {
+            sample: Vec::with_capacity(capacity),
+            capacity,
+            seen: 0,
+            rng,
+        }
+    }
+
+    /// Add a sample to the stream.
+    pub fn add(&mut self, v: T) {
+        self.seen += 1;
+        if self.sample.len() < self.capacity {
+            self.sample.push(v);
+            return;
+        }
+        let i = below(&mut self.rng, self.seen) as usize;
+        if i < self.capacity {
+            self.sample[i] = v;
+        }
+    }
+
+    /// Returns the kept samples, in no particular order.
+    pub fn sample(&self) -> &[T] {
+        &self.sample
+    }
+
+    /// Returns the kept samples, in no particular order.
+    pub fn into_sample(self) -> Vec<T> {
+        self.sample
+    }
+
+    /// Returns the maximum number of samples that are kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of samples in the stream.
+    pub fn len(&self) -> usize {
+        self.seen as usize
+    }
+}
+
+impl<T, R: RandomSource> Commute for Reservoir<T, R> {
+    /// Merge another reservoir, so that every sample of either stream is
+    /// kept with the same probability.
+    ///
+    /// The merged sample is drawn as if from the concatenated streams
+    /// without replacement: each kept sample is taken from one of the two
+    /// reservoirs in proportion to the samples of its stream that have not
+    /// been drawn yet.
+    fn merge(&mut self, v: Reservoir<T, R>) {
+        assert_eq!(self.capacity, v.capacity,
+                   "reservoirs must have the same capacity");
+        let mut left = ::std::mem::replace(&mut self.sample, vec![]);
+        let mut right = v.sample;
+        let (mut n1, mut n2) = (self.seen, v.seen);
+        self.seen += v.seen;
+        while self.sample.len() < self.capacity
+            && !(left.is_empty() && right.is_empty()) {
+            let from_left = right.is_empty()
+                || (!left.is_empty() && below(&mut self.rng, n1 + n2) < n1);
+            let (side, n) = if from_left {
+                (&mut left, &mut n1)
+            } else {
+                (&mut right, &mut n2)
+            };
+            *n -= 1;
+            let i = below(&mut self.rng, side.len() as u64) as usize;
+            self.sample.push(side.swap_remove(i));
+        }
+    }
+}
+
+impl<T, R: RandomSource> Extend<T> for Reservoir<T, R> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Reservoir;
+    use {Commute, OnlineStats, RandomSource, Unsorted};
+
+    #[test]
+    fn keeps_everything_below_capacity() {
+        let mut res = Reservoir::new(10);
+        res.extend(0..5u32);
+        assert_eq!(res.sample(), &[0, 1, 2, 3, 4]);
+        assert_eq!(res.len(), 5);
+    }
+
+    #[test]
+    fn uniform() {
+        // Every value should be kept in about a tenth of the reservoirs.
+        let mut counts = vec![0u32; 100];
+        for seed in 0..2000 {
+            let mut res = Reservoir::with_seed(10, seed);
+            res.extend(0..100usize);
+            assert_eq!(res.sample().len(), 10);
+            for &v in res.sample() {
+                counts[v] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&c| c > 120 && c < 280), "{:?}", counts);
+    }
+
+    #[test]
+    fn merge_by_seen_counts() {
+        // The first stream is nine times longer, so about nine tenths of
+        // the merged sample should come from it.
+        let mut from_first = 0;
+        for seed in 0..500 {
+            let mut r1 = Reservoir::with_seed(10, seed);
+            r1.extend(0..900u32);
+            let mut r2 = Reservoir::with_seed(10, seed + 1000);
+            r2.extend(900..1000u32);
+            r1.merge(r2);
+            assert_eq!((r1.len(), r1.sample().len()), (1000, 10));
+            from_first += r1.sample().iter().filter(|&&v| v < 900).count();
+        }
+        assert!(from_first > 4350 && from_first < 4650, "{}", from_first);
+    }
+
+    #[test]
+    fn custom_rng() {
+        // A source that always draws the first slot.
+        struct First;
+        impl RandomSource for First {
+            fn next_u64(&mut self) -> u64 { 0 }
+        }
+        let mut res = Reservoir::with_rng(3, First);
+        res.extend(0..10u32);
+        assert_eq!(res.sample(), &[9, 1, 2]);
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_feature = "rdrand"))]
+    fn rdrand() {
+        use Rdrand;
+
+        let mut res = Reservoir::with_rng(10, Rdrand);
+        res.extend(0..1000u32);
+        assert_eq!((res.sample().len(), res.len()), (10, 1000));
+        let mut draws: Vec<u64> = (0..8).map(|_| Rdrand.next_u64()).collect();
+        draws.dedup();
+        assert!(draws.len() > 1);
+    }
+
+    #[test]
+    fn downstream() {
+        let mut res = Reservoir::new(100);
+        res.extend(1..51u32);
+        let stats: OnlineStats = res.sample().iter().cloned().collect();
+        assert_eq!(stats.mean(), 25.5);
+        let mut unsorted: Unsorted<u32> = res.into();
+        assert_eq!(unsorted.median(), Some(25.5));
+    }
+}
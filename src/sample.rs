@@ -0,0 +1,82 @@
+use std::hash::Hash;
+
+use hashing::hash_with_seed;
+
+/// A deterministic sampler that selects items by the hash of their key.
+///
+/// An item is included if and only if the hash of its key falls below a
+/// threshold determined by the sampling rate. Since the decision depends
+/// only on the key, the seed and the rate, independent shards (or reruns of
+/// the same job) select exactly the same keys without coordinating. This
+/// makes it possible to, e.g., sample the same users on every machine
+/// before feeding them to `Frequencies` or a cardinality sketch.
+///
+/// For a fixed seed, the keys selected at a lower rate are always a subset
+/// of the keys selected at a higher rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct HashSampler {
+    rate: f64,
+    seed: u64,
+}
+
+impl HashSampler {
+    /// Create a sampler that includes roughly a fraction `rate` of keys.
+    ///
+    /// Rates less than or equal to `0` include nothing and rates greater
+    /// than or equal to `1` include everything.
+    pub fn new(rate: f64) -> HashSampler {
+        HashSampler::with_seed(rate, 0)
+    }
+
+    /// Create a sampler with a specific hash seed.
+    ///
+    /// Samplers with different seeds select independent sets of keys.
+    pub fn with_seed(rate: f64, seed: u64) -> HashSampler {
+        HashSampler { rate, seed }
+    }
+
+    /// Returns the sampling rate.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Returns true if the item with the given key is in the sample.
+    pub fn contains<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        // Use the top 53 bits so that the comparison is exact in an `f64`.
+        let h = hash_with_seed(key, self.seed) >> 11;
+        (h as f64) / ((1u64 << 53) as f64) < self.rate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HashSampler;
+
+    #[test]
+    fn deterministic() {
+        let s1 = HashSampler::new(0.5);
+        let s2 = HashSampler::new(0.5);
+        for k in 0..100u32 {
+            assert_eq!(s1.contains(&k), s2.contains(&k));
+        }
+    }
+
+    #[test]
+    fn rate() {
+        let s = HashSampler::with_seed(0.1, 42);
+        let n = (0..100_000u32).filter(|k| s.contains(k)).count();
+        assert!(n > 9_000 && n < 11_000);
+        assert!(!HashSampler::new(0.0).contains("a"));
+        assert!(HashSampler::new(1.0).contains("a"));
+    }
+
+    #[test]
+    fn nested() {
+        let low = HashSampler::new(0.1);
+        let high = HashSampler::new(0.3);
+        for k in 0..10_000u32 {
+            assert!(!low.contains(&k) || high.contains(&k));
+        }
+    }
+}
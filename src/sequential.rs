@@ -0,0 +1,252 @@
+use std::iter::IntoIterator;
+
+use num_traits::ToPrimitive;
+
+/// The decision of a sequential test once a boundary has been crossed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Decision {
+    /// The data favors the null hypothesis.
+    AcceptNull,
+    /// The data favors the alternative hypothesis.
+    RejectNull,
+}
+
+/// Online state for Wald's sequential probability ratio test of a
+/// proportion.
+///
+/// Every observation updates the log-likelihood ratio of the alternative
+/// `p1` to the null `p0`, and the test stops as soon as the ratio leaves
+/// the interval `[ln(beta / (1 - alpha)), ln((1 - beta) / alpha)]`, which
+/// bounds the rate of false positives by about `alpha` and of false
+/// negatives by about `beta`. On average, this stops much earlier than a
+/// test with a fixed number of samples and the same error rates.
+///
+/// Once a decision is made, further observations are ignored. This does
+/// not implement `Commute`, since the decision depends on the order of the
+/// observations.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Sprt {
+    success: f64,
+    failure: f64,
+    lower: f64,
+    upper: f64,
+    llr: f64,
+    decision: Option<Decision>,
+    len: u64,
+}
+
+impl Sprt {
+    /// Create initial state for a test of the null hypothesis that the
+    /// probability of success is `p0` against the alternative that it is
+    /// `p1`, with error rates `alpha` and `beta`.
+    ///
+    /// This panics unless `p0` and `p1` are different and in `(0, 1)`, and
+    /// `alpha` and `beta` are in `(0, 1)` with a sum less than `1`.
+    pub fn bernoulli(p0: f64, p1: f64, alpha: f64, beta: f64) -> Sprt {
+        assert!(p0 > 0.0 && p0 < 1.0 && p1 > 0.0 && p1 < 1.0,
+                "proportions must be in (0, 1)");
+        assert!(p0 != p1, "proportions must be different");
+        assert!(alpha > 0.0 && beta > 0.0 && alpha + beta < 1.0,
+                "error rates must be positive with a sum less than 1");
+        Sprt {
+            success: (p1 / p0).ln(),
+            failure: ((1.0 - p1) / (1.0 - p0)).ln(),
+            lower: (beta / (1.0 - alpha)).ln(),
+            upper: ((1.0 - beta) / alpha).ln(),
+            llr: 0.0,
+            decision: None,
+            len: 0,
+        }
+    }
+
+    /// Add an observation, which is `true` for a success.
+    pub fn add(&mut self, success: bool) {
+        if self.decision.is_some() {
+            return;
+        }
+        self.len += 1;
+        self.llr += if success { self.success } else { self.failure };
+        if self.llr >= self.upper {
+            self.decision = Some(Decision::RejectNull);
+        } else if self.llr <= self.lower {
+            self.decision = Some(Decision::AcceptNull);
+        }
+    }
+
+    /// Returns the decision of the test.
+    ///
+    /// `None` is returned if no boundary has been crossed yet, so more
+    /// observations are needed.
+    pub fn decision(&self) -> Option<Decision> {
+        self.decision
+    }
+
+    /// Returns the log-likelihood ratio of the alternative to the null
+    /// hypothesis.
+    pub fn log_likelihood_ratio(&self) -> f64 {
+        self.llr
+    }
+
+    /// Returns the number of observations up to the decision.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl Extend<bool> for Sprt {
+    fn extend<I: IntoIterator<Item=bool>>(&mut self, it: I) {
+        for success in it {
+            self.add(success);
+        }
+    }
+}
+
+/// Online state for a mixture sequential probability ratio test of the
+/// mean of normally distributed data with a known standard deviation.
+///
+/// The likelihood ratio of the null mean is taken against a normal mixture
+/// of alternatives around it, so no alternative has to be chosen up front.
+/// Its reciprocal gives a p-value that is valid at every sample size at
+/// once: the data can be monitored continuously and the experiment stopped
+/// as soon as `p_value() <= alpha`, without inflating the rate of false
+/// positives above `alpha`.
+///
+/// The standard deviation of the mixture, `tau`, should be on the scale of
+/// the effects that are expected. Like `Sprt`, this does not implement
+/// `Commute`, since the p-value depends on the order of the samples.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct MixtureSprt {
+    null_mean: f64,
+    variance: f64,
+    mixing_variance: f64,
+    alpha: f64,
+    sum: f64,
+    p_value: f64,
+    len: u64,
+}
+
+impl MixtureSprt {
+    /// Create initial state for a test of the null hypothesis that the mean
+    /// is `null_mean`, for data with standard deviation `stddev`, mixing
+    /// over alternatives with standard deviation `tau`, at significance
+    /// level `alpha`.
+    ///
+    /// This panics unless `stddev` and `tau` are positive and `alpha` is in
+    /// `(0, 1)`.
+    pub fn new(null_mean: f64, stddev: f64, tau: f64, alpha: f64)
+              -> MixtureSprt {
+        assert!(stddev > 0.0 && tau > 0.0,
+                "standard deviations must be positive");
+        assert!(alpha > 0.0 && alpha < 1.0, "alpha must be in (0, 1)");
+        MixtureSprt {
+            null_mean,
+            variance: stddev * stddev,
+            mixing_variance: tau * tau,
+            alpha,
+            sum: 0.0,
+            p_value: 1.0,
+            len: 0,
+        }
+    }
+
+    /// Add a sample.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        self.len += 1;
+        self.sum += sample.to_f64().unwrap() - self.null_mean;
+        let (n, v, t) = (self.len as f64, self.variance, self.mixing_variance);
+        let ln_ratio = 0.5 * (v / (v + n * t)).ln()
+            + t * self.sum * self.sum / (2.0 * v * (v + n * t));
+        self.p_value = self.p_value.min((-ln_ratio).exp());
+    }
+
+    /// Returns the always-valid p-value, which never increases as samples
+    /// are added.
+    pub fn p_value(&self) -> f64 {
+        self.p_value
+    }
+
+    /// Returns `Some(Decision::RejectNull)` once the p-value is at most
+    /// `alpha`.
+    ///
+    /// `None` is returned otherwise. The null hypothesis is never accepted,
+    /// since the test is open-ended.
+    pub fn decision(&self) -> Option<Decision> {
+        if self.p_value <= self.alpha {
+            Some(Decision::RejectNull)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the mean of the samples.
+    ///
+    /// `None` is returned if there are no samples.
+    pub fn mean(&self) -> Option<f64> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(self.null_mean + self.sum / self.len as f64)
+        }
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for MixtureSprt {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Decision, MixtureSprt, Sprt};
+
+    #[test]
+    fn sprt_bernoulli() {
+        let mut test = Sprt::bernoulli(0.5, 0.7, 0.05, 0.2);
+        test.extend(vec![true; 3]);
+        assert_eq!(test.decision(), None);
+        test.extend(vec![true; 10]);
+        assert_eq!(test.decision(), Some(Decision::RejectNull));
+        // The decision stops the test.
+        let n = test.len();
+        test.extend(vec![false; 100]);
+        assert_eq!((test.decision(), test.len()),
+                   (Some(Decision::RejectNull), n));
+
+        let mut test = Sprt::bernoulli(0.5, 0.7, 0.05, 0.2);
+        test.extend((0..100).map(|i| i % 2 == 0));
+        assert_eq!(test.decision(), Some(Decision::AcceptNull));
+        assert!(test.log_likelihood_ratio() <= (0.2f64 / 0.95).ln());
+    }
+
+    #[test]
+    fn mixture_sprt() {
+        let mut test = MixtureSprt::new(0.0, 1.0, 1.0, 0.05);
+        assert_eq!((test.p_value(), test.mean()), (1.0, None));
+        // No effect: the p-value stays at 1.
+        test.extend((0..1000).map(|i| if i % 2 == 0 { 1.0 } else { -1.0 }));
+        assert_eq!(test.p_value(), 1.0);
+        assert_eq!(test.decision(), None);
+
+        let mut test = MixtureSprt::new(0.0, 1.0, 1.0, 0.05);
+        let mut last = 1.0;
+        for i in 0..100 {
+            test.add(if i % 2 == 0 { 2.0 } else { 0.0 });
+            assert!(test.p_value() <= last);
+            last = test.p_value();
+        }
+        assert_eq!(test.mean(), Some(1.0));
+        assert_eq!(test.decision(), Some(Decision::RejectNull));
+        assert!(test.p_value() < 1e-10);
+    }
+}
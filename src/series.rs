@@ -0,0 +1,179 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+/// Online state for statistics that depend on the order of a sequence.
+///
+/// This tracks the maximum drawdown (the largest drop from a running peak
+/// to a later value), the longest strictly increasing and decreasing runs,
+/// and the peak and trough of the sequence.
+///
+/// Unlike the other accumulators in this crate, this does not implement
+/// `Commute`: the statistics are defined by the order of the samples, so
+/// two halves of a sequence cannot be combined in either order.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SeriesStats {
+    len: u64,
+    last: f64,
+    peak: f64,
+    trough: f64,
+    running_peak: f64,
+    max_drawdown: f64,
+    max_drawdown_ratio: f64,
+    run_up: u64,
+    run_down: u64,
+    longest_up: u64,
+    longest_down: u64,
+}
+
+impl SeriesStats {
+    /// Create initial state.
+    pub fn new() -> SeriesStats {
+        Default::default()
+    }
+
+    /// Add the next value of the sequence.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        let x = sample.to_f64().unwrap();
+        if self.len == 0 {
+            self.peak = x;
+            self.trough = x;
+            self.running_peak = x;
+            self.run_up = 1;
+            self.run_down = 1;
+        } else {
+            if x > self.last {
+                self.run_up += 1;
+                self.run_down = 1;
+            } else if x < self.last {
+                self.run_down += 1;
+                self.run_up = 1;
+            } else {
+                self.run_up = 1;
+                self.run_down = 1;
+            }
+            self.peak = self.peak.max(x);
+            self.trough = self.trough.min(x);
+            self.running_peak = self.running_peak.max(x);
+        }
+        let drawdown = self.running_peak - x;
+        self.max_drawdown = self.max_drawdown.max(drawdown);
+        if self.running_peak > 0.0 {
+            self.max_drawdown_ratio =
+                self.max_drawdown_ratio.max(drawdown / self.running_peak);
+        }
+        self.longest_up = self.longest_up.max(self.run_up);
+        self.longest_down = self.longest_down.max(self.run_down);
+        self.last = x;
+        self.len += 1;
+    }
+
+    /// Returns the largest drop from a running peak to a later value.
+    pub fn max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+
+    /// Returns the largest drop from a running peak to a later value,
+    /// relative to that peak, e.g., `0.25` for a 25% loss.
+    ///
+    /// Only positive peaks are considered, since a relative drop from zero
+    /// or a negative value is not meaningful.
+    pub fn max_drawdown_ratio(&self) -> f64 {
+        self.max_drawdown_ratio
+    }
+
+    /// Returns the length of the longest strictly increasing run.
+    pub fn longest_increasing_run(&self) -> u64 {
+        self.longest_up
+    }
+
+    /// Returns the length of the longest strictly decreasing run.
+    pub fn longest_decreasing_run(&self) -> u64 {
+        self.longest_down
+    }
+
+    /// Returns the largest value of the sequence.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    pub fn peak(&self) -> Option<f64> {
+        if self.len == 0 { None } else { Some(self.peak) }
+    }
+
+    /// Returns the smallest value of the sequence.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    pub fn trough(&self) -> Option<f64> {
+        if self.len == 0 { None } else { Some(self.trough) }
+    }
+
+    /// Returns the number of data points.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl Default for SeriesStats {
+    fn default() -> SeriesStats {
+        SeriesStats {
+            len: 0,
+            last: 0.0,
+            peak: 0.0,
+            trough: 0.0,
+            running_peak: 0.0,
+            max_drawdown: 0.0,
+            max_drawdown_ratio: 0.0,
+            run_up: 0,
+            run_down: 0,
+            longest_up: 0,
+            longest_down: 0,
+        }
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for SeriesStats {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> SeriesStats {
+        let mut v = SeriesStats::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for SeriesStats {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SeriesStats;
+
+    #[test]
+    fn drawdown() {
+        let s: SeriesStats =
+            vec![100, 120, 90, 110, 130, 65, 70].into_iter().collect();
+        assert_eq!(s.max_drawdown(), 65.0);
+        assert_eq!(s.max_drawdown_ratio(), 0.5);
+        assert_eq!(s.peak(), Some(130.0));
+        assert_eq!(s.trough(), Some(65.0));
+        assert_eq!(s.len(), 7);
+    }
+
+    #[test]
+    fn runs() {
+        let s: SeriesStats =
+            vec![1, 2, 3, 3, 2, 1, 0, 4, 5].into_iter().collect();
+        assert_eq!(s.longest_increasing_run(), 3);
+        assert_eq!(s.longest_decreasing_run(), 4);
+
+        let empty = SeriesStats::new();
+        assert_eq!(empty.longest_increasing_run(), 0);
+        assert_eq!(empty.peak(), None);
+    }
+}
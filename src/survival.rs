@@ -0,0 +1,250 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::iter::{FromIterator, IntoIterator};
+
+use num_traits::ToPrimitive;
+
+use {Commute, total_cmp};
+use distributions::normal_quantile;
+
+/// One step of a Kaplan–Meier survival curve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SurvivalStep {
+    /// The time at which one or more events occurred.
+    pub time: f64,
+    /// The number of subjects at risk just before `time`.
+    pub at_risk: u64,
+    /// The number of events at `time`.
+    pub events: u64,
+    /// The estimated probability of surviving past `time`.
+    pub survival: f64,
+    /// Greenwood's estimate of the standard error of `survival`.
+    pub std_err: f64,
+}
+
+/// A commutative Kaplan–Meier estimator of a survival function.
+///
+/// Each observation is a duration along with whether it was censored, i.e.,
+/// whether the subject left the study before the event was observed. This
+/// is useful for time-to-failure and churn analyses, where many subjects
+/// have not failed (yet) when the data is collected.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Default)]
+pub struct KaplanMeier {
+    data: Vec<(f64, bool)>,
+}
+
+impl KaplanMeier {
+    /// Create an empty estimator.
+    pub fn new() -> KaplanMeier {
+        Default::default()
+    }
+
+    /// Add an observation with the given duration.
+    ///
+    /// If `censored` is true, the event was not observed and the subject is
+    /// only known to have survived for at least `duration`. Durations that
+    /// are not finite, like `NaN`, are ignored.
+    pub fn add<T: ToPrimitive>(&mut self, duration: T, censored: bool) {
+        let duration = duration.to_f64().unwrap();
+        if duration.is_finite() {
+            self.data.push((duration, censored));
+        }
+    }
+
+    /// Returns the number of observations.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the survival curve, with one step for every distinct time at
+    /// which an event occurred.
+    ///
+    /// Observations censored at the same time as an event are considered
+    /// to be at risk at that time.
+    pub fn curve(&self) -> Vec<SurvivalStep> {
+        // State that was not built by `add`, e.g., deserialized state, may
+        // still have times that never compare equal to themselves.
+        let mut data: Vec<(f64, bool)> =
+            self.data.iter().cloned().filter(|d| d.0.is_finite()).collect();
+        data.sort_unstable_by(|a, b| {
+            // Events sort before censored observations at the same time.
+            total_cmp(&a.0, &b.0).then(a.1.cmp(&b.1))
+        });
+
+        let mut steps = vec![];
+        let mut at_risk = data.len() as u64;
+        let (mut survival, mut greenwood) = (1.0, 0.0);
+        let mut i = 0;
+        while i < data.len() {
+            let time = data[i].0;
+            let (mut events, mut censored) = (0, 0);
+            while i < data.len() && data[i].0 == time {
+                if data[i].1 { censored += 1 } else { events += 1 }
+                i += 1;
+            }
+            if events > 0 {
+                let (n, d) = (at_risk as f64, events as f64);
+                survival *= 1.0 - d / n;
+                if at_risk > events {
+                    greenwood += d / (n * (n - d));
+                }
+                steps.push(SurvivalStep {
+                    time,
+                    at_risk,
+                    events,
+                    survival,
+                    std_err: survival * greenwood.sqrt(),
+                });
+            }
+            at_risk -= events + censored;
+        }
+        steps
+    }
+
+    /// Returns the estimated probability of surviving past time `t`.
+    pub fn survival(&self, t: f64) -> f64 {
+        self.curve()
+            .iter()
+            .take_while(|step| step.time <= t)
+            .last()
+            .map_or(1.0, |step| step.survival)
+    }
+
+    /// Returns the median survival time.
+    ///
+    /// This is the earliest time at which the estimated survival drops to
+    /// `0.5` or below. `None` is returned if it never does.
+    pub fn median(&self) -> Option<f64> {
+        self.curve()
+            .iter()
+            .find(|step| step.survival <= 0.5)
+            .map(|step| step.time)
+    }
+
+    /// Returns pointwise confidence bands for the survival curve.
+    ///
+    /// Each element is `(time, lower, upper)` for the corresponding step of
+    /// `curve`. The bands use the log-log transform of Greenwood's variance,
+    /// so they always lie in `[0, 1]`. `level` is the confidence level, e.g.,
+    /// `0.95`.
+    pub fn confidence_band(&self, level: f64) -> Vec<(f64, f64, f64)> {
+        let z = normal_quantile(1.0 - (1.0 - level) / 2.0);
+        self.curve().iter().map(|step| {
+            let s = step.survival;
+            if s <= 0.0 || s >= 1.0 {
+                return (step.time, s, s);
+            }
+            let sigma = step.std_err / (s * s.ln().abs());
+            (step.time, s.powf((z * sigma).exp()), s.powf((-z * sigma).exp()))
+        }).collect()
+    }
+}
+
+impl Commute for KaplanMeier {
+    fn merge(&mut self, v: KaplanMeier) {
+        self.data.extend(v.data);
+    }
+}
+
+
+impl<T: ToPrimitive> FromIterator<(T, bool)> for KaplanMeier {
+    fn from_iter<I: IntoIterator<Item=(T, bool)>>(it: I) -> KaplanMeier {
+        let mut v = KaplanMeier::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<(T, bool)> for KaplanMeier {
+    fn extend<I: IntoIterator<Item=(T, bool)>>(&mut self, it: I) {
+        for (duration, censored) in it {
+            self.add(duration, censored);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KaplanMeier;
+    use Commute;
+
+    fn example() -> KaplanMeier {
+        vec![(1, false), (2, false), (3, true), (4, false), (5, false)]
+            .into_iter().collect()
+    }
+
+    #[test]
+    fn curve() {
+        let curve = example().curve();
+        let times: Vec<f64> = curve.iter().map(|s| s.time).collect();
+        let surv: Vec<f64> = curve.iter().map(|s| s.survival).collect();
+        let at_risk: Vec<u64> = curve.iter().map(|s| s.at_risk).collect();
+        assert_eq!(times, vec![1.0, 2.0, 4.0, 5.0]);
+        assert_eq!(at_risk, vec![5, 4, 2, 1]);
+        assert!((surv[0] - 0.8).abs() < 1e-12);
+        assert!((surv[1] - 0.6).abs() < 1e-12);
+        assert!((surv[2] - 0.3).abs() < 1e-12);
+        assert_eq!(surv[3], 0.0);
+        assert!((curve[0].std_err - 0.8 * 0.05f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn median_and_lookup() {
+        let km = example();
+        assert_eq!(km.median(), Some(4.0));
+        assert_eq!(km.survival(0.5), 1.0);
+        assert!((km.survival(3.0) - 0.6).abs() < 1e-12);
+
+        let mut censored = KaplanMeier::new();
+        censored.add(1, false);
+        censored.add(2, true);
+        censored.add(3, true);
+        assert_eq!(censored.median(), None);
+    }
+
+    #[test]
+    fn ties_with_censoring() {
+        let km: KaplanMeier =
+            vec![(2, true), (2, false), (2, false), (3, false)]
+                .into_iter().collect();
+        let curve = km.curve();
+        assert_eq!(curve[0].at_risk, 4);
+        assert_eq!(curve[0].events, 2);
+        assert_eq!(curve[1].at_risk, 1);
+    }
+
+    #[test]
+    fn nan_times() {
+        let mut km = example();
+        km.add(::std::f64::NAN, false);
+        km.add(::std::f64::NAN, true);
+        assert_eq!(km.len(), 5);
+        assert_eq!(km.curve(), example().curve());
+        assert_eq!(km.median(), Some(4.0));
+    }
+
+    #[test]
+    fn bands() {
+        let km = example();
+        let band = km.confidence_band(0.95);
+        for (step, &(t, lo, hi)) in km.curve().iter().zip(&band) {
+            assert_eq!(t, step.time);
+            assert!(0.0 <= lo && lo <= step.survival);
+            assert!(step.survival <= hi && hi <= 1.0);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut km1: KaplanMeier =
+            vec![(1, false), (3, true), (5, false)].into_iter().collect();
+        let km2: KaplanMeier =
+            vec![(2, false), (4, false)].into_iter().collect();
+        km1.merge(km2);
+        assert_eq!(km1.curve(), example().curve());
+    }
+}
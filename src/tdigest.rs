@@ -0,0 +1,397 @@
+use std::prelude::v1::*;
+
+use std::default::Default;
+use std::f64::consts::PI;
+use std::fmt;
+use std::iter::{FromIterator, IntoIterator};
+use std::mem;
+
+use num_traits::ToPrimitive;
+
+use {Commute, total_cmp};
+
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// The default compression of a `TDigest`.
+const DEFAULT_COMPRESSION: f64 = 100.0;
+/// Samples are buffered until there are this many per unit of compression.
+const BUFFER_FACTOR: f64 = 5.0;
+
+/// A commutative data structure for approximate quantiles in constant
+/// space.
+///
+/// A t-digest summarizes the data as a list of centroids, each the mean of
+/// a run of adjacent samples. Centroids near the median may hold many
+/// samples, but centroids near the tails hold few, so extreme quantiles
+/// like the 99th or 99.9th percentile stay accurate. The number of
+/// centroids grows with the compression, not with the number of samples:
+/// a compression of `100` keeps at most about a hundred centroids and gives
+/// quantiles with a typical rank error well under one percent.
+///
+/// Like `Unsorted`, samples are buffered and only folded into the centroids
+/// when needed, which is why `quantile` takes `&mut self`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<(f64, f64)>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    /// Create an empty digest with a compression of `100`.
+    pub fn new() -> TDigest {
+        Default::default()
+    }
+
+    /// Create an empty digest with the given compression.
+    ///
+    /// Higher compression gives more accurate quantiles with more memory.
+    /// This panics if `compression` is less than `1`.
+    pub fn with_compression(compression: f64) -> TDigest {
+        assert!(compression >= 1.0, "compression must be at least 1");
+        TDigest {
+            compression,
+            centroids: vec![],
+            buffer: vec![],
+            count: 0,
+            min: ::std::f64::INFINITY,
+            max: ::std::f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a sample to the digest.
+    ///
+    /// `NaN` samples are ignored.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        let v = sample.to_f64().unwrap();
+        if v.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.buffer.push((v, 1.0));
+        if self.buffer.len() as f64 >= BUFFER_FACTOR * self.compression {
+            self.compress();
+        }
+    }
+
+    /// Returns the approximate `q`th quantile of the data.
+    ///
+    /// The smallest and largest samples are tracked exactly, so
+    /// `quantile(0.0)` and `quantile(1.0)` are exact.
+    ///
+    /// `None` is returned if there is no data or if `q` is not in `[0, 1]`.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        if self.count == 0 || !(q >= 0.0 && q <= 1.0) {
+            return None;
+        }
+        self.compress();
+        if q == 0.0 {
+            return Some(self.min);
+        } else if q == 1.0 {
+            return Some(self.max);
+        }
+
+        // Each centroid's samples are taken to be spread evenly around its
+        // mean, so its mean sits at the middle of its cumulative weight.
+        let target = q * self.count as f64;
+        let (mut prev_mean, mut prev_rank) = (self.min, 0.0);
+        let mut seen = 0.0;
+        for &(mean, weight) in &self.centroids {
+            let rank = seen + weight / 2.0;
+            if target < rank {
+                return Some(interpolate(prev_mean, prev_rank,
+                                        mean, rank, target));
+            }
+            prev_mean = mean;
+            prev_rank = rank;
+            seen += weight;
+        }
+        Some(interpolate(prev_mean, prev_rank,
+                         self.max, self.count as f64, target))
+    }
+
+    /// Returns the approximate median of the data.
+    pub fn median(&mut self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    /// Returns the smallest sample, or `None` if there is no data.
+    pub fn min(&self) -> Option<f64> {
+        if self.count == 0 { None } else { Some(self.min) }
+    }
+
+    /// Returns the largest sample, or `None` if there is no data.
+    pub fn max(&self) -> Option<f64> {
+        if self.count == 0 { None } else { Some(self.max) }
+    }
+
+    /// Returns the compression of the digest.
+    pub fn compression(&self) -> f64 {
+        self.compression
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Returns the largest rank error of `quantile`, as a fraction of the
+    /// data.
+    ///
+    /// Samples within a centroid are indistinguishable, so the rank of an
+    /// estimated quantile can be off by up to half of the weight of the
+    /// centroid it falls in. This returns that bound for the heaviest
+    /// centroid. Quantiles in the tails are usually much more accurate.
+    pub fn max_error(&mut self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.compress();
+        let heaviest = self.centroids.iter().fold(0.0f64, |m, c| m.max(c.1));
+        heaviest / 2.0 / self.count as f64
+    }
+
+    /// Returns the number of centroids that summarize the data.
+    pub fn centroid_count(&mut self) -> usize {
+        self.compress();
+        self.centroids.len()
+    }
+
+    /// Returns the approximate number of bytes of memory used, including
+    /// the buffer of samples that have not been folded into the centroids.
+    pub fn bytes_used(&self) -> usize {
+        let capacity = self.centroids.capacity() + self.buffer.capacity();
+        mem::size_of::<TDigest>() + capacity * mem::size_of::<(f64, f64)>()
+    }
+
+    /// Merges many digests into this one, e.g., the digests of thousands of
+    /// shards.
+    ///
+    /// Merging digests one at a time compresses the centroids after every
+    /// merge. This compresses them only once, after all of the digests are
+    /// absorbed, which is much faster and no less accurate.
+    pub fn merge_many<I: IntoIterator<Item=TDigest>>(&mut self, others: I) {
+        for v in others {
+            self.absorb(v);
+        }
+        self.compress();
+    }
+
+    /// Adds the centroids and samples of another digest to the buffer.
+    fn absorb(&mut self, v: TDigest) {
+        self.count += v.count;
+        self.min = self.min.min(v.min);
+        self.max = self.max.max(v.max);
+        self.buffer.extend(v.centroids);
+        self.buffer.extend(v.buffer);
+    }
+
+    /// Folds the buffered samples into the centroids.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = mem::replace(&mut self.buffer, vec![]);
+        all.extend_from_slice(&self.centroids);
+        all.sort_by(|a, b| total_cmp(&a.0, &b.0));
+
+        let total: f64 = all.iter().map(|c| c.1).sum();
+        let mut centroids = Vec::with_capacity(self.centroids.len() + 1);
+        let (mut seen, mut k_left) = (0.0, self.k(0.0));
+        let mut cur = all[0];
+        for &(mean, weight) in &all[1..] {
+            let q = (seen + cur.1 + weight) / total;
+            if self.k(q) - k_left <= 1.0 {
+                cur.1 += weight;
+                cur.0 += (mean - cur.0) * weight / cur.1;
+            } else {
+                seen += cur.1;
+                k_left = self.k(seen / total);
+                centroids.push(cur);
+                cur = (mean, weight);
+            }
+        }
+        centroids.push(cur);
+        self.centroids = centroids;
+    }
+
+    /// The scale function, which limits how many samples a centroid at
+    /// quantile `q` may hold.
+    fn k(&self, q: f64) -> f64 {
+        let x = (2.0 * q - 1.0).max(-1.0).min(1.0);
+        self.compression / (2.0 * PI) * x.asin()
+    }
+}
+
+fn interpolate(x0: f64, r0: f64, x1: f64, r1: f64, target: f64) -> f64 {
+    if r1 <= r0 {
+        return x1;
+    }
+    x0 + (x1 - x0) * (target - r0) / (r1 - r0)
+}
+
+impl Commute for TDigest {
+    fn merge(&mut self, v: TDigest) {
+        self.absorb(v);
+        self.compress();
+    }
+
+    fn consume<I: Iterator<Item=TDigest>>(&mut self, other: I) {
+        self.merge_many(other);
+    }
+}
+
+impl Default for TDigest {
+    fn default() -> TDigest {
+        TDigest::with_compression(DEFAULT_COMPRESSION)
+    }
+}
+
+impl fmt::Debug for TDigest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.min(), self.max()) {
+            (Some(min), Some(max)) => {
+                write!(f, "TDigest({} samples in [{}, {}])",
+                       self.count, min, max)
+            }
+            _ => write!(f, "TDigest(empty)"),
+        }
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for TDigest {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> TDigest {
+        let mut v = TDigest::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for TDigest {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+/// Deserializing checks the compression like `with_compression`, so a
+/// corrupt digest is an error instead of misbehaving when it is compressed.
+#[cfg(feature = "serialize")]
+impl<'de> Deserialize<'de> for TDigest {
+    fn deserialize<D>(d: D) -> Result<TDigest, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts {
+            compression: f64,
+            centroids: Vec<(f64, f64)>,
+            buffer: Vec<(f64, f64)>,
+            count: u64,
+            min: f64,
+            max: f64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if !(parts.compression >= 1.0) {
+            return Err(D::Error::custom("compression must be at least 1"));
+        }
+        Ok(TDigest {
+            compression: parts.compression,
+            centroids: parts.centroids,
+            buffer: parts.buffer,
+            count: parts.count,
+            min: parts.min,
+            max: parts.max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TDigest;
+    use Commute;
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_compression() {
+        use serde_json;
+
+        let mut digest = TDigest::with_compression(20.0);
+        digest.extend(0..1000);
+        let json = serde_json::to_string(&digest).unwrap();
+        let mut back: TDigest = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.quantile(0.5), digest.quantile(0.5));
+
+        let bad = r#"{"compression":0.5,"centroids":[],"buffer":[[1.0,1.0]],
+                      "count":1,"min":1.0,"max":1.0}"#;
+        assert!(serde_json::from_str::<TDigest>(bad).is_err());
+    }
+
+    #[test]
+    fn uniform() {
+        let mut digest: TDigest = (0..100000).collect();
+        for &q in &[0.001, 0.01, 0.25, 0.5, 0.75, 0.99, 0.999] {
+            let got = digest.quantile(q).unwrap();
+            assert!((got - q * 100000.0).abs() < 100.0, "q={} {}", q, got);
+        }
+        assert_eq!(digest.quantile(0.0), Some(0.0));
+        assert_eq!(digest.quantile(1.0), Some(99999.0));
+        assert!(digest.centroid_count() <= 100);
+        assert!(digest.max_error() < 0.02);
+        assert!(digest.bytes_used() < 100000 * 8);
+    }
+
+    #[test]
+    fn compression() {
+        let mut coarse = TDigest::with_compression(20.0);
+        let mut fine = TDigest::with_compression(200.0);
+        coarse.extend(0..10000);
+        fine.extend(0..10000);
+        assert!(coarse.centroid_count() < fine.centroid_count());
+        assert!(coarse.max_error() > fine.max_error());
+        assert_eq!(TDigest::new().max_error(), 0.0);
+    }
+
+    #[test]
+    fn small() {
+        let mut digest: TDigest = vec![1, 2, 3, 4].into_iter().collect();
+        assert_eq!(digest.median(), Some(2.5));
+        assert_eq!(digest.quantile(1.5), None);
+        assert_eq!(TDigest::new().quantile(0.5), None);
+        assert_eq!(TDigest::new().min(), None);
+    }
+
+    #[test]
+    fn merge() {
+        let mut d1: TDigest = (0..50000).map(|i| i * 2).collect();
+        let d2: TDigest = (0..50000).map(|i| i * 2 + 1).collect();
+        d1.merge(d2);
+        assert_eq!(d1.len(), 100000);
+        let p99 = d1.quantile(0.99).unwrap();
+        assert!((p99 - 99000.0).abs() < 100.0, "{}", p99);
+        assert_eq!(d1.max(), Some(99999.0));
+    }
+
+    #[test]
+    fn merge_many() {
+        let shards: Vec<TDigest> = (0..1000)
+            .map(|s| (0..100).map(|i| i * 1000 + s).collect())
+            .collect();
+        let mut all = TDigest::new();
+        all.merge_many(shards.clone());
+        assert_eq!(all.len(), 100000);
+        assert_eq!((all.min(), all.max()), (Some(0.0), Some(99999.0)));
+        let p99 = all.quantile(0.99).unwrap();
+        assert!((p99 - 99000.0).abs() < 100.0, "{}", p99);
+
+        let mut consumed = TDigest::new();
+        consumed.consume(shards.into_iter());
+        assert_eq!(consumed.quantile(0.99), Some(p99));
+    }
+}
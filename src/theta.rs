@@ -0,0 +1,266 @@
+use std::prelude::v1::*;
+
+use std::collections::BTreeSet;
+use std::default::Default;
+use std::fmt;
+use std::hash::Hash;
+use std::iter::{FromIterator, IntoIterator};
+use std::marker::PhantomData;
+
+use {Commute, CommuteRef};
+use hashing::hash_with_seed;
+
+/// The default number of hashes retained by a `ThetaSketch`.
+const DEFAULT_NOMINAL_ENTRIES: usize = 4096;
+
+/// A commutative data structure for approximate distinct counts that
+/// supports set arithmetic.
+///
+/// The sketch retains the `k` smallest hashes of the samples, and `theta`,
+/// the fraction of the hash space they were drawn from. Since hashes are
+/// uniform, the cardinality is estimated as `retained / theta`, with a
+/// relative standard error of about `1 / sqrt(k)`. Until more than `k`
+/// distinct samples are added, the count is exact.
+///
+/// Unlike `HyperLogLog`, which only supports unions, the retained hashes
+/// form a uniform sample of the distinct values, so the intersection and
+/// difference of two sketches can be estimated too. The results are
+/// sketches themselves and can be combined further, e.g., to count users
+/// in segment `A` and `B` but not `C`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ThetaSketch<T> {
+    k: usize,
+    theta: u64,
+    hashes: BTreeSet<u64>,
+    _type: PhantomData<T>,
+}
+
+impl<T> fmt::Debug for ThetaSketch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ThetaSketch(k={}, ~{:.0})", self.k, self.estimate())
+    }
+}
+
+impl<T> ThetaSketch<T> {
+    /// Create an empty sketch that retains up to 4096 hashes.
+    pub fn new() -> ThetaSketch<T> {
+        ThetaSketch::with_nominal_entries(DEFAULT_NOMINAL_ENTRIES)
+    }
+
+    /// Create an empty sketch that retains up to `k` hashes.
+    ///
+    /// This panics if `k` is `0`.
+    pub fn with_nominal_entries(k: usize) -> ThetaSketch<T> {
+        assert!(k > 0, "nominal entries must be positive");
+        ThetaSketch {
+            k,
+            theta: ::std::u64::MAX,
+            hashes: BTreeSet::new(),
+            _type: PhantomData,
+        }
+    }
+
+    /// Returns the estimated number of distinct samples.
+    pub fn estimate(&self) -> f64 {
+        self.hashes.len() as f64 / self.theta_fraction()
+    }
+
+    /// Returns a lower bound of the number of distinct samples, `z`
+    /// standard errors below the estimate.
+    ///
+    /// The bound is never less than the number of retained hashes, which
+    /// are all distinct samples.
+    pub fn lower_bound(&self, z: f64) -> f64 {
+        let bound = self.estimate() - z * self.std_err();
+        bound.max(self.hashes.len() as f64)
+    }
+
+    /// Returns an upper bound of the number of distinct samples, `z`
+    /// standard errors above the estimate.
+    pub fn upper_bound(&self, z: f64) -> f64 {
+        self.estimate() + z * self.std_err()
+    }
+
+    /// Returns true if the estimate is an exact count.
+    pub fn is_exact(&self) -> bool {
+        self.theta == ::std::u64::MAX
+    }
+
+    /// Returns the fraction of the hash space that is sampled, in `(0, 1]`.
+    pub fn theta(&self) -> f64 {
+        self.theta_fraction()
+    }
+
+    /// Returns the number of retained hashes.
+    pub fn retained(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns a sketch of the union of the data of both sketches.
+    ///
+    /// This is the same as merging them, without consuming either.
+    pub fn union(&self, other: &ThetaSketch<T>) -> ThetaSketch<T> {
+        let mut union = ThetaSketch::with_nominal_entries(self.k);
+        union.merge_ref(self);
+        union.merge_ref(other);
+        union
+    }
+
+    /// Returns a sketch of the samples that are in both sketches.
+    pub fn intersection(&self, other: &ThetaSketch<T>) -> ThetaSketch<T> {
+        let theta = self.theta.min(other.theta);
+        let hashes = self.hashes
+            .intersection(&other.hashes)
+            .filter(|&&h| h < theta)
+            .cloned()
+            .collect();
+        self.with_hashes(theta, hashes)
+    }
+
+    /// Returns a sketch of the samples that are in this sketch but not in
+    /// `other`.
+    pub fn difference(&self, other: &ThetaSketch<T>) -> ThetaSketch<T> {
+        let theta = self.theta.min(other.theta);
+        let hashes = self.hashes
+            .difference(&other.hashes)
+            .filter(|&&h| h < theta)
+            .cloned()
+            .collect();
+        self.with_hashes(theta, hashes)
+    }
+
+    fn with_hashes(&self, theta: u64, hashes: BTreeSet<u64>) -> ThetaSketch<T> {
+        ThetaSketch {
+            k: self.k,
+            theta,
+            hashes,
+            _type: PhantomData,
+        }
+    }
+
+    fn theta_fraction(&self) -> f64 {
+        if self.is_exact() {
+            1.0
+        } else {
+            self.theta as f64 / ::std::u64::MAX as f64
+        }
+    }
+
+    /// The standard error of the estimate, treating every distinct sample
+    /// as retained independently with probability `theta`.
+    fn std_err(&self) -> f64 {
+        let p = self.theta_fraction();
+        (self.hashes.len() as f64 * (1.0 - p)).sqrt() / p
+    }
+
+    /// Inserts a hash and drops the largest retained hashes until at most
+    /// `k` are left.
+    fn insert(&mut self, hash: u64) {
+        if hash >= self.theta {
+            return;
+        }
+        self.hashes.insert(hash);
+        while self.hashes.len() > self.k {
+            let largest = *self.hashes.iter().next_back().unwrap();
+            self.hashes.remove(&largest);
+            self.theta = largest;
+        }
+    }
+}
+
+impl<T: Hash> ThetaSketch<T> {
+    /// Add a sample to the sketch.
+    pub fn add(&mut self, v: T) {
+        let hash = hash_with_seed(&v, 0);
+        self.insert(hash);
+    }
+}
+
+impl<T> Commute for ThetaSketch<T> {
+    fn merge(&mut self, v: ThetaSketch<T>) {
+        self.merge_ref(&v);
+    }
+}
+
+impl<T> CommuteRef for ThetaSketch<T> {
+    fn merge_ref(&mut self, v: &ThetaSketch<T>) {
+        if v.theta < self.theta {
+            self.theta = v.theta;
+            let theta = self.theta;
+            self.hashes.retain(|&h| h < theta);
+        }
+        for &h in &v.hashes {
+            self.insert(h);
+        }
+    }
+}
+
+impl<T> Default for ThetaSketch<T> {
+    fn default() -> ThetaSketch<T> {
+        ThetaSketch::new()
+    }
+}
+
+impl<T: Hash> FromIterator<T> for ThetaSketch<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> ThetaSketch<T> {
+        let mut v = ThetaSketch::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Hash> Extend<T> for ThetaSketch<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ThetaSketch;
+    use Commute;
+
+    fn within(estimate: f64, actual: f64, error: f64) -> bool {
+        (estimate - actual).abs() <= error * actual
+    }
+
+    #[test]
+    fn exact_until_full() {
+        let small: ThetaSketch<u32> = (0..100).chain(0..100).collect();
+        assert!(small.is_exact());
+        assert_eq!(small.estimate(), 100.0);
+        assert_eq!(small.lower_bound(2.0), 100.0);
+        assert_eq!(small.upper_bound(2.0), 100.0);
+    }
+
+    #[test]
+    fn set_arithmetic() {
+        let a: ThetaSketch<u32> = (0..60000).collect();
+        let b: ThetaSketch<u32> = (40000..100000).collect();
+        assert!(!a.is_exact());
+        assert!(within(a.estimate(), 60000.0, 0.05));
+        assert!(a.lower_bound(3.0) < 60000.0);
+        assert!(a.upper_bound(3.0) > 60000.0);
+
+        assert!(within(a.union(&b).estimate(), 100000.0, 0.05));
+        let both = a.intersection(&b);
+        assert!(within(both.estimate(), 20000.0, 0.1), "{}", both.estimate());
+        let only_a = a.difference(&b);
+        assert!(within(only_a.estimate(), 40000.0, 0.1));
+        assert!(both.lower_bound(2.0) <= both.upper_bound(2.0));
+    }
+
+    #[test]
+    fn merge() {
+        let mut a: ThetaSketch<u32> = (0..30000).collect();
+        let b: ThetaSketch<u32> = (30000..50000).collect();
+        let all: ThetaSketch<u32> = (0..50000).collect();
+        a.merge(b);
+        assert_eq!(a.retained(), all.retained());
+        assert_eq!(a.theta(), all.theta());
+        assert_eq!(a.estimate(), all.estimate());
+    }
+}
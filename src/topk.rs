@@ -0,0 +1,501 @@
+use std::prelude::v1::*;
+
+use std::cmp::Reverse;
+use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::mem;
+
+use num_traits::Num;
+
+use {Commute, total_cmp};
+#[cfg(feature = "serialize")]
+use serde::de::{Deserialize, Deserializer, Error};
+
+/// A commutative data structure for finding the most frequent values of a
+/// stream in a fixed amount of memory.
+///
+/// This is the Space-Saving algorithm. At most `capacity` values are
+/// tracked, and a new value replaces the value with the lowest count,
+/// inheriting that count as its error. The count of a tracked value
+/// therefore overestimates its true count by at most its error, and every
+/// value that occurs more than `n / capacity` times is tracked.
+///
+/// Unlike `KMajority`, which only finds candidates, this estimates the
+/// counts too, so it can stand in for `Frequencies` on streams with too
+/// many distinct values to count exactly.
+///
+/// The tracked values are kept in a binary heap ordered by count, with an
+/// index from values to their place in it, so adding a sample takes
+/// `O(log capacity)` time even when a value is evicted.
+///
+/// Samples may also carry a positive weight of type `W`, such as `f64`, in
+/// which case the counts are the sums of the weights.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct TopK<T, W = u64> {
+    heap: Vec<Counter<T, W>>,
+    #[cfg_attr(feature = "serialize", serde(skip_serializing))]
+    index: HashMap<T, usize>,
+    capacity: usize,
+    len: u64,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+struct Counter<T, W> {
+    value: T,
+    count: W,
+    error: W,
+}
+
+/// The type of the weights of the samples of a `TopK`, such as `u64` or
+/// `f64`.
+pub trait Weight: Num + Copy + PartialOrd + fmt::Debug {}
+
+impl<W: Num + Copy + PartialOrd + fmt::Debug> Weight for W {}
+
+impl<T, W> fmt::Debug for TopK<T, W>
+        where T: fmt::Debug + Eq + Hash, W: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+         .entries(self.heap.iter().map(|c| (&c.value, &c.count)))
+         .finish()
+    }
+}
+
+impl<T: Eq + Hash + Clone> TopK<T> {
+    /// Create an empty summary that tracks at most `capacity` values.
+    ///
+    /// This panics if `capacity` is `0`.
+    pub fn new(capacity: usize) -> TopK<T> {
+        TopK::weighted(capacity)
+    }
+}
+
+impl<T: Eq + Hash + Clone, W: Weight> TopK<T, W> {
+    /// Create an empty summary of weighted samples that tracks at most
+    /// `capacity` values.
+    ///
+    /// This panics if `capacity` is `0`.
+    pub fn weighted(capacity: usize) -> TopK<T, W> {
+        assert!(capacity > 0, "capacity must be positive");
+        TopK {
+            heap: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// Add a sample to the summary.
+    pub fn add(&mut self, v: T) {
+        self.add_weighted(v, W::one());
+    }
+
+    /// Add a sample with a positive `weight` to the summary.
+    pub fn add_weighted(&mut self, v: T, weight: W) {
+        self.len += 1;
+        if let Some(&i) = self.index.get(&v) {
+            self.heap[i].count = self.heap[i].count + weight;
+            self.sift_down(i);
+            return;
+        }
+        if self.heap.len() < self.capacity {
+            let i = self.heap.len();
+            self.index.insert(v.clone(), i);
+            self.heap.push(Counter {
+                value: v,
+                count: weight,
+                error: W::zero(),
+            });
+            self.sift_up(i);
+            return;
+        }
+        // The least frequent value is at the root of the heap.
+        let min = self.heap[0].count;
+        let old = mem::replace(&mut self.heap[0], Counter {
+            value: v.clone(),
+            count: min + weight,
+            error: min,
+        });
+        self.index.remove(&old.value);
+        self.index.insert(v, 0);
+        self.sift_down(0);
+    }
+
+    /// Returns the `k` tracked values with the largest estimated counts,
+    /// with their counts, in descending order of count.
+    pub fn top(&self, k: usize) -> Vec<(&T, W)> {
+        let mut counts: Vec<(&T, W)> = self.heap
+            .iter()
+            .map(|c| (&c.value, c.count))
+            .collect();
+        counts.sort_by(|&(_, c1), &(_, c2)| total_cmp(&c2, &c1));
+        counts.truncate(k);
+        counts
+    }
+
+    /// Returns the estimated count of `v`.
+    ///
+    /// For a tracked value, this is an upper bound of its true count. A
+    /// value that is not tracked has a count of `0`, although it may have
+    /// occurred up to `min_count()` times.
+    pub fn count(&self, v: &T) -> W {
+        self.index.get(v).map_or(W::zero(), |&i| self.heap[i].count)
+    }
+
+    /// Returns the maximum overestimate of the count of `v`.
+    ///
+    /// The true count of a tracked value is in `[count - error, count]`.
+    pub fn error(&self, v: &T) -> W {
+        self.index.get(v).map_or(self.min_count(), |&i| self.heap[i].error)
+    }
+
+    /// Returns the smallest count of a tracked value, or `0` if fewer than
+    /// `capacity` values are tracked.
+    ///
+    /// No value that is not tracked occurs more often than this.
+    pub fn min_count(&self) -> W {
+        if self.heap.len() < self.capacity {
+            W::zero()
+        } else {
+            self.heap[0].count
+        }
+    }
+
+    /// Merges many summaries into this one, e.g., the summaries of
+    /// thousands of shards.
+    ///
+    /// Merging summaries one at a time sorts the tracked values after every
+    /// merge. This pools the tracked values of all of the summaries and
+    /// sorts them once, which is much faster. Since no value is dropped
+    /// before all of them are pooled, the counts are also no less accurate.
+    ///
+    /// This panics if the summaries have different capacities.
+    pub fn merge_many<I>(&mut self, others: I)
+            where I: IntoIterator<Item=TopK<T, W>> {
+        let this = TopK {
+            heap: mem::replace(&mut self.heap, vec![]),
+            index: mem::replace(&mut self.index, HashMap::new()),
+            capacity: self.capacity,
+            len: 0,
+        };
+        // A value that is missing from a summary may have occurred up to
+        // its minimum count times there, so every value gets the sum of the
+        // minimums of the summaries that do not track it.
+        let mut total_min = W::zero();
+        let mut pooled: Vec<(Counter<T, W>, W)> = vec![];
+        let mut index: HashMap<T, usize> = HashMap::new();
+        for top in Some(this).into_iter().chain(others) {
+            assert_eq!(self.capacity, top.capacity);
+            let min = top.min_count();
+            total_min = total_min + min;
+            self.len += top.len;
+            for c in top.heap {
+                match index.get(&c.value) {
+                    Some(&i) => {
+                        let p = &mut pooled[i];
+                        p.0.count = p.0.count + c.count;
+                        p.0.error = p.0.error + c.error;
+                        p.1 = p.1 + min;
+                    }
+                    None => {
+                        index.insert(c.value.clone(), pooled.len());
+                        pooled.push((c, min));
+                    }
+                }
+            }
+        }
+        let mut counters: Vec<Counter<T, W>> = pooled
+            .into_iter()
+            .map(|(mut c, tracked_min)| {
+                let missing = total_min - tracked_min;
+                c.count = c.count + missing;
+                c.error = c.error + missing;
+                c
+            })
+            .collect();
+        counters.sort_by(|a, b| total_cmp(&b.count, &a.count));
+        counters.truncate(self.capacity);
+        counters.reverse();
+        self.rebuild(counters);
+    }
+
+    /// Returns the maximum number of values that are tracked.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Replace the tracked values with `counters`, which must be sorted in
+    /// ascending order of count and therefore already form a heap.
+    fn rebuild(&mut self, counters: Vec<Counter<T, W>>) {
+        self.index = counters
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.value.clone(), i))
+            .collect();
+        self.heap = counters;
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.heap[parent].count <= self.heap[i].count {
+                break;
+            }
+            self.swap(parent, i);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let (left, right) = (2 * i + 1, 2 * i + 2);
+            let mut least = i;
+            if left < self.heap.len()
+                    && self.heap[left].count < self.heap[least].count {
+                least = left;
+            }
+            if right < self.heap.len()
+                    && self.heap[right].count < self.heap[least].count {
+                least = right;
+            }
+            if least == i {
+                break;
+            }
+            self.swap(i, least);
+            i = least;
+        }
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        *self.index.get_mut(&self.heap[i].value).unwrap() = i;
+        *self.index.get_mut(&self.heap[j].value).unwrap() = j;
+    }
+}
+
+/// Divide all counts by `factor`, which keeps their order.
+pub fn scale<T, W: Weight>(top: &mut TopK<T, W>, factor: W) {
+    for c in top.heap.iter_mut() {
+        c.count = c.count / factor;
+        c.error = c.error / factor;
+    }
+}
+
+/// Returns a summary of exact `counts` that tracks the `capacity` most
+/// frequent values, with no error.
+pub fn from_counts<T, I>(capacity: usize, counts: I) -> TopK<T>
+        where T: Eq + Hash + Clone, I: IntoIterator<Item=(T, u64)> {
+    let mut top = TopK::new(capacity);
+    let mut counters: Vec<Counter<T, u64>> = counts
+        .into_iter()
+        .map(|(v, n)| Counter { value: v, count: n, error: 0 })
+        .collect();
+    top.len = counters.iter().map(|c| c.count).sum();
+    counters.sort_by_key(|c| Reverse(c.count));
+    counters.truncate(capacity);
+    counters.reverse();
+    top.rebuild(counters);
+    top
+}
+
+/// Returns the number of tracked values.
+pub fn tracked<T, W>(top: &TopK<T, W>) -> usize {
+    top.heap.len()
+}
+
+impl<T: Eq + Hash + Clone, W: Weight> Commute for TopK<T, W> {
+    fn merge(&mut self, v: TopK<T, W>) {
+        self.merge_many(Some(v));
+    }
+}
+
+/// Deserializing rebuilds the index of the heap, and checks that the heap
+/// is ordered and holds distinct values that fit in the capacity.
+#[cfg(feature = "serialize")]
+impl<'de, T, W> Deserialize<'de> for TopK<T, W>
+        where T: Eq + Hash + Clone + Deserialize<'de>,
+              W: Weight + Deserialize<'de> {
+    fn deserialize<D>(d: D) -> Result<TopK<T, W>, D::Error>
+            where D: Deserializer<'de> {
+        #[derive(Deserialize)]
+        struct Parts<T, W> {
+            heap: Vec<Counter<T, W>>,
+            capacity: usize,
+            len: u64,
+        }
+        let parts = Parts::deserialize(d)?;
+        if parts.capacity == 0 || parts.heap.len() > parts.capacity {
+            return Err(D::Error::custom("more values than the capacity"));
+        }
+        let ordered = (1..parts.heap.len()).all(|i| {
+            parts.heap[(i - 1) / 2].count <= parts.heap[i].count
+        });
+        if !ordered {
+            return Err(D::Error::custom("counts are not a heap"));
+        }
+        let mut top = TopK::weighted(parts.capacity);
+        top.len = parts.len;
+        top.rebuild(parts.heap);
+        if top.index.len() != top.heap.len() {
+            return Err(D::Error::custom("values are not distinct"));
+        }
+        Ok(top)
+    }
+}
+
+impl<T: Eq + Hash + Clone, W: Weight> Extend<T> for TopK<T, W> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TopK;
+    use Commute;
+
+    #[test]
+    fn exact_below_capacity() {
+        let mut top = TopK::new(3);
+        top.extend(vec!["a", "b", "a", "c", "a", "b"]);
+        assert_eq!(top.top(2), vec![(&"a", 3), (&"b", 2)]);
+        assert_eq!(top.error(&"a"), 0);
+        assert_eq!(top.min_count(), 1);
+        assert_eq!(top.count(&"d"), 0);
+    }
+
+    #[test]
+    fn heavy_hitters() {
+        let mut top = TopK::new(10);
+        for i in 0..10000u32 {
+            // Every fifth sample is 0 and every tenth is 1; the rest are
+            // distinct.
+            let v = if i % 5 == 0 { 0 } else if i % 10 == 1 { 1 } else { i };
+            top.add(v);
+        }
+        let best = top.top(2);
+        assert_eq!((best[0].0, best[1].0), (&0, &1));
+        for &(v, true_count) in &[(0, 2000), (1, 1000)] {
+            let (count, error) = (top.count(&v), top.error(&v));
+            assert!(count >= true_count && count - error <= true_count);
+        }
+    }
+
+    #[test]
+    fn weighted() {
+        let mut top = TopK::weighted(2);
+        top.add_weighted("a", 0.5);
+        top.add_weighted("b", 2.0);
+        top.add_weighted("a", 1.0);
+        top.add_weighted("c", 0.25);
+        // `c` replaces `a`, the lightest, and inherits its weight.
+        assert_eq!(top.top(2), vec![(&"b", 2.0), (&"c", 1.75)]);
+        assert_eq!(top.error(&"c"), 1.5);
+        assert_eq!(top.count(&"a"), 0.0);
+    }
+
+    #[test]
+    fn bounds() {
+        use std::collections::HashMap;
+
+        let mut top = TopK::new(16);
+        let mut exact = HashMap::new();
+        for i in 0..5000u64 {
+            let v = (i * i + 7 * i) % 97 % (1 + i % 40);
+            top.add(v);
+            *exact.entry(v).or_insert(0) += 1;
+        }
+        // Space-Saving never loses a count, it only reassigns it.
+        let total: u64 = top.top(16).iter().map(|&(_, c)| c).sum();
+        assert_eq!(total, 5000);
+        for (v, count) in top.top(16) {
+            let error = top.error(v);
+            assert!(count >= exact[v] && count - error <= exact[v]);
+        }
+        for (v, &n) in &exact {
+            assert!(top.count(v) > 0 || n <= top.min_count());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serialize")]
+    fn deserialize_checks_heap() {
+        use serde_json;
+
+        let mut top = TopK::new(3);
+        top.extend(vec![1u32, 2, 2, 3, 3, 3, 4]);
+        let json = serde_json::to_string(&top).unwrap();
+        let back: TopK<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.top(3), top.top(3));
+        assert_eq!(back.error(&4), top.error(&4));
+
+        let bad = [
+            r#"{"heap":[{"value":1,"count":2,"error":0},
+                        {"value":2,"count":1,"error":0}],
+                "capacity":2,"len":3}"#,
+            r#"{"heap":[{"value":1,"count":1,"error":0},
+                        {"value":1,"count":2,"error":0}],
+                "capacity":2,"len":3}"#,
+            r#"{"heap":[{"value":1,"count":1,"error":0}],
+                "capacity":0,"len":1}"#,
+        ];
+        for json in &bad {
+            assert!(serde_json::from_str::<TopK<u32>>(json).is_err());
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut t1 = TopK::new(2);
+        t1.extend(vec!["a", "a", "a", "b", "c"]);
+        let mut t2 = TopK::new(2);
+        t2.extend(vec!["a", "d", "d", "d"]);
+        t1.merge(t2);
+        assert_eq!(t1.len(), 9);
+        // `d` may have occurred twice in the first summary.
+        assert_eq!(t1.top(2), vec![(&"d", 5), (&"a", 4)]);
+        assert!(t1.count(&"a") >= 4 && t1.count(&"a") - t1.error(&"a") <= 4);
+        assert!(t1.count(&"d") >= 3 && t1.count(&"d") - t1.error(&"d") <= 3);
+    }
+
+    #[test]
+    fn merge_many() {
+        // Value `v` occurs `v` times in every shard, and each shard also
+        // has a few values of its own.
+        let shards: Vec<TopK<u32>> = (0..100u32).map(|s| {
+            let mut top = TopK::new(8);
+            for v in 1..11u32 {
+                top.extend((0..v).map(|_| v));
+            }
+            top.extend(vec![1000 + s, 1000 + s, 2000 + s]);
+            top
+        }).collect();
+        let mut all = TopK::new(8);
+        all.merge_many(shards.clone());
+        assert_eq!(all.len(), 100 * 58);
+        let top: Vec<u32> = all.top(3).into_iter().map(|(&v, _)| v).collect();
+        assert_eq!(top, vec![10, 9, 8]);
+        for v in 8..11u32 {
+            let (count, error) = (all.count(&v), all.error(&v));
+            assert!(count >= 100 * v as u64);
+            assert!(count - error <= 100 * v as u64);
+        }
+
+        // Merging a single summary is the same as `merge`.
+        let mut one = shards[0].clone();
+        one.merge_many(vec![shards[1].clone()]);
+        let mut pair = shards[0].clone();
+        pair.merge(shards[1].clone());
+        assert_eq!(one.top(8), pair.top(8));
+    }
+}
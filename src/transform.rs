@@ -0,0 +1,189 @@
+use std::prelude::v1::*;
+
+use num_traits::ToPrimitive;
+
+use minmax::MinMax;
+use online::OnlineStats;
+
+/// What min-max scaling does with values outside of the fitted range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfRange {
+    /// Scale the value linearly, so it lands outside of the target range.
+    Extrapolate,
+    /// Clamp the value to the fitted range first, so it lands on the
+    /// nearest end of the target range.
+    Clamp,
+}
+
+/// Standardize the data to zero mean and unit variance.
+///
+/// This fits an `OnlineStats` to the data in a first pass and then computes
+/// the z-score of every value in a second pass. To standardize new data
+/// with statistics fitted on a training set, use `OnlineStats::standardize`
+/// instead.
+pub fn standardize<T: ToPrimitive>(data: &[T]) -> Vec<f64> {
+    let stats = OnlineStats::from_slice(data);
+    stats.standardize(data.iter().map(|x| x.to_f64().unwrap())).collect()
+}
+
+/// Returns the standard score of `x` given a mean and standard deviation.
+///
+/// When the standard deviation is zero, every value is considered to be at
+/// the mean, so `0` is returned rather than dividing by zero.
+pub fn z_score(x: f64, mean: f64, stddev: f64) -> f64 {
+    if stddev == 0.0 { 0.0 } else { (x - mean) / stddev }
+}
+
+/// An iterator that standardizes values with fitted statistics.
+///
+/// This is created by `OnlineStats::standardize`.
+#[derive(Clone, Debug)]
+pub struct Standardize<I> {
+    it: I,
+    mean: f64,
+    stddev: f64,
+}
+
+impl<I> Standardize<I> {
+    /// Wrap `it` so that it yields the z-score of each of its values.
+    pub fn new(it: I, stats: &OnlineStats) -> Standardize<I> {
+        Standardize { it, mean: stats.mean(), stddev: stats.stddev() }
+    }
+}
+
+impl<I> Iterator for Standardize<I>
+        where I: Iterator, <I as Iterator>::Item: ToPrimitive {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        self.it.next().map(|x| {
+            z_score(x.to_f64().unwrap(), self.mean, self.stddev)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.it.size_hint()
+    }
+}
+
+/// Rescale the data linearly into `[0, 1]`.
+///
+/// This fits a `MinMax` to the data in a first pass and then scales every
+/// value in a second pass. To scale new data with a range fitted on a
+/// training set, use `MinMax::rescale` instead.
+pub fn normalize<T: PartialOrd + Clone + ToPrimitive>(data: &[T]) -> Vec<f64> {
+    let fitted: MinMax<T> = data.iter().cloned().collect();
+    match fitted.rescale(data.iter().cloned(), 0.0, 1.0,
+                         OutOfRange::Extrapolate) {
+        Some(it) => it.collect(),
+        None => vec![],
+    }
+}
+
+/// Map `x` from the fitted range `[min, max]` to the range `[lo, hi]`.
+///
+/// When the fitted range is empty (`min == max`), every value maps to `lo`.
+pub fn min_max_scale(
+    x: f64,
+    (min, max): (f64, f64),
+    (lo, hi): (f64, f64),
+    policy: OutOfRange,
+) -> f64 {
+    if min == max {
+        return lo;
+    }
+    let x = match policy {
+        OutOfRange::Extrapolate => x,
+        OutOfRange::Clamp => x.max(min).min(max),
+    };
+    lo + (x - min) / (max - min) * (hi - lo)
+}
+
+/// An iterator that rescales values with a fitted range.
+///
+/// This is created by `MinMax::rescale`.
+#[derive(Clone, Debug)]
+pub struct Rescale<I> {
+    it: I,
+    fitted: (f64, f64),
+    target: (f64, f64),
+    policy: OutOfRange,
+}
+
+impl<I> Rescale<I> {
+    /// Wrap `it` so that it maps its values from the fitted range
+    /// `[min, max]` to `[lo, hi]`.
+    pub fn new(
+        it: I,
+        (min, max): (f64, f64),
+        (lo, hi): (f64, f64),
+        policy: OutOfRange,
+    ) -> Rescale<I> {
+        Rescale {
+            it,
+            fitted: (min, max),
+            target: (lo, hi),
+            policy,
+        }
+    }
+}
+
+impl<I> Iterator for Rescale<I>
+        where I: Iterator, <I as Iterator>::Item: ToPrimitive {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        self.it.next().map(|x| {
+            min_max_scale(x.to_f64().unwrap(), self.fitted, self.target,
+                          self.policy)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.it.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OutOfRange, normalize, standardize};
+    use {MinMax, OnlineStats};
+
+    #[test]
+    fn fit_then_transform() {
+        assert_eq!(standardize(&[2, 4, 4, 4, 5, 5, 7, 9]),
+                   vec![-1.5, -0.5, -0.5, -0.5, 0.0, 0.0, 1.0, 2.0]);
+        assert_eq!(standardize(&[3.0, 3.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn fitted_stats() {
+        let train = OnlineStats::from_slice(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        let test: Vec<f64> = train.standardize(vec![1, 11]).collect();
+        assert_eq!(test, vec![-2.0, 3.0]);
+        assert_eq!(train.z_score(5), 0.0);
+    }
+
+    #[test]
+    fn min_max() {
+        assert_eq!(normalize(&[2, 6, 4, 10]), vec![0.0, 0.5, 0.25, 1.0]);
+        assert_eq!(normalize(&[3, 3]), vec![0.0, 0.0]);
+        assert_eq!(normalize::<u8>(&[]), vec![]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let fitted: MinMax<i32> = vec![0, 10].into_iter().collect();
+        let extrapolated: Vec<f64> = fitted
+            .rescale(vec![-5, 5, 20], -1.0, 1.0, OutOfRange::Extrapolate)
+            .unwrap()
+            .collect();
+        assert_eq!(extrapolated, vec![-2.0, 0.0, 3.0]);
+        assert_eq!(fitted.scale(-5, -1.0, 1.0, OutOfRange::Clamp),
+                   Some(-1.0));
+        assert_eq!(fitted.scale(20, -1.0, 1.0, OutOfRange::Clamp), Some(1.0));
+        assert_eq!(MinMax::<i32>::new().scale(1, 0.0, 1.0,
+                                              OutOfRange::Clamp),
+                   None);
+    }
+}
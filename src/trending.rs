@@ -0,0 +1,190 @@
+use std::prelude::v1::*;
+
+use std::fmt;
+use std::hash::Hash;
+
+use Commute;
+use topk::{self, TopK};
+
+/// A commutative data structure for finding the keys that are frequent
+/// recently, in a fixed amount of memory.
+///
+/// Every sample has a weight that halves every `half_life` units of time,
+/// so the score of a key is its recent frequency rather than its frequency
+/// of all time. At most `capacity` keys are tracked, as in the Space-Saving
+/// algorithm of `TopK`: a new key replaces the key with the lowest score and
+/// inherits that score. Scores may therefore overestimate, but never by more
+/// than the lowest tracked score, and every key whose decayed frequency is
+/// above that is tracked.
+///
+/// Samples do not need to arrive in time order. Internally, weights grow
+/// exponentially from a landmark time instead of decaying, so the keys are
+/// tracked by a `TopK` of the weights and adding a sample takes
+/// `O(log capacity)` time.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: Eq + Hash + ::serde::Serialize",
+    deserialize = "T: Eq + Hash + Clone + ::serde::Deserialize<'de>"
+)))]
+pub struct Trending<T> {
+    top: TopK<T, f64>,
+    half_life: f64,
+    landmark: f64,
+}
+
+impl<T: fmt::Debug + Eq + Hash> fmt::Debug for Trending<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.top)
+    }
+}
+
+impl<T: Eq + Hash + Clone> Trending<T> {
+    /// Create a tracker of at most `capacity` keys whose samples lose half
+    /// of their weight every `half_life` units of time.
+    ///
+    /// This panics if `capacity` is `0` or `half_life` is not positive.
+    pub fn new(capacity: usize, half_life: f64) -> Trending<T> {
+        assert!(capacity > 0, "capacity must be positive");
+        assert!(half_life > 0.0, "half life must be positive");
+        Trending {
+            top: TopK::weighted(capacity),
+            half_life,
+            landmark: 0.0,
+        }
+    }
+
+    /// Add a sample observed at `time`.
+    pub fn add(&mut self, v: T, time: f64) {
+        if (time - self.landmark) / self.half_life > MAX_EXPONENT {
+            self.rescale(time);
+        }
+        let weight = self.growth(time);
+        self.top.add_weighted(v, weight);
+    }
+
+    /// Returns the decayed frequency of `v` at time `now`.
+    ///
+    /// Keys that are not tracked have a score of `0`.
+    pub fn score(&self, v: &T, now: f64) -> f64 {
+        self.top.count(v) / self.growth(now)
+    }
+
+    /// Returns the tracked keys with their decayed frequencies at time
+    /// `now`, in descending order of frequency.
+    pub fn trending(&self, now: f64) -> Vec<(&T, f64)> {
+        let norm = self.growth(now);
+        self.top
+            .top(self.top.capacity())
+            .into_iter()
+            .map(|(k, s)| (k, s / norm))
+            .collect()
+    }
+
+    /// Returns the maximum number of keys that are tracked.
+    pub fn capacity(&self) -> usize {
+        self.top.capacity()
+    }
+
+    /// Returns the number of keys that are tracked.
+    pub fn len(&self) -> usize {
+        topk::tracked(&self.top)
+    }
+
+    /// Returns the weight of a sample at `time`, relative to the landmark.
+    fn growth(&self, time: f64) -> f64 {
+        ((time - self.landmark) / self.half_life).exp2()
+    }
+
+    /// Moves the landmark to `time`, so that weights do not overflow.
+    fn rescale(&mut self, time: f64) {
+        let norm = self.growth(time);
+        topk::scale(&mut self.top, norm);
+        self.landmark = time;
+    }
+}
+
+/// Weights are rescaled before they grow past `2^MAX_EXPONENT`.
+const MAX_EXPONENT: f64 = 512.0;
+
+impl<T: Eq + Hash + Clone> Commute for Trending<T> {
+    fn merge(&mut self, mut v: Trending<T>) {
+        assert_eq!(self.half_life, v.half_life);
+        if v.landmark > self.landmark {
+            self.rescale(v.landmark);
+        } else {
+            v.rescale(self.landmark);
+        }
+        self.top.merge(v.top);
+    }
+}
+
+impl<T: Eq + Hash + Clone> Extend<(T, f64)> for Trending<T> {
+    fn extend<I: IntoIterator<Item=(T, f64)>>(&mut self, it: I) {
+        for (v, time) in it {
+            self.add(v, time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Trending;
+    use Commute;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn decays() {
+        let mut t = Trending::new(10, 1.0);
+        t.extend(vec![("old", 0.0), ("old", 0.0), ("old", 0.0)]);
+        t.extend(vec![("new", 3.0), ("new", 3.0)]);
+        assert!(close(t.score(&"old", 3.0), 3.0 / 8.0));
+        assert!(close(t.score(&"new", 3.0), 2.0));
+        assert!(close(t.score(&"new", 4.0), 1.0));
+        assert_eq!(t.score(&"none", 4.0), 0.0);
+
+        let top = t.trending(3.0);
+        assert_eq!(top[0].0, &"new");
+        assert_eq!(top[1].0, &"old");
+    }
+
+    #[test]
+    fn evicts_lowest() {
+        let mut t = Trending::new(2, 10.0);
+        t.extend(vec![("a", 0.0), ("a", 0.0), ("b", 0.0), ("c", 0.0)]);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.score(&"b", 0.0), 0.0);
+        // `c` inherits the score of `b`, so it may overestimate.
+        assert!(close(t.score(&"c", 0.0), 2.0));
+        assert!(close(t.score(&"a", 0.0), 2.0));
+    }
+
+    #[test]
+    fn long_streams() {
+        let mut t = Trending::new(2, 1.0);
+        for i in 0..2000 {
+            t.add(i % 2, i as f64);
+        }
+        let total = t.score(&0, 1999.0) + t.score(&1, 1999.0);
+        assert!(close(total, 2.0));
+        assert!(t.score(&1, 1999.0) > t.score(&0, 1999.0));
+    }
+
+    #[test]
+    fn merge() {
+        let mut t1 = Trending::new(2, 1.0);
+        t1.extend(vec![("a", 0.0), ("b", 1.0)]);
+        let mut t2 = Trending::new(2, 1.0);
+        t2.extend(vec![("b", 2.0), ("c", 2.0), ("c", 2.0)]);
+        t1.merge(t2);
+        assert_eq!(t1.len(), 2);
+        // As in `TopK`, a key missing from a full tracker may have had up
+        // to its lowest score there, so `c` gains the `1 / 4` of `a`.
+        assert!(close(t1.score(&"b", 2.0), 1.5));
+        assert!(close(t1.score(&"c", 2.0), 2.25));
+        assert_eq!(t1.score(&"a", 2.0), 0.0);
+    }
+}
@@ -2,10 +2,29 @@
 use std::prelude::v1::*;
 use std::vec;
 use std::default::Default;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
 use std::iter::{FromIterator, IntoIterator};
+use std::mem;
 use num_traits::ToPrimitive;
 
-use {Commute, Partial};
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use {Commute, CommuteRef, Partial, total_cmp};
+use frequency::{Count, Frequencies, widen};
+use checkpoint::{Checkpoint, CheckpointError, Kind, seal, unseal};
+use records::Record;
+use distributions::normal_quantile;
+use histogram::{BinRule, Histogram, bin_count_on_sorted, bin_width_on_sorted,
+                histogram_on_sorted};
+use hypothesis::{TestResult, kruskal_wallis};
+use kde::{Bandwidth, KernelDensity};
+use random::RandomSource;
+use reservoir::Reservoir;
+use tdigest::TDigest;
 
 /// Compute the exact median on a stream of data.
 ///
@@ -19,7 +38,9 @@ pub fn median<I>(it: I) -> Option<f64>
 ///
 /// (This has time complexity `O(nlogn)` and space complexity `O(n)`.)
 ///
-/// If the data does not have a mode, then `None` is returned.
+/// The mode is the only value that occurs more often than every other
+/// value. If the data does not have a mode, because several values tie or
+/// every value occurs once, then `None` is returned.
 pub fn mode<T, I>(it: I) -> Option<T>
        where T: PartialOrd + Clone, I: Iterator<Item=T> {
     it.collect::<Unsorted<T>>().mode()
@@ -47,6 +68,50 @@ pub fn modes<T, I>(it: I) -> Vec<T>
     it.collect::<Unsorted<T>>().modes()
 }
 
+/// Quantile-normalize `data` to the distribution of `reference`.
+///
+/// Each value is mapped through the empirical CDF of `data` and then through
+/// the empirical quantile function of `reference`. The result follows the
+/// distribution of the reference, but the values keep their original
+/// order, and tied values map to the same value. This is commonly used to
+/// correct batch effects between samples, e.g., in genomics.
+///
+/// `NaN` values of `data` are left out of its CDF and stay `NaN`. `None` is
+/// returned if `reference` is empty.
+pub fn quantile_normalize<T, U>(
+    data: &[T],
+    reference: &mut Unsorted<U>,
+) -> Option<Vec<f64>>
+        where T: ToPrimitive, U: PartialOrd + ToPrimitive {
+    if reference.len() == 0 {
+        return None;
+    }
+    let mut sorted: Vec<f64> = data
+        .iter()
+        .map(|x| x.to_f64().unwrap())
+        .filter(|x| !x.is_nan())
+        .collect();
+    sorted.sort_unstable_by(total_cmp);
+    let n = sorted.len() as f64;
+    data.iter().map(|x| {
+        let x = x.to_f64().unwrap();
+        if x.is_nan() {
+            return Some(x);
+        }
+        reference.quantile_function(count_le(&sorted, x) as f64 / n)
+    }).collect()
+}
+
+/// Returns the number of values in `sorted` that are at most `x`.
+fn count_le(sorted: &[f64], x: f64) -> usize {
+    let (mut lo, mut hi) = (0, sorted.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if sorted[mid] <= x { lo = mid + 1 } else { hi = mid }
+    }
+    lo
+}
+
 fn median_on_sorted<T>(data: &[T]) -> Option<f64>
         where T: PartialOrd + ToPrimitive {
     Some(match data.len() {
@@ -63,81 +128,144 @@ fn median_on_sorted<T>(data: &[T]) -> Option<f64>
     })
 }
 
-fn mode_on_sorted<T, I>(it: I) -> Option<T>
-        where T: PartialOrd, I: Iterator<Item=T> {
-    // This approach to computing the mode works very nicely when the
-    // number of samples is large and is close to its cardinality.
-    // In other cases, a hashmap would be much better.
-    // But really, how can we know this when given an arbitrary stream?
-    // Might just switch to a hashmap to track frequencies. That would also
-    // be generally useful for discovering the cardinality of a sample.
-    let (mut mode, mut next) = (None, None);
-    let (mut mode_count, mut next_count) = (0usize, 0usize);
-    for x in it {
-        if mode.as_ref().map(|y| y == &x).unwrap_or(false) {
-            mode_count += 1;
-        } else if next.as_ref().map(|y| y == &x).unwrap_or(false) {
-            next_count += 1;
-        } else {
-            next = Some(x);
-            next_count = 0;
-        }
+/// Computes the `q`th quantile of sorted data by linear interpolation
+/// between the closest ranks.
+///
+/// This is the method that R calls type 7 and is the default in most
+/// statistical software. `q` must be in `[0, 1]`.
+pub fn quantile_on_sorted<T>(data: &[T], q: f64) -> Option<f64>
+        where T: ToPrimitive {
+    if data.is_empty() || !(q >= 0.0 && q <= 1.0) {
+        return None;
+    }
+    let pos = q * (data.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    let v1 = data[lo].to_f64().unwrap();
+    if lo == hi {
+        return Some(v1);
+    }
+    let v2 = data[hi].to_f64().unwrap();
+    Some(v1 + (v2 - v1) * (pos - lo as f64))
+}
 
-        if next_count > mode_count {
-            mode = next;
-            mode_count = next_count;
-            next = None;
-            next_count = 0;
-        } else if next_count == mode_count {
-            mode = None;
-            mode_count = 0usize;
-        }
+/// Returns the smallest 1-based rank `k` of `n` samples such that
+/// `k / n >= p`, for `p` in `[0, 1]` and positive `n`.
+///
+/// `ceil(p * n)` is off by one whenever `p * n` rounds to just past an
+/// integer, e.g., `0.07 * 100`, so the candidate is corrected against the
+/// inequality itself.
+pub fn nearest_rank(p: f64, n: u64) -> u64 {
+    let mut k = ((p * n as f64).ceil() as u64).max(1).min(n);
+    while k > 1 && (k - 1) as f64 / n as f64 >= p {
+        k -= 1;
     }
-    mode
+    while k < n && (k as f64 / n as f64) < p {
+        k += 1;
+    }
+    k
 }
 
-fn modes_on_sorted<T, I>(it: I) -> Vec<T>
-        where T: PartialOrd, I: Iterator<Item=T> {
+/// Computes the half-sample mode of sorted data.
+///
+/// The data is repeatedly narrowed to the shortest interval containing half
+/// of the remaining samples until at most three are left.
+fn half_sample_mode_on_sorted(data: &[f64]) -> Option<f64> {
+    let mut data = data;
+    loop {
+        match data.len() {
+            0 => return None,
+            1 => return Some(data[0]),
+            2 => return Some((data[0] + data[1]) / 2.0),
+            3 => {
+                let (lo, hi) = (data[1] - data[0], data[2] - data[1]);
+                return Some(if lo < hi {
+                    (data[0] + data[1]) / 2.0
+                } else if lo > hi {
+                    (data[1] + data[2]) / 2.0
+                } else {
+                    data[1]
+                });
+            }
+            n => {
+                let h = (n + 1) / 2;
+                let mut best = 0;
+                for j in 1..(n - h + 1) {
+                    let width = data[j + h - 1] - data[j];
+                    if width < data[best + h - 1] - data[best] {
+                        best = j;
+                    }
+                }
+                data = &data[best..best + h];
+            }
+        }
+    }
+}
 
-    let mut highest_mode = 1_u32;
-    let mut modes: Vec<u32> = vec![];
-    let mut values = vec![];
-    let mut count = 0;
-    for x in it {
-        if values.len() == 0 {
-            values.push(x);
-            modes.push(1);
-            continue
+/// Returns the index of the first element of every longest run of equal
+/// values in sorted data, and the length of those runs.
+///
+/// Nothing is returned if every value is distinct.
+fn modes_on_sorted<T: PartialEq>(data: &[T]) -> (Vec<usize>, usize) {
+    let (mut modes, mut longest) = (vec![], 1);
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = start + 1;
+        while end < data.len() && data[end] == data[start] {
+            end += 1;
         }
-        if x == values[count] {
-            modes[count] += 1;
-            if highest_mode < modes[count] {
-                highest_mode = modes[count];
-            }
-        } else {
-            values.push(x);
-            modes.push(1);
-            count += 1;
+        if end - start > longest {
+            longest = end - start;
+            modes.clear();
+        }
+        if end - start == longest && longest > 1 {
+            modes.push(start);
         }
+        start = end;
     }
-    modes.into_iter()
-        .zip(values)
-        .filter(|(cnt, _val)| *cnt == highest_mode && highest_mode > 1)
-        .map(|(_, val)| val)
-        .collect()
+    let count = if modes.is_empty() { 0 } else { longest };
+    (modes, count)
+}
+
+/// The storage for the samples in `Unsorted`.
+///
+/// With the `smallvec` feature enabled, the first 16 samples are stored
+/// inline, which avoids a heap allocation for the many tiny groups that are
+/// typical of per-key statistics.
+#[cfg(feature = "smallvec")]
+type Buffer<T> = SmallVec<[Partial<T>; 16]>;
+#[cfg(not(feature = "smallvec"))]
+type Buffer<T> = Vec<Partial<T>>;
+
+#[cfg(feature = "smallvec")]
+fn new_buffer<T>() -> Buffer<T> {
+    SmallVec::new()
+}
+
+#[cfg(not(feature = "smallvec"))]
+fn new_buffer<T>() -> Buffer<T> {
+    Vec::with_capacity(1000)
 }
 
 /// A commutative data structure for lazily sorted sequences of data.
 ///
 /// The sort does not occur until statistics need to be computed.
 ///
+/// Statistics that require a scan over the sorted data, like the mode and
+/// the cardinality, are remembered until the data is next modified, so
+/// asking for them repeatedly between updates is cheap.
+///
+/// Enabling the `smallvec` feature stores the first few samples inline
+/// instead of on the heap.
+///
 /// Note that this works on types that do not define a total ordering like
-/// `f32` and `f64`. When an ordering is not defined, an arbitrary order
-/// is returned.
+/// `f32` and `f64`. Values without an ordering, like `NaN`, sort after all
+/// other values.
 #[derive(Clone)]
 pub struct Unsorted<T> {
-    data: Vec<Partial<T>>,
+    data: Buffer<T>,
     sorted: bool,
+    modes: Option<(Vec<usize>, usize)>,
+    cardinality: Option<usize>,
 }
 
 impl<T: PartialOrd> Unsorted<T> {
@@ -157,40 +285,124 @@ impl<T: PartialOrd> Unsorted<T> {
         self.data.len()
     }
 
+    /// Returns a reference to the mode of the data.
+    ///
+    /// This is like `mode`, but does not require the data to be cloneable.
+    pub fn mode_ref(&mut self) -> Option<&T> {
+        self.cache_modes();
+        let modes = &self.modes.as_ref().unwrap().0;
+        if modes.len() == 1 {
+            Some(&self.data[modes[0]].0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns references to the modes of the data.
+    ///
+    /// This is like `modes`, but does not require the data to be cloneable.
+    pub fn modes_ref(&mut self) -> Vec<&T> {
+        self.modes_with_count_ref().0
+    }
+
+    /// Returns references to the modes of the data and the number of times
+    /// that each of them occurs.
+    ///
+    /// This is like `modes_with_count`, but does not require the data to be
+    /// cloneable.
+    pub fn modes_with_count_ref(&mut self) -> (Vec<&T>, usize) {
+        self.cache_modes();
+        let data = &self.data;
+        let &(ref modes, count) = self.modes.as_ref().unwrap();
+        (modes.iter().map(|&i| &data[i].0).collect(), count)
+    }
+
+    /// Returns the low median of the data.
+    ///
+    /// When the number of samples is even, this is the smaller of the two
+    /// middle values. Unlike `median`, the value is never converted to
+    /// `f64`, so this is exact for any type, including `u128`, `i128` and
+    /// arbitrary precision integers.
+    pub fn median_low(&mut self) -> Option<&T> {
+        self.sort();
+        match self.data.len() {
+            0 => None,
+            len => Some(&self.data[(len - 1) / 2].0),
+        }
+    }
+
+    /// Returns the high median of the data.
+    ///
+    /// When the number of samples is even, this is the larger of the two
+    /// middle values. Like `median_low`, this is exact for any type.
+    pub fn median_high(&mut self) -> Option<&T> {
+        self.sort();
+        match self.data.len() {
+            0 => None,
+            len => Some(&self.data[len / 2].0),
+        }
+    }
+
+    /// Return the cardinality (number of unique elements) of the data.
+    pub fn cardinality(&mut self) -> usize {
+        if self.cardinality.is_none() {
+            self.sort();
+            let runs = match self.data.len() {
+                0 => 0,
+                n => 1 + (1..n).filter(|&i| {
+                    self.data[i] != self.data[i - 1]
+                }).count(),
+            };
+            self.cardinality = Some(runs);
+        }
+        self.cardinality.unwrap()
+    }
+
+    fn cache_modes(&mut self) {
+        if self.modes.is_none() {
+            self.sort();
+            self.modes = Some(modes_on_sorted(&self.data));
+        }
+    }
+
     fn sort(&mut self) {
         if !self.sorted {
-            self.data.sort();
+            // Stability is meaningless here since equal elements are
+            // indistinguishable, and the unstable sort is faster and does
+            // not allocate.
+            self.data.sort_unstable();
+            self.sorted = true;
         }
     }
 
     fn dirtied(&mut self) {
         self.sorted = false;
-    }
-}
-
-impl<T: PartialOrd + Eq + Clone> Unsorted<T> {
-    pub fn cardinality(&mut self) -> usize {
-        self.sort();
-        let mut set = self.data.clone();
-        set.dedup();
-        set.len()
+        self.modes = None;
+        self.cardinality = None;
     }
 }
 
 impl<T: PartialOrd + Clone> Unsorted<T> {
     /// Returns the mode of the data.
     pub fn mode(&mut self) -> Option<T> {
-        self.sort();
-        mode_on_sorted(self.data.iter()).map(|p| p.0.clone())
+        self.mode_ref().cloned()
     }
 
     /// Returns the modes of the data.
     pub fn modes(&mut self) -> Vec<T> {
-        self.sort();
-        modes_on_sorted(self.data.iter())
-            .into_iter()
-            .map(|p| p.0.clone())
-            .collect()
+        self.modes_ref().into_iter().cloned().collect()
+    }
+
+    /// Returns the modes of the data and the number of times that each of
+    /// them occurs.
+    ///
+    /// Unlike `mode`, which returns `None` when several values tie for the
+    /// largest count, this returns all of them, in ascending order. If every
+    /// value is distinct, then an empty `Vec` and a count of `0` are
+    /// returned.
+    pub fn modes_with_count(&mut self) -> (Vec<T>, usize) {
+        let (modes, count) = self.modes_with_count_ref();
+        (modes.into_iter().cloned().collect(), count)
     }
 }
 
@@ -200,6 +412,298 @@ impl<T: PartialOrd + ToPrimitive> Unsorted<T> {
         self.sort();
         median_on_sorted(&*self.data)
     }
+
+    /// Returns the median absolute deviation of the data, the median of
+    /// the absolute differences of the samples from their median.
+    ///
+    /// This is a robust estimate of dispersion: unlike the standard
+    /// deviation, it is unaffected by up to half of the samples being
+    /// outliers. `None` is returned if there is no data.
+    pub fn mad(&mut self) -> Option<f64> {
+        let median = self.median()?;
+        let mut deviations: Vec<f64> = self.data
+            .iter()
+            .map(|x| (x.to_f64().unwrap() - median).abs())
+            .collect();
+        deviations.sort_by(total_cmp);
+        median_on_sorted(&deviations)
+    }
+
+    /// Returns the median absolute deviation scaled by `1.4826`, which
+    /// estimates the standard deviation of normally distributed data.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn mad_normal(&mut self) -> Option<f64> {
+        self.mad().map(|mad| 1.4826 * mad)
+    }
+
+    /// Returns the mean of the data without its extreme tails.
+    ///
+    /// `floor(fraction * n)` of the smallest and as many of the largest
+    /// samples are dropped before averaging, so `trimmed_mean(0.0)` is the
+    /// mean and the median is the limit as `fraction` approaches `0.5`.
+    ///
+    /// `None` is returned if there is no data or if `fraction` is not in
+    /// `[0, 0.5)`.
+    pub fn trimmed_mean(&mut self, fraction: f64) -> Option<f64> {
+        let k = self.tail_len(fraction)?;
+        let kept = &self.data[k..self.data.len() - k];
+        let sum: f64 = kept.iter().map(|x| x.to_f64().unwrap()).sum();
+        Some(sum / kept.len() as f64)
+    }
+
+    /// Returns the mean of the data with its extreme tails clamped.
+    ///
+    /// Like `trimmed_mean`, but the dropped samples are replaced by the
+    /// smallest and largest samples that are kept, so they still count
+    /// towards the mean without their outlying values.
+    ///
+    /// `None` is returned if there is no data or if `fraction` is not in
+    /// `[0, 0.5)`.
+    pub fn winsorized_mean(&mut self, fraction: f64) -> Option<f64> {
+        let k = self.tail_len(fraction)?;
+        let n = self.data.len();
+        let (lo, hi) = (self.data[k].to_f64().unwrap(),
+                        self.data[n - 1 - k].to_f64().unwrap());
+        let sum: f64 = self.data[k..n - k]
+            .iter()
+            .map(|x| x.to_f64().unwrap())
+            .sum();
+        Some((sum + k as f64 * (lo + hi)) / n as f64)
+    }
+
+    /// Sorts the data and returns the number of samples in each tail for
+    /// `trimmed_mean` and `winsorized_mean`.
+    fn tail_len(&mut self, fraction: f64) -> Option<usize> {
+        if self.data.is_empty() || !(fraction >= 0.0 && fraction < 0.5) {
+            return None;
+        }
+        self.sort();
+        Some((fraction * self.data.len() as f64).floor() as usize)
+    }
+
+    /// Returns the `q`th quantile of the data, e.g., `0.99` for the 99th
+    /// percentile.
+    ///
+    /// Values between samples are linearly interpolated, so `quantile(0.5)`
+    /// equals `median()`. The data is sorted at most once, no matter how
+    /// many quantiles are asked for.
+    ///
+    /// `None` is returned if there is no data or if `q` is not in `[0, 1]`.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        self.sort();
+        quantile_on_sorted(&self.data, q)
+    }
+
+    /// Returns the quantiles of the data for each of `qs`.
+    ///
+    /// This is like calling `quantile` for each of `qs`.
+    pub fn quantiles(&mut self, qs: &[f64]) -> Vec<Option<f64>> {
+        self.sort();
+        qs.iter().map(|&q| quantile_on_sorted(&self.data, q)).collect()
+    }
+
+    /// Returns the first, second and third quartiles of the data.
+    ///
+    /// The quartiles are the `0.25`, `0.5` and `0.75` quantiles, linearly
+    /// interpolated between the closest ranks as in `quantile`, so the
+    /// second quartile equals `median()`. The data is sorted only once.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn quartiles(&mut self) -> Option<(f64, f64, f64)> {
+        self.sort();
+        let data = &*self.data;
+        Some((quantile_on_sorted(data, 0.25)?,
+              quantile_on_sorted(data, 0.5)?,
+              quantile_on_sorted(data, 0.75)?))
+    }
+
+    /// Returns the interquartile range of the data, the difference of the
+    /// third and first quartiles as computed by `quartiles`.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn iqr(&mut self) -> Option<f64> {
+        self.quartiles().map(|(q1, _, q3)| q3 - q1)
+    }
+
+    /// Returns the empirical quantile function evaluated at `p`.
+    ///
+    /// This is the generalized inverse of the empirical CDF: the smallest
+    /// sample `x` such that at least a fraction `p` of the data is less
+    /// than or equal to `x`. Unlike `median`, no interpolation is done, so
+    /// the value returned is always one of the samples. Feeding uniformly
+    /// distributed values of `p` through this function draws samples from
+    /// the empirical distribution.
+    ///
+    /// `None` is returned if there is no data or if `p` is not in `[0, 1]`.
+    pub fn quantile_function(&mut self, p: f64) -> Option<f64> {
+        if self.data.is_empty() || !(p >= 0.0 && p <= 1.0) {
+            return None;
+        }
+        self.sort();
+        let n = self.data.len();
+        let i = nearest_rank(p, n as u64) as usize - 1;
+        self.data[i].to_f64()
+    }
+
+    /// Returns the half-sample mode of the data.
+    ///
+    /// This estimates the mode of continuous data, where values are rarely
+    /// exactly equal and `mode` is not meaningful. It is the robust
+    /// estimator of Bickel and Frühwirth: the data is repeatedly narrowed to
+    /// the densest half of the remaining samples.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn half_sample_mode(&mut self) -> Option<f64> {
+        self.sort();
+        let data: Vec<f64> =
+            self.data.iter().map(|x| x.to_f64().unwrap()).collect();
+        half_sample_mode_on_sorted(&data)
+    }
+
+    /// Returns Grenander's estimate of the mode of the data.
+    ///
+    /// This is a weighted mean of the midpoints of the intervals spanning
+    /// `k + 1` consecutive samples, where an interval of width `w` has a
+    /// weight of `1 / w^p`. Larger values of `p` focus the estimate on the
+    /// densest intervals. If some intervals have zero width, their weight
+    /// dominates and the mean of their midpoints is returned.
+    ///
+    /// `None` is returned if `k` is `0` or at least the number of samples.
+    pub fn grenander(&mut self, k: usize, p: f64) -> Option<f64> {
+        if k == 0 || k >= self.data.len() {
+            return None;
+        }
+        self.sort();
+        let data: Vec<f64> =
+            self.data.iter().map(|x| x.to_f64().unwrap()).collect();
+        let (mut num, mut den) = (0.0, 0.0);
+        let (mut tied_sum, mut tied) = (0.0, 0);
+        for (lo, hi) in data.iter().zip(&data[k..]) {
+            let width = hi - lo;
+            if width == 0.0 {
+                tied_sum += *lo;
+                tied += 1;
+            } else {
+                let w = width.powf(-p);
+                num += w * (lo + hi) / 2.0;
+                den += w;
+            }
+        }
+        Some(if tied > 0 { tied_sum / tied as f64 } else { num / den })
+    }
+
+    /// Returns the number of histogram bins that `rule` selects for the
+    /// data.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn bin_count(&mut self, rule: BinRule) -> Option<usize> {
+        self.sort();
+        bin_count_on_sorted(rule, &self.data)
+    }
+
+    /// Returns the histogram bin width that `rule` selects for the data.
+    ///
+    /// `None` is returned if there is no data.
+    pub fn bin_width(&mut self, rule: BinRule) -> Option<f64> {
+        self.sort();
+        bin_width_on_sorted(rule, &self.data)
+    }
+
+    /// Returns a histogram of the data, with bins of equal width covering
+    /// its range as chosen by `rule`.
+    ///
+    /// If all of the data is equal, the histogram has one bin of width `1`
+    /// centered on it. `None` is returned if there is no data.
+    pub fn histogram(&mut self, rule: BinRule) -> Option<Histogram> {
+        self.sort();
+        histogram_on_sorted(rule, &self.data)
+    }
+
+    /// Fit a Gaussian kernel density estimate to the data.
+    ///
+    /// `None` is returned if there are fewer than two data points or if the
+    /// selected bandwidth is not positive.
+    pub fn kde(&mut self, bandwidth: Bandwidth) -> Option<KernelDensity> {
+        self.sort();
+        KernelDensity::new(&self.data, bandwidth)
+    }
+
+    /// Returns the points of a normal Q-Q plot of the data.
+    ///
+    /// Each point is a pair of a theoretical quantile of the standard normal
+    /// distribution and the corresponding sample quantile, in ascending
+    /// order. Points that lie close to a straight line indicate that the
+    /// data is approximately normally distributed.
+    pub fn qq_normal(&mut self) -> Vec<(f64, f64)> {
+        self.qq_points(normal_quantile)
+    }
+
+    /// Returns the points of a Q-Q plot of the data against the distribution
+    /// whose inverse CDF is `inverse_cdf`.
+    ///
+    /// The `i`th smallest of `n` samples is paired with the theoretical
+    /// quantile at the plotting position `(i - 0.5) / n`.
+    pub fn qq_points<F>(&mut self, inverse_cdf: F) -> Vec<(f64, f64)>
+            where F: Fn(f64) -> f64 {
+        self.sort();
+        let n = self.data.len() as f64;
+        self.data.iter().enumerate().map(|(i, x)| {
+            let p = (i as f64 + 0.5) / n;
+            (inverse_cdf(p), x.to_f64().unwrap())
+        }).collect()
+    }
+
+    /// Returns the earth mover's distance between the distributions of the
+    /// data and `other`, in the units of the samples.
+    ///
+    /// This is the 1-Wasserstein distance: the area between the two
+    /// empirical distribution functions, or the mean distance that
+    /// samples have to move to turn one sample into the other. Since it
+    /// accounts for how far the samples move, it is more sensitive than
+    /// the Kolmogorov–Smirnov statistic to shifts in the tails. Both
+    /// samples are sorted and then merged, in `O(n log n)` time. See
+    /// `Histogram::earth_movers_distance` for binned data.
+    ///
+    /// `None` is returned if either sample is empty.
+    pub fn earth_movers_distance(
+        &mut self,
+        other: &mut Unsorted<T>,
+    ) -> Option<f64> {
+        if self.data.is_empty() || other.data.is_empty() {
+            return None;
+        }
+        self.sort();
+        other.sort();
+        let (a, b) = (&self.data, &other.data);
+        let (n, m) = (a.len() as f64, b.len() as f64);
+        let (mut i, mut j) = (0, 0);
+        let first = if a[0] <= b[0] { &a[0] } else { &b[0] };
+        let mut last = first.to_f64().unwrap();
+        let mut distance = 0.0;
+        while i < a.len() || j < b.len() {
+            let from_a = j == b.len() || (i < a.len() && a[i] <= b[j]);
+            let x = if from_a { &a[i] } else { &b[j] };
+            let x = x.to_f64().unwrap();
+            // Both distribution functions are constant since `last`.
+            distance += (i as f64 / n - j as f64 / m).abs() * (x - last);
+            if from_a { i += 1 } else { j += 1 }
+            last = x;
+        }
+        Some(distance)
+    }
+}
+
+impl<T: PartialOrd> Unsorted<T> {
+    /// The Kruskal–Wallis test of whether groups of samples come from the
+    /// same distribution.
+    ///
+    /// See `kruskal_wallis` for details.
+    pub fn kruskal_wallis(groups: &[Unsorted<T>]) -> Option<TestResult> {
+        let slices: Vec<&[Partial<T>]> =
+            groups.iter().map(|g| &g.data[..]).collect();
+        kruskal_wallis(&slices)
+    }
 }
 
 impl<T: PartialOrd> Commute for Unsorted<T> {
@@ -209,11 +713,69 @@ impl<T: PartialOrd> Commute for Unsorted<T> {
     }
 }
 
+impl<T: PartialOrd + Clone> CommuteRef for Unsorted<T> {
+    fn merge_ref(&mut self, v: &Unsorted<T>) {
+        self.dirtied();
+        self.data.extend(v.data.iter().cloned());
+    }
+}
+
+/// The samples are written in sorted order, so the same samples give the
+/// same bytes however they were added.
+impl<T: PartialOrd + Record> Checkpoint for Unsorted<T> {
+    fn to_checkpoint(&self) -> Vec<u8> {
+        // Samples that compare equal, like `0.0` and `-0.0` or NaNs with
+        // different bits, are ordered by their bytes, so the same samples
+        // always give the same checkpoint.
+        let mut data: Vec<(&T, Vec<u8>)> = self.data.iter().map(|x| {
+            let mut bytes = vec![];
+            x.0.write_le(&mut bytes);
+            (&x.0, bytes)
+        }).collect();
+        data.sort_by(|a, b| total_cmp(a.0, b.0).then_with(|| a.1.cmp(&b.1)));
+        let mut out = vec![];
+        (data.len() as u64).write_le(&mut out);
+        for (_, bytes) in data {
+            out.extend(bytes);
+        }
+        seal(Kind::Unsorted, out)
+    }
+
+    fn from_checkpoint(bytes: &[u8]) -> Result<Unsorted<T>, CheckpointError> {
+        let mut r = unseal(Kind::Unsorted, bytes)?;
+        let mut v = Unsorted::new();
+        for _ in 0..r.read::<u64>()? {
+            v.add(r.read()?);
+        }
+        r.finish()?;
+        Ok(v)
+    }
+}
+
+/// The samples are serialized as a sequence, in their current order. The
+/// cached statistics are recomputed after deserializing.
+#[cfg(feature = "serialize")]
+impl<T: PartialOrd + Serialize> Serialize for Unsorted<T> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(self.data.iter().map(|x| &x.0))
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de, T: PartialOrd + Deserialize<'de>> Deserialize<'de> for Unsorted<T> {
+    fn deserialize<D>(d: D) -> Result<Unsorted<T>, D::Error>
+            where D: Deserializer<'de> {
+        Vec::<T>::deserialize(d).map(|data| data.into_iter().collect())
+    }
+}
+
 impl<T: PartialOrd> Default for Unsorted<T> {
     fn default() -> Unsorted<T> {
         Unsorted {
-            data: Vec::with_capacity(1000),
+            data: new_buffer(),
             sorted: true,
+            modes: None,
+            cardinality: None,
         }
     }
 }
@@ -233,9 +795,178 @@ impl<T: PartialOrd> Extend<T> for Unsorted<T> {
     }
 }
 
+impl<T, C, S> From<Frequencies<T, C, S>> for Unsorted<T>
+        where T: PartialOrd + Eq + Hash + Clone, C: Count, S: BuildHasher {
+    /// Expand a frequency table, adding every value as many times as it was
+    /// counted.
+    fn from(freqs: Frequencies<T, C, S>) -> Unsorted<T> {
+        let mut v = Unsorted::new();
+        for (x, count) in freqs.data {
+            for _ in 0..widen(count) {
+                v.add(x.clone());
+            }
+        }
+        v
+    }
+}
+
+impl<T: PartialOrd, R: RandomSource> From<Reservoir<T, R>> for Unsorted<T> {
+    /// Collect the sample of a reservoir.
+    fn from(reservoir: Reservoir<T, R>) -> Unsorted<T> {
+        reservoir.into_sample().into_iter().collect()
+    }
+}
+
+impl<T, C, S> From<Unsorted<T>> for Frequencies<T, C, S>
+        where T: PartialOrd + Eq + Hash, C: Count,
+              S: BuildHasher + Default {
+    /// Count the occurrences of every value in the data.
+    fn from(v: Unsorted<T>) -> Frequencies<T, C, S> {
+        v.data.into_iter().map(|x| x.0).collect()
+    }
+}
+
+/// A commutative data structure for quantiles in bounded memory.
+///
+/// Samples are kept exactly, as in `Unsorted`, until there are more than
+/// `limit` of them. Then they are folded into a `TDigest`, so memory stays
+/// bounded, and quantiles become estimates. `is_exact` reports which is the
+/// case.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: PartialOrd + Serialize",
+    deserialize = "T: PartialOrd + Deserialize<'de>"
+)))]
+pub struct BoundedUnsorted<T> {
+    limit: usize,
+    samples: Samples<T>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound(
+    serialize = "T: PartialOrd + Serialize",
+    deserialize = "T: PartialOrd + Deserialize<'de>"
+)))]
+enum Samples<T> {
+    Exact(Unsorted<T>),
+    Approximate(TDigest),
+}
+
+impl<T: PartialOrd + ToPrimitive> BoundedUnsorted<T> {
+    /// Create empty state that is exact for up to `limit` samples.
+    pub fn with_limit(limit: usize) -> BoundedUnsorted<T> {
+        BoundedUnsorted {
+            limit,
+            samples: Samples::Exact(Unsorted::new()),
+        }
+    }
+
+    /// Add a sample.
+    pub fn add(&mut self, v: T) {
+        let spill = match self.samples {
+            Samples::Exact(ref mut data) => {
+                data.add(v);
+                data.len() > self.limit
+            }
+            Samples::Approximate(ref mut digest) => {
+                digest.add(v);
+                false
+            }
+        };
+        if spill {
+            self.spill();
+        }
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        match self.samples {
+            Samples::Exact(ref data) => data.len(),
+            Samples::Approximate(ref digest) => digest.len(),
+        }
+    }
+
+    /// Returns the median of the data.
+    ///
+    /// This is exact if `is_exact` returns true, and an estimate otherwise.
+    pub fn median(&mut self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    /// Returns the `q`th quantile of the data.
+    ///
+    /// This is exact if `is_exact` returns true, and an estimate otherwise.
+    /// `None` is returned if there is no data or if `q` is not in `[0, 1]`.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        match self.samples {
+            Samples::Exact(ref mut data) => data.quantile(q),
+            Samples::Approximate(ref mut digest) => digest.quantile(q),
+        }
+    }
+
+    /// Returns true if the samples are kept exactly.
+    pub fn is_exact(&self) -> bool {
+        match self.samples {
+            Samples::Exact(_) => true,
+            Samples::Approximate(_) => false,
+        }
+    }
+
+    /// Returns the largest number of samples that are kept exactly.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Switches to a digest.
+    fn spill(&mut self) {
+        let digest = TDigest::new();
+        match mem::replace(&mut self.samples, Samples::Approximate(digest)) {
+            Samples::Exact(data) => {
+                if let Samples::Approximate(ref mut digest) = self.samples {
+                    digest.extend(data.data.into_iter().map(|x| x.0));
+                }
+            }
+            approximate => self.samples = approximate,
+        }
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive> Commute for BoundedUnsorted<T> {
+    fn merge(&mut self, v: BoundedUnsorted<T>) {
+        match v.samples {
+            Samples::Exact(data) => {
+                self.extend(data.data.into_iter().map(|x| x.0))
+            }
+            Samples::Approximate(other) => {
+                self.spill();
+                if let Samples::Approximate(ref mut digest) = self.samples {
+                    digest.merge(other);
+                }
+            }
+        }
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive> fmt::Debug for BoundedUnsorted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BoundedUnsorted(len: {}, exact: {})",
+               self.len(), self.is_exact())
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive> Extend<T> for BoundedUnsorted<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{median, mode, modes};
+    use super::{BoundedUnsorted, Unsorted, median, mode, modes};
 
     #[test]
     fn median_stream() {
@@ -252,6 +983,70 @@ mod test {
         assert_eq!(mode(vec![1usize, 1, 2, 3, 3].into_iter()), None);
     }
 
+    #[test]
+    fn quantiles() {
+        let mut u: Unsorted<u32> = (1..101).rev().collect();
+        assert_eq!(u.quantile(0.5), u.median());
+        assert_eq!(u.quantile(0.0), Some(1.0));
+        assert_eq!(u.quantile(1.0), Some(100.0));
+        let qs = u.quantiles(&[0.9, 0.99, 1.5]);
+        assert!((qs[0].unwrap() - 90.1).abs() < 1e-9);
+        assert!((qs[1].unwrap() - 99.01).abs() < 1e-9);
+        assert_eq!(qs[2], None);
+        assert_eq!(Unsorted::<u32>::new().quantile(0.5), None);
+    }
+
+    #[test]
+    fn mad() {
+        let mut u: Unsorted<i32> =
+            vec![1, 1, 2, 2, 4, 6, 9].into_iter().collect();
+        // The deviations from the median of 2 are 1, 1, 0, 0, 2, 4, 7.
+        assert_eq!(u.mad(), Some(1.0));
+        assert_eq!(u.mad_normal(), Some(1.4826));
+        // An outlier barely moves it.
+        u.add(1000);
+        assert_eq!(u.mad(), Some(2.0));
+        assert_eq!(Unsorted::<i32>::new().mad(), None);
+    }
+
+    #[test]
+    fn mad_nan() {
+        // The standard sorts only notice an inconsistent order of NaN with
+        // more than 20 elements.
+        let mut data: Vec<f64> = (0..40).map(|x| x as f64).collect();
+        data[10] = ::std::f64::NAN;
+        let mut u: Unsorted<f64> = data.into_iter().collect();
+        // NaN sorts last, so the median is 20.5 and the deviations are
+        // twice each of `0.5, ..., 9.5` and then the one `10.5` left.
+        assert_eq!(u.mad(), Some(10.0));
+    }
+
+    #[test]
+    fn trimmed_means() {
+        let mut u: Unsorted<i32> =
+            vec![100, 1, 2, 3, 4, 5, 6, 7, 8, -50].into_iter().collect();
+        assert_eq!(u.trimmed_mean(0.0), Some(8.6));
+        // One sample is dropped from each end.
+        assert_eq!(u.trimmed_mean(0.15), Some(4.5));
+        // The ends are clamped to 1 and 8.
+        assert_eq!(u.winsorized_mean(0.1), Some(4.5));
+        u.add(20);
+        // The ends are clamped to 1 and 20.
+        assert_eq!(u.winsorized_mean(0.1), Some(7.0));
+        assert_eq!(u.trimmed_mean(0.5), None);
+        assert_eq!(Unsorted::<i32>::new().trimmed_mean(0.1), None);
+    }
+
+    #[test]
+    fn quartiles() {
+        let mut u: Unsorted<u32> = vec![7, 1, 3, 5, 9].into_iter().collect();
+        assert_eq!(u.quartiles(), Some((3.0, 5.0, 7.0)));
+        assert_eq!(u.iqr(), Some(4.0));
+        u.add(11);
+        assert_eq!(u.quartiles(), Some((3.5, 6.0, 8.5)));
+        assert_eq!(Unsorted::<u32>::new().iqr(), None);
+    }
+
     #[test]
     fn median_floats() {
         assert_eq!(median(vec![3.0f64, 5.0, 7.0, 9.0].into_iter()), Some(6.0));
@@ -279,6 +1074,18 @@ mod test {
         assert_eq!(modes(vec.into_iter()), vec![]);
     }
 
+    #[test]
+    fn modes_with_count() {
+        let mut u: Unsorted<u32> =
+            vec![4, 1, 4, 2, 1, 3, 4, 1].into_iter().collect();
+        assert_eq!(u.modes_with_count(), (vec![1, 4], 3));
+        assert_eq!(u.mode(), None);
+        u.add(4);
+        assert_eq!(u.modes_with_count_ref(), (vec![&4], 4));
+        let mut distinct: Unsorted<u32> = (0..5).collect();
+        assert_eq!(distinct.modes_with_count(), (vec![], 0));
+    }
+
     #[test]
     fn modes_floats() {
         assert_eq!(modes(vec![3_f64, 5.0, 7.0, 9.0].into_iter()), vec![]);
@@ -286,4 +1093,181 @@ mod test {
         assert_eq!(modes(vec![3_f64, 3.0, 4.0, 4.0].into_iter()), vec![3.0, 4.0]);
         assert_eq!(modes(vec![1_f64, 1.0, 2.0, 3.0, 3.0].into_iter()), vec![1.0, 3.0]);
     }
+
+    #[test]
+    fn qq_normal() {
+        let mut data: Unsorted<usize> = vec![3, 1, 2].into_iter().collect();
+        let points = data.qq_normal();
+        assert_eq!(points.len(), 3);
+        assert!((points[0].0 + 0.9674215661017).abs() < 1e-8);
+        assert_eq!(points[1], (0.0, 2.0));
+        assert!((points[2].0 - 0.9674215661017).abs() < 1e-8);
+        assert_eq!(points[2].1, 3.0);
+    }
+
+    #[test]
+    fn qq_points_custom() {
+        let mut data: Unsorted<f64> = vec![0.5, 0.1].into_iter().collect();
+        assert_eq!(data.qq_points(|p| p), vec![(0.25, 0.1), (0.75, 0.5)]);
+    }
+
+    #[test]
+    fn half_sample_mode() {
+        let mut data: Unsorted<f64> =
+            vec![1.0, 2.0, 2.1, 2.15, 2.2, 5.0, 9.0, 20.0]
+                .into_iter().collect();
+        // [2, 2.1, 2.15, 2.2] -> [2.1, 2.15] -> 2.125
+        assert_eq!(data.half_sample_mode(), Some(2.125));
+        data.add(2.12);
+        // [2, 2.1, 2.12, 2.15, 2.2] -> [2.1, 2.12, 2.15] -> 2.11
+        assert!((data.half_sample_mode().unwrap() - 2.11).abs() < 1e-12);
+        assert_eq!(Unsorted::<f64>::new().half_sample_mode(), None);
+    }
+
+    #[test]
+    fn grenander() {
+        let mut data: Unsorted<f64> =
+            vec![0.0, 1.0, 1.1, 1.2, 5.0].into_iter().collect();
+        let mode = data.grenander(1, 2.0).unwrap();
+        assert!(mode > 1.0 && mode < 1.2);
+        let mut tied: Unsorted<u8> = vec![1, 3, 3, 7].into_iter().collect();
+        assert_eq!(tied.grenander(1, 1.0), Some(3.0));
+        assert_eq!(tied.grenander(4, 1.0), None);
+    }
+
+    #[test]
+    fn earth_movers_distance() {
+        let mut a: Unsorted<u32> = vec![3, 1, 2].into_iter().collect();
+        let mut b: Unsorted<u32> = vec![4, 2, 3].into_iter().collect();
+        assert_eq!(a.earth_movers_distance(&mut b), Some(1.0));
+        assert_eq!(b.earth_movers_distance(&mut a), Some(1.0));
+        assert_eq!(a.clone().earth_movers_distance(&mut a), Some(0.0));
+
+        // Both samples of `a` move by 0.5 to meet the one of `b`.
+        let mut a: Unsorted<f64> = vec![0.0, 1.0].into_iter().collect();
+        let mut b: Unsorted<f64> = vec![0.5].into_iter().collect();
+        assert_eq!(a.earth_movers_distance(&mut b), Some(0.5));
+        assert_eq!(a.earth_movers_distance(&mut Unsorted::new()), None);
+    }
+
+    #[test]
+    fn frequencies_roundtrip() {
+        use Frequencies;
+
+        let data: Unsorted<u8> = vec![3, 1, 3, 2, 3].into_iter().collect();
+        let freqs: Frequencies<u8> = Frequencies::from(data);
+        assert_eq!(freqs.count(&3), 3);
+        assert_eq!(freqs.cardinality(), 3);
+        let mut data = Unsorted::from(freqs);
+        assert_eq!(data.len(), 5);
+        assert_eq!(data.median(), Some(3.0));
+    }
+
+    #[test]
+    fn quantile_normalize() {
+        let mut reference: Unsorted<f64> =
+            vec![1.0, 2.0, 3.0, 4.0].into_iter().collect();
+        assert_eq!(super::quantile_normalize(&[10, 30, 20, 20],
+                                             &mut reference),
+                   Some(vec![1.0, 4.0, 3.0, 3.0]));
+        assert_eq!(super::quantile_normalize(&[] as &[u8], &mut reference),
+                   Some(vec![]));
+        assert_eq!(super::quantile_normalize(&[1],
+                                             &mut Unsorted::<u8>::new()),
+                   None);
+
+        let mut data: Vec<f64> = (0..30).map(|x| (30 - x) as f64).collect();
+        data[5] = ::std::f64::NAN;
+        let mut reference: Unsorted<u32> = (1..30).collect();
+        let normalized =
+            super::quantile_normalize(&data, &mut reference).unwrap();
+        assert!(normalized[5].is_nan());
+        assert_eq!((normalized[0], normalized[29]), (29.0, 1.0));
+    }
+
+    #[test]
+    fn quantile_function() {
+        let mut data: Unsorted<u32> = vec![4, 1, 3, 2].into_iter().collect();
+        assert_eq!(data.quantile_function(0.0), Some(1.0));
+        assert_eq!(data.quantile_function(0.25), Some(1.0));
+        assert_eq!(data.quantile_function(0.26), Some(2.0));
+        assert_eq!(data.quantile_function(0.5), Some(2.0));
+        assert_eq!(data.quantile_function(1.0), Some(4.0));
+        assert_eq!(data.quantile_function(1.1), None);
+        // `0.07 * 100` is slightly more than `7`.
+        let mut data: Unsorted<u32> = (1..101).collect();
+        assert_eq!(data.quantile_function(0.07), Some(7.0));
+        assert_eq!(data.quantile_function(0.071), Some(8.0));
+        assert_eq!(Unsorted::<u32>::new().quantile_function(0.5), None);
+    }
+
+    #[test]
+    fn mode_ref_without_clone() {
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct NoClone(u32);
+
+        let mut data: Unsorted<NoClone> =
+            vec![NoClone(2), NoClone(1), NoClone(2)].into_iter().collect();
+        assert_eq!(data.mode_ref(), Some(&NoClone(2)));
+        assert_eq!(data.modes_ref(), vec![&NoClone(2)]);
+    }
+
+    #[test]
+    fn mode_longest_run() {
+        let data = vec![1usize, 1, 1, 1, 2, 2, 2, 2, 3, 3];
+        assert_eq!(mode(data.clone().into_iter()), None);
+        assert_eq!(modes(data.into_iter()), vec![1, 2]);
+    }
+
+    #[test]
+    fn cached_until_dirtied() {
+        let mut data: Unsorted<u32> = vec![1, 2, 2, 3].into_iter().collect();
+        assert_eq!(data.mode(), Some(2));
+        assert_eq!(data.cardinality(), 3);
+        data.extend(vec![3, 3, 4]);
+        assert_eq!(data.mode(), Some(3));
+        assert_eq!(data.cardinality(), 4);
+    }
+
+    #[test]
+    fn median_low_high_exact() {
+        let big = 1u128 << 100;
+        let mut data: Unsorted<u128> =
+            vec![big + 3, big + 1, big + 2, big + 4].into_iter().collect();
+        assert_eq!(data.median_low(), Some(&(big + 2)));
+        assert_eq!(data.median_high(), Some(&(big + 3)));
+        data.add(big);
+        assert_eq!(data.median_low(), Some(&(big + 2)));
+        assert_eq!(data.median_high(), Some(&(big + 2)));
+        assert_eq!(Unsorted::<i128>::new().median_low(), None);
+    }
+
+    #[test]
+    fn mode_wide_integers() {
+        let big = -(1i128 << 90);
+        assert_eq!(mode(vec![big, big + 1, big].into_iter()), Some(big));
+    }
+
+    #[test]
+    fn bounded() {
+        use Commute;
+
+        let mut small = BoundedUnsorted::with_limit(100);
+        small.extend(vec![3u32, 1, 2, 4]);
+        assert!(small.is_exact());
+        assert_eq!(small.median(), Some(2.5));
+
+        let mut big = BoundedUnsorted::with_limit(100);
+        big.extend(0..1001u32);
+        assert!(!big.is_exact());
+        assert_eq!(big.len(), 1001);
+        let med = big.median().unwrap();
+        assert!((med - 500.0).abs() < 10.0, "median {}", med);
+
+        small.merge(big);
+        assert!(!small.is_exact());
+        assert_eq!(small.len(), 1005);
+        assert_eq!(small.quantile(0.0), Some(0.0));
+        assert_eq!(small.quantile(1.0), Some(1000.0));
+    }
 }
@@ -0,0 +1,255 @@
+use std::prelude::v1::*;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::iter::IntoIterator;
+
+use num_traits::ToPrimitive;
+
+/// Online state for the mean and variance of the last `size` samples.
+///
+/// When the window is full, adding a sample evicts the oldest one, so
+/// this is a rolling version of `OnlineStats`. Both adding and evicting
+/// use Welford's updates and take `O(1)` time, but the window keeps its
+/// samples, so it uses `O(size)` memory.
+///
+/// Like `SeriesStats`, this does not implement `Commute`, since the window
+/// depends on the order of the samples.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct WindowStats {
+    size: usize,
+    samples: VecDeque<f64>,
+    mean: f64,
+    q: f64,
+}
+
+impl fmt::Debug for WindowStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.10} +/- {:.10} (last {})",
+               self.mean(), self.stddev(), self.samples.len())
+    }
+}
+
+impl WindowStats {
+    /// Create an empty window of the last `size` samples.
+    ///
+    /// This panics if `size` is `0`.
+    pub fn new(size: usize) -> WindowStats {
+        assert!(size > 0, "window size must be positive");
+        WindowStats {
+            size,
+            samples: VecDeque::with_capacity(size),
+            mean: 0.0,
+            q: 0.0,
+        }
+    }
+
+    /// Add a new sample, evicting the oldest sample if the window is full.
+    pub fn add<T: ToPrimitive>(&mut self, sample: T) {
+        if self.samples.len() == self.size {
+            let old = self.samples.pop_front().unwrap();
+            self.remove(old);
+        }
+        let x = sample.to_f64().unwrap();
+        self.samples.push_back(x);
+        let n = self.samples.len() as f64;
+        let delta = x - self.mean;
+        self.mean += delta / n;
+        self.q += delta * (x - self.mean);
+    }
+
+    fn remove(&mut self, x: f64) {
+        let n = self.samples.len() as f64;
+        if n == 0.0 {
+            self.mean = 0.0;
+            self.q = 0.0;
+            return;
+        }
+        let old_mean = self.mean;
+        self.mean -= (x - old_mean) / n;
+        // Rounding can leave a tiny negative sum for equal samples.
+        self.q = (self.q - (x - old_mean) * (x - self.mean)).max(0.0);
+    }
+
+    /// Returns the mean of the samples in the window.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the standard deviation of the samples in the window.
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the variance of the samples in the window.
+    pub fn variance(&self) -> f64 {
+        self.q / self.samples.len() as f64
+    }
+
+    /// Returns the number of samples in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns the maximum number of samples in the window.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns true if the window holds `size` samples.
+    pub fn is_full(&self) -> bool {
+        self.samples.len() == self.size
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for WindowStats {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+/// Online state for the minimum and maximum of the last `size` samples.
+///
+/// This is a rolling version of `MinMax`. Instead of every sample in the
+/// window, it keeps monotonic queues of the samples that can still become
+/// the minimum or maximum, so adding a sample takes amortized `O(1)` time.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct WindowMinMax<T> {
+    size: usize,
+    len: u64,
+    mins: VecDeque<(u64, T)>,
+    maxs: VecDeque<(u64, T)>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for WindowMinMax<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.mins.front(), self.maxs.front()) {
+            (Some((_, min)), Some((_, max))) => {
+                write!(f, "[{:?}, {:?}]", min, max)
+            }
+            _ => write!(f, "N/A"),
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone> WindowMinMax<T> {
+    /// Create an empty window of the last `size` samples.
+    ///
+    /// This panics if `size` is `0`.
+    pub fn new(size: usize) -> WindowMinMax<T> {
+        assert!(size > 0, "window size must be positive");
+        WindowMinMax {
+            size,
+            len: 0,
+            mins: VecDeque::new(),
+            maxs: VecDeque::new(),
+        }
+    }
+
+    /// Add a new sample, evicting the oldest sample if the window is full.
+    pub fn add(&mut self, sample: T) {
+        let i = self.len;
+        self.len += 1;
+        while self.mins.back().map_or(false, |(_, v)| *v >= sample) {
+            self.mins.pop_back();
+        }
+        while self.maxs.back().map_or(false, |(_, v)| *v <= sample) {
+            self.maxs.pop_back();
+        }
+        self.mins.push_back((i, sample.clone()));
+        self.maxs.push_back((i, sample));
+
+        let size = self.size as u64;
+        while self.mins.front().map_or(false, |&(j, _)| j + size <= i) {
+            self.mins.pop_front();
+        }
+        while self.maxs.front().map_or(false, |&(j, _)| j + size <= i) {
+            self.maxs.pop_front();
+        }
+    }
+
+    /// Returns the minimum of the samples in the window.
+    ///
+    /// `None` is returned if and only if no samples have been added.
+    pub fn min(&self) -> Option<&T> {
+        self.mins.front().map(|(_, v)| v)
+    }
+
+    /// Returns the maximum of the samples in the window.
+    ///
+    /// `None` is returned if and only if no samples have been added.
+    pub fn max(&self) -> Option<&T> {
+        self.maxs.front().map(|(_, v)| v)
+    }
+
+    /// Returns the number of samples in the window.
+    pub fn len(&self) -> usize {
+        self.len.min(self.size as u64) as usize
+    }
+
+    /// Returns the maximum number of samples in the window.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl<T: PartialOrd + Clone> Extend<T> for WindowMinMax<T> {
+    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{WindowMinMax, WindowStats};
+    use online::OnlineStats;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn rolling_stats() {
+        let data = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0, 5.0, 3.0];
+        let mut window = WindowStats::new(4);
+        for (i, &x) in data.iter().enumerate() {
+            window.add(x);
+            let start = i.saturating_sub(3);
+            let expected: OnlineStats =
+                data[start..i + 1].iter().cloned().collect();
+            assert_eq!(window.len(), i + 1 - start);
+            assert!(close(window.mean(), expected.mean()));
+            assert!(close(window.variance(), expected.variance()));
+        }
+        assert!(window.is_full());
+    }
+
+    #[test]
+    fn size_one() {
+        let mut window = WindowStats::new(1);
+        window.extend(vec![1, 7, 4]);
+        assert_eq!(window.mean(), 4.0);
+        assert_eq!(window.stddev(), 0.0);
+    }
+
+    #[test]
+    fn rolling_min_max() {
+        let data = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3];
+        let mut window = WindowMinMax::new(3);
+        assert_eq!(window.min(), None);
+        for (i, &x) in data.iter().enumerate() {
+            window.add(x);
+            let start = i.saturating_sub(2);
+            let slice = &data[start..i + 1];
+            assert_eq!(window.min(), slice.iter().min());
+            assert_eq!(window.max(), slice.iter().max());
+        }
+        assert_eq!(window.len(), 3);
+    }
+}